@@ -1,7 +1,12 @@
 //! CLI argument parsing and command routing
 
+pub mod print;
+
 use clap::{Parser, Subcommand};
 
+pub use self::print::{OutputFormat, PrintOptions};
+use crate::permissions::PermissionMode;
+
 /// Kode: AI-powered terminal assistant
 #[derive(Debug, Parser)]
 #[command(name = "kode")]
@@ -12,6 +17,42 @@ pub struct Cli {
     #[arg(short, long, global = true)]
     pub verbose: bool,
 
+    /// Answer PROMPT without the interactive UI, print the result and exit
+    /// (`-` reads the prompt from stdin)
+    #[arg(short = 'p', long = "print", value_name = "PROMPT")]
+    pub print: Option<String>,
+
+    /// Continue the most recent session in the current directory
+    #[arg(short = 'c', long = "continue")]
+    pub continue_session: bool,
+
+    /// Resume a session by ID, or pick one interactively if no ID is given
+    #[arg(
+        short = 'r',
+        long,
+        value_name = "ID",
+        num_args = 0..=1,
+        conflicts_with = "continue_session"
+    )]
+    pub resume: Option<Option<String>>,
+
+    /// Output format for non-interactive runs
+    #[arg(long, value_enum, default_value_t, global = true)]
+    pub output_format: OutputFormat,
+
+    /// Permission rules to allow without asking, e.g. "Bash(git diff:*)"
+    /// (comma-separated, non-interactive runs)
+    #[arg(long, value_delimiter = ',', global = true)]
+    pub allowed_tools: Vec<String>,
+
+    /// Permission rules to always deny (comma-separated, non-interactive runs)
+    #[arg(long, value_delimiter = ',', global = true)]
+    pub disallowed_tools: Vec<String>,
+
+    /// How tool uses matching no rule are handled in non-interactive runs
+    #[arg(long, value_enum, default_value_t, global = true)]
+    pub permission_mode: PermissionMode,
+
     /// Subcommand to execute
     #[command(subcommand)]
     pub command: Option<Commands>,
@@ -23,7 +64,7 @@ pub enum Commands {
     /// Start interactive REPL
     Repl,
 
-    /// Run a single query
+    /// Run a single query without the interactive UI
     Query {
         /// The query to execute
         query: String,
@@ -65,6 +106,15 @@ pub enum Commands {
         /// Remove a model
         #[arg(long)]
         remove: Option<String>,
+
+        /// Add a profile for every model installed on an Ollama server
+        #[arg(long)]
+        discover: bool,
+
+        /// Ollama server to discover models on (defaults to `OLLAMA_HOST`,
+        /// then `http://localhost:11434`)
+        #[arg(long, value_name = "URL", requires = "discover")]
+        base_url: Option<String>,
     },
 
     /// Manage agents
@@ -84,6 +134,17 @@ impl Cli {
     pub fn parse_args() -> Self {
         Self::parse()
     }
+
+    /// Settings for a non-interactive run
+    #[must_use]
+    pub fn print_options(&self) -> PrintOptions {
+        PrintOptions {
+            output_format: self.output_format,
+            allowed_tools: self.allowed_tools.clone(),
+            disallowed_tools: self.disallowed_tools.clone(),
+            permission_mode: self.permission_mode,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -95,4 +156,40 @@ mod tests {
         // Just ensure the CLI can be constructed
         let _ = Cli::parse_args();
     }
+
+    #[test]
+    fn test_session_flags() {
+        let cli = Cli::parse_from(["kode", "--continue"]);
+        assert!(cli.continue_session);
+        assert!(cli.resume.is_none());
+
+        let cli = Cli::parse_from(["kode", "--resume", "abc"]);
+        assert_eq!(cli.resume, Some(Some("abc".to_string())));
+
+        let cli = Cli::parse_from(["kode", "--resume"]);
+        assert_eq!(cli.resume, Some(None));
+
+        assert!(Cli::try_parse_from(["kode", "--continue", "--resume"]).is_err());
+    }
+
+    #[test]
+    fn test_print_mode_flags() {
+        let cli = Cli::parse_from([
+            "kode",
+            "-p",
+            "explain this repo",
+            "--output-format",
+            "stream-json",
+            "--allowed-tools",
+            "Bash(git diff:*),View",
+            "--permission-mode",
+            "accept-edits",
+        ]);
+
+        assert_eq!(cli.print.as_deref(), Some("explain this repo"));
+        let options = cli.print_options();
+        assert_eq!(options.output_format, OutputFormat::StreamJson);
+        assert_eq!(options.allowed_tools, vec!["Bash(git diff:*)", "View"]);
+        assert_eq!(options.permission_mode, PermissionMode::AcceptEdits);
+    }
 }
@@ -0,0 +1,366 @@
+//! Headless print mode (`kode -p`)
+//!
+//! Runs the full agent loop without a terminal UI and writes the outcome to
+//! stdout in one of three formats:
+//!
+//! - `text`: the final assistant answer
+//! - `json`: a single [`PrintResult`] object with usage and cost
+//! - `stream-json`: every [`ConversationMessage`] as one JSON object per line
+//!
+//! There is nobody to ask for permission, so tool uses that match no allow
+//! rule are denied unless the permission mode says otherwise.
+
+use std::{
+    io::{Read, Write},
+    sync::Arc,
+    time::Instant,
+};
+
+use clap::ValueEnum;
+use futures::StreamExt;
+use indexmap::IndexMap;
+use serde::Serialize;
+
+use crate::{
+    config::{Config, ModelPointerType, PermissionsConfig},
+    cost::{CostTotals, CostTracker},
+    error::{KodeError, Result},
+    messages::{ConversationMessage, Message},
+    permissions::{DenyPrompter, PermissionMode, PermissionRules, PermissionService},
+    query::{self, Compactor, QueryContext, QueryEvent},
+    services::{ModelAdapter, ModelAdapterFactory, Usage},
+    tools::ToolRegistry,
+};
+
+/// Output format for print mode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Final answer as plain text
+    #[default]
+    Text,
+
+    /// Single JSON result object
+    Json,
+
+    /// Newline-delimited JSON conversation messages
+    StreamJson,
+}
+
+/// Settings for a print mode run
+#[derive(Debug, Clone, Default)]
+pub struct PrintOptions {
+    /// How to write the outcome
+    pub output_format: OutputFormat,
+
+    /// Extra allow rules, e.g. `Bash(git diff:*)`
+    pub allowed_tools: Vec<String>,
+
+    /// Extra deny rules
+    pub disallowed_tools: Vec<String>,
+
+    /// How tool uses matching no rule are handled
+    pub permission_mode: PermissionMode,
+}
+
+/// Result object written by the `json` output format
+#[derive(Debug, Clone, Serialize)]
+pub struct PrintResult {
+    /// Always `"result"`
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+
+    /// `"success"` or `"error"`
+    pub subtype: &'static str,
+
+    /// Whether the run failed
+    pub is_error: bool,
+
+    /// Final assistant answer, or the error message
+    pub result: String,
+
+    /// Number of assistant turns
+    pub num_turns: u32,
+
+    /// Wall-clock duration of the run
+    pub duration_ms: u64,
+
+    /// Token usage summed over all turns
+    pub usage: Usage,
+
+    /// Cost summed over all turns
+    pub total_cost_usd: f64,
+
+    /// Usage and cost per model, including summaries written for compaction
+    pub model_usage: IndexMap<String, CostTotals>,
+}
+
+/// Run a prompt headlessly and write the outcome to stdout
+///
+/// A prompt of `-` is read from stdin.
+///
+/// # Errors
+///
+/// Returns an error if no model is configured, the prompt is empty or the
+/// query fails; see [`KodeError::exit_code`] for the matching exit codes
+pub async fn run(prompt: &str, options: &PrintOptions) -> Result<()> {
+    let prompt = read_prompt(prompt)?;
+    let config = Config::load()?;
+    let context = build_context(&config, options)?;
+    let mut costs = CostTracker::new().with_ledger(CostTracker::default_ledger_path());
+
+    let mut stdout = std::io::stdout().lock();
+    execute(&prompt, context, &mut costs, options.output_format, &mut stdout).await
+}
+
+/// Resolve the prompt argument, reading stdin for `-`
+fn read_prompt(prompt: &str) -> Result<String> {
+    let prompt = if prompt == "-" {
+        let mut input = String::new();
+        std::io::stdin().read_to_string(&mut input)?;
+        input
+    } else {
+        prompt.to_string()
+    };
+
+    if prompt.trim().is_empty() {
+        return Err(KodeError::InvalidInput("Prompt is empty".to_string()));
+    }
+    Ok(prompt)
+}
+
+/// Build the query context for a print mode run
+///
+/// # Errors
+///
+/// Returns an error if no main model is configured, its adapter cannot be
+/// created or a permission rule is invalid
+pub fn build_context(config: &Config, options: &PrintOptions) -> Result<QueryContext> {
+    let profile = config
+        .get_model_by_pointer(ModelPointerType::Main)
+        .ok_or_else(|| {
+            KodeError::ModelNotFound(
+                "No main model configured. Please configure a model using `kode models --add`"
+                    .to_string(),
+            )
+        })?;
+    let adapter: Arc<dyn ModelAdapter> = Arc::from(ModelAdapterFactory::create_with_fallbacks(
+        profile,
+        &config.fallback_models(ModelPointerType::Main),
+        config.fallback_cooldown(),
+    )?);
+
+    let mut rules = PermissionRules::from_config(config)?;
+    rules.extend(&PermissionsConfig {
+        allow: options.allowed_tools.clone(),
+        deny: options.disallowed_tools.clone(),
+    })?;
+    let permissions =
+        PermissionService::new(Arc::new(DenyPrompter), Config::project_config_path(), rules)
+            .with_mode(options.permission_mode);
+
+    let mut context =
+        QueryContext::new(adapter.clone(), Arc::new(ToolRegistry::with_builtins()));
+    context.max_tool_concurrency = config.global.max_tool_use_concurrency;
+    context.permissions = Some(Arc::new(permissions));
+    if config.global.auto_compact {
+        context.compactor = Some(Compactor::from_config(config, adapter));
+    }
+    Ok(context)
+}
+
+/// Run the query loop and write its outcome in the requested format
+///
+/// Every priced response is recorded in `costs`; the `json` format reports its
+/// totals. With the `json` format a failed run still produces a result object
+/// (with `is_error` set) before the error is returned.
+///
+/// # Errors
+///
+/// Returns an error if the query fails or the output cannot be written
+pub async fn execute<W: Write>(
+    prompt: &str,
+    context: QueryContext,
+    costs: &mut CostTracker,
+    format: OutputFormat,
+    out: &mut W,
+) -> Result<()> {
+    let start = Instant::now();
+    let model = context.adapter.model().to_string();
+    let mut stream = query::query(vec![Message::user(prompt)], context);
+
+    let mut answer = String::new();
+    let mut num_turns = 0;
+    let mut failure = None;
+
+    // The ledger is best effort; failing to write it must not fail the run
+    while let Some(event) = stream.next().await {
+        match event {
+            Ok(QueryEvent::Chunk(_)) => {}
+            Ok(QueryEvent::Compacted(compaction)) => {
+                if let Some(usage) = &compaction.usage {
+                    let _ = costs.record(&compaction.model, usage, compaction.cost_usd);
+                }
+            }
+            Ok(QueryEvent::Message(message)) => {
+                if let ConversationMessage::Assistant(assistant) = &message {
+                    num_turns += 1;
+                    answer = assistant.message.text_content();
+                    if let Some(usage) = &assistant.usage {
+                        let model = assistant.model.as_deref().unwrap_or(&model);
+                        let _ = costs.record(model, usage, assistant.cost_usd);
+                    }
+                }
+                if format == OutputFormat::StreamJson {
+                    writeln!(out, "{}", serde_json::to_string(&message)?)?;
+                }
+            }
+            Err(e) => {
+                failure = Some(e);
+                break;
+            }
+        }
+    }
+
+    match format {
+        OutputFormat::Text => {
+            if failure.is_none() {
+                writeln!(out, "{answer}")?;
+            }
+        }
+        OutputFormat::Json => {
+            let result = PrintResult {
+                kind: "result",
+                subtype: if failure.is_some() { "error" } else { "success" },
+                is_error: failure.is_some(),
+                result: failure.as_ref().map_or(answer, ToString::to_string),
+                num_turns,
+                duration_ms: u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX),
+                usage: costs.total().usage.clone(),
+                total_cost_usd: costs.total().cost_usd,
+                model_usage: costs.by_model().clone(),
+            };
+            writeln!(out, "{}", serde_json::to_string(&result)?)?;
+        }
+        OutputFormat::StreamJson => {}
+    }
+    out.flush()?;
+
+    failure.map_or(Ok(()), Err)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::{
+        cost::ModelPricing,
+        services::{testing::ScriptedAdapter, CompletionChunk},
+    };
+
+    fn context(turns: Vec<Vec<CompletionChunk>>) -> QueryContext {
+        QueryContext::new(
+            Arc::new(ScriptedAdapter::new(turns)),
+            Arc::new(ToolRegistry::new()),
+        )
+    }
+
+    fn answer(text: &str) -> Vec<CompletionChunk> {
+        vec![
+            CompletionChunk::TextDelta {
+                text: text.to_string(),
+            },
+            CompletionChunk::Done {
+                stop_reason: "end_turn".to_string(),
+                usage: Some(Usage {
+                    input_tokens: 10,
+                    output_tokens: 5,
+                    ..Usage::default()
+                }),
+            },
+        ]
+    }
+
+    async fn run_format(turns: Vec<Vec<CompletionChunk>>, format: OutputFormat) -> String {
+        let mut out = Vec::new();
+        execute("Hi", context(turns), &mut CostTracker::new(), format, &mut out)
+            .await
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_text_output_prints_final_answer() {
+        let output = run_format(vec![answer("Hello there")], OutputFormat::Text).await;
+        assert_eq!(output, "Hello there\n");
+    }
+
+    #[tokio::test]
+    async fn test_json_output_reports_usage() {
+        let output = run_format(vec![answer("Hello")], OutputFormat::Json).await;
+        let result: Value = serde_json::from_str(&output).unwrap();
+
+        assert_eq!(result["type"], "result");
+        assert_eq!(result["subtype"], "success");
+        assert_eq!(result["is_error"], false);
+        assert_eq!(result["result"], "Hello");
+        assert_eq!(result["num_turns"], 1);
+        assert_eq!(result["usage"], json!({"input_tokens": 10, "output_tokens": 5}));
+    }
+
+    #[tokio::test]
+    async fn test_json_output_reports_cost_per_model() {
+        let adapter = ScriptedAdapter::new(vec![answer("Hello")])
+            .with_pricing(ModelPricing::new(1_000.0, 2_000.0, 0.0, 0.0));
+        let context = QueryContext::new(Arc::new(adapter), Arc::new(ToolRegistry::new()));
+        let mut costs = CostTracker::new();
+        let mut out = Vec::new();
+        execute("Hi", context, &mut costs, OutputFormat::Json, &mut out)
+            .await
+            .unwrap();
+
+        // 10 input tokens at $1000/M plus 5 output tokens at $2000/M
+        let result: Value = serde_json::from_slice(&out).unwrap();
+        assert!((result["total_cost_usd"].as_f64().unwrap() - 0.02).abs() < 1e-9);
+        assert_eq!(result["model_usage"]["scripted-model"]["requests"], 1);
+        assert_eq!(costs.total().requests, 1);
+    }
+
+    #[tokio::test]
+    async fn test_stream_json_output_emits_one_message_per_line() {
+        let output = run_format(vec![answer("Hello")], OutputFormat::StreamJson).await;
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(lines.len(), 1);
+        let message: ConversationMessage = serde_json::from_str(lines[0]).unwrap();
+        assert!(message.is_assistant());
+    }
+
+    #[tokio::test]
+    async fn test_json_output_on_error() {
+        let turns = vec![vec![CompletionChunk::Error {
+            message: "overloaded".to_string(),
+        }]];
+        let mut out = Vec::new();
+        let result = execute(
+            "Hi",
+            context(turns),
+            &mut CostTracker::new(),
+            OutputFormat::Json,
+            &mut out,
+        )
+        .await;
+
+        let error = result.unwrap_err();
+        assert_eq!(error.exit_code(), 4);
+        let output: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(output["is_error"], true);
+        assert_eq!(output["subtype"], "error");
+    }
+
+    #[test]
+    fn test_empty_prompt_is_rejected() {
+        let error = read_prompt("  ").unwrap_err();
+        assert_eq!(error.exit_code(), 2);
+    }
+}
@@ -0,0 +1,439 @@
+//! Model capability catalog
+//!
+//! Records what each model accepts so that adapters can shape requests: which
+//! parameters to drop, which output-limit field to send, whether tools are
+//! passed natively, and the context length and output limit new profiles
+//! start with.
+//!
+//! Capabilities are resolved in layers, later ones overriding earlier ones:
+//!
+//! 1. defaults of the provider
+//! 2. built-in rules matching the provider and model name
+//! 3. rules from the `model_capabilities` list of the global config
+//! 4. the `capabilities` of the profile itself
+
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+use super::models::ProviderType;
+
+/// How a model is given tools
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolCallingMode {
+    /// Through the API's function-calling support
+    Native,
+    /// Through the prompt, with calls written in the output text; see
+    /// [`crate::services::text_tools`]
+    Text,
+    /// Not at all; requests are sent without tools
+    None,
+}
+
+/// Request field carrying the output limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MaxTokensParam {
+    /// `max_tokens`, understood by most OpenAI-compatible APIs
+    MaxTokens,
+    /// `max_completion_tokens`, required by o-series and GPT-5 models
+    MaxCompletionTokens,
+}
+
+/// What a model supports
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelCapabilities {
+    /// How tools are passed
+    pub tool_calling: ToolCallingMode,
+
+    /// Accepts images
+    pub vision: bool,
+
+    /// Reports usage in streams when asked with `stream_options`
+    pub streaming_usage: bool,
+
+    /// Accepts system messages; otherwise they are sent as user messages
+    pub system_prompt: bool,
+
+    /// Accepts `temperature` and `top_p`
+    pub temperature: bool,
+
+    /// Can reason before answering, e.g. with extended thinking
+    pub reasoning: bool,
+
+    /// Field carrying the output limit
+    pub max_tokens_param: MaxTokensParam,
+
+    /// Context window in tokens
+    pub context_length: u32,
+
+    /// Output limit new profiles start with
+    pub max_tokens: u32,
+}
+
+impl ModelCapabilities {
+    /// Capabilities of a model, according to the built-in catalog and the
+    /// user's rules
+    #[must_use]
+    pub fn lookup(provider: ProviderType, model: &str) -> Self {
+        resolve(provider, model, &USER_RULES.read())
+    }
+
+    /// These capabilities with the set fields of `overrides` replaced
+    #[must_use]
+    pub fn with(mut self, overrides: &CapabilityOverrides) -> Self {
+        let CapabilityOverrides {
+            tool_calling,
+            vision,
+            streaming_usage,
+            system_prompt,
+            temperature,
+            reasoning,
+            max_tokens_param,
+            context_length,
+            max_tokens,
+        } = *overrides;
+        self.tool_calling = tool_calling.unwrap_or(self.tool_calling);
+        self.vision = vision.unwrap_or(self.vision);
+        self.streaming_usage = streaming_usage.unwrap_or(self.streaming_usage);
+        self.system_prompt = system_prompt.unwrap_or(self.system_prompt);
+        self.temperature = temperature.unwrap_or(self.temperature);
+        self.reasoning = reasoning.unwrap_or(self.reasoning);
+        self.max_tokens_param = max_tokens_param.unwrap_or(self.max_tokens_param);
+        self.context_length = context_length.unwrap_or(self.context_length);
+        self.max_tokens = max_tokens.unwrap_or(self.max_tokens);
+        self
+    }
+}
+
+/// Capabilities to change; unset fields are left as they are
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapabilityOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calling: Option<ToolCallingMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vision: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub streaming_usage: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens_param: Option<MaxTokensParam>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_length: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+}
+
+impl CapabilityOverrides {
+    const NONE: Self = Self {
+        tool_calling: None,
+        vision: None,
+        streaming_usage: None,
+        system_prompt: None,
+        temperature: None,
+        reasoning: None,
+        max_tokens_param: None,
+        context_length: None,
+        max_tokens: None,
+    };
+
+    const fn tools(mut self, mode: ToolCallingMode) -> Self {
+        self.tool_calling = Some(mode);
+        self
+    }
+
+    const fn vision(mut self) -> Self {
+        self.vision = Some(true);
+        self
+    }
+
+    const fn no_system_prompt(mut self) -> Self {
+        self.system_prompt = Some(false);
+        self
+    }
+
+    const fn no_temperature(mut self) -> Self {
+        self.temperature = Some(false);
+        self
+    }
+
+    const fn reasoning(mut self) -> Self {
+        self.reasoning = Some(true);
+        self
+    }
+
+    const fn no_reasoning(mut self) -> Self {
+        self.reasoning = Some(false);
+        self
+    }
+
+    const fn max_completion_tokens(mut self) -> Self {
+        self.max_tokens_param = Some(MaxTokensParam::MaxCompletionTokens);
+        self
+    }
+
+    const fn limits(mut self, context_length: u32, max_tokens: u32) -> Self {
+        self.context_length = Some(context_length);
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+}
+
+/// A user rule: capabilities of the models matching a provider and pattern
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapabilityRule {
+    /// Provider the rule is limited to; any provider if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<ProviderType>,
+
+    /// Model-name pattern, where `*` matches any run of characters
+    pub model: String,
+
+    /// Capabilities the rule sets
+    #[serde(flatten)]
+    pub capabilities: CapabilityOverrides,
+}
+
+/// Rules from the global config, installed when it is loaded
+static USER_RULES: RwLock<Vec<CapabilityRule>> = RwLock::new(Vec::new());
+
+/// Install the user's rules, replacing any installed before
+pub fn set_user_rules(rules: Vec<CapabilityRule>) {
+    *USER_RULES.write() = rules;
+}
+
+/// Resolve capabilities with the given user rules
+fn resolve(provider: ProviderType, model: &str, user_rules: &[CapabilityRule]) -> ModelCapabilities {
+    // Router and organization prefixes, e.g. `deepseek-ai/`, do not matter
+    let model = model.rsplit('/').next().unwrap_or(model).to_lowercase();
+    let applies = |rule_provider: Option<ProviderType>, pattern: &str| {
+        rule_provider.is_none_or(|rule_provider| rule_provider == provider)
+            && matches_pattern(&pattern.to_lowercase(), &model)
+    };
+
+    let builtin = CATALOG
+        .iter()
+        .filter(|(rule_provider, pattern, _)| applies(*rule_provider, pattern))
+        .map(|(_, _, overrides)| overrides);
+    let user = user_rules
+        .iter()
+        .filter(|rule| applies(rule.provider, &rule.model))
+        .map(|rule| &rule.capabilities);
+    builtin
+        .chain(user)
+        .fold(provider_defaults(provider), ModelCapabilities::with)
+}
+
+/// Whether `name` matches `pattern`, where `*` matches any run of characters
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // No `*`: the whole name must match
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(start) => rest = &rest[start + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Capabilities of a provider's models unless a rule says otherwise
+const fn provider_defaults(provider: ProviderType) -> ModelCapabilities {
+    let compatible = ModelCapabilities {
+        tool_calling: ToolCallingMode::Native,
+        vision: false,
+        streaming_usage: false,
+        system_prompt: true,
+        temperature: true,
+        reasoning: false,
+        max_tokens_param: MaxTokensParam::MaxTokens,
+        context_length: 32_768,
+        max_tokens: 4_096,
+    };
+    match provider {
+        ProviderType::Anthropic | ProviderType::Bedrock | ProviderType::Vertex => ModelCapabilities {
+            vision: true,
+            streaming_usage: true,
+            context_length: 200_000,
+            max_tokens: 8_192,
+            ..compatible
+        },
+        ProviderType::OpenAI | ProviderType::Azure => ModelCapabilities {
+            vision: true,
+            streaming_usage: true,
+            max_tokens_param: MaxTokensParam::MaxCompletionTokens,
+            context_length: 128_000,
+            max_tokens: 16_384,
+            ..compatible
+        },
+        ProviderType::Gemini => ModelCapabilities {
+            vision: true,
+            streaming_usage: true,
+            context_length: 1_048_576,
+            max_tokens: 8_192,
+            ..compatible
+        },
+        ProviderType::Deepseek | ProviderType::Qwen | ProviderType::Xai => ModelCapabilities {
+            streaming_usage: true,
+            context_length: 131_072,
+            max_tokens: 8_192,
+            ..compatible
+        },
+        ProviderType::Kimi
+        | ProviderType::Glm
+        | ProviderType::Mistral
+        | ProviderType::Groq
+        | ProviderType::Siliconflow => ModelCapabilities {
+            context_length: 131_072,
+            max_tokens: 8_192,
+            ..compatible
+        },
+        ProviderType::Minimax => ModelCapabilities {
+            context_length: 204_800,
+            max_tokens: 8_192,
+            ..compatible
+        },
+        ProviderType::Ollama => ModelCapabilities {
+            context_length: 8_192,
+            ..compatible
+        },
+        ProviderType::BaiduQianfan
+        | ProviderType::Bigdream
+        | ProviderType::Opendev
+        | ProviderType::Custom
+        | ProviderType::CustomOpenAI => compatible,
+    }
+}
+
+const C: CapabilityOverrides = CapabilityOverrides::NONE;
+
+/// Built-in rules, applied in order; later rules refine earlier ones
+const CATALOG: &[(Option<ProviderType>, &str, CapabilityOverrides)] = &[
+    // Anthropic, also served by Bedrock and Vertex under other names
+    (None, "*claude-3-7-sonnet*", C.reasoning().limits(200_000, 64_000)),
+    (None, "*claude-sonnet-4*", C.reasoning().limits(200_000, 64_000)),
+    (None, "*claude-haiku-4*", C.reasoning().limits(200_000, 64_000)),
+    (None, "*claude-opus-4*", C.reasoning().limits(200_000, 32_000)),
+    // OpenAI
+    (None, "gpt-3.5-turbo*", C.limits(16_385, 4_096)),
+    (None, "gpt-4-turbo*", C.vision().limits(128_000, 4_096)),
+    (None, "gpt-4o*", C.vision().limits(128_000, 16_384)),
+    (None, "gpt-4.1*", C.vision().limits(1_047_576, 32_768)),
+    (None, "gpt-5*", C.vision().reasoning().no_temperature().max_completion_tokens().limits(400_000, 128_000)),
+    (None, "o1*", C.vision().reasoning().no_temperature().max_completion_tokens().limits(200_000, 100_000)),
+    // o1-mini reasons but rejects `reasoning_effort`
+    (None, "o1-mini*", C.tools(ToolCallingMode::None).no_system_prompt().no_reasoning().limits(128_000, 65_536)),
+    (None, "o3*", C.vision().reasoning().no_temperature().max_completion_tokens().limits(200_000, 100_000)),
+    (None, "o4-mini*", C.vision().reasoning().no_temperature().max_completion_tokens().limits(200_000, 100_000)),
+    (None, "gpt-oss*", C.reasoning().limits(131_072, 32_768)),
+    // Google
+    (None, "gemini-1.5-pro*", C.limits(2_097_152, 8_192)),
+    (None, "gemini-2.5*", C.reasoning().limits(1_048_576, 65_536)),
+    (None, "gemma3*", C.vision().tools(ToolCallingMode::Text).limits(131_072, 8_192)),
+    // DeepSeek
+    (None, "deepseek-reasoner*", C.reasoning().no_temperature().limits(131_072, 65_536)),
+    (None, "deepseek-r1*", C.reasoning().tools(ToolCallingMode::Text).limits(131_072, 32_768)),
+    // Moonshot
+    (None, "kimi-k2*", C.limits(262_144, 16_384)),
+    (None, "moonshot-v1-8k*", C.limits(8_192, 4_096)),
+    (None, "moonshot-v1-32k*", C.limits(32_768, 8_192)),
+    (None, "moonshot-v1-128k*", C.limits(131_072, 8_192)),
+    (None, "*vision*", C.vision()),
+    // Alibaba
+    (None, "qwen3*", C.reasoning()),
+    (None, "qwen3-coder*", C.limits(262_144, 65_536)),
+    (None, "qwq*", C.reasoning()),
+    (None, "qwen*-vl*", C.vision()),
+    // Zhipu
+    (None, "glm-4.5*", C.reasoning().limits(131_072, 98_304)),
+    (None, "glm-4.6*", C.reasoning().limits(200_000, 131_072)),
+    (None, "glm-4*v*", C.vision()),
+    // Mistral
+    (None, "pixtral*", C.vision()),
+    (None, "mistral-medium*", C.vision()),
+    (None, "mistral-small*", C.vision()),
+    (None, "magistral*", C.reasoning().limits(40_960, 40_960)),
+    (None, "codestral*", C.limits(256_000, 8_192)),
+    // xAI
+    (None, "grok-4*", C.vision().reasoning().limits(256_000, 16_384)),
+    (None, "grok-2-vision*", C.vision()),
+    // Open models served locally
+    (Some(ProviderType::Ollama), "llava*", C.vision().tools(ToolCallingMode::Text)),
+    (Some(ProviderType::Ollama), "llama3.2-vision*", C.vision().tools(ToolCallingMode::Text)),
+    (Some(ProviderType::Ollama), "phi3*", C.tools(ToolCallingMode::Text)),
+    (Some(ProviderType::Ollama), "codellama*", C.tools(ToolCallingMode::Text)),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("gpt-4o*", "gpt-4o-mini"));
+        assert!(matches_pattern("*claude-sonnet-4*", "us.anthropic.claude-sonnet-4-20250514-v1:0"));
+        assert!(matches_pattern("qwen*-vl*", "qwen2.5-vl-72b-instruct"));
+        assert!(matches_pattern("o3", "o3"));
+        assert!(!matches_pattern("o3", "o3-mini"));
+        assert!(!matches_pattern("gpt-4o*", "chatgpt-4o-latest"));
+        assert!(!matches_pattern("glm-4*v*", "glm-4"));
+    }
+
+    #[test]
+    fn test_builtin_rules_refine_provider_defaults() {
+        let gpt5 = resolve(ProviderType::OpenAI, "gpt-5-mini", &[]);
+        assert!(gpt5.reasoning && !gpt5.temperature);
+        assert_eq!(gpt5.max_tokens_param, MaxTokensParam::MaxCompletionTokens);
+        assert_eq!(gpt5.context_length, 400_000);
+
+        let r1 = resolve(ProviderType::Siliconflow, "deepseek-ai/DeepSeek-R1", &[]);
+        assert_eq!(r1.tool_calling, ToolCallingMode::Text);
+        assert_eq!(r1.max_tokens_param, MaxTokensParam::MaxTokens);
+
+        let haiku = resolve(ProviderType::Anthropic, "claude-3-5-haiku-20241022", &[]);
+        assert!(!haiku.reasoning && haiku.vision);
+        assert_eq!((haiku.context_length, haiku.max_tokens), (200_000, 8_192));
+
+        // Provider-specific rules only apply to their provider
+        let llava = resolve(ProviderType::Groq, "llava-v1.5-7b", &[]);
+        assert_eq!(llava.tool_calling, ToolCallingMode::Native);
+    }
+
+    #[test]
+    fn test_user_rules_and_overrides() {
+        let rules: Vec<CapabilityRule> = serde_json::from_value(serde_json::json!([
+            {"provider": "custom-openai", "model": "my-*", "vision": true, "context_length": 65_536},
+            {"model": "my-tiny*", "tool_calling": "none"}
+        ]))
+        .unwrap();
+
+        let capabilities = resolve(ProviderType::CustomOpenAI, "my-tiny-model", &rules);
+        assert!(capabilities.vision);
+        assert_eq!(capabilities.context_length, 65_536);
+        assert_eq!(capabilities.tool_calling, ToolCallingMode::None);
+
+        let other_provider = resolve(ProviderType::Custom, "my-model", &rules);
+        assert!(!other_provider.vision);
+
+        let overridden = capabilities.with(&CapabilityOverrides {
+            max_tokens: Some(1_000),
+            ..CapabilityOverrides::default()
+        });
+        assert_eq!(overridden.max_tokens, 1_000);
+        assert_eq!(overridden.context_length, 65_536);
+    }
+}
@@ -3,21 +3,23 @@
 //! Implements a hierarchical configuration system:
 //! 1. Global config (`~/.kode.json`)
 //! 2. Project config (`./.kode.json`)
-//! 3. Environment variables
-//! 4. CLI parameters (highest priority)
+//! 3. Local project overrides (`./.kode.local.json`, meant to stay untracked)
+//! 4. Environment variables
+//! 5. CLI parameters (highest priority)
 
+pub mod capabilities;
 pub mod models;
 pub mod settings;
 
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
 use serde::{Deserialize, Serialize};
 
 pub use self::{
     models::{ModelConfig, ModelPointer, ModelPointerType, ModelProfile, ProviderType},
-    settings::{GlobalConfig, ProjectConfig},
+    settings::{GlobalConfig, PermissionsConfig, ProjectConfig},
 };
-use crate::error::Result;
+use crate::{error::Result, services::fallback::DEFAULT_FALLBACK_COOLDOWN};
 
 /// Main configuration structure combining global and project settings
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,6 +31,10 @@ pub struct Config {
     /// Project-specific configuration
     #[serde(skip)]
     pub project: ProjectConfig,
+
+    /// Local, untracked overrides for the project configuration
+    #[serde(skip)]
+    pub local: ProjectConfig,
 }
 
 impl Config {
@@ -40,8 +46,14 @@ impl Config {
     pub fn load() -> Result<Self> {
         let global = GlobalConfig::load()?;
         let project = ProjectConfig::load()?;
-
-        Ok(Self { global, project })
+        let local = ProjectConfig::load_from_path(&Self::local_config_path())?;
+        capabilities::set_user_rules(global.model_capabilities.clone());
+
+        Ok(Self {
+            global,
+            project,
+            local,
+        })
     }
 
     /// Get the configuration directory path
@@ -64,8 +76,16 @@ impl Config {
         PathBuf::from(".kode.json")
     }
 
+    /// Get the local project override file path in the current directory
+    #[must_use]
+    pub fn local_config_path() -> PathBuf {
+        PathBuf::from(".kode.local.json")
+    }
+
     /// Save configuration to disk
     ///
+    /// Local overrides are only ever edited by hand and are not written.
+    ///
     /// # Errors
     ///
     /// Returns an error if configuration files cannot be written
@@ -109,6 +129,30 @@ impl Config {
             self.get_model(model_name)
         }
     }
+
+    /// Fallback models of a pointer, in the order they are tried
+    ///
+    /// Names matching no profile are skipped.
+    #[must_use]
+    pub fn fallback_models(&self, pointer: ModelPointerType) -> Vec<&ModelProfile> {
+        self.global
+            .model_pointers
+            .fallbacks
+            .get(&pointer)
+            .into_iter()
+            .flatten()
+            .filter_map(|name| self.get_model(name))
+            .collect()
+    }
+
+    /// How long a fallback model keeps answering after a failover
+    #[must_use]
+    pub fn fallback_cooldown(&self) -> Duration {
+        self.global
+            .model_pointers
+            .fallback_cooldown_secs
+            .map_or(DEFAULT_FALLBACK_COOLDOWN, Duration::from_secs)
+    }
 }
 
 /// Configuration validation errors
@@ -144,5 +188,50 @@ mod tests {
 
         let project_path = Config::project_config_path();
         assert_eq!(project_path, PathBuf::from(".kode.json"));
+
+        let local_path = Config::local_config_path();
+        assert_eq!(local_path, PathBuf::from(".kode.local.json"));
+    }
+
+    #[test]
+    fn test_fallback_models() {
+        let pointers: ModelPointer = serde_json::from_value(serde_json::json!({
+            "main": "claude-sonnet-4",
+            "fallbacks": {"main": ["gpt-4o", "missing", "llama3.1"]},
+            "fallback_cooldown_secs": 120
+        }))
+        .unwrap();
+        let mut global = GlobalConfig {
+            model_pointers: pointers,
+            ..GlobalConfig::default()
+        };
+        for (name, provider) in [
+            ("claude-sonnet-4", ProviderType::Anthropic),
+            ("gpt-4o", ProviderType::OpenAI),
+            ("llama3.1", ProviderType::Ollama),
+        ] {
+            global.model_profiles.push(ModelProfile::new(
+                name.to_string(),
+                provider,
+                name.to_string(),
+                String::new(),
+                4096,
+                128_000,
+            ));
+        }
+        let config = Config {
+            global,
+            project: ProjectConfig::default(),
+            local: ProjectConfig::default(),
+        };
+
+        let fallbacks: Vec<&str> = config
+            .fallback_models(ModelPointerType::Main)
+            .iter()
+            .map(|profile| profile.model_name.as_str())
+            .collect();
+        assert_eq!(fallbacks, ["gpt-4o", "llama3.1"]);
+        assert!(config.fallback_models(ModelPointerType::Task).is_empty());
+        assert_eq!(config.fallback_cooldown(), Duration::from_secs(2 * 60));
     }
 }
@@ -1,7 +1,12 @@
 //! Model configuration and profiles
 
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
+use super::capabilities::{CapabilityOverrides, ModelCapabilities};
+use crate::{cost::ModelPricing, services::RetryPolicy};
+
 /// AI provider types
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -22,6 +27,8 @@ pub enum ProviderType {
     Xai,
     Groq,
     Gemini,
+    Bedrock,
+    Vertex,
     Ollama,
     Azure,
     Custom,
@@ -39,7 +46,17 @@ impl ProviderType {
             Self::Azure => None, // Azure requires custom endpoint
             Self::Custom => None, // Custom requires user-specified endpoint
             Self::Groq => Some("https://api.groq.com/openai/v1"),
+            Self::Gemini => Some("https://generativelanguage.googleapis.com/v1beta"),
             Self::Ollama => Some("http://localhost:11434"),
+            Self::Mistral => Some("https://api.mistral.ai/v1"),
+            Self::Deepseek => Some("https://api.deepseek.com/v1"),
+            Self::Kimi => Some("https://api.moonshot.cn/v1"),
+            Self::Qwen => Some("https://dashscope.aliyuncs.com/compatible-mode/v1"),
+            Self::Glm => Some("https://open.bigmodel.cn/api/paas/v4"),
+            Self::Minimax => Some("https://api.minimaxi.com/v1"),
+            Self::BaiduQianfan => Some("https://qianfan.baidubce.com/v2"),
+            Self::Siliconflow => Some("https://api.siliconflow.cn/v1"),
+            Self::Xai => Some("https://api.x.ai/v1"),
             _ => None,
         }
     }
@@ -47,10 +64,21 @@ impl ProviderType {
     /// Check if this provider requires an API key
     #[must_use]
     pub const fn requires_api_key(&self) -> bool {
-        !matches!(self, Self::Ollama)
+        !matches!(self, Self::Ollama | Self::Bedrock | Self::Vertex)
     }
 }
 
+/// How requests to a provider authenticate
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMode {
+    /// The profile's API key
+    #[default]
+    ApiKey,
+    /// A Microsoft Entra ID token printed by the profile's token command
+    EntraId,
+}
+
 /// Reasoning effort level (for models that support it, like o1)
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -61,6 +89,30 @@ pub enum ReasoningEffort {
     High,
 }
 
+impl ReasoningEffort {
+    /// Name of the level as sent to the API
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Minimal => "minimal",
+            Self::Low => "low",
+            Self::Medium => "medium",
+            Self::High => "high",
+        }
+    }
+
+    /// Extended thinking budget in tokens for this effort
+    #[must_use]
+    pub const fn thinking_budget(self) -> u32 {
+        match self {
+            Self::Minimal => 1_024,
+            Self::Low => 4_000,
+            Self::Medium => 10_000,
+            Self::High => 32_000,
+        }
+    }
+}
+
 /// Model profile configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelProfile {
@@ -112,6 +164,55 @@ pub struct ModelProfile {
     /// Last validation timestamp
     #[serde(skip_serializing_if = "Option::is_none")]
     pub last_validation: Option<u64>,
+
+    /// Prices overriding the built-in catalog
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pricing: Option<ModelPricing>,
+
+    /// Retry policy overriding the default one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>,
+
+    /// Whether to mark prompt cache breakpoints (Anthropic models)
+    #[serde(default = "default_true")]
+    pub prompt_caching: bool,
+
+    /// Cloud region serving the model (Bedrock, Vertex)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+
+    /// Google Cloud project (Vertex)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+
+    /// Service-account key file used to obtain access tokens (Vertex)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credentials_file: Option<String>,
+
+    /// Shell command printing an access token, such as
+    /// `gcloud auth print-access-token`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_command: Option<String>,
+
+    /// Deployment serving the model, defaulting to the model name (Azure)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deployment: Option<String>,
+
+    /// API version sent with every request (Azure)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_version: Option<String>,
+
+    /// How requests authenticate (Azure)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_mode: Option<AuthMode>,
+
+    /// How long the model stays loaded after a request, such as `30m` (Ollama)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<String>,
+
+    /// Capabilities overriding the catalog
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<CapabilityOverrides>,
 }
 
 fn default_true() -> bool {
@@ -156,9 +257,36 @@ impl ModelProfile {
             is_gpt5: None,
             validation_status: None,
             last_validation: None,
+            pricing: None,
+            retry: None,
+            prompt_caching: true,
+            region: None,
+            project: None,
+            credentials_file: None,
+            token_command: None,
+            deployment: None,
+            api_version: None,
+            auth_mode: None,
+            keep_alive: None,
+            capabilities: None,
         }
     }
 
+    /// Create a new model profile with the catalog's output limit and
+    /// context length for the model
+    #[must_use]
+    pub fn with_defaults(name: String, provider: ProviderType, model_name: String, api_key: String) -> Self {
+        let capabilities = ModelCapabilities::lookup(provider, &model_name);
+        Self::new(
+            name,
+            provider,
+            model_name,
+            api_key,
+            capabilities.max_tokens,
+            capabilities.context_length,
+        )
+    }
+
     /// Get the effective base URL (custom or default)
     #[must_use]
     pub fn effective_base_url(&self) -> Option<String> {
@@ -176,6 +304,45 @@ impl ModelProfile {
         })
     }
 
+    /// Check if this is an o-series reasoning model (o1, o3, o4-mini, ...)
+    #[must_use]
+    pub fn is_o_series_model(&self) -> bool {
+        let model_lower = self.model_name.to_lowercase();
+        let model = model_lower.rsplit('/').next().unwrap_or_default();
+        model
+            .strip_prefix('o')
+            .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+    }
+
+    /// Whether requests go to the Responses API instead of Chat Completions
+    #[must_use]
+    pub fn uses_responses_api(&self) -> bool {
+        self.provider == ProviderType::OpenAI && (self.is_gpt5_model() || self.is_o_series_model())
+    }
+
+    /// Prices of this model: the profile override, else the catalog entry
+    #[must_use]
+    pub fn pricing(&self) -> Option<ModelPricing> {
+        self.pricing.or_else(|| ModelPricing::for_model(&self.model_name))
+    }
+
+    /// Capabilities of this model: the catalog entry with the profile's
+    /// overrides applied
+    #[must_use]
+    pub fn capabilities(&self) -> ModelCapabilities {
+        let capabilities = ModelCapabilities::lookup(self.provider, &self.model_name);
+        match &self.capabilities {
+            Some(overrides) => capabilities.with(overrides),
+            None => capabilities,
+        }
+    }
+
+    /// Retry policy for requests to this model
+    #[must_use]
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.retry.unwrap_or_default()
+    }
+
     /// Update last used timestamp
     pub fn mark_used(&mut self) {
         self.last_used = Some(
@@ -240,6 +407,15 @@ pub struct ModelPointer {
     /// Quick model
     #[serde(default)]
     pub quick: String,
+
+    /// Models tried in order when a pointer's model fails
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub fallbacks: HashMap<ModelPointerType, Vec<String>>,
+
+    /// Seconds a fallback model keeps answering before the pointer's own
+    /// model is tried again
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback_cooldown_secs: Option<u64>,
 }
 
 /// Model configuration helper
@@ -313,6 +489,20 @@ mod tests {
         assert!(profile.is_gpt5_model());
     }
 
+    #[test]
+    fn test_responses_api_detection() {
+        let profile = |provider, model: &str| {
+            ModelProfile::new(model.into(), provider, model.into(), "key".into(), 8192, 200_000)
+        };
+        assert!(profile(ProviderType::OpenAI, "o3").uses_responses_api());
+        assert!(profile(ProviderType::OpenAI, "o4-mini").uses_responses_api());
+        assert!(profile(ProviderType::OpenAI, "gpt-5-mini").uses_responses_api());
+        assert!(!profile(ProviderType::OpenAI, "gpt-4o").uses_responses_api());
+        assert!(!profile(ProviderType::OpenAI, "omni-moderation").uses_responses_api());
+        // Compatible servers may not implement the Responses API
+        assert!(!profile(ProviderType::Groq, "openai/gpt-5").uses_responses_api());
+    }
+
     #[test]
     fn test_model_pointer_from_str() {
         assert_eq!("main".parse::<ModelPointerType>().unwrap(), ModelPointerType::Main);
@@ -4,7 +4,7 @@ use std::{collections::HashMap, fs, path::Path};
 
 use serde::{Deserialize, Serialize};
 
-use super::{ModelPointer, ModelProfile};
+use super::{capabilities::CapabilityRule, ModelPointer, ModelProfile};
 use crate::error::{KodeError, Result};
 
 /// Global configuration (stored in `~/.kode.json`)
@@ -49,6 +49,26 @@ pub struct GlobalConfig {
     /// Projects configuration
     #[serde(default)]
     pub projects: HashMap<String, ProjectConfig>,
+
+    /// Maximum number of concurrency-safe tools run in parallel within one turn
+    #[serde(default = "default_max_tool_use_concurrency")]
+    pub max_tool_use_concurrency: usize,
+
+    /// Permission rules applied in every project
+    #[serde(default)]
+    pub permissions: PermissionsConfig,
+
+    /// Summarize older turns automatically when nearing the context window
+    #[serde(default = "default_true")]
+    pub auto_compact: bool,
+
+    /// Fraction of the context window at which auto-compaction kicks in
+    #[serde(default = "default_auto_compact_threshold")]
+    pub auto_compact_threshold: f64,
+
+    /// Capabilities of models missing from or wrong in the built-in catalog
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub model_capabilities: Vec<CapabilityRule>,
 }
 
 fn default_provider() -> String {
@@ -59,6 +79,14 @@ fn default_true() -> bool {
     true
 }
 
+fn default_max_tool_use_concurrency() -> usize {
+    crate::query::DEFAULT_MAX_TOOL_CONCURRENCY
+}
+
+fn default_auto_compact_threshold() -> f64 {
+    crate::query::DEFAULT_AUTO_COMPACT_THRESHOLD
+}
+
 impl Default for GlobalConfig {
     fn default() -> Self {
         Self {
@@ -72,6 +100,11 @@ impl Default for GlobalConfig {
             stream: true,
             proxy: None,
             projects: HashMap::new(),
+            max_tool_use_concurrency: default_max_tool_use_concurrency(),
+            permissions: PermissionsConfig::default(),
+            auto_compact: true,
+            auto_compact_threshold: default_auto_compact_threshold(),
+            model_capabilities: Vec::new(),
         }
     }
 }
@@ -115,6 +148,16 @@ impl GlobalConfig {
             config.model_pointers = default.model_pointers;
         }
 
+        if !(config.auto_compact_threshold > 0.0 && config.auto_compact_threshold <= 1.0) {
+            return Err(KodeError::ConfigParse {
+                path: path.to_path_buf(),
+                message: format!(
+                    "auto_compact_threshold must be above 0 and at most 1, got {}",
+                    config.auto_compact_threshold
+                ),
+            });
+        }
+
         Ok(config)
     }
 
@@ -159,10 +202,14 @@ impl GlobalConfig {
 /// Project-specific configuration (stored in `./.kode.json`)
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ProjectConfig {
-    /// Allowed tools for this project
+    /// Allowed tools for this project (legacy; read as allow rules)
     #[serde(default)]
     pub allowed_tools: Vec<String>,
 
+    /// Permission rules for this project
+    #[serde(default)]
+    pub permissions: PermissionsConfig,
+
     /// Project context (key-value pairs)
     #[serde(default)]
     pub context: HashMap<String, String>,
@@ -200,6 +247,21 @@ pub struct ProjectConfig {
     pub has_completed_project_onboarding: bool,
 }
 
+/// Allow and deny rules for tool permissions
+///
+/// Rules look like `Bash`, `Bash(git diff:*)` or `Edit(src/**)`; see
+/// [`crate::permissions::rules`] for the syntax.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PermissionsConfig {
+    /// Tool uses that run without asking
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+
+    /// Tool uses that are always rejected, even if an allow rule matches
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<String>,
+}
+
 /// MCP server configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "lowercase")]
@@ -305,6 +367,19 @@ mod tests {
         assert_eq!(loaded.num_startups, 42);
     }
 
+    #[test]
+    fn test_auto_compact_threshold_is_validated() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("config.json");
+
+        for threshold in ["0", "1.5", "-0.2"] {
+            fs::write(&config_path, format!("{{\"auto_compact_threshold\": {threshold}}}")).unwrap();
+            assert!(GlobalConfig::load_from_path(&config_path).is_err(), "{threshold}");
+        }
+        fs::write(&config_path, r#"{"auto_compact_threshold": 1}"#).unwrap();
+        assert!(GlobalConfig::load_from_path(&config_path).is_ok());
+    }
+
     #[test]
     fn test_save_and_load_project_config() {
         let temp_dir = TempDir::new().unwrap();
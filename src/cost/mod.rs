@@ -0,0 +1,351 @@
+//! Cost tracking
+//!
+//! Every priced model response is recorded in a [`CostTracker`], which keeps
+//! running totals for the current session and per model. Each record is also
+//! appended to a usage ledger (`<config>/usage.jsonl`) shared by all sessions,
+//! from which daily and monthly totals are computed.
+
+pub mod pricing;
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+};
+
+use chrono::{DateTime, Datelike, Local, NaiveDate};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+pub use self::pricing::ModelPricing;
+use crate::{config::Config, error::Result, services::Usage};
+
+/// Accumulated usage and cost
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CostTotals {
+    /// Number of model responses
+    pub requests: u32,
+
+    /// Token usage summed over all responses
+    pub usage: Usage,
+
+    /// Cost summed over all responses
+    pub cost_usd: f64,
+}
+
+impl CostTotals {
+    fn add(&mut self, usage: &Usage, cost_usd: f64) {
+        self.requests = self.requests.saturating_add(1);
+        self.usage.add(usage);
+        self.cost_usd += cost_usd;
+    }
+}
+
+/// One model response in the usage ledger
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CostEntry {
+    /// When the response finished
+    pub timestamp: DateTime<Local>,
+
+    /// Session the response belongs to, if recorded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+
+    /// Model that produced the response
+    pub model: String,
+
+    /// Token usage reported by the provider
+    pub usage: Usage,
+
+    /// Cost of the response
+    pub cost_usd: f64,
+}
+
+/// Running cost totals of a session
+#[derive(Debug, Clone, Default)]
+pub struct CostTracker {
+    session_id: Option<String>,
+    ledger: Option<PathBuf>,
+    total: CostTotals,
+    by_model: IndexMap<String, CostTotals>,
+}
+
+impl CostTracker {
+    /// Tracker keeping totals in memory only
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Default location of the usage ledger
+    #[must_use]
+    pub fn default_ledger_path() -> PathBuf {
+        Config::config_dir().join("usage.jsonl")
+    }
+
+    /// Append every record to the ledger at `path`
+    #[must_use]
+    pub fn with_ledger(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ledger = Some(path.into());
+        self
+    }
+
+    /// Attribute records to a session, starting from its totals in the ledger
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the ledger exists but cannot be read
+    pub fn for_session(mut self, session_id: &str) -> Result<Self> {
+        for entry in self.entries()? {
+            if entry.session_id.as_deref() == Some(session_id) {
+                self.add(&entry.model, &entry.usage, entry.cost_usd);
+            }
+        }
+        self.session_id = Some(session_id.to_string());
+        Ok(self)
+    }
+
+    /// Record a model response
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the ledger cannot be written; the totals are
+    /// updated regardless
+    pub fn record(&mut self, model: &str, usage: &Usage, cost_usd: f64) -> Result<()> {
+        self.add(model, usage, cost_usd);
+
+        let Some(path) = &self.ledger else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let entry = CostEntry {
+            timestamp: Local::now(),
+            session_id: self.session_id.clone(),
+            model: model.to_string(),
+            usage: usage.clone(),
+            cost_usd,
+        };
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?
+            .write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Totals of this session
+    #[must_use]
+    pub const fn total(&self) -> &CostTotals {
+        &self.total
+    }
+
+    /// Totals of this session per model, in order of first use
+    #[must_use]
+    pub const fn by_model(&self) -> &IndexMap<String, CostTotals> {
+        &self.by_model
+    }
+
+    /// Totals of all sessions per day, from the ledger
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the ledger exists but cannot be read
+    pub fn daily_totals(&self) -> Result<BTreeMap<NaiveDate, CostTotals>> {
+        let mut days: BTreeMap<NaiveDate, CostTotals> = BTreeMap::new();
+        for entry in self.entries()? {
+            days.entry(entry.timestamp.date_naive())
+                .or_default()
+                .add(&entry.usage, entry.cost_usd);
+        }
+        Ok(days)
+    }
+
+    /// Human-readable report for `/cost`
+    #[must_use]
+    pub fn report(&self) -> String {
+        let mut lines = vec![
+            format!("Total cost:     {}", format_cost(self.total.cost_usd)),
+            format!("Total requests: {}", self.total.requests),
+            format!("Total tokens:   {}", format_usage(&self.total.usage)),
+        ];
+        if let Some(rate) = self.total.usage.cache_hit_rate() {
+            lines.push(format!("Cache hit rate: {}", format_rate(rate)));
+        }
+
+        if !self.by_model.is_empty() {
+            lines.push(String::new());
+            lines.push("By model:".to_string());
+            for (model, totals) in &self.by_model {
+                let mut line = format!(
+                    "  {model}: {} ({} requests; {}",
+                    format_cost(totals.cost_usd),
+                    totals.requests,
+                    format_usage(&totals.usage)
+                );
+                if let Some(rate) = totals.usage.cache_hit_rate() {
+                    line.push_str("; ");
+                    line.push_str(&format_rate(rate));
+                    line.push_str(" cache hits");
+                }
+                line.push(')');
+                lines.push(line);
+            }
+        }
+
+        // The ledger is best effort; a broken one only hides these totals
+        if let Ok(days) = self.daily_totals() {
+            let today = Local::now().date_naive();
+            let month: f64 = days
+                .iter()
+                .filter(|(day, _)| day.year() == today.year() && day.month() == today.month())
+                .map(|(_, totals)| totals.cost_usd)
+                .sum();
+            if !days.is_empty() {
+                lines.push(String::new());
+                lines.push(format!(
+                    "Today, all sessions:      {}",
+                    format_cost(days.get(&today).map_or(0.0, |totals| totals.cost_usd))
+                ));
+                lines.push(format!("This month, all sessions: {}", format_cost(month)));
+            }
+        }
+
+        lines.join("\n")
+    }
+
+    fn add(&mut self, model: &str, usage: &Usage, cost_usd: f64) {
+        self.total.add(usage, cost_usd);
+        self.by_model
+            .entry(model.to_string())
+            .or_default()
+            .add(usage, cost_usd);
+    }
+
+    /// Read the ledger, skipping lines that cannot be parsed
+    fn entries(&self) -> Result<Vec<CostEntry>> {
+        let Some(path) = self.ledger.as_ref().filter(|path| path.exists()) else {
+            return Ok(Vec::new());
+        };
+        let mut entries = Vec::new();
+        for line in BufReader::new(fs::File::open(path)?).lines() {
+            if let Ok(entry) = serde_json::from_str(&line?) {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// Format a cost in USD, with more precision for small amounts
+#[must_use]
+pub fn format_cost(cost_usd: f64) -> String {
+    if cost_usd < 1.0 {
+        format!("${cost_usd:.4}")
+    } else {
+        format!("${cost_usd:.2}")
+    }
+}
+
+fn format_rate(rate: f64) -> String {
+    format!("{:.1}%", rate * 100.0)
+}
+
+fn format_usage(usage: &Usage) -> String {
+    let mut parts = vec![
+        format!("{} input", usage.input_tokens),
+        format!("{} output", usage.output_tokens),
+    ];
+    if let Some(tokens) = usage.cache_creation_input_tokens {
+        parts.push(format!("{tokens} cache write"));
+    }
+    if let Some(tokens) = usage.cache_read_input_tokens {
+        parts.push(format!("{tokens} cache read"));
+    }
+    parts.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn usage(input_tokens: u32, output_tokens: u32) -> Usage {
+        Usage {
+            input_tokens,
+            output_tokens,
+            ..Usage::default()
+        }
+    }
+
+    #[test]
+    fn test_record_accumulates_per_model() {
+        let mut tracker = CostTracker::new();
+        tracker.record("claude-sonnet-4", &usage(100, 10), 0.5).unwrap();
+        tracker.record("claude-3-5-haiku", &usage(50, 5), 0.25).unwrap();
+        tracker.record("claude-sonnet-4", &usage(100, 10), 0.5).unwrap();
+
+        assert_eq!(tracker.total().requests, 3);
+        assert_eq!(tracker.total().usage.input_tokens, 250);
+        assert!((tracker.total().cost_usd - 1.25).abs() < 1e-9);
+
+        let models: Vec<&String> = tracker.by_model().keys().collect();
+        assert_eq!(models, ["claude-sonnet-4", "claude-3-5-haiku"]);
+        assert_eq!(tracker.by_model()["claude-sonnet-4"].requests, 2);
+
+        let report = tracker.report();
+        assert!(report.contains("Total cost:     $1.25"));
+        assert!(report.contains("claude-3-5-haiku: $0.2500 (1 requests; 50 input, 5 output)"));
+    }
+
+    #[test]
+    fn test_ledger_feeds_daily_and_session_totals() {
+        let dir = TempDir::new().unwrap();
+        let ledger = dir.path().join("usage.jsonl");
+
+        let mut first = CostTracker::new().with_ledger(&ledger).for_session("a").unwrap();
+        first.record("gpt-4o", &usage(10, 1), 0.25).unwrap();
+        let mut second = CostTracker::new().with_ledger(&ledger).for_session("b").unwrap();
+        second.record("gpt-4o", &usage(10, 1), 0.5).unwrap();
+
+        let days = second.daily_totals().unwrap();
+        let today = &days[&Local::now().date_naive()];
+        assert_eq!(today.requests, 2);
+        assert!((today.cost_usd - 0.75).abs() < 1e-9);
+
+        // Resuming a session picks up its earlier totals only
+        let resumed = CostTracker::new().with_ledger(&ledger).for_session("a").unwrap();
+        assert_eq!(resumed.total().requests, 1);
+        assert!((resumed.total().cost_usd - 0.25).abs() < 1e-9);
+        assert!(resumed.report().contains("Today, all sessions:      $0.7500"));
+    }
+
+    #[test]
+    fn test_report_shows_cache_hit_rate() {
+        let mut tracker = CostTracker::new();
+        let cached = Usage {
+            input_tokens: 100,
+            output_tokens: 10,
+            cache_creation_input_tokens: Some(100),
+            cache_read_input_tokens: Some(800),
+        };
+        tracker.record("claude-sonnet-4", &cached, 0.01).unwrap();
+        tracker.record("gpt-4o", &usage(100, 10), 0.01).unwrap();
+
+        let report = tracker.report();
+        assert!(report.contains("Cache hit rate: 72.7%"), "{report}");
+        assert!(report.contains("800 cache read; 80.0% cache hits)"), "{report}");
+        assert!(report.contains("gpt-4o: $0.0100 (1 requests; 100 input, 10 output)"));
+    }
+
+    #[test]
+    fn test_format_cost() {
+        assert_eq!(format_cost(0.012_345), "$0.0123");
+        assert_eq!(format_cost(12.5), "$12.50");
+    }
+}
@@ -0,0 +1,150 @@
+//! Model price catalog
+//!
+//! Prices are in USD per million tokens, as published by the providers. A
+//! `ModelProfile` can override them for custom deployments or negotiated
+//! rates; models that are neither listed nor overridden are not priced.
+
+use serde::{Deserialize, Serialize};
+
+use crate::services::Usage;
+
+/// Prices of one model, in USD per million tokens
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPricing {
+    /// Uncached input tokens
+    pub input: f64,
+
+    /// Output tokens, including reasoning tokens
+    pub output: f64,
+
+    /// Input tokens written to the prompt cache; defaults to the input price
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_write: Option<f64>,
+
+    /// Input tokens read from the prompt cache; defaults to the input price
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_read: Option<f64>,
+}
+
+impl ModelPricing {
+    /// Create a price entry
+    #[must_use]
+    pub const fn new(input: f64, output: f64, cache_write: f64, cache_read: f64) -> Self {
+        Self {
+            input,
+            output,
+            cache_write: Some(cache_write),
+            cache_read: Some(cache_read),
+        }
+    }
+
+    /// Look up the catalog price of a model
+    ///
+    /// Matches by name prefix, ignoring router prefixes such as `openai/` and
+    /// Bedrock prefixes such as `us.anthropic.`.
+    #[must_use]
+    pub fn for_model(model: &str) -> Option<Self> {
+        let model = model.rsplit('/').next().unwrap_or(model).to_lowercase();
+        let model = model.find("claude-").map_or(model.as_str(), |start| &model[start..]);
+
+        CATALOG
+            .iter()
+            .find(|(prefix, _)| model.starts_with(prefix))
+            .map(|(_, pricing)| *pricing)
+    }
+
+    /// Cost of a usage report in USD
+    ///
+    /// `usage.input_tokens` counts uncached input only; cached tokens are
+    /// reported separately by the adapters.
+    #[must_use]
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        let per_token = |tokens: u32, price: f64| f64::from(tokens) * price / 1_000_000.0;
+
+        per_token(usage.input_tokens, self.input)
+            + per_token(usage.output_tokens, self.output)
+            + per_token(
+                usage.cache_creation_input_tokens.unwrap_or(0),
+                self.cache_write.unwrap_or(self.input),
+            )
+            + per_token(
+                usage.cache_read_input_tokens.unwrap_or(0),
+                self.cache_read.unwrap_or(self.input),
+            )
+    }
+}
+
+/// Known prices by model name prefix; more specific prefixes come first
+const CATALOG: &[(&str, ModelPricing)] = &[
+    // Anthropic: cache writes cost 1.25x input, cache reads 0.1x
+    ("claude-opus-4-5", ModelPricing::new(5.0, 25.0, 6.25, 0.5)),
+    ("claude-opus-4", ModelPricing::new(15.0, 75.0, 18.75, 1.5)),
+    ("claude-sonnet-4", ModelPricing::new(3.0, 15.0, 3.75, 0.3)),
+    ("claude-haiku-4", ModelPricing::new(1.0, 5.0, 1.25, 0.1)),
+    ("claude-3-7-sonnet", ModelPricing::new(3.0, 15.0, 3.75, 0.3)),
+    ("claude-3-5-sonnet", ModelPricing::new(3.0, 15.0, 3.75, 0.3)),
+    ("claude-3-5-haiku", ModelPricing::new(0.8, 4.0, 1.0, 0.08)),
+    ("claude-3-opus", ModelPricing::new(15.0, 75.0, 18.75, 1.5)),
+    ("claude-3-haiku", ModelPricing::new(0.25, 1.25, 0.3, 0.03)),
+    // OpenAI: caching is automatic, so writes cost the same as input
+    ("gpt-5-nano", ModelPricing::new(0.05, 0.4, 0.05, 0.005)),
+    ("gpt-5-mini", ModelPricing::new(0.25, 2.0, 0.25, 0.025)),
+    ("gpt-5", ModelPricing::new(1.25, 10.0, 1.25, 0.125)),
+    ("gpt-4.1-nano", ModelPricing::new(0.1, 0.4, 0.1, 0.025)),
+    ("gpt-4.1-mini", ModelPricing::new(0.4, 1.6, 0.4, 0.1)),
+    ("gpt-4.1", ModelPricing::new(2.0, 8.0, 2.0, 0.5)),
+    ("gpt-4o-mini", ModelPricing::new(0.15, 0.6, 0.15, 0.075)),
+    ("gpt-4o", ModelPricing::new(2.5, 10.0, 2.5, 1.25)),
+    ("gpt-4-turbo", ModelPricing::new(10.0, 30.0, 10.0, 10.0)),
+    ("gpt-4", ModelPricing::new(30.0, 60.0, 30.0, 30.0)),
+    ("gpt-3.5-turbo", ModelPricing::new(0.5, 1.5, 0.5, 0.5)),
+    ("o4-mini", ModelPricing::new(1.1, 4.4, 1.1, 0.275)),
+    ("o3-mini", ModelPricing::new(1.1, 4.4, 1.1, 0.55)),
+    ("o3", ModelPricing::new(2.0, 8.0, 2.0, 0.5)),
+    ("o1-mini", ModelPricing::new(1.1, 4.4, 1.1, 0.55)),
+    ("o1", ModelPricing::new(15.0, 60.0, 15.0, 7.5)),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_model_prefers_specific_prefixes() {
+        let mini = ModelPricing::for_model("gpt-4o-mini-2024-07-18").unwrap();
+        assert!((mini.input - 0.15).abs() < f64::EPSILON);
+        let full = ModelPricing::for_model("gpt-4o-2024-08-06").unwrap();
+        assert!((full.input - 2.5).abs() < f64::EPSILON);
+
+        let bedrock = ModelPricing::for_model("us.anthropic.claude-sonnet-4-20250514-v1:0");
+        assert_eq!(bedrock, ModelPricing::for_model("claude-sonnet-4-20250514"));
+        assert!(ModelPricing::for_model("openai/gpt-5").is_some());
+        assert!(ModelPricing::for_model("llama3.1:8b").is_none());
+    }
+
+    #[test]
+    fn test_cost_includes_cache_tokens() {
+        let pricing = ModelPricing::for_model("claude-sonnet-4-5").unwrap();
+        let usage = Usage {
+            input_tokens: 1_000_000,
+            output_tokens: 100_000,
+            cache_creation_input_tokens: Some(200_000),
+            cache_read_input_tokens: Some(2_000_000),
+        };
+
+        // 3.00 input + 1.50 output + 0.75 cache write + 0.60 cache read
+        assert!((pricing.cost(&usage) - 5.85).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_cache_prices_default_to_input() {
+        let pricing: ModelPricing = serde_json::from_str(r#"{"input": 2.0, "output": 6.0}"#).unwrap();
+        let usage = Usage {
+            input_tokens: 0,
+            output_tokens: 0,
+            cache_creation_input_tokens: Some(500_000),
+            cache_read_input_tokens: Some(500_000),
+        };
+        assert!((pricing.cost(&usage) - 2.0).abs() < 1e-9);
+    }
+}
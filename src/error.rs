@@ -114,3 +114,59 @@ impl From<&str> for KodeError {
         KodeError::Other(s.to_string())
     }
 }
+
+impl KodeError {
+    /// Process exit code for this error in non-interactive mode
+    ///
+    /// - `2`: invalid input or usage
+    /// - `3`: configuration problems (missing model, API key, bad config file)
+    /// - `4`: API or network failures
+    /// - `5`: a tool use was denied
+    /// - `130`: cancelled by the user
+    /// - `1`: anything else
+    #[must_use]
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::InvalidInput(_) => 2,
+            Self::ConfigParse { .. }
+            | Self::ConfigValidation(_)
+            | Self::InvalidConfig(_)
+            | Self::MissingApiKey { .. }
+            | Self::UnsupportedProvider { .. }
+            | Self::ModelNotFound(_)
+            | Self::AgentNotFound(_)
+            | Self::AgentLoadError(_)
+            | Self::Toml(_) => 3,
+            Self::ApiError { .. } | Self::NetworkError(_) | Self::Http(_) => 4,
+            Self::PermissionDenied(_) => 5,
+            Self::Cancelled => 130,
+            Self::Io(_)
+            | Self::ToolExecution(_)
+            | Self::ToolValidation(_)
+            | Self::NotImplemented(_)
+            | Self::Json(_)
+            | Self::FileNotFound(_)
+            | Self::Mcp(_)
+            | Self::Other(_) => 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes() {
+        assert_eq!(KodeError::InvalidInput("x".to_string()).exit_code(), 2);
+        assert_eq!(KodeError::ModelNotFound("x".to_string()).exit_code(), 3);
+        let api = KodeError::ApiError {
+            provider: "anthropic".to_string(),
+            message: "overloaded".to_string(),
+        };
+        assert_eq!(api.exit_code(), 4);
+        assert_eq!(KodeError::PermissionDenied("Bash".to_string()).exit_code(), 5);
+        assert_eq!(KodeError::Cancelled.exit_code(), 130);
+        assert_eq!(KodeError::Other("x".to_string()).exit_code(), 1);
+    }
+}
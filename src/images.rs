@@ -0,0 +1,214 @@
+//! Image loading and downscaling for multimodal requests
+//!
+//! Images are sent inline as base64. Providers reject images that are too
+//! large, and Anthropic recommends a long edge of at most 1568 pixels, so
+//! oversized images are resized (and re-encoded if needed) before they are
+//! attached to a message.
+
+use std::{io::Cursor, path::Path};
+
+use base64::{engine::general_purpose, Engine as _};
+use image::{
+    codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, ImageFormat, ImageReader,
+};
+
+use crate::{
+    error::{KodeError, Result},
+    messages::ImageSource,
+};
+
+/// Longest edge, in pixels, of an image sent to a provider
+pub const MAX_IMAGE_DIMENSION: u32 = 1568;
+
+/// Largest base64 payload accepted for a single image (Anthropic's limit,
+/// the strictest of the providers)
+pub const MAX_ENCODED_BYTES: usize = 5 * 1024 * 1024;
+
+/// Media types every provider accepts as is; others are converted
+const PROVIDER_MEDIA_TYPES: &[&str] = &["image/png", "image/jpeg", "image/gif", "image/webp"];
+
+/// JPEG qualities tried, best first, when an image must shrink
+const JPEG_QUALITIES: &[u8] = &[85, 70, 50];
+
+/// Media type of an image file, judged by its extension
+#[must_use]
+pub fn media_type_for_path(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    match extension.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        "bmp" => Some("image/bmp"),
+        _ => None,
+    }
+}
+
+/// Read an image file and prepare it for sending
+///
+/// # Errors
+///
+/// Returns an error if the file is not a supported image, cannot be read, or
+/// cannot be decoded.
+pub fn load_image(path: &Path) -> Result<ImageSource> {
+    let media_type = media_type_for_path(path).ok_or_else(|| {
+        KodeError::InvalidInput(format!("Unsupported image type: {}", path.display()))
+    })?;
+    let bytes = std::fs::read(path)?;
+    prepare_image(&bytes, media_type)
+}
+
+/// Fit image bytes within provider limits
+///
+/// Images already within limits are passed through untouched. Larger ones
+/// are resized to [`MAX_IMAGE_DIMENSION`] and re-encoded as PNG when they
+/// have transparency, or as JPEG otherwise (or when PNG is still too big).
+///
+/// # Errors
+///
+/// Returns an error if the data cannot be decoded as `media_type`.
+pub fn prepare_image(bytes: &[u8], media_type: &str) -> Result<ImageSource> {
+    let format = ImageFormat::from_mime_type(media_type).ok_or_else(|| {
+        KodeError::InvalidInput(format!("Unsupported image type: {media_type}"))
+    })?;
+    let (width, height) = ImageReader::with_format(Cursor::new(bytes), format)
+        .into_dimensions()
+        .map_err(decode_error)?;
+
+    if PROVIDER_MEDIA_TYPES.contains(&media_type)
+        && width.max(height) <= MAX_IMAGE_DIMENSION
+        && encoded_len(bytes.len()) <= MAX_ENCODED_BYTES
+    {
+        return Ok(base64_source(bytes, media_type));
+    }
+
+    let mut image = image::load_from_memory_with_format(bytes, format).map_err(decode_error)?;
+    if width.max(height) > MAX_IMAGE_DIMENSION {
+        image = image.resize(MAX_IMAGE_DIMENSION, MAX_IMAGE_DIMENSION, FilterType::CatmullRom);
+    }
+    encode(image)
+}
+
+/// Encode `image` within [`MAX_ENCODED_BYTES`], halving it until it fits
+fn encode(mut image: DynamicImage) -> Result<ImageSource> {
+    loop {
+        if image.color().has_alpha() {
+            let mut png = Cursor::new(Vec::new());
+            image.write_to(&mut png, ImageFormat::Png).map_err(encode_error)?;
+            if encoded_len(png.get_ref().len()) <= MAX_ENCODED_BYTES {
+                return Ok(base64_source(png.get_ref(), "image/png"));
+            }
+        }
+
+        let rgb = DynamicImage::ImageRgb8(image.to_rgb8());
+        for &quality in JPEG_QUALITIES {
+            let mut jpeg = Vec::new();
+            rgb.write_with_encoder(JpegEncoder::new_with_quality(&mut jpeg, quality))
+                .map_err(encode_error)?;
+            if encoded_len(jpeg.len()) <= MAX_ENCODED_BYTES {
+                return Ok(base64_source(&jpeg, "image/jpeg"));
+            }
+        }
+
+        let (width, height) = (image.width(), image.height());
+        if width.max(height) <= 1 {
+            return Err(KodeError::InvalidInput(
+                "Image cannot be shrunk below the size limit".to_string(),
+            ));
+        }
+        image = image.resize((width / 2).max(1), (height / 2).max(1), FilterType::Triangle);
+    }
+}
+
+fn base64_source(bytes: &[u8], media_type: &str) -> ImageSource {
+    ImageSource::Base64 {
+        media_type: media_type.to_string(),
+        data: general_purpose::STANDARD.encode(bytes),
+    }
+}
+
+/// Length of the base64 encoding of `len` bytes
+const fn encoded_len(len: usize) -> usize {
+    len.div_ceil(3) * 4
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn decode_error(e: image::ImageError) -> KodeError {
+    KodeError::InvalidInput(format!("Failed to decode image: {e}"))
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn encode_error(e: image::ImageError) -> KodeError {
+    KodeError::Other(format!("Failed to encode image: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GenericImageView, Rgb, RgbImage, Rgba, RgbaImage};
+
+    use super::*;
+
+    fn encoded(image: &DynamicImage, format: ImageFormat) -> Vec<u8> {
+        let mut bytes = Cursor::new(Vec::new());
+        image.write_to(&mut bytes, format).unwrap();
+        bytes.into_inner()
+    }
+
+    fn decoded(source: &ImageSource) -> DynamicImage {
+        let ImageSource::Base64 { data, .. } = source else {
+            panic!("expected inline data");
+        };
+        image::load_from_memory(&general_purpose::STANDARD.decode(data).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_small_image_passes_through() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(8, 4, Rgb([10, 20, 30])));
+        let bytes = encoded(&image, ImageFormat::Png);
+
+        let source = prepare_image(&bytes, "image/png").unwrap();
+        assert_eq!(
+            source,
+            ImageSource::Base64 {
+                media_type: "image/png".to_string(),
+                data: general_purpose::STANDARD.encode(&bytes),
+            }
+        );
+    }
+
+    #[test]
+    fn test_large_image_is_downscaled() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(3136, 1000, Rgb([200, 0, 0])));
+        let bytes = encoded(&image, ImageFormat::Png);
+
+        let source = prepare_image(&bytes, "image/png").unwrap();
+        assert_eq!(source.media_type(), Some("image/jpeg"));
+        assert_eq!(decoded(&source).dimensions(), (1568, 500));
+    }
+
+    #[test]
+    fn test_transparent_image_stays_png() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(2000, 2000, Rgba([0, 0, 0, 0])));
+        let bytes = encoded(&image, ImageFormat::Png);
+
+        let source = prepare_image(&bytes, "image/png").unwrap();
+        assert_eq!(source.media_type(), Some("image/png"));
+        assert_eq!(decoded(&source).dimensions(), (1568, 1568));
+    }
+
+    #[test]
+    fn test_bmp_is_converted() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 4, Rgb([1, 2, 3])));
+        let bytes = encoded(&image, ImageFormat::Bmp);
+
+        let source = prepare_image(&bytes, "image/bmp").unwrap();
+        assert_eq!(source.media_type(), Some("image/jpeg"));
+    }
+
+    #[test]
+    fn test_invalid_data_is_rejected() {
+        assert!(prepare_image(b"not an image", "image/png").is_err());
+        assert!(media_type_for_path(Path::new("notes.txt")).is_none());
+        assert_eq!(media_type_for_path(Path::new("shot.PNG")), Some("image/png"));
+    }
+}
@@ -5,13 +5,20 @@
 
 #![warn(clippy::all, clippy::pedantic)]
 #![allow(clippy::module_name_repetitions, clippy::too_many_lines)]
+// `Duration::from_mins` and `from_hours` are too recent for older toolchains
+#![allow(clippy::duration_suboptimal_units)]
 
 pub mod agents;
 pub mod cli;
 pub mod config;
+pub mod cost;
 pub mod error;
+pub mod images;
 pub mod messages;
+pub mod permissions;
+pub mod query;
 pub mod services;
+pub mod session;
 pub mod tools;
 pub mod tui;
 
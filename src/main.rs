@@ -5,7 +5,9 @@ use kode_rs::{
     agents::AgentRegistry,
     cli::{Cli, Commands},
     config::{Config, ModelPointerType, ProviderType},
-    services::{anthropic::AnthropicAdapter, openai::OpenAIAdapter},
+    services::{ModelAdapter, ModelAdapterFactory},
+    session::SessionStore,
+    tools::ToolRegistry,
 };
 use std::sync::Arc;
 
@@ -24,15 +26,19 @@ async fn main() -> Result<()> {
             .init();
     }
 
+    // Non-interactive mode
+    if let Some(prompt) = &cli.print {
+        run_print(prompt, &cli).await;
+    }
+
     // Handle commands
     match cli.command {
         Some(Commands::Repl) | None => {
             // Start REPL (default command)
-            start_repl(None).await?;
+            start_repl(None, &cli).await?;
         }
-        Some(Commands::Query { query }) => {
-            // Start REPL with initial query
-            start_repl(Some(query)).await?;
+        Some(Commands::Query { ref query }) => {
+            run_print(query, &cli).await;
         }
         Some(Commands::Config {
             get,
@@ -43,8 +49,18 @@ async fn main() -> Result<()> {
         }) => {
             handle_config_command(get, set, value, list, global)?;
         }
-        Some(Commands::Models { list, add, remove }) => {
-            handle_models_command(list, add, remove)?;
+        Some(Commands::Models {
+            list,
+            add,
+            remove,
+            discover,
+            base_url,
+        }) => {
+            if discover {
+                discover_ollama_models(base_url.as_deref()).await?;
+            } else {
+                handle_models_command(list, add, remove)?;
+            }
         }
         Some(Commands::Agents { list }) => {
             handle_agents_command(list).await?;
@@ -57,8 +73,20 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Run a prompt without the TUI and exit with a code derived from the outcome
+async fn run_print(prompt: &str, cli: &Cli) -> ! {
+    let code = match kode_rs::cli::print::run(prompt, &cli.print_options()).await {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            e.exit_code()
+        }
+    };
+    std::process::exit(code)
+}
+
 /// Start the interactive REPL
-async fn start_repl(initial_query: Option<String>) -> Result<()> {
+async fn start_repl(initial_query: Option<String>, cli: &Cli) -> Result<()> {
     // Load configuration
     let config = Config::load()?;
 
@@ -73,24 +101,49 @@ async fn start_repl(initial_query: Option<String>) -> Result<()> {
         .clone();
 
     // Create adapter based on provider type
-    let adapter: Arc<dyn kode_rs::services::ModelAdapter> = match model_profile.provider {
-        ProviderType::Anthropic => Arc::new(AnthropicAdapter::new(model_profile.clone())?),
-        ProviderType::OpenAI
-        | ProviderType::Ollama
-        | ProviderType::Groq
-        | ProviderType::Xai
-        | ProviderType::CustomOpenAI
-        | ProviderType::Custom => Arc::new(OpenAIAdapter::new(model_profile.clone())?),
-        _ => {
-            return Err(color_eyre::eyre::eyre!(
-                "Provider type {:?} is not yet supported",
-                model_profile.provider
-            ));
-        }
+    let adapter: Arc<dyn ModelAdapter> = Arc::from(ModelAdapterFactory::create_with_fallbacks(
+        &model_profile,
+        &config.fallback_models(ModelPointerType::Main),
+        config.fallback_cooldown(),
+    )?);
+
+    let tools = Arc::new(ToolRegistry::with_builtins());
+
+    // Pick the session transcript to continue, or start a new one
+    let store = SessionStore::for_project(&std::env::current_dir()?);
+    let session = if cli.continue_session {
+        store.latest()?.ok_or_else(|| {
+            color_eyre::eyre::eyre!("No previous session found in this directory")
+        })?
+    } else if let Some(resume) = &cli.resume {
+        let id = match resume {
+            Some(id) => id.clone(),
+            None => {
+                let sessions = store.list()?;
+                if sessions.is_empty() {
+                    return Err(color_eyre::eyre::eyre!(
+                        "No previous session found in this directory"
+                    ));
+                }
+                match kode_rs::tui::pick_session(&sessions)? {
+                    Some(id) => id,
+                    None => return Ok(()),
+                }
+            }
+        };
+        store.open(&id)?
+    } else {
+        store.create()
     };
+    let session_id = session.id().to_string();
+    let session_path = session.path().to_path_buf();
 
     // Run the TUI
-    kode_rs::tui::run(initial_query, model_profile, adapter).await?;
+    kode_rs::tui::run(initial_query, adapter, tools, config, session).await?;
+
+    if session_path.exists() {
+        println!("Resume this session with: kode --resume {session_id}");
+    }
 
     Ok(())
 }
@@ -161,6 +214,56 @@ fn handle_models_command(list: bool, add: bool, remove: Option<String>) -> Resul
     Ok(())
 }
 
+/// Add a profile for every model on an Ollama server that has none yet
+async fn discover_ollama_models(base_url: Option<&str>) -> Result<()> {
+    let (base_url, models) = kode_rs::services::ollama::discover_models(base_url).await?;
+    if models.is_empty() {
+        println!("No models installed on {base_url}. Pull one with `ollama pull <model>`.");
+        return Ok(());
+    }
+
+    let mut config = Config::load()?;
+    println!("Models on {base_url}:");
+    let mut added = Vec::new();
+    for model in &models {
+        let context = model
+            .context_length
+            .map_or_else(|| "unknown context".to_string(), |tokens| format!("{tokens} tokens"));
+        // Profiles are looked up by name, so names must stay unique
+        let existing = config
+            .global
+            .model_profiles
+            .iter()
+            .find(|profile| profile.model_name == model.name || profile.name == model.name);
+        match existing {
+            Some(profile) if profile.provider == ProviderType::Ollama => {
+                println!("  = {} ({context}, already configured)", model.name);
+            }
+            Some(profile) => {
+                println!(
+                    "  ! {} ({context}, skipped: name taken by a {:?} profile)",
+                    model.name, profile.provider
+                );
+            }
+            None => {
+                println!("  + {} ({context})", model.name);
+                config.global.model_profiles.push(model.profile(&base_url));
+                added.push(model.name.clone());
+            }
+        }
+    }
+
+    if let Some(first) = added.first() {
+        if config.global.model_pointers.main.is_empty() {
+            config.global.model_pointers.main.clone_from(first);
+        }
+        config.global.save()?;
+        println!("Added {} profile(s) to {:?}", added.len(), Config::global_config_path());
+    }
+
+    Ok(())
+}
+
 /// Handle agents commands
 async fn handle_agents_command(list: bool) -> Result<()> {
     if list {
@@ -6,6 +6,8 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::services::Usage;
+
 /// Message role in the conversation
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -32,11 +34,58 @@ pub enum ContentBlock {
         content: String,
         #[serde(skip_serializing_if = "Option::is_none")]
         is_error: Option<bool>,
+        /// Images returned with the result, such as a screenshot that was read
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        images: Vec<ImageSource>,
+    },
+    Image {
+        source: ImageSource,
+    },
+    /// Reasoning item of the Responses API, passed back on later turns
+    Reasoning {
+        id: String,
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        summary: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        encrypted_content: Option<String>,
     },
     #[serde(rename = "thinking")]
     Thinking {
         thinking: String,
+        /// Provider signature verifying the thinking when it is sent back
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        signature: Option<String>,
+    },
+    /// Thinking the provider returned encrypted; sent back unchanged
+    RedactedThinking {
+        data: String,
+    },
+}
+
+/// Where the data of an image block comes from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ImageSource {
+    /// Inline image data
+    Base64 {
+        /// MIME type, e.g. `image/png`
+        media_type: String,
+        /// Base64-encoded image bytes
+        data: String,
     },
+    /// Image uploaded through the Anthropic Files API
+    File { file_id: String },
+}
+
+impl ImageSource {
+    /// MIME type of inline data, if known
+    #[must_use]
+    pub fn media_type(&self) -> Option<&str> {
+        match self {
+            Self::Base64 { media_type, .. } => Some(media_type),
+            Self::File { .. } => None,
+        }
+    }
 }
 
 /// A single message in the conversation
@@ -108,8 +157,44 @@ impl Message {
             .filter(|block| matches!(block, ContentBlock::ToolUse { .. }))
             .collect()
     }
+
+    /// This message with images replaced by a note, for models that do not
+    /// accept images
+    #[must_use]
+    pub fn without_images(mut self) -> Self {
+        for block in &mut self.content {
+            match block {
+                ContentBlock::Image { .. } => {
+                    *block = ContentBlock::Text {
+                        text: IMAGE_OMITTED.to_string(),
+                    };
+                }
+                ContentBlock::ToolResult { content, images, .. } if !images.is_empty() => {
+                    images.clear();
+                    content.push_str("\n\n");
+                    content.push_str(IMAGE_OMITTED);
+                }
+                _ => {}
+            }
+        }
+        self
+    }
+
+    /// Check if this is a user message carrying only tool results
+    #[must_use]
+    pub fn is_tool_result(&self) -> bool {
+        self.role == Role::User
+            && !self.content.is_empty()
+            && self
+                .content
+                .iter()
+                .all(|block| matches!(block, ContentBlock::ToolResult { .. }))
+    }
 }
 
+/// Note standing in for images sent to models that do not accept them
+const IMAGE_OMITTED: &str = "[Image omitted: this model does not accept images]";
+
 /// User message with metadata
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserMessage {
@@ -122,6 +207,29 @@ pub struct UserMessage {
     pub tool_use_result: Option<FullToolUseResult>,
 }
 
+impl UserMessage {
+    /// Wrap a plain message typed by the user
+    #[must_use]
+    pub fn new(message: Message) -> Self {
+        let uuid = message.uuid.unwrap_or_else(Uuid::new_v4);
+        Self {
+            message,
+            uuid,
+            options: None,
+            tool_use_result: None,
+        }
+    }
+
+    /// Check if this message is the summary of a compacted conversation
+    #[must_use]
+    pub fn is_compact_summary(&self) -> bool {
+        self.options
+            .as_ref()
+            .and_then(|options| options.is_compact_summary)
+            .unwrap_or(false)
+    }
+}
+
 /// Tool use result containing execution metadata
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FullToolUseResult {
@@ -135,7 +243,7 @@ pub struct FullToolUseResult {
 }
 
 /// Options for user messages
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct UserMessageOptions {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub is_koding_request: Option<bool>,
@@ -147,6 +255,9 @@ pub struct UserMessageOptions {
     pub command_name: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub command_args: Option<String>,
+    /// Summary replacing the conversation before it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_compact_summary: Option<bool>,
 }
 
 /// Assistant message with cost and duration metadata
@@ -160,6 +271,12 @@ pub struct AssistantMessage {
     pub is_api_error_message: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub response_id: Option<String>,
+    /// Token usage reported for this response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    /// Model that produced this response, a fallback if the configured one failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
 }
 
 /// Progress message during tool execution
@@ -215,6 +332,90 @@ impl ConversationMessage {
     }
 }
 
+/// Rebuild the API message history from a conversation transcript
+///
+/// Progress messages are dropped and the tool results of one turn, recorded as
+/// separate messages, are merged back into a single user message. A compaction
+/// summary replaces everything recorded before it.
+#[must_use]
+pub fn normalize_messages_for_api(messages: &[ConversationMessage]) -> Vec<Message> {
+    let mut normalized: Vec<Message> = Vec::new();
+    for message in messages {
+        let message = match message {
+            ConversationMessage::User(user) if user.is_compact_summary() => {
+                normalized.clear();
+                &user.message
+            }
+            ConversationMessage::User(user) => &user.message,
+            ConversationMessage::Assistant(assistant) => &assistant.message,
+            ConversationMessage::Progress(_) => continue,
+        };
+
+        match normalized.last_mut() {
+            Some(last) if last.is_tool_result() && message.is_tool_result() => {
+                last.content.extend(message.content.iter().cloned());
+            }
+            _ => normalized.push(message.clone()),
+        }
+    }
+    answer_interrupted_tool_uses(&mut normalized);
+    normalized
+}
+
+/// Result standing in for a tool use that never got one
+const TOOL_INTERRUPTED: &str = "[Tool use interrupted: no result was recorded]";
+
+/// Give every tool use without a result an error result
+///
+/// A turn cancelled or crashed between a tool use and its result would
+/// otherwise leave a history that providers reject. The results go first in
+/// the user message after the tool use, or in a new one if there is none.
+pub fn answer_interrupted_tool_uses(messages: &mut Vec<Message>) {
+    let mut index = 0;
+    while index < messages.len() {
+        let answered: Vec<&str> = messages
+            .get(index + 1)
+            .into_iter()
+            .flat_map(|next| &next.content)
+            .filter_map(|block| match block {
+                ContentBlock::ToolResult { tool_use_id, .. } => Some(tool_use_id.as_str()),
+                _ => None,
+            })
+            .collect();
+        let missing: Vec<ContentBlock> = messages[index]
+            .content
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::ToolUse { id, .. } if !answered.contains(&id.as_str()) => Some(id),
+                _ => None,
+            })
+            .map(|id| ContentBlock::ToolResult {
+                tool_use_id: id.clone(),
+                content: TOOL_INTERRUPTED.to_string(),
+                is_error: Some(true),
+                images: Vec::new(),
+            })
+            .collect();
+
+        if !missing.is_empty() {
+            match messages.get_mut(index + 1) {
+                Some(next) if next.role == Role::User => {
+                    next.content.splice(0..0, missing);
+                }
+                _ => messages.insert(
+                    index + 1,
+                    Message {
+                        role: Role::User,
+                        content: missing,
+                        uuid: None,
+                    },
+                ),
+            }
+        }
+        index += 1;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -253,4 +454,131 @@ mod tests {
         assert!(msg.has_tool_use());
         assert_eq!(msg.tool_uses().len(), 1);
     }
+
+    #[test]
+    fn test_normalize_messages_for_api() {
+        let tool_result = |id: &str| {
+            ConversationMessage::User(UserMessage::new(Message {
+                role: Role::User,
+                content: vec![ContentBlock::ToolResult {
+                    tool_use_id: id.into(),
+                    content: "ok".into(),
+                    is_error: None,
+                    images: Vec::new(),
+                }],
+                uuid: None,
+            }))
+        };
+        let assistant = ConversationMessage::Assistant(AssistantMessage {
+            message: Message::assistant("Running tools"),
+            uuid: Uuid::new_v4(),
+            cost_usd: 0.0,
+            duration_ms: 0,
+            is_api_error_message: None,
+            response_id: None,
+            usage: None,
+            model: None,
+        });
+        let progress = ConversationMessage::Progress(ProgressMessage {
+            content: match &assistant {
+                ConversationMessage::Assistant(a) => a.clone(),
+                _ => unreachable!(),
+            },
+            tool_use_id: "tool_1".into(),
+            uuid: Uuid::new_v4(),
+            normalized_messages: None,
+            sibling_tool_use_ids: None,
+        });
+
+        let messages = normalize_messages_for_api(&[
+            ConversationMessage::User(UserMessage::new(Message::user("Hi"))),
+            assistant,
+            progress,
+            tool_result("tool_1"),
+            tool_result("tool_2"),
+        ]);
+
+        assert_eq!(messages.len(), 3);
+        assert!(messages[2].is_tool_result());
+        assert_eq!(messages[2].content.len(), 2);
+    }
+
+    #[test]
+    fn test_interrupted_tool_uses_get_results() {
+        let tool_use = |ids: &[&str]| Message {
+            role: Role::Assistant,
+            content: ids
+                .iter()
+                .map(|id| ContentBlock::ToolUse {
+                    id: (*id).into(),
+                    name: "Bash".into(),
+                    input: serde_json::json!({"command": "sleep 100"}),
+                })
+                .collect(),
+            uuid: None,
+        };
+        let result_ids = |message: &Message| -> Vec<(String, Option<bool>)> {
+            message
+                .content
+                .iter()
+                .filter_map(|block| match block {
+                    ContentBlock::ToolResult { tool_use_id, is_error, .. } => {
+                        Some((tool_use_id.clone(), *is_error))
+                    }
+                    _ => None,
+                })
+                .collect()
+        };
+
+        // Cancelled before the results, then the user wrote again
+        let mut messages =
+            vec![Message::user("Wait"), tool_use(&["a", "b"]), Message::user("Stop")];
+        answer_interrupted_tool_uses(&mut messages);
+        assert_eq!(messages.len(), 3);
+        assert_eq!(
+            result_ids(&messages[2]),
+            [("a".to_string(), Some(true)), ("b".to_string(), Some(true))]
+        );
+        assert!(matches!(messages[2].content[0], ContentBlock::ToolResult { .. }));
+        assert_eq!(messages[2].text_content(), "Stop");
+
+        // Crashed after the tool use, with one of two results recorded
+        let transcript = [
+            ConversationMessage::User(UserMessage::new(Message::user("Go"))),
+            ConversationMessage::Assistant(AssistantMessage {
+                message: tool_use(&["a", "b"]),
+                uuid: Uuid::new_v4(),
+                cost_usd: 0.0,
+                duration_ms: 0,
+                is_api_error_message: None,
+                response_id: None,
+                usage: None,
+                model: None,
+            }),
+            ConversationMessage::User(UserMessage::new(Message {
+                role: Role::User,
+                content: vec![ContentBlock::ToolResult {
+                    tool_use_id: "a".into(),
+                    content: "ok".into(),
+                    is_error: None,
+                    images: Vec::new(),
+                }],
+                uuid: None,
+            })),
+        ];
+        let messages = normalize_messages_for_api(&transcript);
+        assert_eq!(messages.len(), 3);
+        assert_eq!(
+            result_ids(&messages[2]),
+            [("b".to_string(), Some(true)), ("a".to_string(), None)]
+        );
+
+        // Crashed right after the tool use
+        let messages = normalize_messages_for_api(&transcript[..2]);
+        assert_eq!(messages.len(), 3);
+        assert_eq!(
+            result_ids(&messages[2]),
+            [("a".to_string(), Some(true)), ("b".to_string(), Some(true))]
+        );
+    }
 }
@@ -0,0 +1,389 @@
+//! Permission checks for side-effecting tools
+//!
+//! Every tool use is first checked against the configured deny and allow
+//! [rules](rules). Denied uses are rejected outright. Uses of tools that need
+//! permissions (see
+//! [`Tool::needs_permissions`](crate::tools::Tool::needs_permissions)) and
+//! match no allow rule are put in front of the user through a
+//! [`PermissionPrompter`], which can allow the call once, allow it for the
+//! project from now on (persisted to `.kode.json`), or deny it.
+
+pub mod rules;
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use serde_json::Value;
+
+pub use self::rules::{PermissionRule, PermissionRules, RuleVerdict};
+use crate::{
+    config::ProjectConfig,
+    error::{KodeError, Result},
+    tools::DynTool,
+};
+
+/// Tool result sent to the model when the user denies a tool use
+pub const REJECT_MESSAGE: &str = "The user doesn't want to proceed with this tool use. The tool \
+     use was rejected (eg. if it was a file edit, the new_string was NOT written to the file). \
+     STOP what you are doing and wait for the user to tell you how to proceed.";
+
+/// A tool use waiting for the user's approval
+#[derive(Debug, Clone)]
+pub struct PermissionRequest {
+    /// ID of the pending tool use
+    pub tool_use_id: String,
+
+    /// Name of the tool, as registered
+    pub tool_name: String,
+
+    /// Short human-readable summary of what the tool is about to do
+    pub description: String,
+
+    /// Raw tool input
+    pub input: Value,
+
+    /// Allow rule saved if the user approves the use for the project
+    pub rule: PermissionRule,
+}
+
+/// The user's answer to a [`PermissionRequest`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermissionDecision {
+    /// Run this tool use only
+    AllowOnce,
+
+    /// Run this tool use and save an allow rule for the project
+    AllowAlways,
+
+    /// Do not run the tool
+    Deny,
+}
+
+/// How tool uses that match no rule are handled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum PermissionMode {
+    /// Ask the user
+    #[default]
+    Default,
+
+    /// Run file edits and writes without asking; ask for everything else
+    AcceptEdits,
+
+    /// Run every tool without asking; deny rules still apply
+    BypassPermissions,
+}
+
+/// Asks the user whether a tool may run
+#[async_trait]
+pub trait PermissionPrompter: Send + Sync {
+    /// Present the request and wait for a decision
+    async fn request_permission(&self, request: PermissionRequest) -> PermissionDecision;
+}
+
+/// Prompter for sessions without a user to ask; every request is denied
+pub struct DenyPrompter;
+
+#[async_trait]
+impl PermissionPrompter for DenyPrompter {
+    async fn request_permission(&self, _request: PermissionRequest) -> PermissionDecision {
+        PermissionDecision::Deny
+    }
+}
+
+/// Gatekeeper for tools that need the user's permission
+pub struct PermissionService {
+    prompter: Arc<dyn PermissionPrompter>,
+    project_config_path: PathBuf,
+    rules: Mutex<PermissionRules>,
+    mode: PermissionMode,
+    dialog: tokio::sync::Mutex<()>,
+}
+
+impl PermissionService {
+    /// Create a service
+    ///
+    /// Approvals made with [`PermissionDecision::AllowAlways`] are added to
+    /// `rules` and appended to the project config at `project_config_path`.
+    #[must_use]
+    pub fn new(
+        prompter: Arc<dyn PermissionPrompter>,
+        project_config_path: PathBuf,
+        rules: PermissionRules,
+    ) -> Self {
+        Self {
+            prompter,
+            project_config_path,
+            rules: Mutex::new(rules),
+            mode: PermissionMode::default(),
+            dialog: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Set how tool uses that match no rule are handled
+    #[must_use]
+    pub fn with_mode(mut self, mode: PermissionMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Check whether a tool use may run, asking the user if needed
+    ///
+    /// Only one request is shown at a time; concurrent callers wait their turn.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KodeError::PermissionDenied`] if a deny rule matches or the
+    /// user denies the tool use
+    pub async fn check(
+        &self,
+        tool: &DynTool,
+        tool_use_id: &str,
+        input: &Value,
+        cwd: &Path,
+    ) -> Result<()> {
+        let denied = || Err(KodeError::PermissionDenied(tool.name().to_string()));
+
+        match self.evaluate(tool.name(), input, cwd) {
+            RuleVerdict::Deny => return denied(),
+            RuleVerdict::Allow => return Ok(()),
+            RuleVerdict::Ask if !tool.needs_permissions(input) => return Ok(()),
+            RuleVerdict::Ask => {}
+        }
+
+        match self.mode {
+            PermissionMode::BypassPermissions => return Ok(()),
+            PermissionMode::AcceptEdits
+                if matches!(rules::canonical_tool_name(tool.name()), "Edit" | "Write") =>
+            {
+                return Ok(());
+            }
+            PermissionMode::AcceptEdits | PermissionMode::Default => {}
+        }
+
+        let _dialog = self.dialog.lock().await;
+
+        // Another request may have saved a matching rule while we were waiting
+        if self.evaluate(tool.name(), input, cwd) == RuleVerdict::Allow {
+            return Ok(());
+        }
+
+        let rule = rules::suggested_rule(tool.name(), input);
+        let request = PermissionRequest {
+            tool_use_id: tool_use_id.to_string(),
+            tool_name: tool.name().to_string(),
+            description: describe_input(input),
+            input: input.clone(),
+            rule: rule.clone(),
+        };
+
+        match self.prompter.request_permission(request).await {
+            PermissionDecision::AllowOnce => Ok(()),
+            PermissionDecision::AllowAlways => {
+                // The rule still holds for this session if it cannot be saved
+                let _ = self.allow_always(rule);
+                Ok(())
+            }
+            PermissionDecision::Deny => denied(),
+        }
+    }
+
+    /// Check a tool use against the current rules without asking the user
+    #[must_use]
+    pub fn evaluate(&self, tool_name: &str, input: &Value, cwd: &Path) -> RuleVerdict {
+        self.rules
+            .lock()
+            .map_or(RuleVerdict::Ask, |rules| rules.evaluate(tool_name, input, cwd))
+    }
+
+    /// Add an allow rule and persist it to the project config
+    fn allow_always(&self, rule: PermissionRule) -> Result<()> {
+        let rule_string = rule.to_string();
+        if let Ok(mut rules) = self.rules.lock() {
+            rules.allow.push(rule);
+        }
+
+        let mut project = ProjectConfig::load_from_path(&self.project_config_path)?;
+        if !project.permissions.allow.contains(&rule_string) {
+            project.permissions.allow.push(rule_string);
+            project.save_to_path(&self.project_config_path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Summarize a tool input for the approval dialog
+fn describe_input(input: &Value) -> String {
+    ["command", "file_path", "path", "url"]
+        .iter()
+        .find_map(|key| input.get(key).and_then(Value::as_str))
+        .map_or_else(|| input.to_string(), String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::tools::{ErasedTool, Tool, ToolContext, ToolStream};
+
+    struct FixedPrompter {
+        decision: PermissionDecision,
+        asked: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl PermissionPrompter for FixedPrompter {
+        async fn request_permission(&self, _request: PermissionRequest) -> PermissionDecision {
+            self.asked.fetch_add(1, Ordering::SeqCst);
+            self.decision
+        }
+    }
+
+    /// Tool that never runs; only its permission requirements matter
+    struct NoopTool {
+        read_only: bool,
+    }
+
+    #[async_trait]
+    impl Tool for NoopTool {
+        type Input = Value;
+        type Output = Value;
+
+        fn name(&self) -> &str {
+            if self.read_only {
+                "Reader"
+            } else {
+                "Writer"
+            }
+        }
+
+        async fn description(&self) -> String {
+            String::new()
+        }
+
+        fn input_schema(&self) -> Value {
+            serde_json::json!({"type": "object"})
+        }
+
+        async fn prompt(&self, _safe_mode: bool) -> String {
+            String::new()
+        }
+
+        fn is_read_only(&self) -> bool {
+            self.read_only
+        }
+
+        async fn call(&self, _input: Value, _context: ToolContext) -> Result<ToolStream<Value>> {
+            Err(KodeError::NotImplemented("noop".to_string()))
+        }
+    }
+
+    fn service(
+        dir: &TempDir,
+        decision: PermissionDecision,
+    ) -> (PermissionService, Arc<FixedPrompter>) {
+        let prompter = Arc::new(FixedPrompter {
+            decision,
+            asked: AtomicUsize::new(0),
+        });
+        let service = PermissionService::new(
+            prompter.clone(),
+            dir.path().join(".kode.json"),
+            PermissionRules::default(),
+        );
+        (service, prompter)
+    }
+
+    #[tokio::test]
+    async fn test_read_only_tools_are_not_prompted() {
+        let dir = TempDir::new().unwrap();
+        let (service, prompter) = service(&dir, PermissionDecision::Deny);
+        let tool = ErasedTool::new(NoopTool { read_only: true });
+
+        assert!(service.check(&tool, "1", &Value::Null, dir.path()).await.is_ok());
+        assert_eq!(prompter.asked.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_allow_once_asks_every_time() {
+        let dir = TempDir::new().unwrap();
+        let (service, prompter) = service(&dir, PermissionDecision::AllowOnce);
+        let tool = ErasedTool::new(NoopTool { read_only: false });
+
+        assert!(service.check(&tool, "1", &Value::Null, dir.path()).await.is_ok());
+        assert!(service.check(&tool, "2", &Value::Null, dir.path()).await.is_ok());
+        assert_eq!(prompter.asked.load(Ordering::SeqCst), 2);
+        assert!(!dir.path().join(".kode.json").exists());
+    }
+
+    #[tokio::test]
+    async fn test_allow_always_is_persisted() {
+        let dir = TempDir::new().unwrap();
+        let (service, prompter) = service(&dir, PermissionDecision::AllowAlways);
+        let tool = ErasedTool::new(NoopTool { read_only: false });
+
+        assert!(service.check(&tool, "1", &Value::Null, dir.path()).await.is_ok());
+        assert!(service.check(&tool, "2", &Value::Null, dir.path()).await.is_ok());
+        assert_eq!(prompter.asked.load(Ordering::SeqCst), 1);
+
+        let project = ProjectConfig::load_from_path(&dir.path().join(".kode.json")).unwrap();
+        assert_eq!(project.permissions.allow, vec!["Writer".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_deny_returns_permission_denied() {
+        let dir = TempDir::new().unwrap();
+        let (service, _prompter) = service(&dir, PermissionDecision::Deny);
+        let tool = ErasedTool::new(NoopTool { read_only: false });
+
+        let result = service.check(&tool, "1", &Value::Null, dir.path()).await;
+        assert!(matches!(result, Err(KodeError::PermissionDenied(_))));
+    }
+
+    #[tokio::test]
+    async fn test_deny_rule_rejects_without_prompting() {
+        let dir = TempDir::new().unwrap();
+        let prompter = Arc::new(FixedPrompter {
+            decision: PermissionDecision::AllowOnce,
+            asked: AtomicUsize::new(0),
+        });
+        let mut rules = PermissionRules::default();
+        rules.deny.push("Reader".parse().unwrap());
+        let service =
+            PermissionService::new(prompter.clone(), dir.path().join(".kode.json"), rules);
+        let tool = ErasedTool::new(NoopTool { read_only: true });
+
+        let result = service.check(&tool, "1", &Value::Null, dir.path()).await;
+        assert!(matches!(result, Err(KodeError::PermissionDenied(_))));
+        assert_eq!(prompter.asked.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_modes_skip_the_prompt() {
+        let dir = TempDir::new().unwrap();
+        let tool = ErasedTool::new(NoopTool { read_only: false });
+
+        let (bypass, prompter) = service(&dir, PermissionDecision::Deny);
+        let bypass = bypass.with_mode(PermissionMode::BypassPermissions);
+        assert!(bypass.check(&tool, "1", &Value::Null, dir.path()).await.is_ok());
+        assert_eq!(prompter.asked.load(Ordering::SeqCst), 0);
+
+        // "Writer" is not a file editing tool, so the user is still asked
+        let (accept_edits, prompter) = service(&dir, PermissionDecision::Deny);
+        let accept_edits = accept_edits.with_mode(PermissionMode::AcceptEdits);
+        assert!(accept_edits.check(&tool, "1", &Value::Null, dir.path()).await.is_err());
+        assert_eq!(prompter.asked.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_describe_input_prefers_command_and_paths() {
+        assert_eq!(describe_input(&serde_json::json!({"command": "ls -la"})), "ls -la");
+        assert_eq!(describe_input(&serde_json::json!({"file_path": "/tmp/a"})), "/tmp/a");
+        assert_eq!(describe_input(&serde_json::json!({"x": 1})), r#"{"x":1}"#);
+    }
+}
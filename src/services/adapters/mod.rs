@@ -3,5 +3,6 @@
 //! This module provides adapters for translating between Kode's internal
 //! message format and provider-specific API formats.
 
-// Placeholder for future adapter implementations
-// e.g., ResponsesAPIAdapter, ChatCompletionsAdapter
+pub mod responses;
+
+pub use responses::ResponsesAPIAdapter;
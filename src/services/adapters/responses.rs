@@ -0,0 +1,637 @@
+//! Responses API adapter
+//!
+//! GPT-5 and o-series models are served through `/v1/responses`. Requests are
+//! stateless (`store: false`): reasoning items come back with their encrypted
+//! content, which is kept in the conversation and passed back on later turns
+//! so the model keeps its chain of thought across tool calls.
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::{Stream, StreamExt};
+use reqwest::{header, Client};
+use serde::Serialize;
+
+use crate::{
+    config::{capabilities::ToolCallingMode, models::ModelProfile},
+    cost::ModelPricing,
+    error::{KodeError, Result},
+    messages::{ContentBlock, ImageSource, Message, Role},
+    services::{
+        retry,
+        streaming::{
+            join_summary, responses_stream::failure, ResponsesOutputContent, ResponsesOutputItem,
+            ResponsesResponse, ResponsesStreamHandler, ResponsesSummaryPart,
+        },
+        CompletionChunk, CompletionOptions, CompletionResponse, CompletionStream, ModelAdapter,
+        ToolSchema,
+    },
+};
+
+/// Responses API adapter
+pub struct ResponsesAPIAdapter {
+    client: Client,
+    profile: ModelProfile,
+    base_url: String,
+}
+
+impl ResponsesAPIAdapter {
+    /// Create a new Responses API adapter
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the API key is not a valid header value.
+    pub fn new(profile: ModelProfile) -> Result<Self> {
+        let api_key = if profile.api_key.is_empty() {
+            std::env::var("OPENAI_API_KEY").unwrap_or_else(|_| "dummy-key".to_string())
+        } else {
+            profile.api_key.clone()
+        };
+
+        let base_url = profile
+            .base_url
+            .clone()
+            .unwrap_or_else(|| "https://api.openai.com/v1".to_string());
+
+        let client = Client::builder()
+            .default_headers({
+                let mut headers = header::HeaderMap::new();
+                headers.insert(
+                    "Authorization",
+                    header::HeaderValue::from_str(&format!("Bearer {api_key}")).map_err(|_| {
+                        KodeError::InvalidConfig("Invalid API key format".to_string())
+                    })?,
+                );
+                headers
+            })
+            .build()?;
+
+        Ok(Self {
+            client,
+            profile,
+            base_url,
+        })
+    }
+
+    /// Convert internal messages to input items
+    ///
+    /// Tool calls and their results become `function_call` and
+    /// `function_call_output` items. Reasoning items are passed back only
+    /// with their encrypted content, as nothing is stored server-side.
+    fn convert_messages(messages: Vec<Message>) -> Vec<ResponsesInputItem> {
+        let mut items = Vec::new();
+        for msg in messages {
+            let role = match msg.role {
+                Role::User => "user",
+                Role::Assistant => "assistant",
+                Role::System => "system",
+            };
+
+            let mut parts = Vec::new();
+            // Function call outputs carry text only, so images go in the user message
+            let mut tool_images = Vec::new();
+            for block in msg.content {
+                match block {
+                    ContentBlock::Text { text } if msg.role == Role::Assistant => {
+                        items.push(ResponsesInputItem::Message {
+                            role: role.to_string(),
+                            content: vec![ResponsesInputContent::OutputText { text }],
+                        });
+                    }
+                    ContentBlock::Text { text } => {
+                        parts.push(ResponsesInputContent::InputText { text });
+                    }
+                    ContentBlock::Image { source } => parts.push(ResponsesInputContent::image(&source)),
+                    ContentBlock::ToolUse { id, name, input } => {
+                        items.push(ResponsesInputItem::FunctionCall {
+                            call_id: id,
+                            name,
+                            arguments: input.to_string(),
+                        });
+                    }
+                    ContentBlock::ToolResult {
+                        tool_use_id,
+                        content,
+                        images,
+                        ..
+                    } => {
+                        if !images.is_empty() {
+                            tool_images.push(ResponsesInputContent::InputText {
+                                text: format!("Images returned by tool call {tool_use_id}:"),
+                            });
+                            tool_images.extend(images.iter().map(ResponsesInputContent::image));
+                        }
+                        items.push(ResponsesInputItem::FunctionCallOutput {
+                            call_id: tool_use_id,
+                            output: content,
+                        });
+                    }
+                    ContentBlock::Reasoning {
+                        id,
+                        summary,
+                        encrypted_content: Some(encrypted_content),
+                    } => items.push(ResponsesInputItem::Reasoning {
+                        id,
+                        summary: if summary.is_empty() {
+                            Vec::new()
+                        } else {
+                            vec![ResponsesSummaryPart::SummaryText { text: summary }]
+                        },
+                        encrypted_content,
+                    }),
+                    // Other providers' thinking cannot be passed to OpenAI
+                    ContentBlock::Reasoning { .. }
+                    | ContentBlock::Thinking { .. }
+                    | ContentBlock::RedactedThinking { .. } => {}
+                }
+            }
+
+            tool_images.append(&mut parts);
+            if !tool_images.is_empty() {
+                items.push(ResponsesInputItem::Message {
+                    role: role.to_string(),
+                    content: tool_images,
+                });
+            }
+        }
+        items
+    }
+
+    /// Build a request body
+    ///
+    /// Reasoning models reject sampling parameters, so `temperature` and
+    /// `top_p` are never sent.
+    fn build_request(
+        &self,
+        messages: Vec<Message>,
+        tools: Vec<ToolSchema>,
+        system_prompt: Option<String>,
+        options: CompletionOptions,
+        stream: bool,
+    ) -> ResponsesRequest {
+        let capabilities = self.profile.capabilities();
+        let messages = if capabilities.vision {
+            messages
+        } else {
+            messages.into_iter().map(Message::without_images).collect()
+        };
+        let tools = match capabilities.tool_calling {
+            ToolCallingMode::Native => tools,
+            ToolCallingMode::Text | ToolCallingMode::None => Vec::new(),
+        };
+        let effort = options.reasoning_effort.or_else(|| {
+            self.profile
+                .reasoning_effort
+                .map(|effort| effort.as_str().to_string())
+        });
+
+        ResponsesRequest {
+            model: self.profile.model_name.clone(),
+            instructions: system_prompt,
+            input: Self::convert_messages(messages),
+            tools: tools
+                .into_iter()
+                .map(|tool| ResponsesTool {
+                    tool_type: "function".to_string(),
+                    name: tool.name,
+                    description: tool.description,
+                    parameters: tool.input_schema,
+                    strict: false,
+                })
+                .collect(),
+            max_output_tokens: Some(options.max_tokens.unwrap_or(self.profile.max_tokens)),
+            reasoning: ResponsesReasoning {
+                effort,
+                summary: "auto".to_string(),
+            },
+            text: options.verbosity.map(|verbosity| ResponsesText { verbosity }),
+            store: false,
+            include: vec!["reasoning.encrypted_content".to_string()],
+            stream,
+        }
+    }
+
+    /// Process SSE byte stream into `CompletionChunk`s
+    ///
+    /// Chunks are yielded as soon as each event is parsed.
+    fn process_stream(
+        byte_stream: impl Stream<Item = reqwest::Result<Bytes>> + Send + 'static,
+    ) -> impl Stream<Item = Result<CompletionChunk>> + Send + 'static {
+        async_stream::stream! {
+            let mut handler = ResponsesStreamHandler::new();
+            let mut byte_stream = Box::pin(byte_stream);
+
+            while let Some(chunk_result) = byte_stream.next().await {
+                let bytes = match chunk_result {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        yield Err(KodeError::NetworkError(e.to_string()));
+                        return;
+                    }
+                };
+
+                match handler.process_bytes(&bytes) {
+                    Ok(chunks) => {
+                        for chunk in chunks {
+                            yield Ok(chunk);
+                        }
+                    }
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                }
+                if handler.is_complete() {
+                    return;
+                }
+            }
+
+            // The connection closed without a `response.completed` event
+            match handler.finish() {
+                Ok(chunks) => {
+                    for chunk in chunks {
+                        yield Ok(chunk);
+                    }
+                }
+                Err(e) => yield Err(e),
+            }
+        }
+    }
+}
+
+/// Convert output items to content blocks
+fn convert_output(output: Vec<ResponsesOutputItem>) -> Result<Vec<ContentBlock>> {
+    let mut content = Vec::new();
+    for item in output {
+        match item {
+            ResponsesOutputItem::Reasoning {
+                id,
+                summary,
+                encrypted_content,
+            } => content.push(ContentBlock::Reasoning {
+                id,
+                summary: join_summary(&summary),
+                encrypted_content,
+            }),
+            ResponsesOutputItem::Message { content: parts } => {
+                for part in parts {
+                    let text = match part {
+                        ResponsesOutputContent::OutputText { text } => text,
+                        ResponsesOutputContent::Refusal { refusal } => refusal,
+                        ResponsesOutputContent::Other => continue,
+                    };
+                    if !text.is_empty() {
+                        content.push(ContentBlock::Text { text });
+                    }
+                }
+            }
+            ResponsesOutputItem::FunctionCall {
+                call_id,
+                name,
+                arguments,
+            } => content.push(ContentBlock::ToolUse {
+                id: call_id,
+                name,
+                input: if arguments.is_empty() {
+                    serde_json::json!({})
+                } else {
+                    serde_json::from_str(&arguments)?
+                },
+            }),
+            ResponsesOutputItem::Other => {}
+        }
+    }
+    Ok(content)
+}
+
+#[async_trait]
+impl ModelAdapter for ResponsesAPIAdapter {
+    fn provider(&self) -> &str {
+        "openai"
+    }
+
+    fn model(&self) -> &str {
+        &self.profile.model_name
+    }
+
+    async fn complete(
+        &self,
+        messages: Vec<Message>,
+        tools: Vec<ToolSchema>,
+        system_prompt: Option<String>,
+        options: CompletionOptions,
+    ) -> Result<CompletionResponse> {
+        let request = self.build_request(messages, tools, system_prompt, options, false);
+
+        let request = self
+            .client
+            .post(format!("{}/responses", self.base_url))
+            .json(&request);
+        let response = retry::send(&self.profile.retry_policy(), "openai", request).await?;
+
+        let mut api_response: ResponsesResponse = response.json().await?;
+        if api_response.status.as_deref() == Some("failed") {
+            return Err(failure(api_response));
+        }
+
+        let content = convert_output(std::mem::take(&mut api_response.output))?;
+        let has_tool_calls = content
+            .iter()
+            .any(|block| matches!(block, ContentBlock::ToolUse { .. }));
+
+        Ok(CompletionResponse {
+            content,
+            stop_reason: Some(api_response.stop_reason(has_tool_calls)),
+            model: Some(api_response.model),
+            usage: api_response.usage.map(Into::into),
+        })
+    }
+
+    async fn stream_complete(
+        &self,
+        messages: Vec<Message>,
+        tools: Vec<ToolSchema>,
+        system_prompt: Option<String>,
+        options: CompletionOptions,
+    ) -> Result<CompletionStream> {
+        let request = self.build_request(messages, tools, system_prompt, options, true);
+
+        let request = self
+            .client
+            .post(format!("{}/responses", self.base_url))
+            .json(&request);
+        Ok(retry::stream(self.profile.retry_policy(), "openai", request, |response| {
+            Self::process_stream(response.bytes_stream())
+        }))
+    }
+
+    fn pricing(&self) -> Option<ModelPricing> {
+        self.profile.pricing()
+    }
+
+    fn max_context_tokens(&self) -> u32 {
+        self.profile.context_length
+    }
+
+    fn max_output_tokens(&self) -> u32 {
+        self.profile.max_tokens
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ResponsesRequest {
+    model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instructions: Option<String>,
+    input: Vec<ResponsesInputItem>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<ResponsesTool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    reasoning: ResponsesReasoning,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<ResponsesText>,
+    store: bool,
+    include: Vec<String>,
+    stream: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ResponsesInputItem {
+    Message {
+        role: String,
+        content: Vec<ResponsesInputContent>,
+    },
+    Reasoning {
+        id: String,
+        summary: Vec<ResponsesSummaryPart>,
+        encrypted_content: String,
+    },
+    FunctionCall {
+        call_id: String,
+        name: String,
+        arguments: String,
+    },
+    FunctionCallOutput {
+        call_id: String,
+        output: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ResponsesInputContent {
+    InputText { text: String },
+    InputImage { image_url: String },
+    OutputText { text: String },
+}
+
+impl ResponsesInputContent {
+    /// Inline images become data URLs; uploaded files cannot be referenced
+    fn image(source: &ImageSource) -> Self {
+        match source {
+            ImageSource::Base64 { media_type, data } => Self::InputImage {
+                image_url: format!("data:{media_type};base64,{data}"),
+            },
+            ImageSource::File { file_id } => Self::InputText {
+                text: format!("[Image file {file_id} is not available to this model]"),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ResponsesTool {
+    #[serde(rename = "type")]
+    tool_type: String,
+    name: String,
+    description: String,
+    parameters: serde_json::Value,
+    /// Strict mode (the default) rejects schemas with optional properties
+    strict: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ResponsesReasoning {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    effort: Option<String>,
+    summary: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ResponsesText {
+    verbosity: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use serde_json::{json, Value};
+    use wiremock::{
+        matchers::{body_partial_json, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::config::models::{ProviderType, ReasoningEffort};
+
+    fn adapter(base_url: &str) -> ResponsesAPIAdapter {
+        let mut profile = ModelProfile::new(
+            "gpt-5".to_string(),
+            ProviderType::OpenAI,
+            "gpt-5".to_string(),
+            "test-key".to_string(),
+            32_000,
+            400_000,
+        );
+        profile.base_url = Some(base_url.to_string());
+        profile.reasoning_effort = Some(ReasoningEffort::High);
+        ResponsesAPIAdapter::new(profile).unwrap()
+    }
+
+    /// A turn where the model reasoned, called a tool and got its result
+    fn tool_turn() -> Vec<Message> {
+        vec![
+            Message::user("List the files"),
+            Message {
+                role: Role::Assistant,
+                content: vec![
+                    ContentBlock::Reasoning {
+                        id: "rs_1".to_string(),
+                        summary: "Use ls".to_string(),
+                        encrypted_content: Some("gAAAA".to_string()),
+                    },
+                    ContentBlock::ToolUse {
+                        id: "call_1".to_string(),
+                        name: "Bash".to_string(),
+                        input: json!({"command": "ls"}),
+                    },
+                ],
+                uuid: None,
+            },
+            Message {
+                role: Role::User,
+                content: vec![ContentBlock::ToolResult {
+                    tool_use_id: "call_1".to_string(),
+                    content: "src".to_string(),
+                    is_error: None,
+                    images: Vec::new(),
+                }],
+                uuid: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_build_request() {
+        let adapter = adapter("http://localhost");
+        let options = CompletionOptions {
+            verbosity: Some("low".to_string()),
+            ..CompletionOptions::default()
+        };
+        let tools = vec![ToolSchema {
+            name: "Bash".to_string(),
+            description: "Run a shell command".to_string(),
+            input_schema: json!({"type": "object"}),
+        }];
+        let request = adapter.build_request(tool_turn(), tools, Some("You are Kode".to_string()), options, true);
+        let request = serde_json::to_value(request).unwrap();
+
+        assert_eq!(request["instructions"], "You are Kode");
+        assert_eq!(request["max_output_tokens"], 8192);
+        assert_eq!(request["reasoning"], json!({"effort": "high", "summary": "auto"}));
+        assert_eq!(request["text"], json!({"verbosity": "low"}));
+        assert_eq!(request["store"], false);
+        assert_eq!(request["include"], json!(["reasoning.encrypted_content"]));
+        assert_eq!(request.get("temperature"), None);
+        assert_eq!(request["tools"][0]["strict"], false);
+        assert_eq!(
+            request["input"],
+            json!([
+                {"type": "message", "role": "user", "content": [{"type": "input_text", "text": "List the files"}]},
+                {
+                    "type": "reasoning",
+                    "id": "rs_1",
+                    "summary": [{"type": "summary_text", "text": "Use ls"}],
+                    "encrypted_content": "gAAAA"
+                },
+                {"type": "function_call", "call_id": "call_1", "name": "Bash", "arguments": "{\"command\":\"ls\"}"},
+                {"type": "function_call_output", "call_id": "call_1", "output": "src"},
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_complete() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/responses"))
+            .and(body_partial_json(json!({"model": "gpt-5", "stream": false})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "resp_1",
+                "model": "gpt-5",
+                "status": "completed",
+                "output": [
+                    {"type": "reasoning", "id": "rs_2", "summary": [], "encrypted_content": "gBBBB"},
+                    {
+                        "type": "message",
+                        "id": "msg_1",
+                        "role": "assistant",
+                        "content": [{"type": "output_text", "text": "Only src.", "annotations": []}]
+                    }
+                ],
+                "usage": {"input_tokens": 50, "output_tokens": 10, "total_tokens": 60}
+            })))
+            .mount(&server)
+            .await;
+
+        let response = adapter(&server.uri())
+            .complete(tool_turn(), Vec::new(), None, CompletionOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(response.stop_reason.as_deref(), Some("end_turn"));
+        assert!(matches!(
+            &response.content[..],
+            [
+                ContentBlock::Reasoning { id, encrypted_content: Some(encrypted), .. },
+                ContentBlock::Text { text },
+            ] if id == "rs_2" && encrypted == "gBBBB" && text == "Only src."
+        ));
+        let usage = response.usage.unwrap();
+        assert_eq!((usage.input_tokens, usage.output_tokens), (50, 10));
+    }
+
+    #[tokio::test]
+    async fn test_stream_complete() {
+        let events: Vec<Value> = vec![
+            json!({"type": "response.created", "response": {"id": "resp_1", "model": "gpt-5", "output": []}}),
+            json!({"type": "response.output_text.delta", "output_index": 0, "content_index": 0, "delta": "Hello"}),
+            json!({"type": "response.completed", "response": {"id": "resp_1", "model": "gpt-5", "status": "completed", "output": []}}),
+        ];
+        let body = events
+            .iter()
+            .map(|event| format!("event: {}\ndata: {event}\n\n", event["type"].as_str().unwrap()))
+            .collect::<Vec<_>>()
+            .concat();
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/responses"))
+            .and(body_partial_json(json!({"stream": true})))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+            .mount(&server)
+            .await;
+
+        let chunks: Vec<_> = adapter(&server.uri())
+            .stream_complete(vec![Message::user("Hi")], Vec::new(), None, CompletionOptions::default())
+            .await
+            .unwrap()
+            .collect()
+            .await;
+
+        assert!(matches!(&chunks[0], Ok(CompletionChunk::TextDelta { text }) if text == "Hello"));
+        assert!(matches!(&chunks[1], Ok(CompletionChunk::Done { stop_reason, .. }) if stop_reason == "end_turn"));
+        assert_eq!(chunks.len(), 2);
+    }
+}
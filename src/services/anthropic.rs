@@ -5,20 +5,30 @@
 //! - AWS Bedrock
 //! - Google Vertex AI
 
+use std::path::Path;
+
 use async_trait::async_trait;
+use base64::Engine;
 use bytes::Bytes;
 use futures::stream::{Stream, StreamExt};
-use reqwest::{header, Client};
+use reqwest::{header, Client, RequestBuilder};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    config::models::ModelProfile,
+    config::{
+        capabilities::ToolCallingMode,
+        models::{ModelProfile, ReasoningEffort},
+    },
+    cost::ModelPricing,
     error::{KodeError, Result},
-    messages::{ContentBlock, Message, Role},
+    messages::{ContentBlock, ImageSource, Message, Role},
 };
 
 use super::{
-    streaming::AnthropicStreamHandler,
+    access_token::{AccessTokenProvider, ServiceAccountKey, TokenSource, CLOUD_PLATFORM_SCOPE},
+    aws::{self, AwsCredentials, RequestSigner},
+    retry,
+    streaming::{AnthropicStreamHandler, EventStreamDecoder, EventStreamMessage},
     CompletionChunk, CompletionOptions, CompletionResponse, CompletionStream, ModelAdapter,
     ToolSchema, Usage,
 };
@@ -71,7 +81,7 @@ impl AnthropicAdapter {
     }
 
     /// Convert internal messages to Anthropic API format
-    fn convert_messages(&self, messages: Vec<Message>) -> Vec<AnthropicMessage> {
+    fn convert_messages(messages: Vec<Message>) -> Vec<AnthropicMessage> {
         messages
             .into_iter()
             .map(|msg| AnthropicMessage {
@@ -80,51 +90,172 @@ impl AnthropicAdapter {
                     Role::Assistant => "assistant".to_string(),
                     Role::System => "user".to_string(), // System messages handled separately
                 },
-                content: self.convert_content_blocks(msg.content),
+                content: Self::convert_content_blocks(msg.content),
             })
             .collect()
     }
 
     /// Convert content blocks to Anthropic format
-    fn convert_content_blocks(&self, blocks: Vec<ContentBlock>) -> Vec<AnthropicContentBlock> {
+    ///
+    /// Thinking without a signature came from another provider and cannot be
+    /// verified, so it is left out.
+    fn convert_content_blocks(blocks: Vec<ContentBlock>) -> Vec<AnthropicContentBlock> {
         blocks
             .into_iter()
-            .map(|block| match block {
-                ContentBlock::Text { text } => AnthropicContentBlock::Text { text },
-                ContentBlock::ToolUse { id, name, input } => AnthropicContentBlock::ToolUse {
-                    id,
-                    name,
-                    input,
-                },
-                ContentBlock::ToolResult {
-                    tool_use_id,
-                    content,
-                    is_error,
-                } => AnthropicContentBlock::ToolResult {
-                    tool_use_id,
-                    content,
-                    is_error: is_error.unwrap_or(false),
-                },
-                ContentBlock::Thinking { thinking } => AnthropicContentBlock::Text {
-                    text: format!("<thinking>{}</thinking>", thinking),
-                },
+            .filter_map(|block| {
+                Some(match block {
+                    ContentBlock::Text { text } => AnthropicContentBlock::Text {
+                        text,
+                        cache_control: None,
+                    },
+                    ContentBlock::ToolUse { id, name, input } => AnthropicContentBlock::ToolUse {
+                        id,
+                        name,
+                        input,
+                        cache_control: None,
+                    },
+                    ContentBlock::ToolResult {
+                        tool_use_id,
+                        content,
+                        is_error,
+                        images,
+                    } => AnthropicContentBlock::ToolResult {
+                        tool_use_id,
+                        content: AnthropicToolResultContent::new(content, images),
+                        is_error: is_error.unwrap_or(false),
+                        cache_control: None,
+                    },
+                    ContentBlock::Image { source } => AnthropicContentBlock::Image {
+                        source,
+                        cache_control: None,
+                    },
+                    ContentBlock::Thinking {
+                        thinking,
+                        signature,
+                    } => AnthropicContentBlock::Thinking {
+                        thinking,
+                        signature: signature?,
+                    },
+                    ContentBlock::RedactedThinking { data } => {
+                        AnthropicContentBlock::RedactedThinking { data }
+                    }
+                    // Other providers' reasoning cannot be passed to Claude
+                    ContentBlock::Reasoning { .. } => return None,
+                })
             })
             .collect()
     }
 
     /// Convert tool schemas to Anthropic format
-    fn convert_tools(&self, tools: Vec<ToolSchema>) -> Vec<AnthropicTool> {
+    fn convert_tools(tools: Vec<ToolSchema>) -> Vec<AnthropicTool> {
         tools
             .into_iter()
             .map(|tool| AnthropicTool {
                 name: tool.name,
                 description: tool.description,
                 input_schema: tool.input_schema,
+                cache_control: None,
             })
             .collect()
     }
 
+    /// POST a Messages API request, opting into the Files API when the
+    /// request references uploaded files
+    fn post(&self, request: &AnthropicRequest) -> RequestBuilder {
+        let builder = self
+            .client
+            .post(format!("{}/v1/messages", self.base_url))
+            .json(request);
+        if request.references_files() {
+            builder.header("anthropic-beta", FILES_API_BETA)
+        } else {
+            builder
+        }
+    }
+
+    /// Build a Messages API request
+    fn build_request(
+        &self,
+        messages: Vec<Message>,
+        tools: Vec<ToolSchema>,
+        system_prompt: Option<String>,
+        options: CompletionOptions,
+        stream: bool,
+    ) -> AnthropicRequest {
+        Self::messages_request(&self.profile, messages, tools, system_prompt, options, stream)
+    }
+
+    /// Build a Messages API request for a profile
+    ///
+    /// With prompt caching enabled, cache breakpoints are placed on the last
+    /// tool, the system prompt and the last two messages. Tools, system prompt
+    /// and history are resent unchanged every turn, so each request reads the
+    /// prefix written by the one before it.
+    fn messages_request(
+        profile: &ModelProfile,
+        messages: Vec<Message>,
+        tools: Vec<ToolSchema>,
+        system_prompt: Option<String>,
+        options: CompletionOptions,
+        stream: bool,
+    ) -> AnthropicRequest {
+        let capabilities = profile.capabilities();
+        let mut max_tokens = options.max_tokens.unwrap_or(profile.max_tokens);
+        let budget = options
+            .thinking_budget
+            .or_else(|| profile.reasoning_effort.map(ReasoningEffort::thinking_budget))
+            .filter(|_| capabilities.reasoning);
+        let thinking = budget.and_then(|budget| {
+            let (budget, allowance) = fit_thinking_budget(budget, max_tokens, profile.max_tokens)?;
+            max_tokens = allowance;
+            Some(AnthropicThinking {
+                thinking_type: "enabled".to_string(),
+                budget_tokens: budget,
+            })
+        });
+        // Thinking only works with the default temperature and a top_p of at least 0.95
+        let (temperature, top_p) = if !capabilities.temperature {
+            (None, None)
+        } else if thinking.is_some() {
+            (None, options.top_p.filter(|top_p| *top_p >= 0.95))
+        } else {
+            (options.temperature, options.top_p)
+        };
+        let messages = if capabilities.vision {
+            messages
+        } else {
+            messages.into_iter().map(Message::without_images).collect()
+        };
+
+        let mut request = AnthropicRequest {
+            model: profile.model_name.clone(),
+            messages: Self::convert_messages(messages),
+            system: system_prompt.map(|text| {
+                vec![AnthropicContentBlock::Text {
+                    text,
+                    cache_control: None,
+                }]
+            }),
+            max_tokens,
+            temperature,
+            top_p,
+            stop_sequences: options.stop_sequences,
+            tools: match capabilities.tool_calling {
+                ToolCallingMode::Native if !tools.is_empty() => Some(Self::convert_tools(tools)),
+                _ => None,
+            },
+            thinking,
+            stream: Some(stream),
+        };
+        if profile.prompt_caching {
+            request.add_cache_breakpoints();
+        }
+        request
+    }
+
     /// Process SSE byte stream into CompletionChunks
+    ///
+    /// Chunks are yielded as soon as each event is parsed.
     fn process_stream(
         byte_stream: impl Stream<Item = reqwest::Result<Bytes>> + Send + 'static,
     ) -> impl Stream<Item = Result<CompletionChunk>> + Send + 'static {
@@ -133,69 +264,38 @@ impl AnthropicAdapter {
             let mut byte_stream = Box::pin(byte_stream);
 
             while let Some(chunk_result) = byte_stream.next().await {
-                match chunk_result {
-                    Ok(bytes) => {
-                        // Parse SSE events from bytes
-                        let text = match std::str::from_utf8(&bytes) {
-                            Ok(t) => t,
-                            Err(e) => {
-                                yield Err(KodeError::Other(format!("Invalid UTF-8 in stream: {}", e)));
-                                continue;
-                            }
-                        };
-
-                        // Process the chunk
-                        match handler.process_chunk(text) {
-                            Ok(done) => {
-                                if done {
-                                    break;
-                                }
-                            }
-                            Err(e) => {
-                                yield Err(e);
-                                break;
-                            }
+                let bytes = match chunk_result {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        yield Err(KodeError::NetworkError(e.to_string()));
+                        return;
+                    }
+                };
+
+                match handler.process_bytes(&bytes) {
+                    Ok(chunks) => {
+                        for chunk in chunks {
+                            yield Ok(chunk);
                         }
                     }
                     Err(e) => {
-                        yield Err(KodeError::NetworkError(e.to_string()));
-                        break;
+                        yield Err(e);
+                        return;
                     }
                 }
+                if handler.is_complete() {
+                    return;
+                }
             }
 
-            // Get final message and emit done event
-            match handler.get_message() {
-                Ok(assistant_message) => {
-                    // Extract content blocks from the message
-                    for block in &assistant_message.message.content {
-                        match block {
-                            ContentBlock::Text { text } => {
-                                yield Ok(CompletionChunk::TextDelta { text: text.clone() });
-                            }
-                            ContentBlock::Thinking { thinking } => {
-                                yield Ok(CompletionChunk::ThinkingDelta { thinking: thinking.clone() });
-                            }
-                            ContentBlock::ToolUse { id, name, input } => {
-                                yield Ok(CompletionChunk::ToolUseComplete {
-                                    id: id.clone(),
-                                    name: name.clone(),
-                                    input: input.clone(),
-                                });
-                            }
-                            _ => {}
-                        }
+            // The connection closed without an end-of-stream event
+            match handler.finish() {
+                Ok(chunks) => {
+                    for chunk in chunks {
+                        yield Ok(chunk);
                     }
-
-                    // Emit done event with usage stats
-                    yield Ok(CompletionChunk::Done {
-                        stop_reason: handler.get_stop_reason().unwrap_or_else(|| "end_turn".to_string()),
-                        usage: Some(handler.get_usage()),
-                    });
-                }
-                Err(e) => {
-                    yield Err(e);
                 }
+                Err(e) => yield Err(e),
             }
         }
     }
@@ -218,74 +318,13 @@ impl ModelAdapter for AnthropicAdapter {
         system_prompt: Option<String>,
         options: CompletionOptions,
     ) -> Result<CompletionResponse> {
-        let request = AnthropicRequest {
-            model: self.profile.model_name.clone(),
-            messages: self.convert_messages(messages),
-            system: system_prompt,
-            max_tokens: options.max_tokens.unwrap_or(8192),
-            temperature: options.temperature,
-            top_p: options.top_p,
-            stop_sequences: options.stop_sequences,
-            tools: if tools.is_empty() {
-                None
-            } else {
-                Some(self.convert_tools(tools))
-            },
-            stream: Some(false),
-        };
+        let request = self.build_request(messages, tools, system_prompt, options, false);
 
-        let response = self
-            .client
-            .post(format!("{}/v1/messages", self.base_url))
-            .json(&request)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await?;
-            return Err(KodeError::ApiError {
-                provider: "anthropic".to_string(),
-                message: format!("HTTP {}: {}", status, error_text),
-            });
-        }
+        let request = self.post(&request);
+        let response = retry::send(&self.profile.retry_policy(), "anthropic", request).await?;
 
         let api_response: AnthropicResponse = response.json().await?;
-
-        // Convert API response to CompletionResponse
-        let content = api_response
-            .content
-            .into_iter()
-            .map(|block| match block {
-                AnthropicContentBlock::Text { text } => ContentBlock::Text { text },
-                AnthropicContentBlock::ToolUse { id, name, input } => ContentBlock::ToolUse {
-                    id,
-                    name,
-                    input,
-                },
-                AnthropicContentBlock::ToolResult {
-                    tool_use_id,
-                    content,
-                    is_error,
-                } => ContentBlock::ToolResult {
-                    tool_use_id,
-                    content,
-                    is_error: Some(is_error),
-                },
-            })
-            .collect();
-
-        Ok(CompletionResponse {
-            content,
-            model: Some(api_response.model),
-            stop_reason: api_response.stop_reason,
-            usage: api_response.usage.map(|u| Usage {
-                input_tokens: u.input_tokens,
-                output_tokens: u.output_tokens,
-                cache_creation_input_tokens: u.cache_creation_input_tokens,
-                cache_read_input_tokens: u.cache_read_input_tokens,
-            }),
-        })
+        Ok(api_response.into())
     }
 
     async fn stream_complete(
@@ -295,49 +334,20 @@ impl ModelAdapter for AnthropicAdapter {
         system_prompt: Option<String>,
         options: CompletionOptions,
     ) -> Result<CompletionStream> {
-        let request = AnthropicRequest {
-            model: self.profile.model_name.clone(),
-            messages: self.convert_messages(messages),
-            system: system_prompt,
-            max_tokens: options.max_tokens.unwrap_or(8192),
-            temperature: options.temperature,
-            top_p: options.top_p,
-            stop_sequences: options.stop_sequences,
-            tools: if tools.is_empty() {
-                None
-            } else {
-                Some(self.convert_tools(tools))
-            },
-            stream: Some(true),
-        };
+        let request = self.build_request(messages, tools, system_prompt, options, true);
 
-        let response = self
-            .client
-            .post(format!("{}/v1/messages", self.base_url))
-            .json(&request)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await?;
-            return Err(KodeError::ApiError {
-                provider: "anthropic".to_string(),
-                message: format!("HTTP {}: {}", status, error_text),
-            });
-        }
-
-        // Create SSE parser and stream handler
-        let byte_stream = response.bytes_stream();
-        let stream = Self::process_stream(byte_stream);
+        let request = self.post(&request);
+        Ok(retry::stream(self.profile.retry_policy(), "anthropic", request, |response| {
+            Self::process_stream(response.bytes_stream())
+        }))
+    }
 
-        Ok(Box::pin(stream))
+    fn pricing(&self) -> Option<ModelPricing> {
+        self.profile.pricing()
     }
 
     fn max_context_tokens(&self) -> u32 {
-        // Default context window for Claude models
-        // TODO: Make this configurable per model
-        200_000
+        self.profile.context_length
     }
 
     fn max_output_tokens(&self) -> u32 {
@@ -346,13 +356,217 @@ impl ModelAdapter for AnthropicAdapter {
 }
 
 /// AWS Bedrock adapter (uses Anthropic models via Bedrock)
+///
+/// Requests are the Messages API bodies without `model` and `stream`, signed
+/// with AWS Signature Version 4. Streamed responses arrive in the AWS event
+/// stream framing, each event wrapping one Messages API stream event.
 pub struct BedrockAdapter {
+    client: Client,
     profile: ModelProfile,
+    credentials: AwsCredentials,
+    region: String,
+    base_url: String,
+    model_id: String,
 }
 
 impl BedrockAdapter {
+    /// Create a Bedrock adapter with credentials from the environment or
+    /// the shared AWS files
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no AWS credentials are found
     pub fn new(profile: ModelProfile) -> Result<Self> {
-        Ok(Self { profile })
+        Self::with_credentials(profile, AwsCredentials::load()?)
+    }
+
+    /// Create a Bedrock adapter with the given credentials
+    ///
+    /// The region is the profile's, else the AWS default one, else
+    /// `us-east-1`; the profile's base URL replaces the regional endpoint.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the HTTP client cannot be built
+    pub fn with_credentials(profile: ModelProfile, credentials: AwsCredentials) -> Result<Self> {
+        let region = profile
+            .region
+            .clone()
+            .or_else(aws::default_region)
+            .unwrap_or_else(|| "us-east-1".to_string());
+        let base_url = profile.base_url.clone().map_or_else(
+            || format!("https://bedrock-runtime.{region}.amazonaws.com"),
+            |url| url.trim_end_matches('/').to_string(),
+        );
+        let model_id = bedrock_model_id(&profile.model_name, &region);
+
+        Ok(Self {
+            client: Client::builder().build()?,
+            profile,
+            credentials,
+            region,
+            base_url,
+            model_id,
+        })
+    }
+
+    /// Signed POST of a request body to a model action
+    fn post(&self, action: &str, request: AnthropicRequest, accept: &str) -> Result<RequestBuilder> {
+        let body = request.platform_body(BEDROCK_VERSION, false)?;
+        let url = reqwest::Url::parse(&format!(
+            "{}/model/{}/{action}",
+            self.base_url,
+            aws::uri_encode(&self.model_id)
+        ))
+        .map_err(|e| KodeError::InvalidConfig(format!("Invalid Bedrock endpoint: {e}")))?;
+
+        let headers = [("content-type", "application/json"), ("accept", accept)];
+        let signer = RequestSigner {
+            credentials: &self.credentials,
+            region: &self.region,
+            service: "bedrock",
+        };
+        let auth_headers = signer.sign("POST", &url, &headers, &body, chrono::Utc::now());
+
+        let mut builder = self.client.post(url).body(body);
+        for (name, value) in headers {
+            builder = builder.header(name, value);
+        }
+        for (name, value) in auth_headers {
+            builder = builder.header(name, value);
+        }
+        Ok(builder)
+    }
+
+    /// Process an event stream into `CompletionChunk`s
+    ///
+    /// Each event carries a base64-encoded Messages API stream event, which
+    /// is handed to [`AnthropicStreamHandler`].
+    fn process_stream(
+        byte_stream: impl Stream<Item = reqwest::Result<Bytes>> + Send + 'static,
+    ) -> impl Stream<Item = Result<CompletionChunk>> + Send + 'static {
+        async_stream::stream! {
+            let mut decoder = EventStreamDecoder::new();
+            let mut handler = AnthropicStreamHandler::new();
+            let mut byte_stream = Box::pin(byte_stream);
+
+            while let Some(chunk_result) = byte_stream.next().await {
+                let bytes = match chunk_result {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        yield Err(KodeError::NetworkError(e.to_string()));
+                        return;
+                    }
+                };
+
+                let messages = match decoder.push(&bytes) {
+                    Ok(messages) => messages,
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                };
+                for message in messages {
+                    match bedrock_event_data(&message).and_then(|data| match data {
+                        Some(data) => handler.process_event_data(&data),
+                        None => Ok(Vec::new()),
+                    }) {
+                        Ok(chunks) => {
+                            for chunk in chunks {
+                                yield Ok(chunk);
+                            }
+                        }
+                        Err(e) => {
+                            yield Err(e);
+                            return;
+                        }
+                    }
+                    if handler.is_complete() {
+                        return;
+                    }
+                }
+            }
+
+            // The connection closed without a `message_stop` event
+            match handler.finish() {
+                Ok(chunks) => {
+                    for chunk in chunks {
+                        yield Ok(chunk);
+                    }
+                }
+                Err(e) => yield Err(e),
+            }
+        }
+    }
+}
+
+/// `anthropic_version` Bedrock expects in request bodies
+const BEDROCK_VERSION: &str = "bedrock-2023-05-31";
+
+/// Bedrock model ID for a model name
+///
+/// Bedrock model IDs, inference profile IDs and ARNs are used as they are.
+/// Anthropic model names get Bedrock's `anthropic.` prefix and version
+/// suffix, plus the cross-region inference profile of the region's
+/// geography, which newer Claude models can only be invoked through.
+#[must_use]
+pub fn bedrock_model_id(model: &str, region: &str) -> String {
+    if model.contains("anthropic.") || model.starts_with("arn:") {
+        return model.to_string();
+    }
+    let version = if model == "claude-3-5-sonnet-20241022" { "v2:0" } else { "v1:0" };
+    let geography = if region.starts_with("us-gov-") {
+        Some("us-gov")
+    } else {
+        match region.split('-').next() {
+            Some("us") => Some("us"),
+            Some("eu") => Some("eu"),
+            Some("ap") => Some("apac"),
+            _ => None,
+        }
+    };
+    match geography {
+        Some(geography) => format!("{geography}.anthropic.{model}-{version}"),
+        None => format!("anthropic.{model}-{version}"),
+    }
+}
+
+/// The Messages API event carried by an event stream message, if any
+///
+/// # Errors
+///
+/// Returns an error for exception messages and malformed payloads
+fn bedrock_event_data(message: &EventStreamMessage) -> Result<Option<String>> {
+    #[derive(Deserialize)]
+    struct Payload {
+        #[serde(default)]
+        bytes: Option<String>,
+        #[serde(default)]
+        message: Option<String>,
+    }
+
+    let payload: Payload = serde_json::from_slice(&message.payload)?;
+    match message.header(":message-type") {
+        Some("event") if message.header(":event-type") == Some("chunk") => {
+            let Some(bytes) = payload.bytes else {
+                return Ok(None);
+            };
+            let data = base64::engine::general_purpose::STANDARD
+                .decode(bytes)
+                .map_err(|e| KodeError::Other(format!("Invalid Bedrock event payload: {e}")))?;
+            Ok(Some(String::from_utf8_lossy(&data).into_owned()))
+        }
+        Some("exception" | "error") => {
+            let kind = message
+                .header(":exception-type")
+                .or_else(|| message.header(":error-code"))
+                .unwrap_or("exception");
+            Err(KodeError::ApiError {
+                provider: "bedrock".to_string(),
+                message: format!("{kind}: {}", payload.message.unwrap_or_default()),
+            })
+        }
+        _ => Ok(None),
     }
 }
 
@@ -368,30 +582,47 @@ impl ModelAdapter for BedrockAdapter {
 
     async fn complete(
         &self,
-        _messages: Vec<Message>,
-        _tools: Vec<ToolSchema>,
-        _system_prompt: Option<String>,
-        _options: CompletionOptions,
+        messages: Vec<Message>,
+        tools: Vec<ToolSchema>,
+        system_prompt: Option<String>,
+        options: CompletionOptions,
     ) -> Result<CompletionResponse> {
-        Err(KodeError::NotImplemented(
-            "Bedrock adapter not yet implemented".to_string(),
-        ))
+        let request =
+            AnthropicAdapter::messages_request(&self.profile, messages, tools, system_prompt, options, false);
+
+        let request = self.post("invoke", request, "application/json")?;
+        let response = retry::send(&self.profile.retry_policy(), "bedrock", request).await?;
+
+        let api_response: AnthropicResponse = response.json().await?;
+        Ok(api_response.into())
     }
 
     async fn stream_complete(
         &self,
-        _messages: Vec<Message>,
-        _tools: Vec<ToolSchema>,
-        _system_prompt: Option<String>,
-        _options: CompletionOptions,
+        messages: Vec<Message>,
+        tools: Vec<ToolSchema>,
+        system_prompt: Option<String>,
+        options: CompletionOptions,
     ) -> Result<CompletionStream> {
-        Err(KodeError::NotImplemented(
-            "Bedrock streaming not yet implemented".to_string(),
-        ))
+        let request =
+            AnthropicAdapter::messages_request(&self.profile, messages, tools, system_prompt, options, true);
+
+        let request = self.post(
+            "invoke-with-response-stream",
+            request,
+            "application/vnd.amazon.eventstream",
+        )?;
+        Ok(retry::stream(self.profile.retry_policy(), "bedrock", request, |response| {
+            Self::process_stream(response.bytes_stream())
+        }))
+    }
+
+    fn pricing(&self) -> Option<ModelPricing> {
+        self.profile.pricing()
     }
 
     fn max_context_tokens(&self) -> u32 {
-        200_000
+        self.profile.context_length
     }
 
     fn max_output_tokens(&self) -> u32 {
@@ -400,13 +631,125 @@ impl ModelAdapter for BedrockAdapter {
 }
 
 /// Google Vertex AI adapter (uses Anthropic models via Vertex)
+///
+/// Requests are the Messages API bodies without `model`, sent to the
+/// publisher model's `rawPredict` and `streamRawPredict` methods with an
+/// OAuth access token. Streamed responses are the usual Messages API SSE.
 pub struct VertexAdapter {
+    client: Client,
     profile: ModelProfile,
+    tokens: AccessTokenProvider,
+    base_url: String,
+    project: String,
+    region: String,
+    model_id: String,
 }
 
 impl VertexAdapter {
+    /// Create a Vertex AI adapter
+    ///
+    /// Tokens come from the profile's token command if it has one, else from
+    /// the service-account key in its credentials file or
+    /// `GOOGLE_APPLICATION_CREDENTIALS`. The project is the profile's, else
+    /// `ANTHROPIC_VERTEX_PROJECT_ID`, `GOOGLE_CLOUD_PROJECT` or the key's;
+    /// the region is the profile's, else `CLOUD_ML_REGION`, else `us-east5`.
+    /// The profile's base URL replaces the regional endpoint.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no token source or project is configured, or the
+    /// key file cannot be read
     pub fn new(profile: ModelProfile) -> Result<Self> {
-        Ok(Self { profile })
+        let key_file = profile
+            .credentials_file
+            .clone()
+            .or_else(|| std::env::var("GOOGLE_APPLICATION_CREDENTIALS").ok());
+        let source = match (&profile.token_command, key_file) {
+            (Some(command), _) => TokenSource::Command(command.clone()),
+            (None, Some(path)) => TokenSource::ServiceAccount(ServiceAccountKey::from_file(Path::new(&path))?),
+            (None, None) => {
+                return Err(KodeError::InvalidConfig(
+                    "Vertex AI needs a service-account key (credentials_file or GOOGLE_APPLICATION_CREDENTIALS) or a token_command"
+                        .to_string(),
+                ))
+            }
+        };
+
+        let key_project = match &source {
+            TokenSource::ServiceAccount(key) => key.project_id.clone(),
+            TokenSource::Command(_) => None,
+        };
+        let project = profile
+            .project
+            .clone()
+            .or_else(|| std::env::var("ANTHROPIC_VERTEX_PROJECT_ID").ok())
+            .or_else(|| std::env::var("GOOGLE_CLOUD_PROJECT").ok())
+            .or(key_project)
+            .ok_or_else(|| KodeError::InvalidConfig("No Google Cloud project set for Vertex AI".to_string()))?;
+        let region = profile
+            .region
+            .clone()
+            .or_else(|| std::env::var("CLOUD_ML_REGION").ok())
+            .unwrap_or_else(|| "us-east5".to_string());
+        let base_url = profile.base_url.clone().map_or_else(
+            || {
+                if region == "global" {
+                    "https://aiplatform.googleapis.com".to_string()
+                } else {
+                    format!("https://{region}-aiplatform.googleapis.com")
+                }
+            },
+            |url| url.trim_end_matches('/').to_string(),
+        );
+        let model_id = vertex_model_id(&profile.model_name);
+
+        Ok(Self {
+            client: Client::builder().build()?,
+            profile,
+            tokens: AccessTokenProvider::new(source, CLOUD_PLATFORM_SCOPE),
+            base_url,
+            project,
+            region,
+            model_id,
+        })
+    }
+
+    /// Authorized POST of a request body to a model method
+    async fn post(&self, method: &str, request: AnthropicRequest, stream: bool) -> Result<RequestBuilder> {
+        let body = request.platform_body(VERTEX_VERSION, stream)?;
+        let token = self.tokens.token(&self.client).await?;
+        Ok(self
+            .client
+            .post(format!(
+                "{}/v1/projects/{}/locations/{}/publishers/anthropic/models/{}:{method}",
+                self.base_url, self.project, self.region, self.model_id
+            ))
+            .bearer_auth(token)
+            .header("content-type", "application/json")
+            .body(body))
+    }
+}
+
+/// `anthropic_version` Vertex AI expects in request bodies
+const VERTEX_VERSION: &str = "vertex-2023-10-16";
+
+/// Vertex AI model ID for a model name
+///
+/// Vertex separates the date from the model name with `@`, as in
+/// `claude-sonnet-4@20250514`. Names already in that form pass through.
+#[must_use]
+pub fn vertex_model_id(model: &str) -> String {
+    if model.contains('@') {
+        return model.to_string();
+    }
+    if model == "claude-3-5-sonnet-20241022" {
+        return "claude-3-5-sonnet-v2@20241022".to_string();
+    }
+    match model.rsplit_once('-') {
+        Some((name, date)) if date.len() == 8 && date.bytes().all(|b| b.is_ascii_digit()) => {
+            format!("{name}@{date}")
+        }
+        _ => model.to_string(),
     }
 }
 
@@ -422,30 +765,43 @@ impl ModelAdapter for VertexAdapter {
 
     async fn complete(
         &self,
-        _messages: Vec<Message>,
-        _tools: Vec<ToolSchema>,
-        _system_prompt: Option<String>,
-        _options: CompletionOptions,
+        messages: Vec<Message>,
+        tools: Vec<ToolSchema>,
+        system_prompt: Option<String>,
+        options: CompletionOptions,
     ) -> Result<CompletionResponse> {
-        Err(KodeError::NotImplemented(
-            "Vertex adapter not yet implemented".to_string(),
-        ))
+        let request =
+            AnthropicAdapter::messages_request(&self.profile, messages, tools, system_prompt, options, false);
+
+        let request = self.post("rawPredict", request, false).await?;
+        let response = retry::send(&self.profile.retry_policy(), "vertex", request).await?;
+
+        let api_response: AnthropicResponse = response.json().await?;
+        Ok(api_response.into())
     }
 
     async fn stream_complete(
         &self,
-        _messages: Vec<Message>,
-        _tools: Vec<ToolSchema>,
-        _system_prompt: Option<String>,
-        _options: CompletionOptions,
+        messages: Vec<Message>,
+        tools: Vec<ToolSchema>,
+        system_prompt: Option<String>,
+        options: CompletionOptions,
     ) -> Result<CompletionStream> {
-        Err(KodeError::NotImplemented(
-            "Vertex streaming not yet implemented".to_string(),
-        ))
+        let request =
+            AnthropicAdapter::messages_request(&self.profile, messages, tools, system_prompt, options, true);
+
+        let request = self.post("streamRawPredict", request, true).await?;
+        Ok(retry::stream(self.profile.retry_policy(), "vertex", request, |response| {
+            AnthropicAdapter::process_stream(response.bytes_stream())
+        }))
+    }
+
+    fn pricing(&self) -> Option<ModelPricing> {
+        self.profile.pricing()
     }
 
     fn max_context_tokens(&self) -> u32 {
-        200_000
+        self.profile.context_length
     }
 
     fn max_output_tokens(&self) -> u32 {
@@ -453,6 +809,27 @@ impl ModelAdapter for VertexAdapter {
     }
 }
 
+/// Beta flag required to reference uploaded files
+const FILES_API_BETA: &str = "files-api-2025-04-14";
+
+/// Smallest thinking budget the API accepts
+const MIN_THINKING_BUDGET: u32 = 1_024;
+
+/// Output tokens kept free for the answer after thinking
+const MIN_ANSWER_TOKENS: u32 = 1_024;
+
+/// Fit a thinking budget into the output allowance
+///
+/// Thinking counts against `max_tokens`, so the allowance grows by the budget,
+/// up to the profile's output cap. If the cap leaves too little room, the
+/// budget shrinks instead, and thinking is skipped once it would fall below
+/// the API minimum. Returns the budget and the new allowance.
+fn fit_thinking_budget(budget: u32, max_tokens: u32, cap: u32) -> Option<(u32, u32)> {
+    let allowance = max_tokens.saturating_add(budget).min(cap.max(max_tokens));
+    let budget = budget.min(allowance.saturating_sub(MIN_ANSWER_TOKENS));
+    (budget >= MIN_THINKING_BUDGET).then_some((budget, allowance))
+}
+
 // Anthropic API types
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -460,7 +837,7 @@ struct AnthropicRequest {
     model: String,
     messages: Vec<AnthropicMessage>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    system: Option<String>,
+    system: Option<Vec<AnthropicContentBlock>>,
     max_tokens: u32,
     #[serde(skip_serializing_if = "Option::is_none")]
     temperature: Option<f32>,
@@ -471,9 +848,87 @@ struct AnthropicRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     tools: Option<Vec<AnthropicTool>>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    thinking: Option<AnthropicThinking>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     stream: Option<bool>,
 }
 
+/// Extended thinking configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AnthropicThinking {
+    #[serde(rename = "type")]
+    thinking_type: String,
+    budget_tokens: u32,
+}
+
+impl AnthropicRequest {
+    /// Body for a cloud platform serving Claude, which takes the model from
+    /// the URL and the API version from the body
+    fn platform_body(self, anthropic_version: &str, stream: bool) -> Result<Vec<u8>> {
+        let mut body = serde_json::to_value(self)?;
+        if let Some(body) = body.as_object_mut() {
+            body.remove("model");
+            body.remove("stream");
+            if stream {
+                body.insert("stream".to_string(), serde_json::Value::Bool(true));
+            }
+            body.insert(
+                "anthropic_version".to_string(),
+                serde_json::Value::String(anthropic_version.to_string()),
+            );
+        }
+        Ok(serde_json::to_vec(&body)?)
+    }
+
+    /// Whether any image refers to a file uploaded through the Files API
+    fn references_files(&self) -> bool {
+        self.messages
+            .iter()
+            .flat_map(|message| &message.content)
+            .any(|block| match block {
+                AnthropicContentBlock::Image { source, .. } => {
+                    matches!(source, ImageSource::File { .. })
+                }
+                AnthropicContentBlock::ToolResult { content, .. } => content
+                    .images()
+                    .any(|source| matches!(source, ImageSource::File { .. })),
+                _ => false,
+            })
+    }
+
+    /// Mark the cache breakpoints: the last tool, the system prompt and the
+    /// last two messages (four, the most the API accepts)
+    fn add_cache_breakpoints(&mut self) {
+        if let Some(tool) = self.tools.as_mut().and_then(|tools| tools.last_mut()) {
+            tool.cache_control = Some(CacheControl::ephemeral());
+        }
+        if let Some(block) = self.system.as_mut().and_then(|system| system.last_mut()) {
+            block.set_cache_control();
+        }
+        for message in self.messages.iter_mut().rev().take(2) {
+            // Empty text blocks cannot be cached; mark the last one that can
+            if let Some(block) = message.content.iter_mut().rev().find(|b| b.is_cacheable()) {
+                block.set_cache_control();
+            }
+        }
+    }
+}
+
+/// Prompt cache breakpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheControl {
+    #[serde(rename = "type")]
+    cache_type: String,
+}
+
+impl CacheControl {
+    fn ephemeral() -> Self {
+        Self {
+            cache_type: "ephemeral".to_string(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct AnthropicMessage {
     role: String,
@@ -485,18 +940,112 @@ struct AnthropicMessage {
 enum AnthropicContentBlock {
     Text {
         text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
     },
     ToolUse {
         id: String,
         name: String,
         input: serde_json::Value,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
     },
     ToolResult {
         tool_use_id: String,
-        content: String,
-        #[serde(skip_serializing_if = "is_false")]
+        content: AnthropicToolResultContent,
+        #[serde(default, skip_serializing_if = "is_false")]
         is_error: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    Image {
+        source: ImageSource,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    Thinking {
+        thinking: String,
+        signature: String,
     },
+    RedactedThinking {
+        data: String,
+    },
+}
+
+/// Content of a tool result: plain text, or blocks when images are attached
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum AnthropicToolResultContent {
+    Text(String),
+    Blocks(Vec<AnthropicContentBlock>),
+}
+
+impl AnthropicToolResultContent {
+    fn new(text: String, images: Vec<ImageSource>) -> Self {
+        if images.is_empty() {
+            return Self::Text(text);
+        }
+        let text = AnthropicContentBlock::Text {
+            text,
+            cache_control: None,
+        };
+        let images = images.into_iter().map(|source| AnthropicContentBlock::Image {
+            source,
+            cache_control: None,
+        });
+        Self::Blocks(std::iter::once(text).chain(images).collect())
+    }
+
+    /// Split into the text and the attached images
+    fn into_parts(self) -> (String, Vec<ImageSource>) {
+        match self {
+            Self::Text(text) => (text, Vec::new()),
+            Self::Blocks(blocks) => {
+                let mut text = String::new();
+                let mut images = Vec::new();
+                for block in blocks {
+                    match block {
+                        AnthropicContentBlock::Text { text: part, .. } => text.push_str(&part),
+                        AnthropicContentBlock::Image { source, .. } => images.push(source),
+                        _ => {}
+                    }
+                }
+                (text, images)
+            }
+        }
+    }
+
+    fn images(&self) -> impl Iterator<Item = &ImageSource> {
+        let blocks = match self {
+            Self::Text(_) => &[][..],
+            Self::Blocks(blocks) => blocks.as_slice(),
+        };
+        blocks.iter().filter_map(|block| match block {
+            AnthropicContentBlock::Image { source, .. } => Some(source),
+            _ => None,
+        })
+    }
+}
+
+impl AnthropicContentBlock {
+    /// Whether the block can carry a cache breakpoint
+    fn is_cacheable(&self) -> bool {
+        match self {
+            Self::Text { text, .. } => !text.is_empty(),
+            Self::ToolUse { .. } | Self::ToolResult { .. } | Self::Image { .. } => true,
+            Self::Thinking { .. } | Self::RedactedThinking { .. } => false,
+        }
+    }
+
+    fn set_cache_control(&mut self) {
+        if let Self::Text { cache_control, .. }
+        | Self::ToolUse { cache_control, .. }
+        | Self::ToolResult { cache_control, .. }
+        | Self::Image { cache_control, .. } = self
+        {
+            *cache_control = Some(CacheControl::ephemeral());
+        }
+    }
 }
 
 fn is_false(b: &bool) -> bool {
@@ -508,6 +1057,8 @@ struct AnthropicTool {
     name: String,
     description: String,
     input_schema: serde_json::Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cache_control: Option<CacheControl>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -532,3 +1083,518 @@ struct AnthropicUsage {
     #[serde(skip_serializing_if = "Option::is_none")]
     cache_read_input_tokens: Option<u32>,
 }
+
+impl From<AnthropicResponse> for CompletionResponse {
+    fn from(response: AnthropicResponse) -> Self {
+        let content = response
+            .content
+            .into_iter()
+            .map(|block| match block {
+                AnthropicContentBlock::Text { text, .. } => ContentBlock::Text { text },
+                AnthropicContentBlock::ToolUse {
+                    id, name, input, ..
+                } => ContentBlock::ToolUse { id, name, input },
+                AnthropicContentBlock::ToolResult {
+                    tool_use_id,
+                    content,
+                    is_error,
+                    ..
+                } => {
+                    let (content, images) = content.into_parts();
+                    ContentBlock::ToolResult {
+                        tool_use_id,
+                        content,
+                        is_error: Some(is_error),
+                        images,
+                    }
+                }
+                AnthropicContentBlock::Image { source, .. } => ContentBlock::Image { source },
+                AnthropicContentBlock::Thinking {
+                    thinking,
+                    signature,
+                } => ContentBlock::Thinking {
+                    thinking,
+                    signature: Some(signature),
+                },
+                AnthropicContentBlock::RedactedThinking { data } => {
+                    ContentBlock::RedactedThinking { data }
+                }
+            })
+            .collect();
+
+        Self {
+            content,
+            model: Some(response.model),
+            stop_reason: response.stop_reason,
+            usage: response.usage.map(|u| Usage {
+                input_tokens: u.input_tokens,
+                output_tokens: u.output_tokens,
+                cache_creation_input_tokens: u.cache_creation_input_tokens,
+                cache_read_input_tokens: u.cache_read_input_tokens,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::config::models::ProviderType;
+
+    fn adapter(prompt_caching: bool) -> AnthropicAdapter {
+        let mut profile = ModelProfile::new(
+            "sonnet".to_string(),
+            ProviderType::Anthropic,
+            "claude-sonnet-4-5".to_string(),
+            "test-key".to_string(),
+            8192,
+            200_000,
+        );
+        profile.prompt_caching = prompt_caching;
+        AnthropicAdapter::new(profile).unwrap()
+    }
+
+    fn request(adapter: &AnthropicAdapter) -> Value {
+        let messages = vec![
+            Message::user("first question"),
+            Message::assistant("first answer"),
+            Message::user("second question"),
+        ];
+        let tools = ["Read", "Write"]
+            .map(|name| ToolSchema {
+                name: name.to_string(),
+                description: format!("{name} a file"),
+                input_schema: json!({"type": "object"}),
+            })
+            .to_vec();
+        let request = adapter.build_request(
+            messages,
+            tools,
+            Some("You are Kode".to_string()),
+            CompletionOptions::default(),
+            true,
+        );
+        serde_json::to_value(request).unwrap()
+    }
+
+    #[test]
+    fn test_cache_breakpoints() {
+        let request = request(&adapter(true));
+        let ephemeral = json!({"type": "ephemeral"});
+
+        assert_eq!(request["tools"][0].get("cache_control"), None);
+        assert_eq!(request["tools"][1]["cache_control"], ephemeral);
+        assert_eq!(
+            request["system"],
+            json!([{"type": "text", "text": "You are Kode", "cache_control": ephemeral}])
+        );
+        let marked: Vec<bool> = request["messages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|message| message["content"][0].get("cache_control").is_some())
+            .collect();
+        assert_eq!(marked, [false, true, true]);
+    }
+
+    #[test]
+    fn test_cache_breakpoints_disabled() {
+        let request = request(&adapter(false)).to_string();
+        assert!(!request.contains("cache_control"));
+    }
+
+    #[test]
+    fn test_thinking_blocks_round_trip() {
+        let mut adapter = adapter(false);
+        adapter.profile.reasoning_effort = Some(ReasoningEffort::Low);
+        let assistant = Message {
+            role: Role::Assistant,
+            content: vec![
+                ContentBlock::Thinking {
+                    thinking: "Check the file first".to_string(),
+                    signature: Some("sig-1".to_string()),
+                },
+                ContentBlock::RedactedThinking {
+                    data: "opaque".to_string(),
+                },
+                // Reasoning from another provider cannot be verified
+                ContentBlock::Thinking {
+                    thinking: "unsigned".to_string(),
+                    signature: None,
+                },
+                ContentBlock::Text {
+                    text: "Reading it".to_string(),
+                },
+            ],
+            uuid: None,
+        };
+
+        let request = adapter.build_request(
+            vec![Message::user("Fix it"), assistant],
+            Vec::new(),
+            None,
+            CompletionOptions::default(),
+            true,
+        );
+        let request = serde_json::to_value(request).unwrap();
+
+        assert_eq!(
+            request["messages"][1]["content"],
+            json!([
+                {"type": "thinking", "thinking": "Check the file first", "signature": "sig-1"},
+                {"type": "redacted_thinking", "data": "opaque"},
+                {"type": "text", "text": "Reading it"},
+            ])
+        );
+        assert_eq!(request["thinking"], json!({"type": "enabled", "budget_tokens": 4000}));
+        assert_eq!(request["max_tokens"], 8192);
+        assert_eq!(request.get("temperature"), None);
+    }
+
+    #[test]
+    fn test_thinking_budget_overrides_effort() {
+        let adapter = adapter(false);
+        let options = CompletionOptions {
+            thinking_budget: Some(2_000),
+            ..CompletionOptions::default()
+        };
+        let request = adapter.build_request(vec![Message::user("Hi")], Vec::new(), None, options, true);
+
+        assert_eq!(request.thinking.map(|thinking| thinking.budget_tokens), Some(2_000));
+        assert_eq!(request.max_tokens, 8192);
+    }
+
+    #[test]
+    fn test_fit_thinking_budget() {
+        // The allowance grows by the budget, up to the cap
+        assert_eq!(fit_thinking_budget(4_000, 8_192, 64_000), Some((4_000, 12_192)));
+        assert_eq!(fit_thinking_budget(32_000, 8_192, 32_000), Some((30_976, 32_000)));
+        // A cap below the requested allowance keeps the requested one
+        assert_eq!(fit_thinking_budget(10_000, 8_192, 4_096), Some((7_168, 8_192)));
+        assert_eq!(fit_thinking_budget(10_000, 1_500, 1_500), None);
+    }
+
+    #[test]
+    fn test_image_blocks() {
+        let adapter = adapter(false);
+        let png = ImageSource::Base64 {
+            media_type: "image/png".to_string(),
+            data: "iVBORw0KGgo=".to_string(),
+        };
+        let messages = vec![
+            Message {
+                role: Role::User,
+                content: vec![
+                    ContentBlock::Text {
+                        text: "What is this?".to_string(),
+                    },
+                    ContentBlock::Image { source: png.clone() },
+                ],
+                uuid: None,
+            },
+            Message {
+                role: Role::User,
+                content: vec![ContentBlock::ToolResult {
+                    tool_use_id: "toolu_1".to_string(),
+                    content: "Read image file (base64 encoded)".to_string(),
+                    is_error: None,
+                    images: vec![png],
+                }],
+                uuid: None,
+            },
+        ];
+        let request = adapter.build_request(messages, Vec::new(), None, CompletionOptions::default(), true);
+        assert!(!request.references_files());
+        let request = serde_json::to_value(request).unwrap();
+
+        let source = json!({"type": "base64", "media_type": "image/png", "data": "iVBORw0KGgo="});
+        assert_eq!(request["messages"][0]["content"][1], json!({"type": "image", "source": source}));
+        assert_eq!(
+            request["messages"][1]["content"][0]["content"],
+            json!([
+                {"type": "text", "text": "Read image file (base64 encoded)"},
+                {"type": "image", "source": source},
+            ])
+        );
+    }
+
+    #[test]
+    fn test_file_images_need_files_api() {
+        let adapter = adapter(false);
+        let message = Message {
+            role: Role::User,
+            content: vec![ContentBlock::Image {
+                source: ImageSource::File {
+                    file_id: "file_011".to_string(),
+                },
+            }],
+            uuid: None,
+        };
+        let request =
+            adapter.build_request(vec![message], Vec::new(), None, CompletionOptions::default(), true);
+
+        assert!(request.references_files());
+        let request = serde_json::to_value(request).unwrap();
+        assert_eq!(
+            request["messages"][0]["content"][0]["source"],
+            json!({"type": "file", "file_id": "file_011"})
+        );
+    }
+
+    fn bedrock(base_url: &str) -> BedrockAdapter {
+        let mut profile = ModelProfile::new(
+            "bedrock sonnet".to_string(),
+            ProviderType::Bedrock,
+            "claude-sonnet-4-20250514".to_string(),
+            String::new(),
+            8192,
+            200_000,
+        );
+        profile.base_url = Some(base_url.to_string());
+        profile.region = Some("us-east-1".to_string());
+        let credentials = AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None,
+        };
+        BedrockAdapter::with_credentials(profile, credentials).unwrap()
+    }
+
+    #[test]
+    fn test_bedrock_model_id() {
+        assert_eq!(
+            bedrock_model_id("claude-sonnet-4-20250514", "us-west-2"),
+            "us.anthropic.claude-sonnet-4-20250514-v1:0"
+        );
+        assert_eq!(
+            bedrock_model_id("claude-3-5-sonnet-20241022", "eu-central-1"),
+            "eu.anthropic.claude-3-5-sonnet-20241022-v2:0"
+        );
+        assert_eq!(
+            bedrock_model_id("claude-3-haiku-20240307", "ap-northeast-1"),
+            "apac.anthropic.claude-3-haiku-20240307-v1:0"
+        );
+        assert_eq!(
+            bedrock_model_id("claude-3-haiku-20240307", "ca-central-1"),
+            "anthropic.claude-3-haiku-20240307-v1:0"
+        );
+        // Bedrock IDs and ARNs pass through
+        assert_eq!(
+            bedrock_model_id("anthropic.claude-3-haiku-20240307-v1:0", "us-east-1"),
+            "anthropic.claude-3-haiku-20240307-v1:0"
+        );
+        let arn = "arn:aws:bedrock:us-east-1:123456789012:application-inference-profile/abc";
+        assert_eq!(bedrock_model_id(arn, "us-east-1"), arn);
+    }
+
+    #[tokio::test]
+    async fn test_bedrock_complete() {
+        use wiremock::{
+            matchers::{body_partial_json, header, method, path},
+            Mock, MockServer, Request, ResponseTemplate,
+        };
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/model/us.anthropic.claude-sonnet-4-20250514-v1%3A0/invoke"))
+            .and(header("accept", "application/json"))
+            .and(body_partial_json(json!({"anthropic_version": "bedrock-2023-05-31"})))
+            .and(|request: &Request| {
+                let body: Value = serde_json::from_slice(&request.body).unwrap();
+                let authorization = request.headers.get("authorization").unwrap().to_str().unwrap();
+                body.get("model").is_none()
+                    && body.get("stream").is_none()
+                    && authorization.starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/")
+                    && authorization.contains("/us-east-1/bedrock/aws4_request")
+                    && request.headers.contains_key("x-amz-date")
+            })
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "msg_bdrk_01",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-sonnet-4-20250514",
+                "content": [{"type": "text", "text": "Hello from Bedrock"}],
+                "stop_reason": "end_turn",
+                "usage": {"input_tokens": 12, "output_tokens": 6}
+            })))
+            .mount(&server)
+            .await;
+
+        let response = bedrock(&server.uri())
+            .complete(vec![Message::user("Hi")], Vec::new(), None, CompletionOptions::default())
+            .await
+            .unwrap();
+
+        assert!(matches!(&response.content[..], [ContentBlock::Text { text }] if text == "Hello from Bedrock"));
+        assert_eq!(response.stop_reason.as_deref(), Some("end_turn"));
+    }
+
+    #[tokio::test]
+    async fn test_bedrock_stream_fixtures() {
+        use futures::StreamExt;
+        use wiremock::{
+            matchers::{method, path},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let fixture = |name: &str| {
+            std::fs::read(format!("{}/tests/fixtures/bedrock/{name}", env!("CARGO_MANIFEST_DIR"))).unwrap()
+        };
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/model/us.anthropic.claude-sonnet-4-20250514-v1%3A0/invoke-with-response-stream"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_raw(fixture("invoke_with_response_stream.bin"), "application/vnd.amazon.eventstream"),
+            )
+            .mount(&server)
+            .await;
+
+        let chunks: Vec<CompletionChunk> = bedrock(&server.uri())
+            .stream_complete(vec![Message::user("Hi")], Vec::new(), None, CompletionOptions::default())
+            .await
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        let text: String = chunks
+            .iter()
+            .filter_map(|chunk| match chunk {
+                CompletionChunk::TextDelta { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(text, "Hello from Bedrock");
+        assert!(matches!(
+            chunks.last(),
+            Some(CompletionChunk::Done { stop_reason, usage: Some(usage) })
+                if stop_reason == "end_turn" && usage.output_tokens == 6
+        ));
+
+        // Exceptions raised mid-stream surface as API errors
+        let mut decoder = EventStreamDecoder::new();
+        let messages = decoder.push(&fixture("throttling_exception.bin")).unwrap();
+        assert!(bedrock_event_data(&messages[0]).unwrap().is_some());
+        let error = bedrock_event_data(&messages[1]).unwrap_err();
+        assert!(error.to_string().contains("throttlingException: Too many requests"));
+    }
+
+    /// A Vertex adapter whose token and model endpoints are `server`
+    fn vertex(server: &str, key_dir: &Path) -> VertexAdapter {
+        let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/vertex/service_account.json");
+        let mut key: Value = serde_json::from_str(&std::fs::read_to_string(fixture).unwrap()).unwrap();
+        key["token_uri"] = json!(format!("{server}/token"));
+        let key_file = key_dir.join("key.json");
+        std::fs::write(&key_file, key.to_string()).unwrap();
+
+        let mut profile = ModelProfile::new(
+            "vertex sonnet".to_string(),
+            ProviderType::Vertex,
+            "claude-sonnet-4-20250514".to_string(),
+            String::new(),
+            8192,
+            200_000,
+        );
+        profile.base_url = Some(server.to_string());
+        profile.region = Some("us-east5".to_string());
+        profile.credentials_file = Some(key_file.display().to_string());
+        VertexAdapter::new(profile).unwrap()
+    }
+
+    #[test]
+    fn test_vertex_model_id() {
+        assert_eq!(vertex_model_id("claude-sonnet-4-20250514"), "claude-sonnet-4@20250514");
+        assert_eq!(vertex_model_id("claude-3-5-sonnet-20241022"), "claude-3-5-sonnet-v2@20241022");
+        assert_eq!(vertex_model_id("claude-opus-4-1@20250805"), "claude-opus-4-1@20250805");
+        assert_eq!(vertex_model_id("claude-sonnet-4-5"), "claude-sonnet-4-5");
+    }
+
+    #[tokio::test]
+    async fn test_vertex_complete_and_stream() {
+        use futures::StreamExt;
+        use wiremock::{
+            matchers::{body_partial_json, body_string_contains, header, method, path},
+            Mock, MockServer, Request, ResponseTemplate,
+        };
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .and(body_string_contains("grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Ajwt-bearer"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "access_token": "ya29.test",
+                "expires_in": 3599,
+                "token_type": "Bearer"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let model_path = "/v1/projects/kode-test/locations/us-east5/publishers/anthropic/models/claude-sonnet-4@20250514";
+        Mock::given(method("POST"))
+            .and(path(format!("{model_path}:rawPredict")))
+            .and(header("authorization", "Bearer ya29.test"))
+            .and(body_partial_json(json!({"anthropic_version": "vertex-2023-10-16"})))
+            .and(|request: &Request| {
+                let body: Value = serde_json::from_slice(&request.body).unwrap();
+                body.get("model").is_none() && body.get("stream").is_none()
+            })
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "msg_vrtx_01",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-sonnet-4-20250514",
+                "content": [{"type": "text", "text": "Hello from Vertex"}],
+                "stop_reason": "end_turn",
+                "usage": {"input_tokens": 10, "output_tokens": 4}
+            })))
+            .mount(&server)
+            .await;
+        let events = [
+            json!({"type": "message_start", "message": {"id": "msg_vrtx_02", "type": "message", "role": "assistant", "model": "claude-sonnet-4-20250514", "content": [], "usage": {"input_tokens": 10, "output_tokens": 1}}}),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Streamed"}}),
+            json!({"type": "content_block_stop", "index": 0}),
+            json!({"type": "message_delta", "delta": {"stop_reason": "end_turn"}, "usage": {"output_tokens": 2}}),
+            json!({"type": "message_stop"}),
+        ];
+        let body = events
+            .iter()
+            .map(|event| format!("event: {}\ndata: {event}\n\n", event["type"].as_str().unwrap()))
+            .collect::<Vec<_>>()
+            .concat();
+        Mock::given(method("POST"))
+            .and(path(format!("{model_path}:streamRawPredict")))
+            .and(header("authorization", "Bearer ya29.test"))
+            .and(body_partial_json(json!({"stream": true})))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+            .mount(&server)
+            .await;
+
+        let key_dir = tempfile::tempdir().unwrap();
+        let adapter = vertex(&server.uri(), key_dir.path());
+
+        let response = adapter
+            .complete(vec![Message::user("Hi")], Vec::new(), None, CompletionOptions::default())
+            .await
+            .unwrap();
+        assert!(matches!(&response.content[..], [ContentBlock::Text { text }] if text == "Hello from Vertex"));
+
+        // The cached token is reused for the streaming request
+        let text: String = adapter
+            .stream_complete(vec![Message::user("Hi")], Vec::new(), None, CompletionOptions::default())
+            .await
+            .unwrap()
+            .filter_map(|chunk| async move {
+                match chunk.unwrap() {
+                    CompletionChunk::TextDelta { text } => Some(text),
+                    _ => None,
+                }
+            })
+            .collect()
+            .await;
+        assert_eq!(text, "Streamed");
+    }
+}
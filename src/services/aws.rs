@@ -0,0 +1,436 @@
+//! AWS credentials and Signature Version 4 request signing
+//!
+//! Credentials come from the standard environment variables, then from the
+//! shared `~/.aws/credentials` and `~/.aws/config` files. Only static keys
+//! (optionally with a session token) are supported; SSO and credential
+//! processes are not.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Write as _},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use ring::{digest, hmac};
+
+use crate::error::{KodeError, Result};
+
+/// Signing algorithm name
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+/// Static AWS credentials
+#[derive(Clone, PartialEq, Eq)]
+pub struct AwsCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+impl AwsCredentials {
+    /// Load credentials from the environment, then the shared files
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no credentials are found or a file cannot be read
+    pub fn load() -> Result<Self> {
+        if let Some(credentials) = Self::from_env() {
+            return Ok(credentials);
+        }
+        Self::from_files(&credentials_path(), &config_path(), &profile_name())?.ok_or_else(|| {
+            KodeError::InvalidConfig(
+                "No AWS credentials found; set AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY or configure ~/.aws/credentials"
+                    .to_string(),
+            )
+        })
+    }
+
+    /// Credentials from `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and
+    /// `AWS_SESSION_TOKEN`
+    #[must_use]
+    pub fn from_env() -> Option<Self> {
+        let access_key_id = std::env::var("AWS_ACCESS_KEY_ID").ok().filter(|v| !v.is_empty())?;
+        let secret_access_key = std::env::var("AWS_SECRET_ACCESS_KEY").ok().filter(|v| !v.is_empty())?;
+        Some(Self {
+            access_key_id,
+            secret_access_key,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok().filter(|v| !v.is_empty()),
+        })
+    }
+
+    /// Credentials of `profile` in the shared credentials file, falling back
+    /// to the config file
+    ///
+    /// Missing files are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a file exists but cannot be read
+    pub fn from_files(credentials_file: &Path, config_file: &Path, profile: &str) -> Result<Option<Self>> {
+        let credentials = read_ini(credentials_file)?;
+        let config = read_ini(config_file)?;
+        let sections = [
+            credentials.get(profile),
+            config.get(&config_section(profile)),
+        ];
+
+        let found = sections.into_iter().flatten().find_map(|section| {
+            Some(Self {
+                access_key_id: section.get("aws_access_key_id")?.clone(),
+                secret_access_key: section.get("aws_secret_access_key")?.clone(),
+                session_token: section.get("aws_session_token").cloned(),
+            })
+        });
+        Ok(found)
+    }
+}
+
+// The secret parts are kept out of logs
+impl fmt::Debug for AwsCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AwsCredentials")
+            .field("access_key_id", &self.access_key_id)
+            .field("secret_access_key", &"<redacted>")
+            .field("session_token", &self.session_token.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+/// Region from `AWS_REGION`, `AWS_DEFAULT_REGION` or the config file
+#[must_use]
+pub fn default_region() -> Option<String> {
+    std::env::var("AWS_REGION")
+        .or_else(|_| std::env::var("AWS_DEFAULT_REGION"))
+        .ok()
+        .filter(|region| !region.is_empty())
+        .or_else(|| region_from_config(&config_path(), &profile_name()))
+}
+
+/// Region of `profile` in a config file
+#[must_use]
+pub fn region_from_config(config_file: &Path, profile: &str) -> Option<String> {
+    read_ini(config_file)
+        .ok()?
+        .get(&config_section(profile))?
+        .get("region")
+        .cloned()
+}
+
+/// Profile selected by `AWS_PROFILE`
+fn profile_name() -> String {
+    std::env::var("AWS_PROFILE").unwrap_or_else(|_| "default".to_string())
+}
+
+/// Config files name every profile but the default one `profile <name>`
+fn config_section(profile: &str) -> String {
+    if profile == "default" {
+        profile.to_string()
+    } else {
+        format!("profile {profile}")
+    }
+}
+
+fn aws_dir() -> PathBuf {
+    dirs::home_dir().unwrap_or_default().join(".aws")
+}
+
+fn credentials_path() -> PathBuf {
+    std::env::var_os("AWS_SHARED_CREDENTIALS_FILE")
+        .map_or_else(|| aws_dir().join("credentials"), PathBuf::from)
+}
+
+fn config_path() -> PathBuf {
+    std::env::var_os("AWS_CONFIG_FILE").map_or_else(|| aws_dir().join("config"), PathBuf::from)
+}
+
+/// Read an INI file into sections of key-value pairs; a missing file is empty
+fn read_ini(path: &Path) -> Result<HashMap<String, HashMap<String, String>>> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => Ok(parse_ini(&text)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn parse_ini(text: &str) -> HashMap<String, HashMap<String, String>> {
+    let mut sections: HashMap<String, HashMap<String, String>> = HashMap::new();
+    let mut current = None;
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            let name = name.trim().to_string();
+            sections.entry(name.clone()).or_default();
+            current = Some(name);
+        } else if let (Some(section), Some((key, value))) = (&current, line.split_once('=')) {
+            sections
+                .entry(section.clone())
+                .or_default()
+                .insert(key.trim().to_string(), value.trim().to_string());
+        }
+    }
+    sections
+}
+
+/// Signs requests to one AWS service in one region
+pub struct RequestSigner<'a> {
+    pub credentials: &'a AwsCredentials,
+    pub region: &'a str,
+    pub service: &'a str,
+}
+
+impl RequestSigner<'_> {
+    /// Sign a request made at `time`
+    ///
+    /// `headers` are the headers to sign besides `host`, which is taken from
+    /// `url`. Returns the headers to add to the request: `x-amz-date`, the
+    /// session token if any, and `authorization`.
+    #[must_use]
+    pub fn sign(
+        &self,
+        method: &str,
+        url: &Url,
+        headers: &[(&str, &str)],
+        body: &[u8],
+        time: DateTime<Utc>,
+    ) -> Vec<(String, String)> {
+        let amz_date = time.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = &amz_date[..8];
+
+        let mut added = vec![("x-amz-date".to_string(), amz_date.clone())];
+        if let Some(token) = &self.credentials.session_token {
+            added.push(("x-amz-security-token".to_string(), token.clone()));
+        }
+
+        let host = match url.port() {
+            Some(port) => format!("{}:{port}", url.host_str().unwrap_or_default()),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let mut signed: Vec<(String, String)> = headers
+            .iter()
+            .map(|(name, value)| (name.to_lowercase(), normalize_header_value(value)))
+            .chain(std::iter::once(("host".to_string(), host)))
+            .chain(added.iter().cloned())
+            .collect();
+        signed.sort();
+
+        let canonical_headers = signed
+            .iter()
+            .map(|(name, value)| format!("{name}:{value}\n"))
+            .collect::<Vec<_>>()
+            .concat();
+        let signed_headers = signed
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+
+        let canonical_request = format!(
+            "{method}\n{}\n{}\n{canonical_headers}\n{signed_headers}\n{}",
+            canonical_uri(url),
+            canonical_query(url),
+            hex_sha256(body),
+        );
+        let scope = format!("{date}/{}/{}/aws4_request", self.region, self.service);
+        let string_to_sign = format!(
+            "{ALGORITHM}\n{amz_date}\n{scope}\n{}",
+            hex_sha256(canonical_request.as_bytes())
+        );
+
+        let key = [date, self.region, self.service, "aws4_request"].iter().fold(
+            format!("AWS4{}", self.credentials.secret_access_key).into_bytes(),
+            |key, part| hmac_sha256(&key, part.as_bytes()),
+        );
+        let signature = hex(&hmac_sha256(&key, string_to_sign.as_bytes()));
+
+        added.push((
+            "authorization".to_string(),
+            format!(
+                "{ALGORITHM} Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+                self.credentials.access_key_id
+            ),
+        ));
+        added
+    }
+}
+
+/// Percent-encode everything but RFC 3986 unreserved characters
+#[must_use]
+pub fn uri_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~') {
+            encoded.push(char::from(byte));
+        } else {
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+    }
+    encoded
+}
+
+/// The URL's path with every segment encoded once more, as all services
+/// but S3 expect
+fn canonical_uri(url: &Url) -> String {
+    let path = url
+        .path()
+        .split('/')
+        .map(uri_encode)
+        .collect::<Vec<_>>()
+        .join("/");
+    if path.is_empty() {
+        "/".to_string()
+    } else {
+        path
+    }
+}
+
+fn canonical_query(url: &Url) -> String {
+    let mut pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(key, value)| (uri_encode(&key), uri_encode(&value)))
+        .collect();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Trim the value and collapse runs of spaces
+fn normalize_header_value(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data)
+        .as_ref()
+        .to_vec()
+}
+
+fn hex_sha256(data: &[u8]) -> String {
+    hex(digest::digest(&digest::SHA256, data).as_ref())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect::<Vec<_>>().concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn example_credentials() -> AwsCredentials {
+        AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None,
+        }
+    }
+
+    #[test]
+    fn test_sign_matches_aws_example() {
+        // The IAM ListUsers example from the Signature Version 4 documentation
+        let credentials = example_credentials();
+        let signer = RequestSigner {
+            credentials: &credentials,
+            region: "us-east-1",
+            service: "iam",
+        };
+        let url = Url::parse("https://iam.amazonaws.com/?Action=ListUsers&Version=2010-05-08").unwrap();
+        let time = Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap();
+
+        let headers = signer.sign(
+            "GET",
+            &url,
+            &[("Content-Type", "application/x-www-form-urlencoded; charset=utf-8")],
+            b"",
+            time,
+        );
+
+        assert_eq!(headers[0], ("x-amz-date".to_string(), "20150830T123600Z".to_string()));
+        assert_eq!(
+            headers[1].1,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/iam/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date, \
+             Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+        );
+    }
+
+    #[test]
+    fn test_canonical_uri_encodes_twice() {
+        let url = Url::parse("https://bedrock-runtime.us-east-1.amazonaws.com/model/anthropic.claude-v1%3A0/invoke")
+            .unwrap();
+        assert_eq!(canonical_uri(&url), "/model/anthropic.claude-v1%253A0/invoke");
+    }
+
+    #[test]
+    fn test_session_token_is_signed() {
+        let credentials = AwsCredentials {
+            session_token: Some("token".to_string()),
+            ..example_credentials()
+        };
+        let signer = RequestSigner {
+            credentials: &credentials,
+            region: "us-west-2",
+            service: "bedrock",
+        };
+        let url = Url::parse("https://bedrock-runtime.us-west-2.amazonaws.com/model/m/invoke").unwrap();
+
+        let headers = signer.sign("POST", &url, &[], b"{}", Utc::now());
+
+        assert_eq!(headers[1], ("x-amz-security-token".to_string(), "token".to_string()));
+        assert!(headers[2].1.contains("SignedHeaders=host;x-amz-date;x-amz-security-token,"));
+    }
+
+    #[test]
+    fn test_debug_redacts_secrets() {
+        let credentials = AwsCredentials {
+            session_token: Some("session-secret".to_string()),
+            ..example_credentials()
+        };
+        let debug = format!("{credentials:?}");
+        assert!(debug.contains("AKIDEXAMPLE"));
+        assert!(!debug.contains("wJalrXUtnFEMI"));
+        assert!(!debug.contains("session-secret"));
+    }
+
+    #[test]
+    fn test_profile_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let credentials_file = dir.path().join("credentials");
+        let config_file = dir.path().join("config");
+        std::fs::write(
+            &credentials_file,
+            "[default]\naws_access_key_id = AKIDDEFAULT\naws_secret_access_key = secret\n",
+        )
+        .unwrap();
+        std::fs::write(
+            &config_file,
+            "[default]\nregion = eu-west-1\n\n[profile work]\nregion = us-west-2\n\
+             aws_access_key_id=AKIDWORK\naws_secret_access_key=worksecret\naws_session_token=session\n",
+        )
+        .unwrap();
+
+        let default = AwsCredentials::from_files(&credentials_file, &config_file, "default")
+            .unwrap()
+            .unwrap();
+        assert_eq!(default.access_key_id, "AKIDDEFAULT");
+        assert_eq!(default.session_token, None);
+
+        let work = AwsCredentials::from_files(&credentials_file, &config_file, "work")
+            .unwrap()
+            .unwrap();
+        assert_eq!(work.access_key_id, "AKIDWORK");
+        assert_eq!(work.session_token.as_deref(), Some("session"));
+        assert_eq!(region_from_config(&config_file, "work").as_deref(), Some("us-west-2"));
+        assert_eq!(region_from_config(&config_file, "default").as_deref(), Some("eu-west-1"));
+
+        let missing = dir.path().join("missing");
+        assert_eq!(AwsCredentials::from_files(&missing, &missing, "default").unwrap(), None);
+    }
+}
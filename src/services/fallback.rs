@@ -0,0 +1,383 @@
+//! Failing over to fallback models
+//!
+//! A model pointer may list fallback profiles to use when its model is
+//! overloaded, rate limited, unreachable or handed a request longer than its
+//! context window. [`FallbackAdapter`] tries the chain in order and, after
+//! failing over, stays on the model that answered for a cooldown period
+//! before giving the primary another chance.
+//!
+//! A streamed request only fails over while nothing but retry notices has
+//! been received; once content has been streamed the error is returned.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use futures::StreamExt;
+
+use super::{
+    CompletionChunk, CompletionOptions, CompletionResponse, CompletionStream, ModelAdapter,
+    ToolSchema,
+};
+use crate::{
+    cost::ModelPricing,
+    error::{KodeError, Result},
+    messages::Message,
+};
+
+/// How long a fallback model is kept unless the configuration says otherwise
+pub const DEFAULT_FALLBACK_COOLDOWN: Duration = Duration::from_secs(5 * 60);
+
+/// Phrases of the errors providers return for requests exceeding the context
+/// window, lowercased
+const CONTEXT_ERROR_PATTERNS: &[&str] = &[
+    "prompt is too long",
+    "context_length_exceeded",
+    "maximum context length",
+    "context window",
+    "input is too long",
+    "exceeds the maximum number of tokens",
+    "too many tokens",
+];
+
+/// Which model of the chain is preferred
+#[derive(Debug, Default)]
+struct FallbackState {
+    /// Index of the model tried first
+    active: usize,
+
+    /// When the primary model is tried again
+    until: Option<Instant>,
+
+    /// Why the chain moved past the primary
+    reason: String,
+}
+
+/// Adapter trying a chain of models in order
+pub struct FallbackAdapter {
+    /// The primary model followed by its fallbacks
+    adapters: Vec<Arc<dyn ModelAdapter>>,
+
+    /// How long a fallback is preferred after a failover
+    cooldown: Duration,
+
+    state: Arc<Mutex<FallbackState>>,
+}
+
+impl FallbackAdapter {
+    /// Create an adapter for `primary` failing over to `fallbacks` in order
+    #[must_use]
+    pub fn new(
+        primary: Box<dyn ModelAdapter>,
+        fallbacks: Vec<Box<dyn ModelAdapter>>,
+        cooldown: Duration,
+    ) -> Self {
+        Self {
+            adapters: std::iter::once(primary)
+                .chain(fallbacks)
+                .map(Arc::from)
+                .collect(),
+            cooldown,
+            state: Arc::new(Mutex::new(FallbackState::default())),
+        }
+    }
+
+    /// Index of the model to try first, returning to the primary once the
+    /// cooldown has passed
+    fn start_index(&self) -> usize {
+        let mut state = lock(&self.state);
+        if state.until.is_some_and(|until| Instant::now() >= until) {
+            *state = FallbackState::default();
+        }
+        state.active
+    }
+
+    /// The model currently preferred
+    fn active(&self) -> &dyn ModelAdapter {
+        let index = lock(&self.state).active;
+        self.adapters[index].as_ref()
+    }
+}
+
+fn lock(state: &Mutex<FallbackState>) -> std::sync::MutexGuard<'_, FallbackState> {
+    // The state stays consistent even if a holder panicked
+    state.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+/// Prefer the model at `index` until the cooldown passes
+fn fail_over(state: &Mutex<FallbackState>, index: usize, cooldown: Duration, reason: &str) {
+    let mut state = lock(state);
+    state.active = index;
+    state.until = Some(Instant::now() + cooldown);
+    state.reason = reason.to_string();
+}
+
+/// Why `error` warrants trying the next model, or `None` if it does not
+///
+/// Requests that exhausted their retries on rate limits, overload, server
+/// errors or network failures fail over, as do requests exceeding the
+/// context window. Other errors, such as invalid requests or bad
+/// credentials, would fail on every model alike.
+#[must_use]
+pub fn failover_reason(error: &KodeError) -> Option<String> {
+    match error {
+        KodeError::ApiError { message, .. } => {
+            let lower = message.to_lowercase();
+            if CONTEXT_ERROR_PATTERNS.iter().any(|pattern| lower.contains(pattern)) {
+                return Some("context length exceeded".to_string());
+            }
+            // Failed HTTP requests are reported as "HTTP <status>: <body>"
+            let status = message
+                .strip_prefix("HTTP ")
+                .and_then(|rest| rest.get(..3))
+                .and_then(|code| code.parse::<u16>().ok());
+            match status {
+                Some(429) => Some("rate limited".to_string()),
+                Some(529) => Some("overloaded".to_string()),
+                Some(code @ (408 | 409 | 500..=599)) => Some(format!("HTTP {code}")),
+                // Errors reported inside a stream
+                None if lower.contains("overloaded") => Some("overloaded".to_string()),
+                None if lower.contains("rate limit") || lower.contains("rate_limit") => {
+                    Some("rate limited".to_string())
+                }
+                _ => None,
+            }
+        }
+        KodeError::NetworkError(_) => Some("network error".to_string()),
+        KodeError::Http(e) if e.is_connect() || e.is_timeout() => Some("network error".to_string()),
+        _ => None,
+    }
+}
+
+#[async_trait]
+impl ModelAdapter for FallbackAdapter {
+    fn provider(&self) -> &str {
+        self.adapters[0].provider()
+    }
+
+    fn model(&self) -> &str {
+        self.adapters[0].model()
+    }
+
+    async fn complete(
+        &self,
+        messages: Vec<Message>,
+        tools: Vec<ToolSchema>,
+        system_prompt: Option<String>,
+        options: CompletionOptions,
+    ) -> Result<CompletionResponse> {
+        let mut index = self.start_index();
+        loop {
+            let error = match self.adapters[index]
+                .complete(messages.clone(), tools.clone(), system_prompt.clone(), options.clone())
+                .await
+            {
+                Ok(response) => return Ok(response),
+                Err(e) => e,
+            };
+            match failover_reason(&error) {
+                Some(reason) if index + 1 < self.adapters.len() => {
+                    let reason = format!("{} failed: {reason}", self.adapters[index].model());
+                    index += 1;
+                    tracing::warn!("{reason}, falling back to {}", self.adapters[index].model());
+                    fail_over(&self.state, index, self.cooldown, &reason);
+                }
+                _ => return Err(error),
+            }
+        }
+    }
+
+    async fn stream_complete(
+        &self,
+        messages: Vec<Message>,
+        tools: Vec<ToolSchema>,
+        system_prompt: Option<String>,
+        options: CompletionOptions,
+    ) -> Result<CompletionStream> {
+        let mut index = self.start_index();
+        let adapters = self.adapters.clone();
+        let state = self.state.clone();
+        let cooldown = self.cooldown;
+
+        Ok(Box::pin(async_stream::try_stream! {
+            if index > 0 {
+                let reason = lock(&state).reason.clone();
+                yield CompletionChunk::Fallback {
+                    model: adapters[index].model().to_string(),
+                    reason,
+                };
+            }
+
+            loop {
+                let adapter = &adapters[index];
+                let error = match adapter
+                    .stream_complete(messages.clone(), tools.clone(), system_prompt.clone(), options.clone())
+                    .await
+                {
+                    Err(e) => e,
+                    Ok(mut stream) => {
+                        let mut started = false;
+                        let mut error = None;
+                        while let Some(chunk) = stream.next().await {
+                            match chunk {
+                                Ok(CompletionChunk::Error { message }) if !started => {
+                                    error = Some(KodeError::ApiError {
+                                        provider: adapter.provider().to_string(),
+                                        message,
+                                    });
+                                    break;
+                                }
+                                Err(e) if !started => {
+                                    error = Some(e);
+                                    break;
+                                }
+                                chunk => {
+                                    let chunk = chunk?;
+                                    started |= !matches!(chunk, CompletionChunk::Retrying { .. });
+                                    yield chunk;
+                                }
+                            }
+                        }
+                        match error {
+                            Some(e) => e,
+                            None => return,
+                        }
+                    }
+                };
+
+                match failover_reason(&error) {
+                    Some(reason) if index + 1 < adapters.len() => {
+                        let reason = format!("{} failed: {reason}", adapter.model());
+                        index += 1;
+                        fail_over(&state, index, cooldown, &reason);
+                        yield CompletionChunk::Fallback {
+                            model: adapters[index].model().to_string(),
+                            reason,
+                        };
+                    }
+                    _ => Err(error)?,
+                }
+            }
+        }))
+    }
+
+    fn pricing(&self) -> Option<ModelPricing> {
+        self.active().pricing()
+    }
+
+    fn max_context_tokens(&self) -> u32 {
+        self.active().max_context_tokens()
+    }
+
+    fn max_output_tokens(&self) -> u32 {
+        self.active().max_output_tokens()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::testing::ScriptedAdapter;
+
+    fn answer(text: &str) -> Vec<CompletionChunk> {
+        vec![
+            CompletionChunk::TextDelta {
+                text: text.to_string(),
+            },
+            CompletionChunk::Done {
+                stop_reason: "end_turn".to_string(),
+                usage: None,
+            },
+        ]
+    }
+
+    fn overloaded() -> Vec<CompletionChunk> {
+        vec![CompletionChunk::Error {
+            message: "Overloaded".to_string(),
+        }]
+    }
+
+    async fn collect(adapter: &FallbackAdapter) -> Result<Vec<CompletionChunk>> {
+        let mut stream = adapter
+            .stream_complete(vec![Message::user("Hi")], Vec::new(), None, CompletionOptions::default())
+            .await?;
+        let mut chunks = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            chunks.push(chunk?);
+        }
+        Ok(chunks)
+    }
+
+    #[test]
+    fn test_failover_reasons() {
+        let api = |message: &str| KodeError::ApiError {
+            provider: "anthropic".to_string(),
+            message: message.to_string(),
+        };
+        assert_eq!(
+            failover_reason(&api("HTTP 529 <unknown status code>: overloaded")).as_deref(),
+            Some("overloaded")
+        );
+        assert_eq!(
+            failover_reason(&api("HTTP 429 Too Many Requests: slow down")).as_deref(),
+            Some("rate limited")
+        );
+        assert_eq!(
+            failover_reason(&api("HTTP 400 Bad Request: prompt is too long: 210000 tokens > 200000 maximum")).as_deref(),
+            Some("context length exceeded")
+        );
+        assert_eq!(failover_reason(&api("HTTP 401 Unauthorized: invalid x-api-key")), None);
+        assert_eq!(failover_reason(&KodeError::Cancelled), None);
+    }
+
+    #[tokio::test]
+    async fn test_stream_fails_over_and_cools_down() {
+        let primary = ScriptedAdapter::new(vec![overloaded(), answer("primary")]);
+        let fallback = ScriptedAdapter::new(vec![answer("fallback"), answer("still fallback")]);
+        let adapter = FallbackAdapter::new(Box::new(primary), vec![Box::new(fallback)], Duration::from_millis(50));
+
+        let chunks = collect(&adapter).await.unwrap();
+        assert!(matches!(&chunks[0], CompletionChunk::Fallback { reason, .. } if reason.contains("overloaded")));
+        assert!(matches!(&chunks[1], CompletionChunk::TextDelta { text } if text == "fallback"));
+
+        // The fallback keeps answering during the cooldown
+        let chunks = collect(&adapter).await.unwrap();
+        assert!(matches!(&chunks[0], CompletionChunk::Fallback { .. }));
+        assert!(matches!(&chunks[1], CompletionChunk::TextDelta { text } if text == "still fallback"));
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let chunks = collect(&adapter).await.unwrap();
+        assert!(matches!(&chunks[0], CompletionChunk::TextDelta { text } if text == "primary"));
+    }
+
+    #[tokio::test]
+    async fn test_last_error_is_returned() {
+        let primary = ScriptedAdapter::new(vec![overloaded()]);
+        let fallback = ScriptedAdapter::new(vec![overloaded()]);
+        let adapter = FallbackAdapter::new(Box::new(primary), vec![Box::new(fallback)], DEFAULT_FALLBACK_COOLDOWN);
+
+        let error = collect(&adapter).await.unwrap_err();
+        assert!(error.to_string().contains("Overloaded"));
+    }
+
+    #[tokio::test]
+    async fn test_no_failover_after_content() {
+        let primary = ScriptedAdapter::new(vec![vec![
+            CompletionChunk::TextDelta {
+                text: "partial".to_string(),
+            },
+            CompletionChunk::Error {
+                message: "Overloaded".to_string(),
+            },
+        ]]);
+        let fallback = ScriptedAdapter::new(vec![answer("fallback")]);
+        let adapter = FallbackAdapter::new(Box::new(primary), vec![Box::new(fallback)], DEFAULT_FALLBACK_COOLDOWN);
+
+        // The error chunk is passed on for the caller to report
+        let chunks = collect(&adapter).await.unwrap();
+        assert!(matches!(&chunks[1], CompletionChunk::Error { .. }));
+        assert!(!chunks.iter().any(|chunk| matches!(chunk, CompletionChunk::Fallback { .. })));
+    }
+}
@@ -0,0 +1,777 @@
+//! Google Gemini API adapter
+//!
+//! Talks to the native `generateContent` and `streamGenerateContent`
+//! endpoints. Gemini accepts only a subset of JSON Schema for function
+//! declarations, so tool schemas are trimmed down before they are sent.
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::{Stream, StreamExt};
+use reqwest::{header, Client};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use crate::{
+    config::{
+        capabilities::ToolCallingMode,
+        models::{ModelProfile, ProviderType, ReasoningEffort},
+    },
+    cost::ModelPricing,
+    error::{KodeError, Result},
+    messages::{ContentBlock, ImageSource, Message, Role},
+};
+
+use super::{
+    retry,
+    streaming::{
+        gemini_stop_reason, GeminiContent, GeminiFunctionCall, GeminiFunctionResponse,
+        GeminiInlineData, GeminiPart, GeminiResponse, GeminiStreamHandler,
+    },
+    CompletionChunk, CompletionOptions, CompletionResponse, CompletionStream, ModelAdapter,
+    ToolSchema,
+};
+
+/// Largest thinking budget every Gemini 2.5 model accepts
+const MAX_THINKING_BUDGET: u32 = 24_576;
+
+/// Schema keywords Gemini understands; everything else is dropped
+const SCHEMA_KEYWORDS: &[&str] = &[
+    "description",
+    "enum",
+    "nullable",
+    "required",
+    "minItems",
+    "maxItems",
+    "minimum",
+    "maximum",
+    "minLength",
+    "maxLength",
+    "pattern",
+    "title",
+];
+
+/// String and number formats Gemini accepts
+const SCHEMA_FORMATS: &[&str] = &["enum", "date-time", "int32", "int64", "float", "double"];
+
+/// Gemini API adapter
+pub struct GeminiAdapter {
+    client: Client,
+    profile: ModelProfile,
+    base_url: String,
+}
+
+impl GeminiAdapter {
+    /// Create a new Gemini adapter
+    ///
+    /// The API key comes from the profile, then `GEMINI_API_KEY`, then
+    /// `GOOGLE_API_KEY`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no API key is set or it is not a valid header value.
+    pub fn new(profile: ModelProfile) -> Result<Self> {
+        let api_key = if profile.api_key.is_empty() {
+            std::env::var("GEMINI_API_KEY")
+                .or_else(|_| std::env::var("GOOGLE_API_KEY"))
+                .map_err(|_| KodeError::MissingApiKey {
+                    provider: "gemini".to_string(),
+                })?
+        } else {
+            profile.api_key.clone()
+        };
+
+        let base_url = profile
+            .base_url
+            .clone()
+            .or_else(|| ProviderType::Gemini.default_base_url().map(str::to_string))
+            .unwrap_or_default()
+            .trim_end_matches('/')
+            .to_string();
+
+        let client = Client::builder()
+            .default_headers({
+                let mut headers = header::HeaderMap::new();
+                headers.insert(
+                    "x-goog-api-key",
+                    header::HeaderValue::from_str(&api_key).map_err(|_| {
+                        KodeError::InvalidConfig("Invalid API key format".to_string())
+                    })?,
+                );
+                headers
+            })
+            .build()?;
+
+        Ok(Self {
+            client,
+            profile,
+            base_url,
+        })
+    }
+
+    /// URL of a model method, such as `generateContent`
+    fn endpoint(&self, method: &str) -> String {
+        let model = self.profile.model_name.trim_start_matches("models/");
+        format!("{}/models/{model}:{method}", self.base_url)
+    }
+
+    /// Convert internal messages to Gemini contents
+    ///
+    /// Function responses are matched to their calls by name, so the name of
+    /// every tool call is remembered. System messages join the system
+    /// instruction, which is returned alongside the contents.
+    fn convert_messages(messages: Vec<Message>) -> (Vec<GeminiContent>, Vec<GeminiPart>) {
+        let mut contents = Vec::new();
+        let mut system = Vec::new();
+        let mut call_names = HashMap::new();
+
+        for msg in messages {
+            // Function responses lead their turn, followed by any images they returned
+            let mut responses = Vec::new();
+            let mut tool_images = Vec::new();
+            let mut parts = Vec::new();
+            for block in msg.content {
+                match block {
+                    ContentBlock::Text { text } => parts.push(text_part(text)),
+                    ContentBlock::Image { source } => parts.push(image_part(&source)),
+                    ContentBlock::ToolUse { id, name, input } => {
+                        call_names.insert(id, name.clone());
+                        parts.push(GeminiPart {
+                            function_call: Some(GeminiFunctionCall {
+                                id: None,
+                                name,
+                                args: input,
+                            }),
+                            ..GeminiPart::default()
+                        });
+                    }
+                    ContentBlock::ToolResult {
+                        tool_use_id,
+                        content,
+                        is_error,
+                        images,
+                    } => {
+                        if !images.is_empty() {
+                            tool_images.push(text_part(format!(
+                                "Images returned by tool call {tool_use_id}:"
+                            )));
+                            tool_images.extend(images.iter().map(image_part));
+                        }
+                        let response = if is_error == Some(true) {
+                            json!({ "error": content })
+                        } else {
+                            json!({ "result": content })
+                        };
+                        responses.push(GeminiPart {
+                            function_response: Some(GeminiFunctionResponse {
+                                name: call_names.get(&tool_use_id).cloned().unwrap_or(tool_use_id),
+                                response,
+                            }),
+                            ..GeminiPart::default()
+                        });
+                    }
+                    // Other providers' thinking cannot be passed to Gemini
+                    ContentBlock::Thinking { .. }
+                    | ContentBlock::RedactedThinking { .. }
+                    | ContentBlock::Reasoning { .. } => {}
+                }
+            }
+
+            responses.append(&mut tool_images);
+            responses.append(&mut parts);
+            if responses.is_empty() {
+                continue;
+            }
+            let role = match msg.role {
+                Role::User => "user",
+                Role::Assistant => "model",
+                Role::System => {
+                    system.append(&mut responses);
+                    continue;
+                }
+            };
+            contents.push(GeminiContent {
+                role: Some(role.to_string()),
+                parts: responses,
+            });
+        }
+        (contents, system)
+    }
+
+    /// Build a request body
+    fn build_request(
+        &self,
+        messages: Vec<Message>,
+        tools: Vec<ToolSchema>,
+        system_prompt: Option<String>,
+        options: CompletionOptions,
+    ) -> GeminiRequest {
+        let capabilities = self.profile.capabilities();
+        let messages = if capabilities.vision {
+            messages
+        } else {
+            messages.into_iter().map(Message::without_images).collect()
+        };
+        let (contents, mut system) = Self::convert_messages(messages);
+        if let Some(prompt) = system_prompt {
+            system.insert(0, text_part(prompt));
+        }
+
+        let tools = match capabilities.tool_calling {
+            ToolCallingMode::Native => tools,
+            ToolCallingMode::Text | ToolCallingMode::None => Vec::new(),
+        };
+        let declarations: Vec<GeminiFunctionDeclaration> = tools
+            .into_iter()
+            .map(|tool| {
+                let parameters = sanitize_schema(&tool.input_schema);
+                GeminiFunctionDeclaration {
+                    name: tool.name,
+                    description: tool.description,
+                    // Gemini rejects object schemas without properties
+                    parameters: parameters.get("properties").is_some().then_some(parameters),
+                }
+            })
+            .collect();
+
+        let budget = options
+            .thinking_budget
+            .or_else(|| self.profile.reasoning_effort.map(ReasoningEffort::thinking_budget))
+            .filter(|_| capabilities.reasoning);
+        let (temperature, top_p) = if capabilities.temperature {
+            (options.temperature, options.top_p)
+        } else {
+            (None, None)
+        };
+
+        GeminiRequest {
+            contents,
+            system_instruction: (!system.is_empty()).then_some(GeminiContent {
+                role: None,
+                parts: system,
+            }),
+            tools: if declarations.is_empty() {
+                Vec::new()
+            } else {
+                vec![GeminiTool {
+                    function_declarations: declarations,
+                }]
+            },
+            generation_config: GeminiGenerationConfig {
+                max_output_tokens: Some(options.max_tokens.unwrap_or(self.profile.max_tokens)),
+                temperature,
+                top_p,
+                stop_sequences: options.stop_sequences,
+                thinking_config: budget.map(|budget| GeminiThinkingConfig {
+                    thinking_budget: budget.min(MAX_THINKING_BUDGET),
+                    include_thoughts: true,
+                }),
+            },
+        }
+    }
+
+    /// Process SSE byte stream into `CompletionChunk`s
+    ///
+    /// Chunks are yielded as soon as each event is parsed.
+    fn process_stream(
+        byte_stream: impl Stream<Item = reqwest::Result<Bytes>> + Send + 'static,
+    ) -> impl Stream<Item = Result<CompletionChunk>> + Send + 'static {
+        async_stream::stream! {
+            let mut handler = GeminiStreamHandler::new();
+            let mut byte_stream = Box::pin(byte_stream);
+
+            while let Some(chunk_result) = byte_stream.next().await {
+                let bytes = match chunk_result {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        yield Err(KodeError::NetworkError(e.to_string()));
+                        return;
+                    }
+                };
+
+                match handler.process_bytes(&bytes) {
+                    Ok(chunks) => {
+                        for chunk in chunks {
+                            yield Ok(chunk);
+                        }
+                    }
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                }
+            }
+
+            // Gemini ends the stream by closing the connection
+            match handler.finish() {
+                Ok(chunks) => {
+                    for chunk in chunks {
+                        yield Ok(chunk);
+                    }
+                }
+                Err(e) => yield Err(e),
+            }
+        }
+    }
+}
+
+/// A text part
+fn text_part(text: String) -> GeminiPart {
+    GeminiPart {
+        text: Some(text),
+        ..GeminiPart::default()
+    }
+}
+
+/// Inline images become `inlineData`; uploaded files cannot be referenced
+fn image_part(source: &ImageSource) -> GeminiPart {
+    match source {
+        ImageSource::Base64 { media_type, data } => GeminiPart {
+            inline_data: Some(GeminiInlineData {
+                mime_type: media_type.clone(),
+                data: data.clone(),
+            }),
+            ..GeminiPart::default()
+        },
+        ImageSource::File { file_id } => text_part(format!("[Image file {file_id}]")),
+    }
+}
+
+/// Trim a JSON schema down to the subset Gemini accepts
+///
+/// Unknown keywords are dropped, `"type": [T, "null"]` becomes a nullable
+/// `T`, `const` becomes a one-value `enum`, and `required` only keeps
+/// properties that exist.
+#[must_use]
+pub fn sanitize_schema(schema: &Value) -> Value {
+    let Value::Object(map) = schema else {
+        return schema.clone();
+    };
+
+    let mut out = Map::new();
+    for (key, value) in map {
+        match (key.as_str(), value) {
+            ("type", Value::Array(types)) => {
+                let mut kinds = types.iter().filter(|kind| kind.as_str() != Some("null"));
+                if let Some(kind) = kinds.next() {
+                    out.insert("type".to_string(), kind.clone());
+                }
+                if types.iter().any(|kind| kind.as_str() == Some("null")) {
+                    out.insert("nullable".to_string(), Value::Bool(true));
+                }
+            }
+            ("type", _) => {
+                out.insert(key.clone(), value.clone());
+            }
+            ("format", Value::String(format)) if SCHEMA_FORMATS.contains(&format.as_str()) => {
+                out.insert(key.clone(), value.clone());
+            }
+            ("properties", Value::Object(properties)) if !properties.is_empty() => {
+                let properties = properties
+                    .iter()
+                    .map(|(name, property)| (name.clone(), sanitize_schema(property)))
+                    .collect();
+                out.insert(key.clone(), Value::Object(properties));
+            }
+            ("items", _) => {
+                out.insert(key.clone(), sanitize_schema(value));
+            }
+            ("anyOf", Value::Array(variants)) => {
+                out.insert(key.clone(), variants.iter().map(sanitize_schema).collect());
+            }
+            ("const", _) => {
+                out.insert("enum".to_string(), json!([value]));
+            }
+            (key, _) if SCHEMA_KEYWORDS.contains(&key) => {
+                out.insert(key.to_string(), value.clone());
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(Value::Array(required)) = out.get("required") {
+        let required: Vec<Value> = required
+            .iter()
+            .filter(|name| {
+                name.as_str()
+                    .is_some_and(|name| out.get("properties").and_then(|p| p.get(name)).is_some())
+            })
+            .cloned()
+            .collect();
+        if required.is_empty() {
+            out.remove("required");
+        } else {
+            out.insert("required".to_string(), Value::Array(required));
+        }
+    }
+
+    Value::Object(out)
+}
+
+/// Convert a response's first candidate to content blocks
+fn convert_response(response: GeminiResponse) -> Result<(Vec<ContentBlock>, Option<String>)> {
+    let Some(candidate) = response.candidates.into_iter().next() else {
+        let reason = response
+            .prompt_feedback
+            .and_then(|feedback| feedback.block_reason)
+            .unwrap_or_else(|| "no candidates returned".to_string());
+        return Err(KodeError::ApiError {
+            provider: "gemini".to_string(),
+            message: format!("Prompt blocked: {reason}"),
+        });
+    };
+
+    let mut content = Vec::new();
+    for part in candidate.content.map(|content| content.parts).unwrap_or_default() {
+        if let Some(text) = part.text.filter(|text| !text.is_empty()) {
+            content.push(if part.thought == Some(true) {
+                ContentBlock::Thinking {
+                    thinking: text,
+                    signature: None,
+                }
+            } else {
+                ContentBlock::Text { text }
+            });
+        }
+        if let Some(call) = part.function_call {
+            content.push(ContentBlock::ToolUse {
+                id: call.call_id(),
+                input: call.input(),
+                name: call.name,
+            });
+        }
+    }
+    Ok((content, candidate.finish_reason))
+}
+
+#[async_trait]
+impl ModelAdapter for GeminiAdapter {
+    fn provider(&self) -> &str {
+        "gemini"
+    }
+
+    fn model(&self) -> &str {
+        &self.profile.model_name
+    }
+
+    async fn complete(
+        &self,
+        messages: Vec<Message>,
+        tools: Vec<ToolSchema>,
+        system_prompt: Option<String>,
+        options: CompletionOptions,
+    ) -> Result<CompletionResponse> {
+        let request = self.build_request(messages, tools, system_prompt, options);
+
+        let request = self
+            .client
+            .post(self.endpoint("generateContent"))
+            .json(&request);
+        let response = retry::send(&self.profile.retry_policy(), "gemini", request).await?;
+
+        let mut api_response: GeminiResponse = response.json().await?;
+        let usage = api_response.usage_metadata.take().map(Into::into);
+        let model = api_response.model_version.take();
+        let (content, finish_reason) = convert_response(api_response)?;
+        let has_tool_calls = content
+            .iter()
+            .any(|block| matches!(block, ContentBlock::ToolUse { .. }));
+
+        Ok(CompletionResponse {
+            content,
+            stop_reason: Some(gemini_stop_reason(finish_reason.as_deref(), has_tool_calls)),
+            model: model.or_else(|| Some(self.profile.model_name.clone())),
+            usage,
+        })
+    }
+
+    async fn stream_complete(
+        &self,
+        messages: Vec<Message>,
+        tools: Vec<ToolSchema>,
+        system_prompt: Option<String>,
+        options: CompletionOptions,
+    ) -> Result<CompletionStream> {
+        let request = self.build_request(messages, tools, system_prompt, options);
+
+        let request = self
+            .client
+            .post(self.endpoint("streamGenerateContent"))
+            .query(&[("alt", "sse")])
+            .json(&request);
+        Ok(retry::stream(self.profile.retry_policy(), "gemini", request, |response| {
+            Self::process_stream(response.bytes_stream())
+        }))
+    }
+
+    fn pricing(&self) -> Option<ModelPricing> {
+        self.profile.pricing()
+    }
+
+    fn max_context_tokens(&self) -> u32 {
+        self.profile.context_length
+    }
+
+    fn max_output_tokens(&self) -> u32 {
+        self.profile.max_tokens
+    }
+}
+
+// Gemini API types
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiRequest {
+    contents: Vec<GeminiContent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<GeminiContent>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<GeminiTool>,
+    generation_config: GeminiGenerationConfig,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiTool {
+    function_declarations: Vec<GeminiFunctionDeclaration>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct GeminiFunctionDeclaration {
+    name: String,
+    description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parameters: Option<Value>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiGenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_sequences: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking_config: Option<GeminiThinkingConfig>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiThinkingConfig {
+    thinking_budget: u32,
+    include_thoughts: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{body_partial_json, header, method, path, query_param},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    fn adapter(base_url: &str) -> GeminiAdapter {
+        let mut profile = ModelProfile::new(
+            "Gemini 2.5 Pro".to_string(),
+            ProviderType::Gemini,
+            "gemini-2.5-pro".to_string(),
+            "test-key".to_string(),
+            8192,
+            1_048_576,
+        );
+        profile.base_url = Some(base_url.to_string());
+        GeminiAdapter::new(profile).unwrap()
+    }
+
+    /// A turn where the model called a tool and got its result
+    fn tool_turn() -> Vec<Message> {
+        vec![
+            Message::user("List the files"),
+            Message {
+                role: Role::Assistant,
+                content: vec![ContentBlock::ToolUse {
+                    id: "call_1".to_string(),
+                    name: "Bash".to_string(),
+                    input: json!({"command": "ls"}),
+                }],
+                uuid: None,
+            },
+            Message {
+                role: Role::User,
+                content: vec![ContentBlock::ToolResult {
+                    tool_use_id: "call_1".to_string(),
+                    content: "src".to_string(),
+                    is_error: None,
+                    images: Vec::new(),
+                }],
+                uuid: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_sanitize_schema() {
+        let schema = json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "type": "object",
+            "additionalProperties": false,
+            "properties": {
+                "path": {"type": "string", "format": "uri", "description": "File path"},
+                "limit": {"type": ["integer", "null"], "default": 10},
+                "mode": {"const": "fast"},
+                "tags": {"type": "array", "items": {"type": "string", "examples": ["a"]}}
+            },
+            "required": ["path", "missing"]
+        });
+
+        assert_eq!(
+            sanitize_schema(&schema),
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": {"type": "string", "description": "File path"},
+                    "limit": {"type": "integer", "nullable": true},
+                    "mode": {"enum": ["fast"]},
+                    "tags": {"type": "array", "items": {"type": "string"}}
+                },
+                "required": ["path"]
+            })
+        );
+        assert_eq!(sanitize_schema(&json!({"type": "object", "properties": {}})), json!({"type": "object"}));
+    }
+
+    #[test]
+    fn test_build_request() {
+        let adapter = adapter("http://localhost");
+        let options = CompletionOptions {
+            thinking_budget: Some(2_000),
+            ..CompletionOptions::default()
+        };
+        let tools = vec![
+            ToolSchema {
+                name: "Bash".to_string(),
+                description: "Run a shell command".to_string(),
+                input_schema: json!({"type": "object", "properties": {"command": {"type": "string"}}}),
+            },
+            ToolSchema {
+                name: "Ping".to_string(),
+                description: "Check the connection".to_string(),
+                input_schema: json!({"type": "object"}),
+            },
+        ];
+        let request = adapter.build_request(tool_turn(), tools, Some("You are Kode".to_string()), options);
+        let request = serde_json::to_value(request).unwrap();
+
+        assert_eq!(request["systemInstruction"], json!({"parts": [{"text": "You are Kode"}]}));
+        assert_eq!(
+            request["contents"],
+            json!([
+                {"role": "user", "parts": [{"text": "List the files"}]},
+                {"role": "model", "parts": [{"functionCall": {"name": "Bash", "args": {"command": "ls"}}}]},
+                {"role": "user", "parts": [{"functionResponse": {"name": "Bash", "response": {"result": "src"}}}]},
+            ])
+        );
+        let declarations = &request["tools"][0]["functionDeclarations"];
+        assert_eq!(declarations[0]["parameters"]["properties"]["command"], json!({"type": "string"}));
+        assert_eq!(declarations[1].get("parameters"), None);
+        assert_eq!(request["generationConfig"]["maxOutputTokens"], 8192);
+        assert_eq!(
+            request["generationConfig"]["thinkingConfig"],
+            json!({"thinkingBudget": 2_000, "includeThoughts": true})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_complete() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/models/gemini-2.5-pro:generateContent"))
+            .and(header("x-goog-api-key", "test-key"))
+            .and(body_partial_json(json!({"contents": [{"role": "user"}]})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "candidates": [{
+                    "content": {"role": "model", "parts": [
+                        {"text": "Checking", "thought": true},
+                        {"functionCall": {"name": "Bash", "args": {"command": "ls"}}}
+                    ]},
+                    "finishReason": "STOP"
+                }],
+                "usageMetadata": {
+                    "promptTokenCount": 100,
+                    "candidatesTokenCount": 10,
+                    "cachedContentTokenCount": 40
+                },
+                "modelVersion": "gemini-2.5-pro-002"
+            })))
+            .mount(&server)
+            .await;
+
+        let response = adapter(&server.uri())
+            .complete(vec![Message::user("List the files")], Vec::new(), None, CompletionOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(response.stop_reason.as_deref(), Some("tool_use"));
+        assert_eq!(response.model.as_deref(), Some("gemini-2.5-pro-002"));
+        assert!(matches!(
+            &response.content[..],
+            [
+                ContentBlock::Thinking { thinking, .. },
+                ContentBlock::ToolUse { name, input, .. },
+            ] if thinking == "Checking" && name == "Bash" && *input == json!({"command": "ls"})
+        ));
+        let usage = response.usage.unwrap();
+        assert_eq!((usage.input_tokens, usage.output_tokens), (60, 10));
+        assert_eq!(usage.cache_read_input_tokens, Some(40));
+    }
+
+    #[tokio::test]
+    async fn test_stream_complete() {
+        let events = [
+            json!({"candidates": [{"content": {"role": "model", "parts": [{"text": "Hello"}]}}]}),
+            json!({
+                "candidates": [{"content": {"role": "model", "parts": [{"text": " there"}]}, "finishReason": "STOP"}],
+                "usageMetadata": {"promptTokenCount": 5, "candidatesTokenCount": 2}
+            }),
+        ];
+        let body = events
+            .iter()
+            .map(|event| format!("data: {event}\r\n\r\n"))
+            .collect::<Vec<_>>()
+            .concat();
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/models/gemini-2.5-pro:streamGenerateContent"))
+            .and(query_param("alt", "sse"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+            .mount(&server)
+            .await;
+
+        let mut stream = adapter(&server.uri())
+            .stream_complete(vec![Message::user("Hi")], Vec::new(), None, CompletionOptions::default())
+            .await
+            .unwrap();
+
+        let mut text = String::new();
+        let mut stop = None;
+        while let Some(chunk) = stream.next().await {
+            match chunk.unwrap() {
+                CompletionChunk::TextDelta { text: delta } => text.push_str(&delta),
+                CompletionChunk::Done { stop_reason, usage } => {
+                    stop = Some(stop_reason);
+                    assert_eq!(usage.map(|usage| usage.output_tokens), Some(2));
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(text, "Hello there");
+        assert_eq!(stop.as_deref(), Some("end_turn"));
+    }
+}
@@ -5,20 +5,36 @@
 //! - OpenAI (ChatGPT)
 //! - AWS Bedrock
 //! - Google Vertex AI
+//! - Ollama
 //! - Custom OpenAI-compatible endpoints
 
+pub mod access_token;
 pub mod adapters;
 pub mod anthropic;
+pub mod aws;
+pub mod fallback;
+pub mod gemini;
+pub mod ollama;
 pub mod openai;
+pub mod retry;
 pub mod streaming;
+#[cfg(test)]
+pub(crate) mod testing;
+pub mod text_tools;
+pub mod tokens;
 
 use async_trait::async_trait;
 use futures::Stream;
 use serde::{Deserialize, Serialize};
 use std::pin::Pin;
 
+pub use self::fallback::FallbackAdapter;
+pub use self::retry::RetryPolicy;
+pub use self::text_tools::TextToolAdapter;
+pub use self::tokens::{Encoding, TokenCounter};
 use crate::{
-    config::models::ModelProfile,
+    config::{capabilities::ToolCallingMode, models::ModelProfile},
+    cost::ModelPricing,
     error::Result,
     messages::{ContentBlock, Message},
 };
@@ -53,6 +69,10 @@ pub struct CompletionOptions {
     /// Verbosity level (for some models)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub verbosity: Option<String>,
+
+    /// Extended thinking budget in tokens, overriding the profile's reasoning effort
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thinking_budget: Option<u32>,
 }
 
 fn default_stream() -> bool {
@@ -69,6 +89,7 @@ impl Default for CompletionOptions {
             stream: true,
             reasoning_effort: None,
             verbosity: None,
+            thinking_budget: None,
         }
     }
 }
@@ -83,6 +104,19 @@ pub enum CompletionChunk {
     /// Thinking/reasoning content (for reasoning models)
     ThinkingDelta { thinking: String },
 
+    /// Signature of the thinking block just streamed
+    ThinkingSignature { signature: String },
+
+    /// Encrypted thinking block
+    RedactedThinking { data: String },
+
+    /// Reasoning item (Responses API) whose summary was just streamed
+    Reasoning {
+        id: String,
+        summary: String,
+        encrypted_content: Option<String>,
+    },
+
     /// Tool use started
     ToolUseStart {
         id: String,
@@ -102,6 +136,21 @@ pub enum CompletionChunk {
         input: serde_json::Value,
     },
 
+    /// A request failed transiently and is retried after `delay_ms`
+    Retrying {
+        attempt: u32,
+        max_retries: u32,
+        delay_ms: u64,
+        reason: String,
+    },
+
+    /// The request is answered by the fallback model `model`, because the
+    /// preferred one failed or is still cooling down after failing
+    Fallback {
+        model: String,
+        reason: String,
+    },
+
     /// Stream completed
     Done {
         stop_reason: String,
@@ -115,7 +164,7 @@ pub enum CompletionChunk {
 }
 
 /// Token usage statistics
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Usage {
     pub input_tokens: u32,
     pub output_tokens: u32,
@@ -127,6 +176,42 @@ pub struct Usage {
     pub cache_read_input_tokens: Option<u32>,
 }
 
+impl Usage {
+    /// Add the counts of another usage report to this one
+    pub fn add(&mut self, other: &Self) {
+        fn add_optional(total: &mut Option<u32>, value: Option<u32>) {
+            if let Some(value) = value {
+                *total = Some(total.unwrap_or(0).saturating_add(value));
+            }
+        }
+
+        self.input_tokens = self.input_tokens.saturating_add(other.input_tokens);
+        self.output_tokens = self.output_tokens.saturating_add(other.output_tokens);
+        add_optional(
+            &mut self.cache_creation_input_tokens,
+            other.cache_creation_input_tokens,
+        );
+        add_optional(&mut self.cache_read_input_tokens, other.cache_read_input_tokens);
+    }
+
+    /// Share of prompt tokens read from the prompt cache
+    ///
+    /// Returns `None` if the provider reported no cache usage.
+    #[must_use]
+    pub fn cache_hit_rate(&self) -> Option<f64> {
+        if self.cache_creation_input_tokens.is_none() && self.cache_read_input_tokens.is_none() {
+            return None;
+        }
+        let read = self.cache_read_input_tokens.unwrap_or(0);
+        let prompt = u64::from(self.input_tokens)
+            + u64::from(self.cache_creation_input_tokens.unwrap_or(0))
+            + u64::from(read);
+        // Prompt token counts stay far below 2^53, so the conversion is exact
+        #[allow(clippy::cast_precision_loss)]
+        (prompt > 0).then(|| f64::from(read) / prompt as f64)
+    }
+}
+
 /// Completion stream type
 pub type CompletionStream = Pin<Box<dyn Stream<Item = Result<CompletionChunk>> + Send>>;
 
@@ -180,10 +265,29 @@ pub trait ModelAdapter: Send + Sync {
         options: CompletionOptions,
     ) -> Result<CompletionStream>;
 
-    /// Count tokens in a message (estimate if not supported by provider)
+    /// Token counter matching this provider and model
+    fn token_counter(&self) -> TokenCounter {
+        TokenCounter::for_model(self.provider(), self.model())
+    }
+
+    /// Count tokens in a piece of text
     fn count_tokens(&self, text: &str) -> u32 {
-        // Simple approximation: ~4 chars per token
-        (text.len() / 4) as u32
+        self.token_counter().count_text(text)
+    }
+
+    /// Count the tokens a request would use, including tool definitions
+    fn count_request_tokens(
+        &self,
+        messages: &[Message],
+        system_prompt: Option<&str>,
+        tools: &[ToolSchema],
+    ) -> u32 {
+        self.token_counter().count_request(messages, system_prompt, tools)
+    }
+
+    /// Prices used to compute the cost of a response, if known
+    fn pricing(&self) -> Option<ModelPricing> {
+        ModelPricing::for_model(self.model())
     }
 
     /// Get maximum context window size for this model
@@ -201,16 +305,69 @@ impl ModelAdapterFactory {
     pub fn create(profile: &ModelProfile) -> Result<Box<dyn ModelAdapter>> {
         use crate::config::models::ProviderType;
 
-        match profile.provider {
-            ProviderType::Anthropic => Ok(Box::new(anthropic::AnthropicAdapter::new(profile.clone())?)),
-            ProviderType::OpenAI | ProviderType::CustomOpenAI => Ok(Box::new(openai::OpenAIAdapter::new(profile.clone())?)),
-            ProviderType::Azure => Ok(Box::new(openai::OpenAIAdapter::new(profile.clone())?)), // Azure uses OpenAI API
-            ProviderType::Custom => Ok(Box::new(openai::OpenAIAdapter::new(profile.clone())?)), // Assume OpenAI-compatible
-            ProviderType::Ollama => Ok(Box::new(openai::OpenAIAdapter::new(profile.clone())?)), // Ollama uses OpenAI API
-            ProviderType::Groq => Ok(Box::new(openai::OpenAIAdapter::new(profile.clone())?)), // Groq uses OpenAI API
-            _ => Err(crate::error::KodeError::UnsupportedProvider {
-                provider: format!("{:?}", profile.provider),
-            }),
+        let adapter: Box<dyn ModelAdapter> = match profile.provider {
+            ProviderType::Anthropic => Box::new(anthropic::AnthropicAdapter::new(profile.clone())?),
+            ProviderType::Bedrock => Box::new(anthropic::BedrockAdapter::new(profile.clone())?),
+            ProviderType::Vertex => Box::new(anthropic::VertexAdapter::new(profile.clone())?),
+            // GPT-5 and o-series models need the Responses API
+            ProviderType::OpenAI if profile.uses_responses_api() => Box::new(adapters::ResponsesAPIAdapter::new(profile.clone())?),
+            ProviderType::OpenAI | ProviderType::CustomOpenAI => Box::new(openai::OpenAIAdapter::new(profile.clone())?),
+            ProviderType::Gemini => Box::new(gemini::GeminiAdapter::new(profile.clone())?),
+            ProviderType::Azure => Box::new(openai::AzureOpenAIAdapter::new(profile.clone())?),
+            ProviderType::Ollama => Box::new(ollama::OllamaAdapter::new(profile.clone())?),
+            // Everything else speaks the OpenAI-compatible chat API
+            ProviderType::Custom
+            | ProviderType::Groq
+            | ProviderType::Xai
+            | ProviderType::Mistral
+            | ProviderType::Deepseek
+            | ProviderType::Kimi
+            | ProviderType::Qwen
+            | ProviderType::Glm
+            | ProviderType::Minimax
+            | ProviderType::BaiduQianfan
+            | ProviderType::Siliconflow => Box::new(openai::OpenAIAdapter::new(profile.clone())?),
+            ProviderType::Bigdream | ProviderType::Opendev => {
+                return Err(crate::error::KodeError::UnsupportedProvider {
+                    provider: format!("{:?}", profile.provider),
+                })
+            }
+        };
+
+        // Models without usable function calling get tools through text
+        if profile.capabilities().tool_calling == ToolCallingMode::Text {
+            return Ok(Box::new(TextToolAdapter::new(adapter)));
+        }
+        Ok(adapter)
+    }
+
+    /// Create an adapter for a profile that fails over to `fallbacks` in order
+    ///
+    /// Fallbacks whose adapter cannot be created, e.g. for lack of an API key,
+    /// are left out of the chain.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the adapter for `profile` cannot be created
+    pub fn create_with_fallbacks(
+        profile: &ModelProfile,
+        fallbacks: &[&ModelProfile],
+        cooldown: std::time::Duration,
+    ) -> Result<Box<dyn ModelAdapter>> {
+        let primary = Self::create(profile)?;
+        let fallbacks: Vec<Box<dyn ModelAdapter>> = fallbacks
+            .iter()
+            .filter_map(|fallback| match Self::create(fallback) {
+                Ok(adapter) => Some(adapter),
+                Err(e) => {
+                    tracing::warn!("Skipping fallback model {}: {e}", fallback.model_name);
+                    None
+                }
+            })
+            .collect();
+        if fallbacks.is_empty() {
+            return Ok(primary);
         }
+        Ok(Box::new(FallbackAdapter::new(primary, fallbacks, cooldown)))
     }
 }
@@ -0,0 +1,787 @@
+//! Ollama API adapter
+//!
+//! Talks to Ollama's native `/api/chat` endpoint, which streams
+//! newline-delimited JSON and takes model options such as `num_ctx` that the
+//! OpenAI-compatible endpoint ignores. Ollama loads models with a small
+//! context window unless told otherwise, so every request asks for the
+//! profile's context length, capped at what the model supports according to
+//! `/api/show`.
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::{Stream, StreamExt};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::OnceCell;
+
+use crate::{
+    config::{
+        capabilities::ToolCallingMode,
+        models::{ModelProfile, ProviderType},
+    },
+    cost::ModelPricing,
+    error::{KodeError, Result},
+    messages::{ContentBlock, ImageSource, Message, Role},
+};
+
+use super::{
+    retry,
+    streaming::{
+        new_call_id, ollama_stop_reason, OllamaChatResponse, OllamaFunctionCall,
+        OllamaMessage, OllamaStreamHandler, OllamaToolCall,
+    },
+    CompletionChunk, CompletionOptions, CompletionResponse, CompletionStream, ModelAdapter,
+    ToolSchema,
+};
+
+/// Ollama API adapter
+pub struct OllamaAdapter {
+    client: Client,
+    profile: ModelProfile,
+    base_url: String,
+
+    /// Context length of the model according to `/api/show`, fetched before
+    /// the first request; `None` if Ollama could not tell
+    model_context: OnceCell<Option<u32>>,
+}
+
+impl OllamaAdapter {
+    /// Create a new Ollama adapter
+    ///
+    /// The server is the profile's base URL, else `OLLAMA_HOST`, else
+    /// `http://localhost:11434`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the HTTP client cannot be built.
+    pub fn new(profile: ModelProfile) -> Result<Self> {
+        let base_url = server_url(profile.base_url.as_deref());
+        Ok(Self {
+            client: Client::builder().build()?,
+            profile,
+            base_url,
+            model_context: OnceCell::new(),
+        })
+    }
+
+    /// Context length to load the model with
+    fn context_length(&self) -> u32 {
+        match self.model_context.get() {
+            Some(Some(model_context)) => self.profile.context_length.min(*model_context),
+            _ => self.profile.context_length,
+        }
+    }
+
+    /// Look up the model's context length, once
+    ///
+    /// Failures are not fatal: the profile's context length is used instead.
+    async fn load_model_context(&self) {
+        self.model_context
+            .get_or_init(|| async {
+                match show_context_length(&self.client, &self.base_url, &self.profile.model_name).await {
+                    Ok(context) => context,
+                    Err(e) => {
+                        tracing::debug!("Could not read the context length of {}: {e}", self.profile.model_name);
+                        None
+                    }
+                }
+            })
+            .await;
+    }
+
+    /// A POST request to an API path, authenticated if the profile has a key
+    fn post(&self, path: &str) -> reqwest::RequestBuilder {
+        let request = self.client.post(format!("{}{path}", self.base_url));
+        if self.profile.api_key.is_empty() {
+            request
+        } else {
+            // For servers behind an authenticating proxy
+            request.bearer_auth(&self.profile.api_key)
+        }
+    }
+
+    /// Convert internal messages to Ollama messages
+    ///
+    /// Tool results become `tool` messages naming the tool they answer, placed
+    /// before the rest of the user turn. Images returned by tools travel with
+    /// the user message, as tool messages carry text only.
+    fn convert_messages(messages: Vec<Message>) -> Vec<OllamaMessage> {
+        let mut converted = Vec::new();
+        let mut call_names = HashMap::new();
+
+        for msg in messages {
+            let mut text = Vec::new();
+            let mut images = Vec::new();
+            let mut tool_calls = Vec::new();
+            for block in msg.content {
+                match block {
+                    ContentBlock::Text { text: block_text } => text.push(block_text),
+                    ContentBlock::Image { source } => add_image(&source, &mut text, &mut images),
+                    ContentBlock::ToolUse { id, name, input } => {
+                        call_names.insert(id, name.clone());
+                        tool_calls.push(OllamaToolCall {
+                            function: OllamaFunctionCall {
+                                name,
+                                arguments: input,
+                            },
+                        });
+                    }
+                    ContentBlock::ToolResult {
+                        tool_use_id,
+                        content,
+                        images: result_images,
+                        ..
+                    } => {
+                        if !result_images.is_empty() {
+                            text.push(format!("Images returned by tool call {tool_use_id}:"));
+                            for source in &result_images {
+                                add_image(source, &mut text, &mut images);
+                            }
+                        }
+                        converted.push(OllamaMessage {
+                            role: "tool".to_string(),
+                            content,
+                            tool_name: call_names.get(&tool_use_id).cloned(),
+                            ..OllamaMessage::default()
+                        });
+                    }
+                    // Other providers' thinking cannot be passed to Ollama
+                    ContentBlock::Thinking { .. }
+                    | ContentBlock::RedactedThinking { .. }
+                    | ContentBlock::Reasoning { .. } => {}
+                }
+            }
+
+            if text.is_empty() && images.is_empty() && tool_calls.is_empty() {
+                // Nothing besides tool results
+                continue;
+            }
+            let role = match msg.role {
+                Role::User => "user",
+                Role::Assistant => "assistant",
+                Role::System => "system",
+            };
+            converted.push(OllamaMessage {
+                role: role.to_string(),
+                content: text.join("\n\n"),
+                images,
+                tool_calls,
+                ..OllamaMessage::default()
+            });
+        }
+        converted
+    }
+
+    /// Build a request body
+    fn build_request(
+        &self,
+        messages: Vec<Message>,
+        tools: Vec<ToolSchema>,
+        system_prompt: Option<String>,
+        options: CompletionOptions,
+        stream: bool,
+    ) -> OllamaRequest {
+        let capabilities = self.profile.capabilities();
+        let messages = if capabilities.vision {
+            messages
+        } else {
+            messages.into_iter().map(Message::without_images).collect()
+        };
+        let tools = match capabilities.tool_calling {
+            ToolCallingMode::Native => tools,
+            ToolCallingMode::Text | ToolCallingMode::None => Vec::new(),
+        };
+        let (temperature, top_p) = if capabilities.temperature {
+            (options.temperature, options.top_p)
+        } else {
+            (None, None)
+        };
+
+        let mut ollama_messages = Vec::new();
+        if let Some(system) = system_prompt {
+            ollama_messages.push(OllamaMessage {
+                role: "system".to_string(),
+                content: system,
+                ..OllamaMessage::default()
+            });
+        }
+        ollama_messages.extend(Self::convert_messages(messages));
+
+        OllamaRequest {
+            model: self.profile.model_name.clone(),
+            messages: ollama_messages,
+            tools: tools
+                .into_iter()
+                .map(|tool| OllamaTool {
+                    tool_type: "function",
+                    function: OllamaFunctionDef {
+                        name: tool.name,
+                        description: tool.description,
+                        parameters: tool.input_schema,
+                    },
+                })
+                .collect(),
+            stream,
+            // Only thinking models accept `think`, so it is sent only when asked for
+            think: self
+                .profile
+                .reasoning_effort
+                .filter(|_| capabilities.reasoning)
+                .map(|_| true),
+            keep_alive: self.profile.keep_alive.clone(),
+            options: OllamaOptions {
+                num_ctx: self.context_length(),
+                num_predict: Some(options.max_tokens.unwrap_or(self.profile.max_tokens)),
+                temperature,
+                top_p,
+                stop: options.stop_sequences,
+            },
+        }
+    }
+
+    /// Process an NDJSON byte stream into `CompletionChunk`s
+    ///
+    /// Chunks are yielded as soon as each line is parsed.
+    fn process_stream(
+        byte_stream: impl Stream<Item = reqwest::Result<Bytes>> + Send + 'static,
+    ) -> impl Stream<Item = Result<CompletionChunk>> + Send + 'static {
+        async_stream::stream! {
+            let mut handler = OllamaStreamHandler::new();
+            let mut byte_stream = Box::pin(byte_stream);
+
+            while let Some(chunk_result) = byte_stream.next().await {
+                let bytes = match chunk_result {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        yield Err(KodeError::NetworkError(e.to_string()));
+                        return;
+                    }
+                };
+
+                match handler.process_bytes(&bytes) {
+                    Ok(chunks) => {
+                        for chunk in chunks {
+                            yield Ok(chunk);
+                        }
+                    }
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                }
+                if handler.is_complete() {
+                    return;
+                }
+            }
+
+            // The connection closed without the final line
+            match handler.finish() {
+                Ok(chunks) => {
+                    for chunk in chunks {
+                        yield Ok(chunk);
+                    }
+                }
+                Err(e) => yield Err(e),
+            }
+        }
+    }
+}
+
+/// Base URL of an Ollama server, without a trailing `/v1` left over from
+/// profiles that used the OpenAI-compatible endpoint
+fn server_url(base_url: Option<&str>) -> String {
+    let url = base_url.map_or_else(
+        || {
+            std::env::var("OLLAMA_HOST")
+                .ok()
+                .filter(|host| !host.is_empty())
+                .map_or_else(
+                    || ProviderType::Ollama.default_base_url().unwrap_or_default().to_string(),
+                    |host| {
+                        if host.contains("://") {
+                            host
+                        } else {
+                            format!("http://{host}")
+                        }
+                    },
+                )
+        },
+        str::to_string,
+    );
+    let url = url.trim_end_matches('/');
+    url.strip_suffix("/v1").unwrap_or(url).to_string()
+}
+
+/// Images are sent as bare base64; uploaded files cannot be referenced
+fn add_image(source: &ImageSource, text: &mut Vec<String>, images: &mut Vec<String>) {
+    match source {
+        ImageSource::Base64 { data, .. } => images.push(data.clone()),
+        ImageSource::File { file_id } => text.push(format!("[Image file {file_id}]")),
+    }
+}
+
+/// Context length of a model according to `/api/show`
+///
+/// # Errors
+///
+/// Returns an error if the server cannot be reached or does not know the model
+pub async fn show_context_length(client: &Client, base_url: &str, model: &str) -> Result<Option<u32>> {
+    #[derive(Deserialize)]
+    struct ShowResponse {
+        #[serde(default)]
+        model_info: serde_json::Map<String, Value>,
+    }
+
+    let response = client
+        .post(format!("{base_url}/api/show"))
+        .json(&json!({ "model": model }))
+        .send()
+        .await?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(KodeError::ApiError {
+            provider: "ollama".to_string(),
+            message: format!("Showing {model} failed with HTTP {status}: {body}"),
+        });
+    }
+
+    // Keys are prefixed with the architecture, e.g. `llama.context_length`
+    let show: ShowResponse = response.json().await?;
+    let architecture = show
+        .model_info
+        .get("general.architecture")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let context = show
+        .model_info
+        .get(&format!("{architecture}.context_length"))
+        .or_else(|| {
+            show.model_info
+                .iter()
+                .find(|(key, _)| key.ends_with(".context_length"))
+                .map(|(_, value)| value)
+        })
+        .and_then(Value::as_u64);
+    Ok(context.map(|context| u32::try_from(context).unwrap_or(u32::MAX)))
+}
+
+/// A model installed on an Ollama server
+#[derive(Debug, Clone, Deserialize)]
+pub struct LocalModel {
+    /// Name to request the model by, such as `llama3.1:8b`
+    pub name: String,
+
+    /// Size on disk in bytes
+    #[serde(default)]
+    pub size: u64,
+
+    /// Context length according to `/api/show`
+    #[serde(skip)]
+    pub context_length: Option<u32>,
+}
+
+impl LocalModel {
+    /// A profile for this model on the server at `base_url`
+    ///
+    /// The context length reported by `/api/show` takes precedence over the
+    /// capability catalog.
+    #[must_use]
+    pub fn profile(&self, base_url: &str) -> ModelProfile {
+        let mut profile = ModelProfile::with_defaults(
+            self.name.clone(),
+            ProviderType::Ollama,
+            self.name.clone(),
+            String::new(),
+        );
+        if let Some(context_length) = self.context_length {
+            profile.context_length = context_length;
+        }
+        profile.max_tokens = profile.max_tokens.min(profile.context_length / 2);
+        profile.base_url = Some(base_url.to_string());
+        profile
+    }
+}
+
+/// List the models installed on an Ollama server with their context lengths
+///
+/// `base_url` defaults as it does for [`OllamaAdapter::new`].
+///
+/// # Errors
+///
+/// Returns an error if the server cannot be reached
+pub async fn discover_models(base_url: Option<&str>) -> Result<(String, Vec<LocalModel>)> {
+    #[derive(Deserialize)]
+    struct TagsResponse {
+        #[serde(default)]
+        models: Vec<LocalModel>,
+    }
+
+    let base_url = server_url(base_url);
+    let client = Client::builder().build()?;
+    let response = client.get(format!("{base_url}/api/tags")).send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(KodeError::ApiError {
+            provider: "ollama".to_string(),
+            message: format!("Listing models failed with HTTP {status}: {body}"),
+        });
+    }
+
+    let mut models = response.json::<TagsResponse>().await?.models;
+    for model in &mut models {
+        // A model whose details cannot be read is still listed
+        model.context_length = match show_context_length(&client, &base_url, &model.name).await {
+            Ok(context) => context,
+            Err(e) => {
+                tracing::debug!("Could not read the context length of {}: {e}", model.name);
+                None
+            }
+        };
+    }
+    Ok((base_url, models))
+}
+
+/// Convert a complete chat response to content blocks
+fn convert_response(response: &OllamaChatResponse) -> Vec<ContentBlock> {
+    let mut content = Vec::new();
+    let Some(message) = &response.message else {
+        return content;
+    };
+    if let Some(thinking) = message.thinking.clone().filter(|thinking| !thinking.is_empty()) {
+        content.push(ContentBlock::Thinking {
+            thinking,
+            signature: None,
+        });
+    }
+    if !message.content.is_empty() {
+        content.push(ContentBlock::Text {
+            text: message.content.clone(),
+        });
+    }
+    for call in &message.tool_calls {
+        content.push(ContentBlock::ToolUse {
+            id: new_call_id(),
+            name: call.function.name.clone(),
+            input: call.function.input(),
+        });
+    }
+    content
+}
+
+#[async_trait]
+impl ModelAdapter for OllamaAdapter {
+    fn provider(&self) -> &str {
+        "ollama"
+    }
+
+    fn model(&self) -> &str {
+        &self.profile.model_name
+    }
+
+    async fn complete(
+        &self,
+        messages: Vec<Message>,
+        tools: Vec<ToolSchema>,
+        system_prompt: Option<String>,
+        options: CompletionOptions,
+    ) -> Result<CompletionResponse> {
+        self.load_model_context().await;
+        let request = self.build_request(messages, tools, system_prompt, options, false);
+
+        let request = self.post("/api/chat").json(&request);
+        let response = retry::send(&self.profile.retry_policy(), "ollama", request).await?;
+
+        let api_response: OllamaChatResponse = response.json().await?;
+        if let Some(message) = api_response.error {
+            return Err(KodeError::ApiError {
+                provider: "ollama".to_string(),
+                message,
+            });
+        }
+        let content = convert_response(&api_response);
+        let has_tool_calls = content
+            .iter()
+            .any(|block| matches!(block, ContentBlock::ToolUse { .. }));
+
+        Ok(CompletionResponse {
+            content,
+            stop_reason: Some(ollama_stop_reason(api_response.done_reason.as_deref(), has_tool_calls)),
+            usage: Some(api_response.usage()),
+            model: api_response.model.or_else(|| Some(self.profile.model_name.clone())),
+        })
+    }
+
+    async fn stream_complete(
+        &self,
+        messages: Vec<Message>,
+        tools: Vec<ToolSchema>,
+        system_prompt: Option<String>,
+        options: CompletionOptions,
+    ) -> Result<CompletionStream> {
+        self.load_model_context().await;
+        let request = self.build_request(messages, tools, system_prompt, options, true);
+
+        let request = self.post("/api/chat").json(&request);
+        Ok(retry::stream(self.profile.retry_policy(), "ollama", request, |response| {
+            Self::process_stream(response.bytes_stream())
+        }))
+    }
+
+    fn pricing(&self) -> Option<ModelPricing> {
+        self.profile.pricing()
+    }
+
+    fn max_context_tokens(&self) -> u32 {
+        self.context_length()
+    }
+
+    fn max_output_tokens(&self) -> u32 {
+        self.profile.max_tokens
+    }
+}
+
+// Ollama API types
+
+#[derive(Debug, Clone, Serialize)]
+struct OllamaRequest {
+    model: String,
+    messages: Vec<OllamaMessage>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<OllamaTool>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    think: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<String>,
+    options: OllamaOptions,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct OllamaTool {
+    #[serde(rename = "type")]
+    tool_type: &'static str,
+    function: OllamaFunctionDef,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct OllamaFunctionDef {
+    name: String,
+    description: String,
+    parameters: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct OllamaOptions {
+    num_ctx: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop: Option<Vec<String>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{body_partial_json, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    fn profile(base_url: &str) -> ModelProfile {
+        let mut profile = ModelProfile::new(
+            "qwen3".to_string(),
+            ProviderType::Ollama,
+            "qwen3:8b".to_string(),
+            String::new(),
+            4096,
+            200_000,
+        );
+        profile.base_url = Some(format!("{base_url}/v1"));
+        profile.keep_alive = Some("30m".to_string());
+        profile
+    }
+
+    async fn mount_show(server: &MockServer, model: &str, context_length: u64) {
+        Mock::given(method("POST"))
+            .and(path("/api/show"))
+            .and(body_partial_json(json!({"model": model})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "parameters": "temperature 0.6",
+                "model_info": {
+                    "general.architecture": "qwen3",
+                    "qwen3.context_length": context_length,
+                    "qwen3.embedding_length": 4096
+                }
+            })))
+            .mount(server)
+            .await;
+    }
+
+    #[test]
+    fn test_convert_tool_round_trip() {
+        let messages = vec![
+            Message::user("List the files"),
+            Message {
+                role: Role::Assistant,
+                content: vec![ContentBlock::ToolUse {
+                    id: "call_1".to_string(),
+                    name: "Bash".to_string(),
+                    input: json!({"command": "ls"}),
+                }],
+                uuid: None,
+            },
+            Message {
+                role: Role::User,
+                content: vec![ContentBlock::ToolResult {
+                    tool_use_id: "call_1".to_string(),
+                    content: "Cargo.toml".to_string(),
+                    is_error: None,
+                    images: Vec::new(),
+                }],
+                uuid: None,
+            },
+        ];
+
+        let converted = OllamaAdapter::convert_messages(messages);
+        assert_eq!(converted.len(), 3);
+        assert_eq!(converted[1].tool_calls[0].function.arguments, json!({"command": "ls"}));
+        assert_eq!(converted[2].role, "tool");
+        assert_eq!(converted[2].tool_name.as_deref(), Some("Bash"));
+        assert_eq!(converted[2].content, "Cargo.toml");
+    }
+
+    #[tokio::test]
+    async fn test_stream_uses_model_context() {
+        let server = MockServer::start().await;
+        mount_show(&server, "qwen3:8b", 40_960).await;
+        let body = [
+            json!({"model": "qwen3:8b", "message": {"role": "assistant", "content": "Hello"}, "done": false}),
+            json!({"model": "qwen3:8b", "message": {"role": "assistant", "content": ""}, "done": true,
+                "done_reason": "stop", "prompt_eval_count": 12, "eval_count": 2}),
+        ]
+        .iter()
+        .map(|line| format!("{line}\n"))
+        .collect::<Vec<_>>()
+        .concat();
+        Mock::given(method("POST"))
+            .and(path("/api/chat"))
+            .and(body_partial_json(json!({
+                "model": "qwen3:8b",
+                "stream": true,
+                "keep_alive": "30m",
+                "tools": [{"type": "function", "function": {"name": "Bash"}}],
+                "options": {"num_ctx": 40_960, "num_predict": 4096}
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "application/x-ndjson"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let adapter = OllamaAdapter::new(profile(&server.uri())).unwrap();
+        assert_eq!(adapter.max_context_tokens(), 200_000);
+        let tools = vec![ToolSchema {
+            name: "Bash".to_string(),
+            description: "Run a command".to_string(),
+            input_schema: json!({"type": "object", "properties": {"command": {"type": "string"}}}),
+        }];
+        let options = CompletionOptions {
+            max_tokens: None,
+            ..CompletionOptions::default()
+        };
+        let chunks: Vec<CompletionChunk> = adapter
+            .stream_complete(vec![Message::user("Hi")], tools, None, options)
+            .await
+            .unwrap()
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+
+        assert!(matches!(&chunks[0], CompletionChunk::TextDelta { text } if text == "Hello"));
+        assert!(matches!(&chunks[1], CompletionChunk::Done { stop_reason, .. } if stop_reason == "end_turn"));
+        assert_eq!(adapter.max_context_tokens(), 40_960);
+    }
+
+    #[tokio::test]
+    async fn test_complete_with_tool_call() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/chat"))
+            .and(body_partial_json(json!({"stream": false})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "model": "qwen3:8b",
+                "message": {"role": "assistant", "content": "", "tool_calls": [
+                    {"function": {"name": "Bash", "arguments": {"command": "ls"}}}
+                ]},
+                "done": true,
+                "done_reason": "stop",
+                "prompt_eval_count": 30,
+                "eval_count": 9
+            })))
+            .mount(&server)
+            .await;
+
+        // Without `/api/show` the profile's context length is used
+        let adapter = OllamaAdapter::new(profile(&server.uri())).unwrap();
+        let response = adapter
+            .complete(vec![Message::user("Hi")], Vec::new(), None, CompletionOptions::default())
+            .await
+            .unwrap();
+
+        assert!(matches!(&response.content[..], [ContentBlock::ToolUse { name, .. }] if name == "Bash"));
+        assert_eq!(response.stop_reason.as_deref(), Some("tool_use"));
+        assert_eq!(adapter.max_context_tokens(), 200_000);
+    }
+
+    #[tokio::test]
+    async fn test_discover_models() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/tags"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "models": [
+                    {"name": "qwen3:8b", "model": "qwen3:8b", "size": 5_225_388_164_u64},
+                    {"name": "llama3.2:1b", "model": "llama3.2:1b", "size": 1_321_098_329},
+                    {"name": "broken:latest", "model": "broken:latest", "size": 1}
+                ]
+            })))
+            .mount(&server)
+            .await;
+        mount_show(&server, "qwen3:8b", 40_960).await;
+        Mock::given(method("POST"))
+            .and(path("/api/show"))
+            .and(body_partial_json(json!({"model": "llama3.2:1b"})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "model_info": {"general.architecture": "llama", "llama.context_length": 131_072}
+            })))
+            .mount(&server)
+            .await;
+        // A model whose details fail to load is still listed
+        Mock::given(method("POST"))
+            .and(path("/api/show"))
+            .and(body_partial_json(json!({"model": "broken:latest"})))
+            .respond_with(ResponseTemplate::new(500).set_body_string("model is corrupt"))
+            .mount(&server)
+            .await;
+
+        let (base_url, models) = discover_models(Some(&format!("{}/", server.uri()))).await.unwrap();
+        assert_eq!(base_url, server.uri());
+        let profiles: Vec<ModelProfile> = models.iter().map(|model| model.profile(&base_url)).collect();
+        assert_eq!(profiles[0].model_name, "qwen3:8b");
+        assert_eq!(profiles[0].context_length, 40_960);
+        assert_eq!(profiles[1].context_length, 131_072);
+        assert_eq!(profiles[1].provider, ProviderType::Ollama);
+        assert!(profiles[1].api_key.is_empty());
+        assert_eq!(models[2].context_length, None);
+        assert_eq!(profiles[2].context_length, 8_192);
+    }
+}
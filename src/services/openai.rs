@@ -3,6 +3,7 @@
 //! Supports:
 //! - OpenAI official API (ChatGPT, GPT-4, etc.)
 //! - OpenAI-compatible endpoints (Ollama, LM Studio, etc.)
+//! - Model deployments on Azure
 
 use async_trait::async_trait;
 use bytes::Bytes;
@@ -11,13 +12,19 @@ use reqwest::{header, Client};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    config::models::ModelProfile,
+    config::{
+        capabilities::{MaxTokensParam, ToolCallingMode},
+        models::{AuthMode, ModelProfile},
+    },
+    cost::ModelPricing,
     error::{KodeError, Result},
-    messages::{ContentBlock, Message, Role},
+    messages::{ContentBlock, ImageSource, Message, Role},
 };
 
 use super::{
-    streaming::OpenAIStreamHandler,
+    access_token::{AccessTokenProvider, TokenSource},
+    retry,
+    streaming::{OpenAIStreamHandler, OpenAIUsage},
     CompletionChunk, CompletionOptions, CompletionResponse, CompletionStream, ModelAdapter,
     ToolSchema, Usage,
 };
@@ -39,8 +46,7 @@ impl OpenAIAdapter {
         };
 
         let base_url = profile
-            .base_url
-            .clone()
+            .effective_base_url()
             .unwrap_or_else(|| "https://api.openai.com/v1".to_string());
 
         let client = Client::builder()
@@ -64,70 +70,81 @@ impl OpenAIAdapter {
     }
 
     /// Convert internal messages to OpenAI format
-    fn convert_messages(&self, messages: Vec<Message>) -> Vec<OpenAIMessage> {
-        messages
-            .into_iter()
-            .map(|msg| {
-                let role = match msg.role {
-                    Role::User => "user",
-                    Role::Assistant => "assistant",
-                    Role::System => "system",
-                }
-                .to_string();
-
-                // Extract text content
-                let text_content: Vec<String> = msg
-                    .content
-                    .iter()
-                    .filter_map(|block| match block {
-                        ContentBlock::Text { text } => Some(text.clone()),
-                        ContentBlock::ToolResult {
-                            tool_use_id,
-                            content,
-                            ..
-                        } => Some(format!("Tool result for {}: {}", tool_use_id, content)),
-                        _ => None,
-                    })
-                    .collect();
-
-                // Extract tool calls
-                let tool_calls: Vec<OpenAIToolCall> = msg
-                    .content
-                    .iter()
-                    .filter_map(|block| match block {
-                        ContentBlock::ToolUse { id, name, input } => Some(OpenAIToolCall {
-                            id: id.clone(),
-                            call_type: "function".to_string(),
-                            function: OpenAIFunction {
-                                name: name.clone(),
-                                arguments: serde_json::to_string(input).ok()?,
-                            },
-                        }),
-                        _ => None,
-                    })
-                    .collect();
-
-                OpenAIMessage {
-                    role,
-                    content: if text_content.is_empty() {
-                        None
-                    } else {
-                        Some(text_content.join("\n"))
-                    },
-                    tool_calls: if tool_calls.is_empty() {
-                        None
-                    } else {
-                        Some(tool_calls)
-                    },
-                    tool_call_id: None,
-                    name: None,
+    ///
+    /// Each tool result becomes a `tool` message answering its call. They are
+    /// placed before the rest of the user turn, as the API expects them right
+    /// after the assistant message that made the calls.
+    fn convert_messages(messages: Vec<Message>) -> Vec<OpenAIMessage> {
+        let mut converted = Vec::new();
+        for msg in messages {
+            let role = match msg.role {
+                Role::User => "user",
+                Role::Assistant => "assistant",
+                Role::System => "system",
+            }
+            .to_string();
+
+            let mut parts = Vec::new();
+            let mut tool_calls = Vec::new();
+            // Tool messages carry text only, so images go in the user message
+            let mut tool_images = Vec::new();
+            for block in msg.content {
+                match block {
+                    ContentBlock::Text { text } => parts.push(OpenAIContentPart::text(text)),
+                    ContentBlock::Image { source } => parts.push(OpenAIContentPart::image(&source)),
+                    ContentBlock::ToolUse { id, name, input } => tool_calls.push(OpenAIToolCall {
+                        id,
+                        call_type: "function".to_string(),
+                        function: OpenAIFunction {
+                            name,
+                            arguments: input.to_string(),
+                        },
+                    }),
+                    ContentBlock::ToolResult {
+                        tool_use_id,
+                        content,
+                        images,
+                        ..
+                    } => {
+                        if !images.is_empty() {
+                            tool_images.push(OpenAIContentPart::text(format!(
+                                "Images returned by tool call {tool_use_id}:"
+                            )));
+                            tool_images.extend(images.iter().map(OpenAIContentPart::image));
+                        }
+                        converted.push(OpenAIMessage {
+                            role: "tool".to_string(),
+                            content: Some(OpenAIContent::Text(content)),
+                            tool_calls: None,
+                            tool_call_id: Some(tool_use_id),
+                            name: None,
+                        });
+                    }
+                    ContentBlock::Thinking { .. }
+                    | ContentBlock::RedactedThinking { .. }
+                    | ContentBlock::Reasoning { .. } => {}
                 }
-            })
-            .collect()
+            }
+
+            tool_images.append(&mut parts);
+            let content = OpenAIContent::from_parts(tool_images);
+            if content.is_none() && tool_calls.is_empty() {
+                // Nothing besides tool results
+                continue;
+            }
+            converted.push(OpenAIMessage {
+                role,
+                content,
+                tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
+                tool_call_id: None,
+                name: None,
+            });
+        }
+        converted
     }
 
     /// Convert tool schemas to OpenAI format
-    fn convert_tools(&self, tools: Vec<ToolSchema>) -> Vec<OpenAITool> {
+    fn convert_tools(tools: Vec<ToolSchema>) -> Vec<OpenAITool> {
         tools
             .into_iter()
             .map(|tool| OpenAITool {
@@ -141,107 +158,24 @@ impl OpenAIAdapter {
             .collect()
     }
 
-    /// Process SSE byte stream into CompletionChunks
-    fn process_stream(
-        byte_stream: impl Stream<Item = reqwest::Result<Bytes>> + Send + 'static,
-    ) -> impl Stream<Item = Result<CompletionChunk>> + Send + 'static {
-        async_stream::stream! {
-            let mut handler = OpenAIStreamHandler::new();
-            let mut byte_stream = Box::pin(byte_stream);
-
-            while let Some(chunk_result) = byte_stream.next().await {
-                match chunk_result {
-                    Ok(bytes) => {
-                        // Parse SSE events from bytes
-                        let text = match std::str::from_utf8(&bytes) {
-                            Ok(t) => t,
-                            Err(e) => {
-                                yield Err(KodeError::Other(format!("Invalid UTF-8 in stream: {}", e)));
-                                continue;
-                            }
-                        };
-
-                        // Process the chunk
-                        match handler.process_chunk(text) {
-                            Ok(done) => {
-                                if done {
-                                    break;
-                                }
-                            }
-                            Err(e) => {
-                                yield Err(e);
-                                break;
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        yield Err(KodeError::NetworkError(e.to_string()));
-                        break;
-                    }
-                }
-            }
-
-            // Get final message and emit done event
-            match handler.get_message() {
-                Ok(assistant_message) => {
-                    // Extract content blocks from the message
-                    for block in &assistant_message.message.content {
-                        match block {
-                            ContentBlock::Text { text } => {
-                                yield Ok(CompletionChunk::TextDelta { text: text.clone() });
-                            }
-                            ContentBlock::Thinking { thinking } => {
-                                yield Ok(CompletionChunk::ThinkingDelta { thinking: thinking.clone() });
-                            }
-                            ContentBlock::ToolUse { id, name, input } => {
-                                yield Ok(CompletionChunk::ToolUseComplete {
-                                    id: id.clone(),
-                                    name: name.clone(),
-                                    input: input.clone(),
-                                });
-                            }
-                            _ => {}
-                        }
-                    }
-
-                    // Emit done event with usage stats
-                    yield Ok(CompletionChunk::Done {
-                        stop_reason: handler.get_finish_reason().unwrap_or_else(|| "stop".to_string()),
-                        usage: Some(handler.get_usage()),
-                    });
-                }
-                Err(e) => {
-                    yield Err(e);
-                }
-            }
-        }
-    }
-}
-
-#[async_trait]
-impl ModelAdapter for OpenAIAdapter {
-    fn provider(&self) -> &str {
-        "openai"
-    }
-
-    fn model(&self) -> &str {
-        &self.profile.model_name
-    }
-
-    async fn complete(
-        &self,
+    /// Build a Chat Completions request for a profile
+    fn chat_request(
+        profile: &ModelProfile,
         messages: Vec<Message>,
         tools: Vec<ToolSchema>,
         system_prompt: Option<String>,
         options: CompletionOptions,
-    ) -> Result<CompletionResponse> {
+        stream: bool,
+    ) -> OpenAIRequest {
+        let capabilities = profile.capabilities();
         let mut openai_messages = Vec::new();
 
-        // Add system message if provided
+        // Add system message if provided, as a user message for models
+        // rejecting the system role
         if let Some(system) = system_prompt {
             openai_messages.push(OpenAIMessage {
-                role: "system".to_string(),
-                content: Some(system),
+                role: if capabilities.system_prompt { "system" } else { "user" }.to_string(),
+                content: Some(OpenAIContent::Text(system)),
                 tool_calls: None,
                 tool_call_id: None,
                 name: None,
@@ -249,43 +183,53 @@ impl ModelAdapter for OpenAIAdapter {
         }
 
         // Add converted messages
-        openai_messages.extend(self.convert_messages(messages));
+        let messages = if capabilities.vision {
+            messages
+        } else {
+            messages.into_iter().map(Message::without_images).collect()
+        };
+        openai_messages.extend(Self::convert_messages(messages));
 
-        let request = OpenAIRequest {
-            model: self.profile.model_name.clone(),
+        let (temperature, top_p) = if capabilities.temperature {
+            (options.temperature, options.top_p)
+        } else {
+            (None, None)
+        };
+        let (max_tokens, max_completion_tokens) = match capabilities.max_tokens_param {
+            MaxTokensParam::MaxTokens => (options.max_tokens, None),
+            MaxTokensParam::MaxCompletionTokens => (None, options.max_tokens),
+        };
+        let reasoning_effort = if capabilities.reasoning {
+            options
+                .reasoning_effort
+                .or_else(|| profile.reasoning_effort.map(|effort| effort.as_str().to_string()))
+        } else {
+            None
+        };
+        let tools = match capabilities.tool_calling {
+            ToolCallingMode::Native if !tools.is_empty() => Some(Self::convert_tools(tools)),
+            _ => None,
+        };
+
+        OpenAIRequest {
+            model: profile.model_name.clone(),
             messages: openai_messages,
-            temperature: options.temperature,
-            max_tokens: options.max_tokens,
-            top_p: options.top_p,
+            temperature,
+            max_tokens,
+            max_completion_tokens,
+            top_p,
             stop: options.stop_sequences,
-            tools: if tools.is_empty() {
-                None
-            } else {
-                Some(self.convert_tools(tools))
-            },
+            reasoning_effort,
+            tools,
             tool_choice: None,
-            stream: Some(false),
-        };
-
-        let response = self
-            .client
-            .post(format!("{}/chat/completions", self.base_url))
-            .json(&request)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await?;
-            return Err(KodeError::ApiError {
-                provider: "openai".to_string(),
-                message: format!("HTTP {}: {}", status, error_text),
-            });
+            stream: Some(stream),
+            stream_options: (stream && capabilities.streaming_usage)
+                .then_some(OpenAIStreamOptions { include_usage: true }),
         }
+    }
 
-        let api_response: OpenAIResponse = response.json().await?;
-
-        // Convert OpenAI response to CompletionResponse
+    /// Convert a Chat Completions response
+    fn convert_response(api_response: OpenAIResponse) -> Result<CompletionResponse> {
         let choice = api_response.choices.into_iter().next().ok_or_else(|| {
             KodeError::ApiError {
                 provider: "openai".to_string(),
@@ -296,7 +240,7 @@ impl ModelAdapter for OpenAIAdapter {
         let mut content = Vec::new();
 
         // Add text content if present
-        if let Some(text) = choice.message.content {
+        if let Some(text) = choice.message.content.map(OpenAIContent::into_text) {
             if !text.is_empty() {
                 content.push(ContentBlock::Text { text });
             }
@@ -320,15 +264,86 @@ impl ModelAdapter for OpenAIAdapter {
             content,
             model: Some(api_response.model),
             stop_reason: Some(choice.finish_reason),
-            usage: api_response.usage.map(|u| Usage {
-                input_tokens: u.prompt_tokens,
-                output_tokens: u.completion_tokens,
-                cache_creation_input_tokens: None,
-                cache_read_input_tokens: None,
-            }),
+            usage: api_response.usage.map(Usage::from),
         })
     }
 
+    /// Process SSE byte stream into CompletionChunks
+    ///
+    /// Chunks are yielded as soon as each event is parsed.
+    fn process_stream(
+        byte_stream: impl Stream<Item = reqwest::Result<Bytes>> + Send + 'static,
+    ) -> impl Stream<Item = Result<CompletionChunk>> + Send + 'static {
+        async_stream::stream! {
+            let mut handler = OpenAIStreamHandler::new();
+            let mut byte_stream = Box::pin(byte_stream);
+
+            while let Some(chunk_result) = byte_stream.next().await {
+                let bytes = match chunk_result {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        yield Err(KodeError::NetworkError(e.to_string()));
+                        return;
+                    }
+                };
+
+                match handler.process_bytes(&bytes) {
+                    Ok(chunks) => {
+                        for chunk in chunks {
+                            yield Ok(chunk);
+                        }
+                    }
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                }
+                if handler.is_complete() {
+                    return;
+                }
+            }
+
+            // The connection closed without an end-of-stream event
+            match handler.finish() {
+                Ok(chunks) => {
+                    for chunk in chunks {
+                        yield Ok(chunk);
+                    }
+                }
+                Err(e) => yield Err(e),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl ModelAdapter for OpenAIAdapter {
+    fn provider(&self) -> &str {
+        "openai"
+    }
+
+    fn model(&self) -> &str {
+        &self.profile.model_name
+    }
+
+    async fn complete(
+        &self,
+        messages: Vec<Message>,
+        tools: Vec<ToolSchema>,
+        system_prompt: Option<String>,
+        options: CompletionOptions,
+    ) -> Result<CompletionResponse> {
+        let request = Self::chat_request(&self.profile, messages, tools, system_prompt, options, false);
+
+        let request = self
+            .client
+            .post(format!("{}/chat/completions", self.base_url))
+            .json(&request);
+        let response = retry::send(&self.profile.retry_policy(), "openai", request).await?;
+
+        Self::convert_response(response.json().await?)
+    }
+
     async fn stream_complete(
         &self,
         messages: Vec<Message>,
@@ -336,65 +351,170 @@ impl ModelAdapter for OpenAIAdapter {
         system_prompt: Option<String>,
         options: CompletionOptions,
     ) -> Result<CompletionStream> {
-        let mut openai_messages = Vec::new();
+        let request = Self::chat_request(&self.profile, messages, tools, system_prompt, options, true);
 
-        // Add system message if provided
-        if let Some(system) = system_prompt {
-            openai_messages.push(OpenAIMessage {
-                role: "system".to_string(),
-                content: Some(system),
-                tool_calls: None,
-                tool_call_id: None,
-                name: None,
-            });
-        }
+        let request = self
+            .client
+            .post(format!("{}/chat/completions", self.base_url))
+            .json(&request);
+        Ok(retry::stream(self.profile.retry_policy(), "openai", request, |response| {
+            Self::process_stream(response.bytes_stream())
+        }))
+    }
 
-        // Add converted messages
-        openai_messages.extend(self.convert_messages(messages));
+    fn pricing(&self) -> Option<ModelPricing> {
+        self.profile.pricing()
+    }
 
-        let request = OpenAIRequest {
-            model: self.profile.model_name.clone(),
-            messages: openai_messages,
-            temperature: options.temperature,
-            max_tokens: options.max_tokens,
-            top_p: options.top_p,
-            stop: options.stop_sequences,
-            tools: if tools.is_empty() {
-                None
-            } else {
-                Some(self.convert_tools(tools))
-            },
-            tool_choice: None,
-            stream: Some(true),
+    fn max_context_tokens(&self) -> u32 {
+        self.profile.context_length
+    }
+
+    fn max_output_tokens(&self) -> u32 {
+        self.profile.max_tokens
+    }
+}
+
+/// API version used when the profile does not name one
+const AZURE_API_VERSION: &str = "2024-10-21";
+
+/// Scope of Entra ID tokens for Azure AI services
+const AZURE_SCOPE: &str = "https://cognitiveservices.azure.com/.default";
+
+/// How Azure requests authenticate
+enum AzureAuth {
+    ApiKey(header::HeaderValue),
+    EntraId(AccessTokenProvider),
+}
+
+/// Adapter for models deployed on Azure
+///
+/// Azure serves each model from a named deployment at
+/// `{base_url}/openai/deployments/{deployment}/chat/completions`, with the API
+/// version as a query parameter. Requests authenticate with an `api-key`
+/// header, or with an Entra ID token printed by the profile's token command.
+pub struct AzureOpenAIAdapter {
+    client: Client,
+    profile: ModelProfile,
+    auth: AzureAuth,
+    url: String,
+}
+
+impl AzureOpenAIAdapter {
+    /// Create a new Azure adapter
+    ///
+    /// The base URL is the resource endpoint, such as
+    /// `https://my-resource.openai.azure.com`. The API key comes from the
+    /// profile, then `AZURE_OPENAI_API_KEY`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the base URL is missing, or the auth mode lacks
+    /// its API key or token command
+    pub fn new(profile: ModelProfile) -> Result<Self> {
+        let base_url = profile.base_url.clone().ok_or_else(|| {
+            KodeError::InvalidConfig(
+                "Azure OpenAI needs base_url set to the resource endpoint, e.g. https://<resource>.openai.azure.com"
+                    .to_string(),
+            )
+        })?;
+        let deployment = profile.deployment.as_deref().unwrap_or(&profile.model_name);
+        let api_version = profile.api_version.as_deref().unwrap_or(AZURE_API_VERSION);
+        let url = format!(
+            "{}/openai/deployments/{deployment}/chat/completions?api-version={api_version}",
+            base_url.trim_end_matches('/')
+        );
+
+        let auth = match profile.auth_mode.unwrap_or_default() {
+            AuthMode::ApiKey => {
+                let api_key = if profile.api_key.is_empty() {
+                    std::env::var("AZURE_OPENAI_API_KEY").map_err(|_| KodeError::MissingApiKey {
+                        provider: "azure".to_string(),
+                    })?
+                } else {
+                    profile.api_key.clone()
+                };
+                AzureAuth::ApiKey(header::HeaderValue::from_str(&api_key).map_err(|_| {
+                    KodeError::InvalidConfig("Invalid API key format".to_string())
+                })?)
+            }
+            AuthMode::EntraId => {
+                let command = profile.token_command.clone().ok_or_else(|| {
+                    KodeError::InvalidConfig(
+                        "Entra ID authentication needs a token_command, e.g. `az account get-access-token --resource https://cognitiveservices.azure.com --query accessToken -o tsv`"
+                            .to_string(),
+                    )
+                })?;
+                AzureAuth::EntraId(AccessTokenProvider::new(TokenSource::Command(command), AZURE_SCOPE))
+            }
         };
 
-        let response = self
-            .client
-            .post(format!("{}/chat/completions", self.base_url))
-            .json(&request)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await?;
-            return Err(KodeError::ApiError {
-                provider: "openai".to_string(),
-                message: format!("HTTP {}: {}", status, error_text),
-            });
-        }
+        Ok(Self {
+            client: Client::builder().build()?,
+            profile,
+            auth,
+            url,
+        })
+    }
+
+    /// Authenticated POST of a Chat Completions request
+    async fn post(&self, request: &OpenAIRequest) -> Result<reqwest::RequestBuilder> {
+        let builder = self.client.post(&self.url).json(request);
+        Ok(match &self.auth {
+            AzureAuth::ApiKey(api_key) => builder.header("api-key", api_key.clone()),
+            AzureAuth::EntraId(tokens) => builder.bearer_auth(tokens.token(&self.client).await?),
+        })
+    }
+}
+
+#[async_trait]
+impl ModelAdapter for AzureOpenAIAdapter {
+    fn provider(&self) -> &str {
+        "azure"
+    }
+
+    fn model(&self) -> &str {
+        &self.profile.model_name
+    }
 
-        // Create SSE stream
-        let byte_stream = response.bytes_stream();
-        let stream = Self::process_stream(byte_stream);
+    async fn complete(
+        &self,
+        messages: Vec<Message>,
+        tools: Vec<ToolSchema>,
+        system_prompt: Option<String>,
+        options: CompletionOptions,
+    ) -> Result<CompletionResponse> {
+        let request =
+            OpenAIAdapter::chat_request(&self.profile, messages, tools, system_prompt, options, false);
 
-        Ok(Box::pin(stream))
+        let request = self.post(&request).await?;
+        let response = retry::send(&self.profile.retry_policy(), "azure", request).await?;
+
+        OpenAIAdapter::convert_response(response.json().await?)
+    }
+
+    async fn stream_complete(
+        &self,
+        messages: Vec<Message>,
+        tools: Vec<ToolSchema>,
+        system_prompt: Option<String>,
+        options: CompletionOptions,
+    ) -> Result<CompletionStream> {
+        let request =
+            OpenAIAdapter::chat_request(&self.profile, messages, tools, system_prompt, options, true);
+
+        let request = self.post(&request).await?;
+        Ok(retry::stream(self.profile.retry_policy(), "azure", request, |response| {
+            OpenAIAdapter::process_stream(response.bytes_stream())
+        }))
+    }
+
+    fn pricing(&self) -> Option<ModelPricing> {
+        self.profile.pricing()
     }
 
     fn max_context_tokens(&self) -> u32 {
-        // Default context window for GPT-4 models
-        // TODO: Make this configurable per model
-        128_000
+        self.profile.context_length
     }
 
     fn max_output_tokens(&self) -> u32 {
@@ -413,22 +533,34 @@ struct OpenAIRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     max_tokens: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    max_completion_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     top_p: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     stop: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning_effort: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     tools: Option<Vec<OpenAITool>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     tool_choice: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<OpenAIStreamOptions>,
+}
+
+/// Asks for usage in a final stream chunk
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OpenAIStreamOptions {
+    include_usage: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct OpenAIMessage {
     role: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    content: Option<String>,
+    /// `null` for assistant messages that only call tools
+    content: Option<OpenAIContent>,
     #[serde(skip_serializing_if = "Option::is_none")]
     tool_calls: Option<Vec<OpenAIToolCall>>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -437,6 +569,74 @@ struct OpenAIMessage {
     name: Option<String>,
 }
 
+/// Message content: a plain string, or parts when images are attached
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum OpenAIContent {
+    Text(String),
+    Parts(Vec<OpenAIContentPart>),
+}
+
+impl OpenAIContent {
+    /// Plain text unless an image is present; `None` when there are no parts
+    fn from_parts(parts: Vec<OpenAIContentPart>) -> Option<Self> {
+        if parts.is_empty() {
+            return None;
+        }
+        if parts.iter().any(|part| matches!(part, OpenAIContentPart::ImageUrl { .. })) {
+            return Some(Self::Parts(parts));
+        }
+        Some(Self::Text(Self::Parts(parts).into_text()))
+    }
+
+    /// Text of the content, ignoring images
+    fn into_text(self) -> String {
+        match self {
+            Self::Text(text) => text,
+            Self::Parts(parts) => parts
+                .into_iter()
+                .filter_map(|part| match part {
+                    OpenAIContentPart::Text { text } => Some(text),
+                    OpenAIContentPart::ImageUrl { .. } => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OpenAIContentPart {
+    Text { text: String },
+    ImageUrl { image_url: OpenAIImageUrl },
+}
+
+impl OpenAIContentPart {
+    fn text(text: impl Into<String>) -> Self {
+        Self::Text { text: text.into() }
+    }
+
+    /// Inline images become data URLs; uploaded files cannot be referenced
+    fn image(source: &ImageSource) -> Self {
+        match source {
+            ImageSource::Base64 { media_type, data } => Self::ImageUrl {
+                image_url: OpenAIImageUrl {
+                    url: format!("data:{media_type};base64,{data}"),
+                },
+            },
+            ImageSource::File { file_id } => {
+                Self::text(format!("[Image file {file_id} is not available to this model]"))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OpenAIImageUrl {
+    url: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct OpenAIToolCall {
     id: String,
@@ -483,9 +683,316 @@ struct OpenAIChoice {
     finish_reason: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct OpenAIUsage {
-    prompt_tokens: u32,
-    completion_tokens: u32,
-    total_tokens: u32,
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::config::models::ProviderType;
+
+    #[test]
+    fn test_images_become_data_urls() {
+        let messages = vec![
+            Message::user("plain text"),
+            Message {
+                role: Role::User,
+                content: vec![
+                    ContentBlock::Text {
+                        text: "What is this?".to_string(),
+                    },
+                    ContentBlock::Image {
+                        source: ImageSource::Base64 {
+                            media_type: "image/jpeg".to_string(),
+                            data: "/9j/4AAQ".to_string(),
+                        },
+                    },
+                ],
+                uuid: None,
+            },
+        ];
+
+        let converted = serde_json::to_value(OpenAIAdapter::convert_messages(messages)).unwrap();
+        assert_eq!(converted[0]["content"], json!("plain text"));
+        assert_eq!(
+            converted[1]["content"],
+            json!([
+                {"type": "text", "text": "What is this?"},
+                {"type": "image_url", "image_url": {"url": "data:image/jpeg;base64,/9j/4AAQ"}},
+            ])
+        );
+    }
+
+    #[test]
+    fn test_tool_results_become_tool_messages() {
+        let messages = vec![
+            Message {
+                role: Role::Assistant,
+                content: vec![
+                    ContentBlock::ToolUse {
+                        id: "call_1".to_string(),
+                        name: "Bash".to_string(),
+                        input: json!({"command": "ls"}),
+                    },
+                    ContentBlock::ToolUse {
+                        id: "call_2".to_string(),
+                        name: "View".to_string(),
+                        input: json!({"file_path": "shot.png"}),
+                    },
+                ],
+                uuid: None,
+            },
+            Message {
+                role: Role::User,
+                content: vec![
+                    ContentBlock::ToolResult {
+                        tool_use_id: "call_1".to_string(),
+                        content: "src".to_string(),
+                        is_error: None,
+                        images: Vec::new(),
+                    },
+                    ContentBlock::ToolResult {
+                        tool_use_id: "call_2".to_string(),
+                        content: "Read image file (base64 encoded)".to_string(),
+                        is_error: None,
+                        images: vec![ImageSource::Base64 {
+                            media_type: "image/png".to_string(),
+                            data: "iVBORw0KGgo=".to_string(),
+                        }],
+                    },
+                    ContentBlock::Text {
+                        text: "Now fix it".to_string(),
+                    },
+                ],
+                uuid: None,
+            },
+            Message {
+                role: Role::User,
+                content: vec![ContentBlock::ToolResult {
+                    tool_use_id: "call_3".to_string(),
+                    content: "done".to_string(),
+                    is_error: Some(true),
+                    images: Vec::new(),
+                }],
+                uuid: None,
+            },
+        ];
+
+        let converted = serde_json::to_value(OpenAIAdapter::convert_messages(messages)).unwrap();
+        assert_eq!(
+            converted,
+            json!([
+                {
+                    "role": "assistant",
+                    "content": null,
+                    "tool_calls": [
+                        {"id": "call_1", "type": "function", "function": {"name": "Bash", "arguments": "{\"command\":\"ls\"}"}},
+                        {"id": "call_2", "type": "function", "function": {"name": "View", "arguments": "{\"file_path\":\"shot.png\"}"}},
+                    ],
+                },
+                {"role": "tool", "content": "src", "tool_call_id": "call_1"},
+                {"role": "tool", "content": "Read image file (base64 encoded)", "tool_call_id": "call_2"},
+                {
+                    "role": "user",
+                    "content": [
+                        {"type": "text", "text": "Images returned by tool call call_2:"},
+                        {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBORw0KGgo="}},
+                        {"type": "text", "text": "Now fix it"},
+                    ],
+                },
+                {"role": "tool", "content": "done", "tool_call_id": "call_3"},
+            ])
+        );
+    }
+
+    #[test]
+    fn test_request_follows_capabilities() {
+        let tools = || {
+            vec![ToolSchema {
+                name: "Bash".to_string(),
+                description: "Run a command".to_string(),
+                input_schema: json!({"type": "object"}),
+            }]
+        };
+        let messages = || {
+            vec![Message {
+                role: Role::User,
+                content: vec![ContentBlock::Image {
+                    source: ImageSource::Base64 {
+                        media_type: "image/png".to_string(),
+                        data: "iVBORw0KGgo=".to_string(),
+                    },
+                }],
+                uuid: None,
+            }]
+        };
+        let request = |profile: &ModelProfile| {
+            let request = OpenAIAdapter::chat_request(
+                profile,
+                messages(),
+                tools(),
+                Some("Be brief".to_string()),
+                CompletionOptions {
+                    reasoning_effort: Some("low".to_string()),
+                    ..CompletionOptions::default()
+                },
+                true,
+            );
+            serde_json::to_value(request).unwrap()
+        };
+
+        let gpt4o = ModelProfile::with_defaults(String::new(), ProviderType::OpenAI, "gpt-4o".to_string(), String::new());
+        let gpt4o_body = request(&gpt4o);
+        assert!(gpt4o_body["temperature"].is_number());
+        assert_eq!(gpt4o_body["max_completion_tokens"], 8192);
+        assert!(gpt4o_body.get("max_tokens").is_none());
+        assert!(gpt4o_body.get("reasoning_effort").is_none());
+        assert_eq!(gpt4o_body["stream_options"], json!({"include_usage": true}));
+        assert_eq!(gpt4o_body["messages"][1]["content"][0]["type"], "image_url");
+
+        let o1_mini = ModelProfile::with_defaults(String::new(), ProviderType::OpenAI, "o1-mini".to_string(), String::new());
+        let o1_body = request(&o1_mini);
+        assert!(o1_body.get("temperature").is_none());
+        assert!(o1_body.get("tools").is_none());
+        assert!(o1_body.get("reasoning_effort").is_none());
+        assert_eq!(o1_body["messages"][0]["role"], "user");
+
+        let o3_mini = ModelProfile::with_defaults(String::new(), ProviderType::OpenAI, "o3-mini".to_string(), String::new());
+        assert_eq!(request(&o3_mini)["reasoning_effort"], "low");
+
+        let r1 = ModelProfile::with_defaults(
+            String::new(),
+            ProviderType::Siliconflow,
+            "deepseek-ai/DeepSeek-R1".to_string(),
+            String::new(),
+        );
+        let deepseek_body = request(&r1);
+        assert_eq!(deepseek_body["max_tokens"], 8192);
+        assert!(deepseek_body.get("tools").is_none());
+        assert!(deepseek_body.get("stream_options").is_none());
+        assert_eq!(deepseek_body["messages"][0]["role"], "system");
+        assert_eq!(
+            deepseek_body["messages"][1]["content"],
+            "[Image omitted: this model does not accept images]"
+        );
+        assert_eq!((r1.context_length, r1.max_tokens), (131_072, 32_768));
+    }
+
+    fn azure_profile(base_url: &str) -> ModelProfile {
+        let mut profile = ModelProfile::new(
+            "azure gpt-4o".to_string(),
+            ProviderType::Azure,
+            "gpt-4o".to_string(),
+            "azure-key".to_string(),
+            4096,
+            128_000,
+        );
+        profile.base_url = Some(format!("{base_url}/"));
+        profile.deployment = Some("gpt4o-prod".to_string());
+        profile.api_version = Some("2025-01-01-preview".to_string());
+        profile
+    }
+
+    #[tokio::test]
+    async fn test_azure_api_key() {
+        use wiremock::{
+            matchers::{header, method, path, query_param},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/openai/deployments/gpt4o-prod/chat/completions"))
+            .and(query_param("api-version", "2025-01-01-preview"))
+            .and(header("api-key", "azure-key"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion",
+                "created": 1_700_000_000,
+                "model": "gpt-4o-2024-11-20",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "Hello from Azure"},
+                    "finish_reason": "stop"
+                }],
+                "usage": {
+                    "prompt_tokens": 9,
+                    "completion_tokens": 4,
+                    "total_tokens": 13,
+                    "prompt_tokens_details": {"cached_tokens": 6}
+                }
+            })))
+            .mount(&server)
+            .await;
+
+        let adapter = AzureOpenAIAdapter::new(azure_profile(&server.uri())).unwrap();
+        let response = adapter
+            .complete(vec![Message::user("Hi")], Vec::new(), None, CompletionOptions::default())
+            .await
+            .unwrap();
+
+        assert!(matches!(&response.content[..], [ContentBlock::Text { text }] if text == "Hello from Azure"));
+        // Cached prompt tokens are priced as cache reads
+        let usage = response.usage.unwrap();
+        assert_eq!(usage.input_tokens, 3);
+        assert_eq!(usage.cache_read_input_tokens, Some(6));
+    }
+
+    #[tokio::test]
+    async fn test_azure_entra_id_stream() {
+        use futures::StreamExt;
+        use wiremock::{
+            matchers::{header, method, path},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        let events = [
+            json!({"id": "c1", "object": "chat.completion.chunk", "created": 1_700_000_000, "model": "gpt-4o", "choices": [{"index": 0, "delta": {"role": "assistant", "content": "Streamed"}, "finish_reason": null}]}),
+            json!({"id": "c1", "object": "chat.completion.chunk", "created": 1_700_000_000, "model": "gpt-4o", "choices": [{"index": 0, "delta": {}, "finish_reason": "stop"}]}),
+        ];
+        let body = events
+            .iter()
+            .map(|event| format!("data: {event}\n\n"))
+            .chain(std::iter::once("data: [DONE]\n\n".to_string()))
+            .collect::<Vec<_>>()
+            .concat();
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/openai/deployments/gpt4o-prod/chat/completions"))
+            .and(header("authorization", "Bearer entra-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+            .mount(&server)
+            .await;
+
+        let mut profile = azure_profile(&server.uri());
+        profile.api_key = String::new();
+        profile.auth_mode = Some(AuthMode::EntraId);
+        profile.token_command = Some("echo entra-token".to_string());
+        let adapter = AzureOpenAIAdapter::new(profile).unwrap();
+
+        let text: String = adapter
+            .stream_complete(vec![Message::user("Hi")], Vec::new(), None, CompletionOptions::default())
+            .await
+            .unwrap()
+            .filter_map(|chunk| async move {
+                match chunk.unwrap() {
+                    CompletionChunk::TextDelta { text } => Some(text),
+                    _ => None,
+                }
+            })
+            .collect()
+            .await;
+        assert_eq!(text, "Streamed");
+    }
+
+    #[test]
+    fn test_azure_config_errors() {
+        let mut profile = azure_profile("http://localhost");
+        profile.base_url = None;
+        assert!(AzureOpenAIAdapter::new(profile).is_err());
+
+        let mut profile = azure_profile("http://localhost");
+        profile.auth_mode = Some(AuthMode::EntraId);
+        assert!(AzureOpenAIAdapter::new(profile).is_err());
+    }
 }
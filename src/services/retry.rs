@@ -0,0 +1,500 @@
+//! Retries for transient provider failures
+//!
+//! Rate limits (429), overload (529), other server errors and dropped
+//! connections are retried with jittered exponential backoff. When the
+//! provider says how long to wait, through `retry-after` or, on a rate limit,
+//! the reset headers of the exhausted limit, that delay is used instead. An
+//! explicit `x-should-retry` header overrides the status-based decision.
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::BuildHasher,
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt};
+use reqwest::{header::HeaderMap, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use super::{CompletionChunk, CompletionStream};
+use crate::error::{KodeError, Result};
+
+/// Longest server-requested delay that is waited; longer requests wait this long
+const MAX_HEADER_DELAY: Duration = Duration::from_secs(60);
+
+/// Rate-limit headers carrying what is left of a limit and the time at which
+/// it resets
+const RESET_TIMESTAMP_HEADERS: &[(&str, &str)] = &[
+    ("anthropic-ratelimit-requests-remaining", "anthropic-ratelimit-requests-reset"),
+    ("anthropic-ratelimit-tokens-remaining", "anthropic-ratelimit-tokens-reset"),
+    ("anthropic-ratelimit-input-tokens-remaining", "anthropic-ratelimit-input-tokens-reset"),
+    ("anthropic-ratelimit-output-tokens-remaining", "anthropic-ratelimit-output-tokens-reset"),
+];
+
+/// Rate-limit headers carrying what is left of a limit and the time until it
+/// resets, e.g. `6m0s`
+const RESET_DURATION_HEADERS: &[(&str, &str)] = &[
+    ("x-ratelimit-remaining-requests", "x-ratelimit-reset-requests"),
+    ("x-ratelimit-remaining-tokens", "x-ratelimit-reset-tokens"),
+];
+
+/// How failed requests are retried
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; 0 disables retrying
+    pub max_retries: u32,
+
+    /// Backoff before the first retry, doubled for every further retry
+    pub initial_delay_ms: u64,
+
+    /// Upper bound of the backoff
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            initial_delay_ms: 500,
+            max_delay_ms: 32_000,
+        }
+    }
+}
+
+/// Outcome of a single attempt
+enum Attempt {
+    Success(Response),
+    Retry { delay: Duration, reason: String },
+}
+
+impl RetryPolicy {
+    /// Jittered exponential backoff before retry number `retry` (starting at 0)
+    #[must_use]
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let base = self
+            .initial_delay_ms
+            .saturating_mul(1 << retry.min(20))
+            .min(self.max_delay_ms);
+        // Shaving off up to a quarter spreads out clients that failed together
+        let jitter = RandomState::new().hash_one(retry) % (base / 4 + 1);
+        Duration::from_millis(base - jitter)
+    }
+}
+
+/// Send `request`, retrying transient failures as `policy` allows
+///
+/// # Errors
+///
+/// Returns the last error once the request fails for good: an
+/// [`KodeError::ApiError`] with the response body for HTTP errors
+pub async fn send(policy: &RetryPolicy, provider: &str, request: RequestBuilder) -> Result<Response> {
+    let mut retry = 0;
+    loop {
+        match attempt(policy, provider, &request, retry).await? {
+            Attempt::Success(response) => return Ok(response),
+            Attempt::Retry { delay, reason } => {
+                tracing::debug!("{provider}: {reason}, retrying in {delay:?}");
+                tokio::time::sleep(delay).await;
+                retry += 1;
+            }
+        }
+    }
+}
+
+/// Send a streaming `request`, retrying transient failures
+///
+/// Every wait is announced with a [`CompletionChunk::Retrying`]; once a
+/// response arrives, the chunks `process` produces from it follow. Errors
+/// after the response has started streaming are not retried.
+pub fn stream<F, S>(
+    policy: RetryPolicy,
+    provider: &str,
+    request: RequestBuilder,
+    process: F,
+) -> CompletionStream
+where
+    F: FnOnce(Response) -> S + Send + 'static,
+    S: Stream<Item = Result<CompletionChunk>> + Send + 'static,
+{
+    let provider = provider.to_string();
+    Box::pin(async_stream::try_stream! {
+        let mut retry = 0;
+        let response = loop {
+            match attempt(&policy, &provider, &request, retry).await? {
+                Attempt::Success(response) => break response,
+                Attempt::Retry { delay, reason } => {
+                    yield CompletionChunk::Retrying {
+                        attempt: retry + 1,
+                        max_retries: policy.max_retries,
+                        delay_ms: u64::try_from(delay.as_millis()).unwrap_or(u64::MAX),
+                        reason,
+                    };
+                    tokio::time::sleep(delay).await;
+                    retry += 1;
+                }
+            }
+        };
+
+        let mut chunks = Box::pin(process(response));
+        while let Some(chunk) = chunks.next().await {
+            yield chunk?;
+        }
+    })
+}
+
+async fn attempt(
+    policy: &RetryPolicy,
+    provider: &str,
+    request: &RequestBuilder,
+    retry: u32,
+) -> Result<Attempt> {
+    let request = request
+        .try_clone()
+        .ok_or_else(|| KodeError::Other("Request cannot be retried".to_string()))?;
+    let can_retry = retry < policy.max_retries;
+
+    match request.send().await {
+        Ok(response) if response.status().is_success() => Ok(Attempt::Success(response)),
+        Ok(response) => {
+            let status = response.status();
+            if can_retry && should_retry(status, response.headers()) {
+                return Ok(Attempt::Retry {
+                    delay: header_delay(status, response.headers())
+                        .unwrap_or_else(|| policy.backoff(retry)),
+                    reason: format!("HTTP {status}"),
+                });
+            }
+            let body = response.text().await?;
+            Err(KodeError::ApiError {
+                provider: provider.to_string(),
+                message: format!("HTTP {status}: {body}"),
+            })
+        }
+        // Connection failures, resets and timeouts before a response
+        Err(e) if can_retry && (e.is_connect() || e.is_timeout() || e.is_request()) => {
+            Ok(Attempt::Retry {
+                delay: policy.backoff(retry),
+                reason: "connection error".to_string(),
+            })
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Whether a failed response is worth retrying
+fn should_retry(status: StatusCode, headers: &HeaderMap) -> bool {
+    match header_str(headers, "x-should-retry") {
+        Some("true") => true,
+        Some("false") => false,
+        _ => {
+            matches!(status.as_u16(), 408 | 409 | 429) || status.is_server_error() || status.as_u16() == 529
+        }
+    }
+}
+
+/// Delay requested by the provider, if it names one, capped at [`MAX_HEADER_DELAY`]
+///
+/// Rate-limit reset headers come with every response, so they only count when
+/// a rate limit was hit.
+fn header_delay(status: StatusCode, headers: &HeaderMap) -> Option<Duration> {
+    let delay = retry_after(headers).or_else(|| {
+        (status == StatusCode::TOO_MANY_REQUESTS).then(|| rate_limit_reset(headers)).flatten()
+    })?;
+    Some(delay.min(MAX_HEADER_DELAY))
+}
+
+/// `retry-after-ms`, or `retry-after` in seconds or as an HTTP date
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    if let Some(ms) = header_str(headers, "retry-after-ms").and_then(|v| v.parse::<f64>().ok()) {
+        return Duration::try_from_secs_f64(ms / 1000.0).ok();
+    }
+
+    let value = header_str(headers, "retry-after")?;
+    if let Ok(seconds) = value.parse::<f64>() {
+        return Duration::try_from_secs_f64(seconds).ok();
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(until(date.with_timezone(&Utc)))
+}
+
+/// Longest wait until the exhausted rate limits named in the headers reset
+fn rate_limit_reset(headers: &HeaderMap) -> Option<Duration> {
+    let exhausted = |remaining: &str| {
+        header_str(headers, remaining).and_then(|v| v.parse::<u64>().ok()) == Some(0)
+    };
+    let timestamps = RESET_TIMESTAMP_HEADERS
+        .iter()
+        .filter(|(remaining, _)| exhausted(remaining))
+        .filter_map(|(_, reset)| {
+            let date = DateTime::parse_from_rfc3339(header_str(headers, reset)?).ok()?;
+            Some(until(date.with_timezone(&Utc)))
+        });
+    let durations = RESET_DURATION_HEADERS
+        .iter()
+        .filter(|(remaining, _)| exhausted(remaining))
+        .filter_map(|(_, reset)| parse_duration(header_str(headers, reset)?));
+    timestamps.chain(durations).max()
+}
+
+/// Parse a Go-style duration such as `1m30.5s`, `250ms` or `2h`
+fn parse_duration(value: &str) -> Option<Duration> {
+    let mut total = 0.0;
+    let mut rest = value.trim();
+    if rest.is_empty() {
+        return None;
+    }
+    while !rest.is_empty() {
+        let number_end = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let number: f64 = rest[..number_end].parse().ok()?;
+        rest = &rest[number_end..];
+
+        let unit_end = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(rest.len());
+        let seconds_per_unit = match &rest[..unit_end] {
+            "h" => 3600.0,
+            "m" => 60.0,
+            "s" => 1.0,
+            "ms" => 0.001,
+            _ => return None,
+        };
+        rest = &rest[unit_end..];
+        total += number * seconds_per_unit;
+    }
+    Duration::try_from_secs_f64(total).ok()
+}
+
+/// Time from now until `date`, zero if it has passed
+fn until(date: DateTime<Utc>) -> Duration {
+    (date - Utc::now()).to_std().unwrap_or(Duration::ZERO)
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name)?.to_str().ok().map(str::trim)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use reqwest::header::HeaderValue;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    fn fast_policy(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            initial_delay_ms: 1,
+            max_delay_ms: 5,
+        }
+    }
+
+    fn headers(pairs: &[(&'static str, String)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    async fn mount(server: &MockServer, response: ResponseTemplate, times: u64) {
+        Mock::given(method("POST"))
+            .and(path("/v1/messages"))
+            .respond_with(response)
+            .up_to_n_times(times)
+            .mount(server)
+            .await;
+    }
+
+    fn request(server: &MockServer) -> RequestBuilder {
+        reqwest::Client::new()
+            .post(format!("{}/v1/messages", server.uri()))
+            .json(&serde_json::json!({"model": "test"}))
+    }
+
+    #[test]
+    fn test_backoff_grows_with_jitter_and_cap() {
+        let policy = RetryPolicy::default();
+        for retry in 0..8 {
+            let base = (500u64 << retry).min(32_000);
+            let delay = u64::try_from(policy.backoff(retry).as_millis()).unwrap();
+            assert!(delay <= base && delay >= base - base / 4, "retry {retry}: {delay}ms");
+        }
+        assert!(policy.backoff(40) <= Duration::from_secs(32));
+    }
+
+    #[test]
+    fn test_should_retry() {
+        let none = HeaderMap::new();
+        for status in [408, 409, 429, 500, 502, 503, 529] {
+            assert!(should_retry(StatusCode::from_u16(status).unwrap(), &none), "{status}");
+        }
+        for status in [400, 401, 403, 404, 413] {
+            assert!(!should_retry(StatusCode::from_u16(status).unwrap(), &none), "{status}");
+        }
+
+        let never = headers(&[("x-should-retry", "false".to_string())]);
+        assert!(!should_retry(StatusCode::TOO_MANY_REQUESTS, &never));
+        let always = headers(&[("x-should-retry", "true".to_string())]);
+        assert!(should_retry(StatusCode::BAD_REQUEST, &always));
+    }
+
+    #[test]
+    fn test_header_delay() {
+        let limited = StatusCode::TOO_MANY_REQUESTS;
+        let seconds = headers(&[("retry-after", "7".to_string())]);
+        assert_eq!(header_delay(limited, &seconds), Some(Duration::from_secs(7)));
+        assert_eq!(
+            header_delay(StatusCode::SERVICE_UNAVAILABLE, &seconds),
+            Some(Duration::from_secs(7))
+        );
+
+        let millis = headers(&[
+            ("retry-after-ms", "1500".to_string()),
+            ("retry-after", "7".to_string()),
+        ]);
+        assert_eq!(header_delay(limited, &millis), Some(Duration::from_millis(1500)));
+
+        let date = (Utc::now() + chrono::Duration::seconds(30)).to_rfc2822();
+        let delay = header_delay(limited, &headers(&[("retry-after", date)])).unwrap();
+        assert!(delay > Duration::from_secs(25) && delay <= Duration::from_secs(30));
+
+        let hour = headers(&[("retry-after", "3600".to_string())]);
+        assert_eq!(header_delay(limited, &hour), Some(MAX_HEADER_DELAY));
+    }
+
+    #[test]
+    fn test_rate_limit_reset_headers() {
+        let limited = StatusCode::TOO_MANY_REQUESTS;
+        let reset = (Utc::now() + chrono::Duration::seconds(10)).to_rfc3339();
+        let tomorrow = (Utc::now() + chrono::Days::new(1)).to_rfc3339();
+        let anthropic = headers(&[
+            ("anthropic-ratelimit-tokens-remaining", "0".to_string()),
+            ("anthropic-ratelimit-tokens-reset", reset),
+            ("anthropic-ratelimit-requests-remaining", "49".to_string()),
+            ("anthropic-ratelimit-requests-reset", tomorrow),
+        ]);
+        let delay = header_delay(limited, &anthropic).unwrap();
+        assert!(delay > Duration::from_secs(5) && delay <= Duration::from_secs(10));
+
+        // Only the exhausted limit counts
+        let openai = headers(&[
+            ("x-ratelimit-remaining-requests", "0".to_string()),
+            ("x-ratelimit-reset-requests", "20.5s".to_string()),
+            ("x-ratelimit-remaining-tokens", "15000".to_string()),
+            ("x-ratelimit-reset-tokens", "6h0m0s".to_string()),
+        ]);
+        assert_eq!(header_delay(limited, &openai), Some(Duration::from_millis(20_500)));
+        let openai = headers(&[
+            ("x-ratelimit-remaining-tokens", "0".to_string()),
+            ("x-ratelimit-reset-tokens", "1m0.5s".to_string()),
+        ]);
+        assert_eq!(header_delay(limited, &openai), Some(MAX_HEADER_DELAY), "capped at a minute");
+
+        // Reset headers come with every response; other errors back off
+        assert_eq!(header_delay(StatusCode::INTERNAL_SERVER_ERROR, &openai), None);
+        let unexhausted = headers(&[
+            ("x-ratelimit-remaining-requests", "9999".to_string()),
+            ("x-ratelimit-reset-requests", "8h0m0s".to_string()),
+        ]);
+        assert_eq!(header_delay(limited, &unexhausted), None);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("6m0s"), Some(Duration::from_secs(360)));
+        assert_eq!(parse_duration("1h2m3s"), Some(Duration::from_secs(3723)));
+        assert_eq!(parse_duration("250ms"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_duration("soon"), None);
+        assert_eq!(parse_duration(""), None);
+    }
+
+    #[tokio::test]
+    async fn test_send_retries_rate_limits_then_succeeds() {
+        let server = MockServer::start().await;
+        mount(&server, ResponseTemplate::new(429).insert_header("retry-after", "0"), 1).await;
+        mount(&server, ResponseTemplate::new(529), 1).await;
+        mount(&server, ResponseTemplate::new(200).set_body_string("ok"), 1).await;
+
+        let response = send(&fast_policy(3), "anthropic", request(&server)).await.unwrap();
+
+        assert_eq!(response.text().await.unwrap(), "ok");
+        assert_eq!(server.received_requests().await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_send_gives_up_after_max_retries() {
+        let server = MockServer::start().await;
+        mount(&server, ResponseTemplate::new(529).set_body_string("overloaded"), 10).await;
+
+        let error = send(&fast_policy(2), "anthropic", request(&server)).await.unwrap_err();
+
+        assert!(error.to_string().contains("overloaded"));
+        assert_eq!(server.received_requests().await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_send_does_not_retry_client_errors() {
+        let server = MockServer::start().await;
+        mount(&server, ResponseTemplate::new(400).set_body_string("bad request"), 10).await;
+
+        let error = send(&fast_policy(3), "anthropic", request(&server)).await.unwrap_err();
+
+        assert!(matches!(error, KodeError::ApiError { .. }));
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_send_retries_connection_resets() {
+        // A server that hangs up on every connection without answering
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/v1/messages", listener.local_addr().unwrap());
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let counter = accepted.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                drop(socket);
+            }
+        });
+
+        let error = send(&fast_policy(2), "anthropic", reqwest::Client::new().post(url))
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, KodeError::Http(_)), "{error:?}");
+        assert_eq!(accepted.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_stream_announces_retries() {
+        let server = MockServer::start().await;
+        mount(&server, ResponseTemplate::new(503), 1).await;
+        mount(&server, ResponseTemplate::new(200).set_body_string("ok"), 1).await;
+
+        let stream = stream(fast_policy(3), "anthropic", request(&server), |response| {
+            futures::stream::once(async move {
+                Ok(CompletionChunk::TextDelta {
+                    text: response.text().await?,
+                })
+            })
+        });
+        let chunks: Vec<CompletionChunk> = stream.map(|chunk| chunk.unwrap()).collect().await;
+
+        assert_eq!(chunks.len(), 2);
+        assert!(matches!(
+            &chunks[0],
+            CompletionChunk::Retrying { attempt: 1, max_retries: 3, reason, .. } if reason.contains("503")
+        ));
+        assert!(matches!(&chunks[1], CompletionChunk::TextDelta { text } if text == "ok"));
+    }
+}
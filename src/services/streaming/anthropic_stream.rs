@@ -1,7 +1,8 @@
 //! Anthropic API streaming handler
 //!
-//! Processes Server-Sent Events from Anthropic's streaming API
-//! and assembles them into complete messages.
+//! Processes Server-Sent Events from Anthropic's streaming API, emitting
+//! completion chunks as each event arrives while assembling the complete
+//! message.
 
 use std::collections::HashMap;
 
@@ -10,7 +11,7 @@ use serde_json;
 use crate::{
     error::{KodeError, Result},
     messages::{AssistantMessage, ContentBlock, Message, Role},
-    services::Usage,
+    services::{CompletionChunk, Usage},
 };
 
 use super::{
@@ -39,6 +40,9 @@ pub struct AnthropicStreamHandler {
 
     /// Stop sequence
     stop_sequence: Option<String>,
+
+    /// Whether `message_stop` has been received
+    complete: bool,
 }
 
 impl AnthropicStreamHandler {
@@ -57,55 +61,114 @@ impl AnthropicStreamHandler {
             },
             stop_reason: None,
             stop_sequence: None,
+            complete: false,
         }
     }
 
+    /// Process a chunk of raw streaming bytes
+    ///
+    /// Returns the completion chunks for every event completed by these bytes
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for malformed events and provider error events
+    pub fn process_bytes(&mut self, bytes: &[u8]) -> Result<Vec<CompletionChunk>> {
+        let events = self.parser.parse_bytes(bytes);
+        self.process_events(events)
+    }
+
     /// Process a chunk of streaming data
     ///
-    /// Returns true if the stream is complete (message_stop received)
-    pub fn process_chunk(&mut self, chunk: &str) -> Result<bool> {
+    /// Returns the completion chunks for every event completed by this chunk
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for malformed events and provider error events
+    pub fn process_chunk(&mut self, chunk: &str) -> Result<Vec<CompletionChunk>> {
         let events = self.parser.parse_chunk(chunk);
+        self.process_events(events)
+    }
 
-        for event in events {
-            if self.process_event(event)? {
-                return Ok(true); // Stream complete
+    /// Process the JSON data of a single event delivered without SSE framing,
+    /// as Bedrock does
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for malformed events and provider error events
+    pub fn process_event_data(&mut self, data: &str) -> Result<Vec<CompletionChunk>> {
+        let event = SseEvent {
+            event_type: None,
+            data: data.to_string(),
+            id: None,
+            retry: None,
+        };
+        self.process_events(vec![event])
+    }
+
+    /// Whether the stream is complete (`message_stop` received)
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Finish a stream whose bytes have run out
+    ///
+    /// Processes a trailing event and emits `Done` if `message_stop` never came
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the trailing event is malformed or no message started
+    pub fn finish(&mut self) -> Result<Vec<CompletionChunk>> {
+        let mut chunks = Vec::new();
+        if let Some(event) = self.parser.flush().filter(|_| !self.complete) {
+            self.process_event(&event, &mut chunks)?;
+        }
+        if !self.complete {
+            if self.message_metadata.is_none() {
+                return Err(KodeError::Other("No message metadata received".to_string()));
             }
+            self.complete = true;
+            chunks.push(self.done_chunk());
         }
+        Ok(chunks)
+    }
 
-        Ok(false)
+    fn process_events(&mut self, events: Vec<SseEvent>) -> Result<Vec<CompletionChunk>> {
+        let mut chunks = Vec::new();
+        for event in events {
+            if self.complete {
+                break;
+            }
+            self.process_event(&event, &mut chunks)?;
+        }
+        Ok(chunks)
     }
 
-    /// Process a single SSE event
-    ///
-    /// Returns true if stream is complete
-    fn process_event(&mut self, event: SseEvent) -> Result<bool> {
+    /// Process a single SSE event, appending the chunks it produces
+    fn process_event(&mut self, event: &SseEvent, chunks: &mut Vec<CompletionChunk>) -> Result<()> {
         // Parse JSON data
         let stream_event: AnthropicStreamEvent = serde_json::from_str(&event.data)
-            .map_err(|e| KodeError::Other(format!("Failed to parse SSE event: {}", e)))?;
+            .map_err(|e| KodeError::Other(format!("Failed to parse SSE event: {e}")))?;
 
         match stream_event {
             AnthropicStreamEvent::MessageStart { message } => {
-                self.message_metadata = Some(message.clone());
-                self.usage = message.usage;
-                Ok(false)
+                self.usage = message.usage.clone();
+                self.message_metadata = Some(message);
             }
 
             AnthropicStreamEvent::ContentBlockStart {
                 index,
                 content_block,
             } => {
-                self.handle_content_block_start(index, content_block);
-                Ok(false)
+                chunks.extend(self.handle_content_block_start(index, content_block));
             }
 
             AnthropicStreamEvent::ContentBlockDelta { index, delta } => {
-                self.handle_content_block_delta(index, delta)?;
-                Ok(false)
+                chunks.extend(self.handle_content_block_delta(index, delta));
             }
 
             AnthropicStreamEvent::ContentBlockStop { index } => {
-                self.handle_content_block_stop(index)?;
-                Ok(false)
+                chunks.extend(self.handle_content_block_stop(index)?);
             }
 
             AnthropicStreamEvent::MessageDelta { delta, usage } => {
@@ -120,26 +183,41 @@ impl AnthropicStreamHandler {
                         self.usage.output_tokens = output_tokens;
                     }
                 }
-                Ok(false)
             }
 
             AnthropicStreamEvent::MessageStop => {
                 // Clear buffers
                 self.input_json_buffers.clear();
-                Ok(true) // Signal stream complete
+                self.complete = true;
+                chunks.push(self.done_chunk());
+            }
+
+            AnthropicStreamEvent::Ping => {}
+
+            AnthropicStreamEvent::Error { error } => {
+                return Err(KodeError::ApiError {
+                    provider: "Anthropic".to_string(),
+                    message: format!("Stream error: {} - {}", error.error_type, error.message),
+                });
             }
+        }
 
-            AnthropicStreamEvent::Ping => Ok(false),
+        Ok(())
+    }
 
-            AnthropicStreamEvent::Error { error } => Err(KodeError::ApiError {
-                provider: "Anthropic".to_string(),
-                message: format!("Stream error: {} - {}", error.error_type, error.message),
-            }),
+    fn done_chunk(&self) -> CompletionChunk {
+        CompletionChunk::Done {
+            stop_reason: self.stop_reason.clone().unwrap_or_else(|| "end_turn".to_string()),
+            usage: Some(self.usage.clone()),
         }
     }
 
     /// Handle content_block_start event
-    fn handle_content_block_start(&mut self, index: usize, content_block: ContentBlockStart) {
+    fn handle_content_block_start(
+        &mut self,
+        index: usize,
+        content_block: ContentBlockStart,
+    ) -> Option<CompletionChunk> {
         // Ensure vector is large enough
         while self.content_blocks.len() <= index {
             self.content_blocks.push(ContentBlock::Text {
@@ -149,27 +227,39 @@ impl AnthropicStreamHandler {
 
         match content_block {
             ContentBlockStart::Text { text } => {
-                self.content_blocks[index] = ContentBlock::Text { text };
+                self.content_blocks[index] = ContentBlock::Text { text: text.clone() };
+                (!text.is_empty()).then_some(CompletionChunk::TextDelta { text })
             }
             ContentBlockStart::ToolUse { id, name } => {
                 self.content_blocks[index] = ContentBlock::ToolUse {
-                    id,
-                    name,
+                    id: id.clone(),
+                    name: name.clone(),
                     input: serde_json::Value::Object(serde_json::Map::new()),
                 };
                 // Initialize JSON buffer
                 self.input_json_buffers.insert(index, String::new());
+                Some(CompletionChunk::ToolUseStart { id, name })
             }
             ContentBlockStart::Thinking { thinking } => {
                 self.content_blocks[index] = ContentBlock::Thinking {
-                    thinking,
+                    thinking: thinking.clone(),
+                    signature: None,
                 };
+                (!thinking.is_empty()).then_some(CompletionChunk::ThinkingDelta { thinking })
+            }
+            ContentBlockStart::RedactedThinking { data } => {
+                self.content_blocks[index] = ContentBlock::RedactedThinking { data: data.clone() };
+                Some(CompletionChunk::RedactedThinking { data })
             }
         }
     }
 
     /// Handle content_block_delta event
-    fn handle_content_block_delta(&mut self, index: usize, delta: ContentDelta) -> Result<()> {
+    fn handle_content_block_delta(
+        &mut self,
+        index: usize,
+        delta: ContentDelta,
+    ) -> Option<CompletionChunk> {
         // Ensure content block exists
         while self.content_blocks.len() <= index {
             self.content_blocks.push(ContentBlock::Text {
@@ -183,57 +273,85 @@ impl AnthropicStreamHandler {
                     existing.push_str(&text);
                 } else {
                     // Initialize if not already text block
-                    self.content_blocks[index] = ContentBlock::Text { text };
+                    self.content_blocks[index] = ContentBlock::Text { text: text.clone() };
                 }
+                Some(CompletionChunk::TextDelta { text })
             }
             ContentDelta::InputJsonDelta { partial_json } => {
                 // Accumulate JSON in buffer
                 self.input_json_buffers
                     .entry(index)
-                    .or_insert_with(String::new)
+                    .or_default()
                     .push_str(&partial_json);
+                match &self.content_blocks[index] {
+                    ContentBlock::ToolUse { id, .. } if !partial_json.is_empty() => {
+                        Some(CompletionChunk::ToolInputDelta {
+                            id: id.clone(),
+                            partial_json,
+                        })
+                    }
+                    _ => None,
+                }
             }
             ContentDelta::ThinkingDelta { thinking } => {
                 if let ContentBlock::Thinking {
                     thinking: ref mut existing,
+                    ..
                 } = self.content_blocks[index]
                 {
                     existing.push_str(&thinking);
                 } else {
                     // Initialize if not already thinking block
                     self.content_blocks[index] = ContentBlock::Thinking {
-                        thinking,
+                        thinking: thinking.clone(),
+                        signature: None,
                     };
                 }
+                Some(CompletionChunk::ThinkingDelta { thinking })
+            }
+            ContentDelta::SignatureDelta { signature } => {
+                if let ContentBlock::Thinking {
+                    signature: ref mut existing,
+                    ..
+                } = self.content_blocks[index]
+                {
+                    *existing = Some(signature.clone());
+                }
+                Some(CompletionChunk::ThinkingSignature { signature })
             }
         }
-
-        Ok(())
     }
 
     /// Handle content_block_stop event
-    fn handle_content_block_stop(&mut self, index: usize) -> Result<()> {
+    fn handle_content_block_stop(&mut self, index: usize) -> Result<Option<CompletionChunk>> {
         // If this is a tool_use block, parse the accumulated JSON
-        if let Some(json_str) = self.input_json_buffers.remove(&index) {
-            if let ContentBlock::ToolUse {
-                ref mut input,
-                ..
-            } = self.content_blocks[index]
-            {
-                *input = serde_json::from_str(&json_str).map_err(|e| {
-                    KodeError::Other(format!("Failed to parse tool input JSON: {}", e))
-                })?;
-            }
+        let Some(json_str) = self.input_json_buffers.remove(&index) else {
+            return Ok(None);
+        };
+        let Some(ContentBlock::ToolUse { id, name, input }) = self.content_blocks.get_mut(index)
+        else {
+            return Ok(None);
+        };
+
+        // Tools without parameters may stream no input at all
+        if !json_str.trim().is_empty() {
+            *input = serde_json::from_str(&json_str).map_err(|e| {
+                KodeError::Other(format!("Failed to parse tool input JSON: {e}"))
+            })?;
         }
 
-        Ok(())
+        Ok(Some(CompletionChunk::ToolUseComplete {
+            id: id.clone(),
+            name: name.clone(),
+            input: input.clone(),
+        }))
     }
 
     /// Get the assembled message
     ///
     /// Should be called after stream is complete
     pub fn get_message(&self) -> Result<AssistantMessage> {
-        let _metadata = self
+        let metadata = self
             .message_metadata
             .as_ref()
             .ok_or_else(|| KodeError::Other("No message metadata received".to_string()))?;
@@ -249,6 +367,8 @@ impl AnthropicStreamHandler {
             duration_ms: 0,
             is_api_error_message: None,
             response_id: None,
+            usage: Some(self.usage.clone()),
+            model: Some(metadata.model.clone()),
         })
     }
 
@@ -287,28 +407,33 @@ mod tests {
 data: {"type":"message_start","message":{"id":"msg_123","model":"claude-3","role":"assistant","type":"message","usage":{"input_tokens":10,"output_tokens":0}}}
 
 "#;
-        assert!(!handler.process_chunk(chunk1).unwrap());
+        assert!(handler.process_chunk(chunk1).unwrap().is_empty());
 
         // content_block_start
         let chunk2 = r#"event: content_block_start
 data: {"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}
 
 "#;
-        assert!(!handler.process_chunk(chunk2).unwrap());
+        assert!(handler.process_chunk(chunk2).unwrap().is_empty());
 
         // content_block_delta
         let chunk3 = r#"event: content_block_delta
 data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hello"}}
 
 "#;
-        assert!(!handler.process_chunk(chunk3).unwrap());
+        // The delta is emitted as soon as its event is parsed
+        let emitted = handler.process_chunk(chunk3).unwrap();
+        assert!(matches!(&emitted[..], [CompletionChunk::TextDelta { text }] if text == "Hello"));
+        assert!(!handler.is_complete());
 
         // message_stop
         let chunk4 = r#"event: message_stop
 data: {"type":"message_stop"}
 
 "#;
-        assert!(handler.process_chunk(chunk4).unwrap());
+        let emitted = handler.process_chunk(chunk4).unwrap();
+        assert!(matches!(&emitted[..], [CompletionChunk::Done { .. }]));
+        assert!(handler.is_complete());
 
         let message = handler.get_message().unwrap();
         assert_eq!(message.message.content.len(), 1);
@@ -335,14 +460,23 @@ data: {"type":"message_start","message":{"id":"msg_123","model":"claude-3","role
 data: {"type":"content_block_start","index":0,"content_block":{"type":"tool_use","id":"tool_1","name":"test_tool"}}
 
 "#;
-        handler.process_chunk(chunk2).unwrap();
+        let emitted = handler.process_chunk(chunk2).unwrap();
+        assert!(matches!(
+            &emitted[..],
+            [CompletionChunk::ToolUseStart { id, name }] if id == "tool_1" && name == "test_tool"
+        ));
 
         // input_json_delta
         let chunk3 = r#"event: content_block_delta
 data: {"type":"content_block_delta","index":0,"delta":{"type":"input_json_delta","partial_json":"{\"arg\":"}}
 
 "#;
-        handler.process_chunk(chunk3).unwrap();
+        let emitted = handler.process_chunk(chunk3).unwrap();
+        assert!(matches!(
+            &emitted[..],
+            [CompletionChunk::ToolInputDelta { id, partial_json }]
+                if id == "tool_1" && partial_json == "{\"arg\":"
+        ));
 
         let chunk4 = r#"event: content_block_delta
 data: {"type":"content_block_delta","index":0,"delta":{"type":"input_json_delta","partial_json":"\"value\"}"}}
@@ -355,7 +489,11 @@ data: {"type":"content_block_delta","index":0,"delta":{"type":"input_json_delta"
 data: {"type":"content_block_stop","index":0}
 
 "#;
-        handler.process_chunk(chunk5).unwrap();
+        let emitted = handler.process_chunk(chunk5).unwrap();
+        assert!(matches!(
+            &emitted[..],
+            [CompletionChunk::ToolUseComplete { input, .. }] if input["arg"] == "value"
+        ));
 
         // message_stop
         let chunk6 = r#"event: message_stop
@@ -372,4 +510,69 @@ data: {"type":"message_stop"}
             panic!("Expected tool_use block");
         }
     }
+
+    #[test]
+    fn test_bytes_split_inside_characters() {
+        let mut handler = AnthropicStreamHandler::new();
+        let stream = concat!(
+            "event: message_start\n",
+            r#"data: {"type":"message_start","message":{"id":"msg_123","model":"claude-3","role":"assistant","type":"message","usage":{"input_tokens":10,"output_tokens":0}}}"#,
+            "\n\nevent: content_block_delta\n",
+            r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"naïve ✓"}}"#,
+            "\n\n",
+        );
+
+        // Feed one byte at a time, as a worst-case network
+        let mut text = String::new();
+        for byte in stream.as_bytes() {
+            for emitted in handler.process_bytes(std::slice::from_ref(byte)).unwrap() {
+                if let CompletionChunk::TextDelta { text: delta } = emitted {
+                    text.push_str(&delta);
+                }
+            }
+        }
+        assert_eq!(text, "naïve ✓");
+
+        // The stream ends without message_stop
+        let emitted = handler.finish().unwrap();
+        assert!(matches!(&emitted[..], [CompletionChunk::Done { stop_reason, .. }] if stop_reason == "end_turn"));
+    }
+
+    #[test]
+    fn test_thinking_stream_keeps_signatures() {
+        let mut handler = AnthropicStreamHandler::new();
+        let stream = concat!(
+            "event: message_start\n",
+            r#"data: {"type":"message_start","message":{"id":"msg_123","model":"claude-3","role":"assistant","type":"message","usage":{"input_tokens":10,"output_tokens":0}}}"#,
+            "\n\nevent: content_block_start\n",
+            r#"data: {"type":"content_block_start","index":0,"content_block":{"type":"thinking","thinking":""}}"#,
+            "\n\nevent: content_block_delta\n",
+            r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"thinking_delta","thinking":"Hmm"}}"#,
+            "\n\nevent: content_block_delta\n",
+            r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"signature_delta","signature":"sig-1"}}"#,
+            "\n\nevent: content_block_start\n",
+            r#"data: {"type":"content_block_start","index":1,"content_block":{"type":"redacted_thinking","data":"opaque"}}"#,
+            "\n\n",
+        );
+
+        let emitted = handler.process_chunk(stream).unwrap();
+        assert!(matches!(
+            &emitted[..],
+            [
+                CompletionChunk::ThinkingDelta { thinking },
+                CompletionChunk::ThinkingSignature { signature },
+                CompletionChunk::RedactedThinking { data },
+            ] if thinking == "Hmm" && signature == "sig-1" && data == "opaque"
+        ));
+
+        handler.finish().unwrap();
+        let message = handler.get_message().unwrap();
+        assert!(matches!(
+            &message.message.content[..],
+            [
+                ContentBlock::Thinking { signature: Some(signature), .. },
+                ContentBlock::RedactedThinking { .. },
+            ] if signature == "sig-1"
+        ));
+    }
 }
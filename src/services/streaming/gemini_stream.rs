@@ -0,0 +1,247 @@
+//! Google Gemini streaming handler
+//!
+//! Processes the Server-Sent Events of `streamGenerateContent?alt=sse`. Each
+//! event is a partial `GenerateContentResponse`: text arrives in pieces,
+//! function calls arrive whole, and the stream simply ends after the event
+//! carrying the finish reason.
+
+use crate::{
+    error::{KodeError, Result},
+    services::{CompletionChunk, Usage},
+};
+
+use super::{gemini_stop_reason, GeminiResponse, SseEvent, SseParser};
+
+/// Handler for Gemini streaming responses
+pub struct GeminiStreamHandler {
+    /// SSE parser
+    parser: SseParser,
+
+    /// Whether any event was received
+    started: bool,
+
+    /// Latest usage report (counts are cumulative)
+    usage: Option<Usage>,
+
+    /// Finish reason of the candidate
+    finish_reason: Option<String>,
+
+    /// Whether the model called a function
+    has_tool_calls: bool,
+
+    /// Whether `Done` has been emitted
+    complete: bool,
+}
+
+impl GeminiStreamHandler {
+    /// Create a new handler
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            parser: SseParser::new(),
+            started: false,
+            usage: None,
+            finish_reason: None,
+            has_tool_calls: false,
+            complete: false,
+        }
+    }
+
+    /// Process a chunk of raw streaming bytes
+    ///
+    /// Returns the completion chunks for every event completed by these bytes
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for malformed events and blocked prompts
+    pub fn process_bytes(&mut self, bytes: &[u8]) -> Result<Vec<CompletionChunk>> {
+        let events = self.parser.parse_bytes(bytes);
+        self.process_events(events)
+    }
+
+    /// Process a chunk of streaming data
+    ///
+    /// Returns the completion chunks for every event completed by this chunk
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for malformed events and blocked prompts
+    pub fn process_chunk(&mut self, chunk: &str) -> Result<Vec<CompletionChunk>> {
+        let events = self.parser.parse_chunk(chunk);
+        self.process_events(events)
+    }
+
+    /// Whether `Done` has been emitted
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Finish the stream once its bytes have run out, emitting `Done`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the trailing event is malformed or nothing was received
+    pub fn finish(&mut self) -> Result<Vec<CompletionChunk>> {
+        let mut chunks = Vec::new();
+        if self.complete {
+            return Ok(chunks);
+        }
+        if let Some(event) = self.parser.flush() {
+            self.process_event(&event, &mut chunks)?;
+        }
+        if !self.started {
+            return Err(KodeError::Other("No response received".to_string()));
+        }
+        self.complete = true;
+        chunks.push(CompletionChunk::Done {
+            stop_reason: gemini_stop_reason(self.finish_reason.as_deref(), self.has_tool_calls),
+            usage: self.usage.take(),
+        });
+        Ok(chunks)
+    }
+
+    fn process_events(&mut self, events: Vec<SseEvent>) -> Result<Vec<CompletionChunk>> {
+        let mut chunks = Vec::new();
+        for event in events {
+            self.process_event(&event, &mut chunks)?;
+        }
+        Ok(chunks)
+    }
+
+    /// Process a single SSE event, appending the chunks it produces
+    fn process_event(&mut self, event: &SseEvent, chunks: &mut Vec<CompletionChunk>) -> Result<()> {
+        let response: GeminiResponse = serde_json::from_str(&event.data)
+            .map_err(|e| KodeError::Other(format!("Failed to parse SSE event: {e}")))?;
+        self.started = true;
+
+        if let Some(usage) = response.usage_metadata {
+            self.usage = Some(usage.into());
+        }
+        let Some(candidate) = response.candidates.into_iter().next() else {
+            if let Some(reason) = response.prompt_feedback.and_then(|f| f.block_reason) {
+                return Err(KodeError::ApiError {
+                    provider: "gemini".to_string(),
+                    message: format!("Prompt blocked: {reason}"),
+                });
+            }
+            return Ok(());
+        };
+
+        for part in candidate.content.map(|content| content.parts).unwrap_or_default() {
+            if let Some(text) = part.text.filter(|text| !text.is_empty()) {
+                chunks.push(if part.thought == Some(true) {
+                    CompletionChunk::ThinkingDelta { thinking: text }
+                } else {
+                    CompletionChunk::TextDelta { text }
+                });
+            }
+            if let Some(call) = part.function_call {
+                self.has_tool_calls = true;
+                let id = call.call_id();
+                let input = call.input();
+                chunks.push(CompletionChunk::ToolUseStart {
+                    id: id.clone(),
+                    name: call.name.clone(),
+                });
+                chunks.push(CompletionChunk::ToolInputDelta {
+                    id: id.clone(),
+                    partial_json: input.to_string(),
+                });
+                chunks.push(CompletionChunk::ToolUseComplete {
+                    id,
+                    name: call.name,
+                    input,
+                });
+            }
+        }
+        if candidate.finish_reason.is_some() {
+            self.finish_reason = candidate.finish_reason;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for GeminiStreamHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn event(data: &serde_json::Value) -> String {
+        format!("data: {data}\r\n\r\n")
+    }
+
+    #[test]
+    fn test_text_and_function_call() {
+        let mut handler = GeminiStreamHandler::new();
+
+        let first = event(&json!({
+            "candidates": [{"content": {"role": "model", "parts": [
+                {"text": "Weighing options", "thought": true},
+                {"text": "Let me "}
+            ]}}]
+        }));
+        let emitted = handler.process_chunk(&first).unwrap();
+        assert!(matches!(&emitted[0], CompletionChunk::ThinkingDelta { thinking } if thinking == "Weighing options"));
+        assert!(matches!(&emitted[1], CompletionChunk::TextDelta { text } if text == "Let me "));
+
+        let last = event(&json!({
+            "candidates": [{
+                "content": {"role": "model", "parts": [
+                    {"text": "look."},
+                    {"functionCall": {"name": "Bash", "args": {"command": "ls"}}}
+                ]},
+                "finishReason": "STOP"
+            }],
+            "usageMetadata": {"promptTokenCount": 30, "candidatesTokenCount": 8, "thoughtsTokenCount": 4},
+            "modelVersion": "gemini-2.5-pro"
+        }));
+        let emitted = handler.process_chunk(&last).unwrap();
+        assert!(matches!(&emitted[0], CompletionChunk::TextDelta { text } if text == "look."));
+        let CompletionChunk::ToolUseStart { id, name } = &emitted[1] else {
+            panic!("Expected tool use start, got {:?}", emitted[1]);
+        };
+        assert!(id.starts_with("call_"));
+        assert_eq!(name, "Bash");
+        assert!(matches!(
+            &emitted[3],
+            CompletionChunk::ToolUseComplete { id: done_id, input, .. }
+                if done_id == id && *input == json!({"command": "ls"})
+        ));
+
+        let emitted = handler.finish().unwrap();
+        match &emitted[..] {
+            [CompletionChunk::Done {
+                stop_reason,
+                usage: Some(usage),
+            }] => {
+                assert_eq!(stop_reason, "tool_use");
+                assert_eq!((usage.input_tokens, usage.output_tokens), (30, 12));
+            }
+            other => panic!("Expected done, got {other:?}"),
+        }
+        assert!(handler.is_complete());
+    }
+
+    #[test]
+    fn test_blocked_prompt() {
+        let mut handler = GeminiStreamHandler::new();
+        let blocked = event(&json!({"promptFeedback": {"blockReason": "SAFETY"}}));
+
+        let error = handler.process_chunk(&blocked).unwrap_err();
+        assert!(error.to_string().contains("Prompt blocked: SAFETY"));
+    }
+
+    #[test]
+    fn test_empty_stream() {
+        assert!(GeminiStreamHandler::new().finish().is_err());
+    }
+}
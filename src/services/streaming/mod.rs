@@ -4,11 +4,19 @@
 //! from various AI providers (Anthropic, OpenAI, etc.).
 
 pub mod anthropic_stream;
+pub mod event_stream;
+pub mod gemini_stream;
+pub mod ollama_stream;
 pub mod openai_stream;
+pub mod responses_stream;
 pub mod sse_parser;
 
 pub use anthropic_stream::AnthropicStreamHandler;
+pub use event_stream::{EventStreamDecoder, EventStreamMessage};
+pub use gemini_stream::GeminiStreamHandler;
+pub use ollama_stream::OllamaStreamHandler;
 pub use openai_stream::OpenAIStreamHandler;
+pub use responses_stream::ResponsesStreamHandler;
 pub use sse_parser::{SseEvent, SseParser};
 
 use crate::services::Usage;
@@ -83,6 +91,9 @@ pub enum ContentBlockStart {
     Thinking {
         thinking: String,
     },
+    RedactedThinking {
+        data: String,
+    },
 }
 
 /// Content delta types
@@ -98,6 +109,9 @@ pub enum ContentDelta {
     ThinkingDelta {
         thinking: String,
     },
+    SignatureDelta {
+        signature: String,
+    },
 }
 
 /// Message delta data
@@ -129,7 +143,8 @@ pub struct OpenAIStreamChunk {
     pub created: u64,
     pub model: String,
     pub choices: Vec<OpenAIChoice>,
-    pub usage: Option<Usage>,
+    #[serde(default)]
+    pub usage: Option<OpenAIUsage>,
 }
 
 /// OpenAI choice in stream
@@ -164,3 +179,445 @@ pub struct FunctionDelta {
     pub name: Option<String>,
     pub arguments: Option<String>,
 }
+
+/// Token usage of a Chat Completions response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAIUsage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    #[serde(default)]
+    pub prompt_tokens_details: Option<InputTokensDetails>,
+}
+
+impl From<OpenAIUsage> for Usage {
+    /// Cached tokens are reported apart from the other prompt tokens, as
+    /// Anthropic does
+    fn from(usage: OpenAIUsage) -> Self {
+        let cached = usage.prompt_tokens_details.map(|details| details.cached_tokens);
+        Self {
+            input_tokens: usage.prompt_tokens.saturating_sub(cached.unwrap_or(0)),
+            output_tokens: usage.completion_tokens,
+            cache_creation_input_tokens: None,
+            cache_read_input_tokens: cached,
+        }
+    }
+}
+
+/// Response object of the Responses API (`/v1/responses`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponsesResponse {
+    pub id: String,
+    pub model: String,
+    #[serde(default)]
+    pub status: Option<String>,
+    #[serde(default)]
+    pub output: Vec<ResponsesOutputItem>,
+    #[serde(default)]
+    pub usage: Option<ResponsesUsage>,
+    #[serde(default)]
+    pub incomplete_details: Option<IncompleteDetails>,
+    #[serde(default)]
+    pub error: Option<ResponsesError>,
+}
+
+impl ResponsesResponse {
+    /// Stop reason in the terms the rest of Kode uses
+    #[must_use]
+    pub fn stop_reason(&self, has_tool_calls: bool) -> String {
+        if let Some(details) = &self.incomplete_details {
+            return match details.reason.as_str() {
+                "max_output_tokens" => "max_tokens".to_string(),
+                reason => reason.to_string(),
+            };
+        }
+        if has_tool_calls { "tool_use" } else { "end_turn" }.to_string()
+    }
+}
+
+/// Output item of a response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponsesOutputItem {
+    Message {
+        #[serde(default)]
+        content: Vec<ResponsesOutputContent>,
+    },
+    Reasoning {
+        id: String,
+        #[serde(default)]
+        summary: Vec<ResponsesSummaryPart>,
+        #[serde(default)]
+        encrypted_content: Option<String>,
+    },
+    FunctionCall {
+        call_id: String,
+        name: String,
+        #[serde(default)]
+        arguments: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// Content of an output message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponsesOutputContent {
+    OutputText { text: String },
+    Refusal { refusal: String },
+    #[serde(other)]
+    Other,
+}
+
+/// Part of a reasoning summary
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponsesSummaryPart {
+    SummaryText { text: String },
+}
+
+/// Join the parts of a reasoning summary into one text
+#[must_use]
+pub fn join_summary(parts: &[ResponsesSummaryPart]) -> String {
+    parts
+        .iter()
+        .map(|ResponsesSummaryPart::SummaryText { text }| text.as_str())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Token usage of a response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponsesUsage {
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    #[serde(default)]
+    pub input_tokens_details: Option<InputTokensDetails>,
+}
+
+/// Breakdown of the input tokens
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputTokensDetails {
+    #[serde(default)]
+    pub cached_tokens: u32,
+}
+
+impl From<ResponsesUsage> for Usage {
+    /// Cached tokens are reported apart from the other input tokens, as
+    /// Anthropic does
+    fn from(usage: ResponsesUsage) -> Self {
+        let cached = usage.input_tokens_details.map(|details| details.cached_tokens);
+        Self {
+            input_tokens: usage.input_tokens.saturating_sub(cached.unwrap_or(0)),
+            output_tokens: usage.output_tokens,
+            cache_creation_input_tokens: None,
+            cache_read_input_tokens: cached,
+        }
+    }
+}
+
+/// Why a response stopped early
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IncompleteDetails {
+    pub reason: String,
+}
+
+/// Error of a failed response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponsesError {
+    #[serde(default)]
+    pub code: Option<String>,
+    pub message: String,
+}
+
+/// Stream event of the Responses API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ResponsesStreamEvent {
+    #[serde(rename = "response.created")]
+    Created { response: ResponsesResponse },
+
+    #[serde(rename = "response.output_item.added")]
+    OutputItemAdded {
+        output_index: usize,
+        item: ResponsesOutputItem,
+    },
+
+    #[serde(rename = "response.output_item.done")]
+    OutputItemDone {
+        output_index: usize,
+        item: ResponsesOutputItem,
+    },
+
+    #[serde(rename = "response.output_text.delta")]
+    OutputTextDelta { delta: String },
+
+    #[serde(rename = "response.reasoning_summary_text.delta")]
+    ReasoningSummaryTextDelta {
+        output_index: usize,
+        summary_index: usize,
+        delta: String,
+    },
+
+    #[serde(rename = "response.function_call_arguments.delta")]
+    FunctionCallArgumentsDelta { output_index: usize, delta: String },
+
+    #[serde(rename = "response.completed")]
+    Completed { response: ResponsesResponse },
+
+    #[serde(rename = "response.incomplete")]
+    Incomplete { response: ResponsesResponse },
+
+    #[serde(rename = "response.failed")]
+    Failed { response: ResponsesResponse },
+
+    #[serde(rename = "error")]
+    Error {
+        #[serde(default)]
+        code: Option<String>,
+        message: String,
+    },
+
+    /// Events carrying nothing new, e.g. `response.output_text.done`
+    #[serde(other)]
+    Other,
+}
+
+/// Content of a Gemini request or response turn
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GeminiContent {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub parts: Vec<GeminiPart>,
+}
+
+/// Part of a Gemini content; exactly one data field is set
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiPart {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Whether `text` is a thought summary
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thought: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inline_data: Option<GeminiInlineData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub function_call: Option<GeminiFunctionCall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub function_response: Option<GeminiFunctionResponse>,
+}
+
+/// Inline file data, such as an image
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiInlineData {
+    pub mime_type: String,
+    pub data: String,
+}
+
+/// Function call predicted by the model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeminiFunctionCall {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub name: String,
+    #[serde(default)]
+    pub args: serde_json::Value,
+}
+
+impl GeminiFunctionCall {
+    /// The call's ID, made up when Gemini did not send one
+    #[must_use]
+    pub fn call_id(&self) -> String {
+        self.id
+            .clone()
+            .unwrap_or_else(new_call_id)
+    }
+
+    /// The call's arguments, as an object even when none were given
+    #[must_use]
+    pub fn input(&self) -> serde_json::Value {
+        if self.args.is_null() {
+            serde_json::json!({})
+        } else {
+            self.args.clone()
+        }
+    }
+}
+
+/// Result of a function call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeminiFunctionResponse {
+    pub name: String,
+    pub response: serde_json::Value,
+}
+
+/// Response of `generateContent`, and each event of `streamGenerateContent`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiResponse {
+    #[serde(default)]
+    pub candidates: Vec<GeminiCandidate>,
+    #[serde(default)]
+    pub usage_metadata: Option<GeminiUsageMetadata>,
+    #[serde(default)]
+    pub model_version: Option<String>,
+    #[serde(default)]
+    pub prompt_feedback: Option<GeminiPromptFeedback>,
+}
+
+/// Candidate answer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiCandidate {
+    #[serde(default)]
+    pub content: Option<GeminiContent>,
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+}
+
+/// Why a prompt was blocked
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiPromptFeedback {
+    #[serde(default)]
+    pub block_reason: Option<String>,
+}
+
+/// Token usage of a Gemini response
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiUsageMetadata {
+    #[serde(default)]
+    pub prompt_token_count: u32,
+    #[serde(default)]
+    pub candidates_token_count: u32,
+    #[serde(default)]
+    pub thoughts_token_count: u32,
+    #[serde(default)]
+    pub cached_content_token_count: Option<u32>,
+}
+
+impl From<GeminiUsageMetadata> for Usage {
+    /// Thoughts are billed as output; cached tokens are reported apart from
+    /// the other prompt tokens, as Anthropic does
+    fn from(usage: GeminiUsageMetadata) -> Self {
+        let cached = usage.cached_content_token_count;
+        Self {
+            input_tokens: usage.prompt_token_count.saturating_sub(cached.unwrap_or(0)),
+            output_tokens: usage
+                .candidates_token_count
+                .saturating_add(usage.thoughts_token_count),
+            cache_creation_input_tokens: None,
+            cache_read_input_tokens: cached,
+        }
+    }
+}
+
+/// Stop reason in the terms the rest of Kode uses
+#[must_use]
+pub fn gemini_stop_reason(finish_reason: Option<&str>, has_tool_calls: bool) -> String {
+    match finish_reason {
+        Some("MAX_TOKENS") => "max_tokens".to_string(),
+        Some("STOP") | None if has_tool_calls => "tool_use".to_string(),
+        Some("STOP") | None => "end_turn".to_string(),
+        Some(reason) => reason.to_lowercase(),
+    }
+}
+
+/// Message of an Ollama chat request or response
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OllamaMessage {
+    pub role: String,
+    #[serde(default)]
+    pub content: String,
+    /// Base64-encoded images
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<String>,
+    /// Reasoning of thinking models
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<OllamaToolCall>,
+    /// Tool whose result a `tool` message carries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_name: Option<String>,
+}
+
+/// Tool call made by an Ollama model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OllamaToolCall {
+    pub function: OllamaFunctionCall,
+}
+
+/// Function called by an Ollama model; arguments arrive as an object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OllamaFunctionCall {
+    pub name: String,
+    #[serde(default)]
+    pub arguments: serde_json::Value,
+}
+
+impl OllamaFunctionCall {
+    /// The call's arguments, as an object even when none were given
+    #[must_use]
+    pub fn input(&self) -> serde_json::Value {
+        if self.arguments.is_null() {
+            serde_json::json!({})
+        } else {
+            self.arguments.clone()
+        }
+    }
+}
+
+/// Response of `/api/chat`, and each line of its NDJSON stream
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OllamaChatResponse {
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub message: Option<OllamaMessage>,
+    #[serde(default)]
+    pub done: bool,
+    #[serde(default)]
+    pub done_reason: Option<String>,
+    #[serde(default)]
+    pub prompt_eval_count: u32,
+    #[serde(default)]
+    pub eval_count: u32,
+    /// Set instead of everything else when generation failed
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl OllamaChatResponse {
+    /// Token usage reported by the final message
+    #[must_use]
+    pub fn usage(&self) -> Usage {
+        Usage {
+            input_tokens: self.prompt_eval_count,
+            output_tokens: self.eval_count,
+            cache_creation_input_tokens: None,
+            cache_read_input_tokens: None,
+        }
+    }
+}
+
+/// Made-up ID for a tool call that arrived without one
+#[must_use]
+pub fn new_call_id() -> String {
+    format!("call_{}", uuid::Uuid::new_v4().simple())
+}
+
+/// Stop reason in the terms the rest of Kode uses
+#[must_use]
+pub fn ollama_stop_reason(done_reason: Option<&str>, has_tool_calls: bool) -> String {
+    match done_reason {
+        Some("length") => "max_tokens".to_string(),
+        Some("stop") | None if has_tool_calls => "tool_use".to_string(),
+        Some("stop") | None => "end_turn".to_string(),
+        Some(reason) => reason.to_string(),
+    }
+}
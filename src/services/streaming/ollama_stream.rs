@@ -0,0 +1,218 @@
+//! Ollama streaming handler
+//!
+//! `/api/chat` streams newline-delimited JSON rather than Server-Sent
+//! Events. Every line is a partial chat response: text and thinking arrive
+//! in pieces, tool calls arrive whole, and the last line has `done` set along
+//! with the token counts.
+
+use crate::{
+    error::{KodeError, Result},
+    services::CompletionChunk,
+};
+
+use super::{new_call_id, ollama_stop_reason, OllamaChatResponse};
+
+/// Handler for Ollama streaming responses
+pub struct OllamaStreamHandler {
+    /// Bytes of the line still being received
+    buffer: Vec<u8>,
+
+    /// Whether the model called a tool
+    has_tool_calls: bool,
+
+    /// Whether `Done` has been emitted
+    complete: bool,
+}
+
+impl OllamaStreamHandler {
+    /// Create a new handler
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            buffer: Vec::new(),
+            has_tool_calls: false,
+            complete: false,
+        }
+    }
+
+    /// Process a chunk of raw streaming bytes
+    ///
+    /// Returns the completion chunks for every line completed by these bytes
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for malformed lines and errors reported by Ollama
+    pub fn process_bytes(&mut self, bytes: &[u8]) -> Result<Vec<CompletionChunk>> {
+        self.buffer.extend_from_slice(bytes);
+
+        let mut chunks = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            if !self.complete {
+                self.process_line(&line, &mut chunks)?;
+            }
+        }
+        Ok(chunks)
+    }
+
+    /// Process a chunk of streaming data
+    ///
+    /// Returns the completion chunks for every line completed by this chunk
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for malformed lines and errors reported by Ollama
+    pub fn process_chunk(&mut self, chunk: &str) -> Result<Vec<CompletionChunk>> {
+        self.process_bytes(chunk.as_bytes())
+    }
+
+    /// Whether `Done` has been emitted
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Finish the stream once its bytes have run out
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the trailing line is malformed or the final line
+    /// never arrived
+    pub fn finish(&mut self) -> Result<Vec<CompletionChunk>> {
+        let mut chunks = Vec::new();
+        let line = std::mem::take(&mut self.buffer);
+        if !self.complete {
+            self.process_line(&line, &mut chunks)?;
+        }
+        if !self.complete {
+            return Err(KodeError::Other(
+                "Stream ended before the final message".to_string(),
+            ));
+        }
+        Ok(chunks)
+    }
+
+    /// Process a single line, appending the chunks it produces
+    fn process_line(&mut self, line: &[u8], chunks: &mut Vec<CompletionChunk>) -> Result<()> {
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(());
+        }
+
+        let response: OllamaChatResponse = serde_json::from_str(line)
+            .map_err(|e| KodeError::Other(format!("Failed to parse stream line: {e}")))?;
+        if let Some(message) = response.error {
+            return Err(KodeError::ApiError {
+                provider: "ollama".to_string(),
+                message,
+            });
+        }
+
+        if let Some(message) = &response.message {
+            if let Some(thinking) = message.thinking.clone().filter(|thinking| !thinking.is_empty()) {
+                chunks.push(CompletionChunk::ThinkingDelta { thinking });
+            }
+            if !message.content.is_empty() {
+                chunks.push(CompletionChunk::TextDelta {
+                    text: message.content.clone(),
+                });
+            }
+            for call in &message.tool_calls {
+                self.has_tool_calls = true;
+                let id = new_call_id();
+                let input = call.function.input();
+                chunks.push(CompletionChunk::ToolUseStart {
+                    id: id.clone(),
+                    name: call.function.name.clone(),
+                });
+                chunks.push(CompletionChunk::ToolInputDelta {
+                    id: id.clone(),
+                    partial_json: input.to_string(),
+                });
+                chunks.push(CompletionChunk::ToolUseComplete {
+                    id,
+                    name: call.function.name.clone(),
+                    input,
+                });
+            }
+        }
+
+        if response.done {
+            self.complete = true;
+            chunks.push(CompletionChunk::Done {
+                stop_reason: ollama_stop_reason(response.done_reason.as_deref(), self.has_tool_calls),
+                usage: Some(response.usage()),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Default for OllamaStreamHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn line(data: &serde_json::Value) -> String {
+        format!("{data}\n")
+    }
+
+    #[test]
+    fn test_split_lines_and_tool_call() {
+        let body = [
+            line(&json!({"model": "qwen3", "message": {"role": "assistant", "content": "", "thinking": "Listing"}, "done": false})),
+            line(&json!({"model": "qwen3", "message": {"role": "assistant", "content": "Let me look…"}, "done": false})),
+            line(&json!({"model": "qwen3", "message": {"role": "assistant", "content": "", "tool_calls": [
+                {"function": {"name": "Bash", "arguments": {"command": "ls"}}}
+            ]}, "done": false})),
+            line(&json!({"model": "qwen3", "message": {"role": "assistant", "content": ""}, "done": true,
+                "done_reason": "stop", "prompt_eval_count": 42, "eval_count": 7})),
+        ]
+        .concat();
+
+        // Lines may be split anywhere, even inside a multi-byte character
+        let mut handler = OllamaStreamHandler::new();
+        let mut chunks: Vec<CompletionChunk> = body
+            .as_bytes()
+            .chunks(5)
+            .flat_map(|piece| handler.process_bytes(piece).unwrap())
+            .collect();
+        chunks.extend(handler.finish().unwrap());
+
+        assert!(matches!(&chunks[0], CompletionChunk::ThinkingDelta { thinking } if thinking == "Listing"));
+        assert!(matches!(&chunks[1], CompletionChunk::TextDelta { text } if text == "Let me look…"));
+        assert!(matches!(&chunks[2], CompletionChunk::ToolUseStart { name, .. } if name == "Bash"));
+        assert!(
+            matches!(&chunks[4], CompletionChunk::ToolUseComplete { input, .. } if input == &json!({"command": "ls"}))
+        );
+        let CompletionChunk::Done { stop_reason, usage } = &chunks[5] else {
+            panic!("expected Done, got {:?}", chunks[5]);
+        };
+        assert_eq!(stop_reason, "tool_use");
+        assert_eq!(usage.as_ref().map(|usage| (usage.input_tokens, usage.output_tokens)), Some((42, 7)));
+        assert!(handler.is_complete());
+    }
+
+    #[test]
+    fn test_error_and_truncated_stream() {
+        let mut handler = OllamaStreamHandler::new();
+        let error = handler
+            .process_chunk(&line(&json!({"error": "model requires more system memory"})))
+            .unwrap_err();
+        assert!(error.to_string().contains("more system memory"));
+
+        let mut handler = OllamaStreamHandler::new();
+        handler
+            .process_chunk(&line(&json!({"message": {"role": "assistant", "content": "Hi"}, "done": false})))
+            .unwrap();
+        assert!(handler.finish().is_err());
+    }
+}
@@ -1,7 +1,8 @@
 //! OpenAI API streaming handler
 //!
-//! Processes Server-Sent Events from OpenAI's streaming API
-//! and assembles them into complete messages.
+//! Processes Server-Sent Events from OpenAI's streaming API, emitting
+//! completion chunks as each event arrives while assembling the complete
+//! message.
 
 use std::collections::HashMap;
 
@@ -10,7 +11,7 @@ use serde_json;
 use crate::{
     error::{KodeError, Result},
     messages::{AssistantMessage, ContentBlock, Message, Role},
-    services::Usage,
+    services::{CompletionChunk, Usage},
 };
 
 use super::{OpenAIStreamChunk, SseEvent, SseParser};
@@ -21,6 +22,9 @@ struct ToolCallBuilder {
     id: String,
     name: String,
     arguments: String,
+
+    /// Whether `ToolUseStart` has been emitted
+    started: bool,
 }
 
 /// Handler for OpenAI streaming responses
@@ -51,6 +55,12 @@ pub struct OpenAIStreamHandler {
 
     /// Stop reason
     finish_reason: Option<String>,
+
+    /// Whether `ToolUseComplete` has been emitted for the tool calls
+    tools_completed: bool,
+
+    /// Whether the [DONE] marker has been received
+    complete: bool,
 }
 
 impl OpenAIStreamHandler {
@@ -66,31 +76,82 @@ impl OpenAIStreamHandler {
             thinking_content: None,
             usage: None,
             finish_reason: None,
+            tools_completed: false,
+            complete: false,
         }
     }
 
+    /// Process a chunk of raw streaming bytes
+    ///
+    /// Returns the completion chunks for every event completed by these bytes
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for malformed events and provider error events
+    pub fn process_bytes(&mut self, bytes: &[u8]) -> Result<Vec<CompletionChunk>> {
+        let events = self.parser.parse_bytes(bytes);
+        self.process_events(events)
+    }
+
     /// Process a chunk of streaming data
     ///
-    /// Returns true if the stream is complete ([DONE] marker received)
-    pub fn process_chunk(&mut self, chunk: &str) -> Result<bool> {
+    /// Returns the completion chunks for every event completed by this chunk
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for malformed events and provider error events
+    pub fn process_chunk(&mut self, chunk: &str) -> Result<Vec<CompletionChunk>> {
         let events = self.parser.parse_chunk(chunk);
+        self.process_events(events)
+    }
 
-        for event in events {
-            if event.is_done_marker() {
-                return Ok(true); // Stream complete
-            }
+    /// Whether the stream is complete ([DONE] marker received)
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
 
-            self.process_event(event)?;
+    /// Finish a stream whose bytes have run out
+    ///
+    /// Processes a trailing event and emits `Done` if the [DONE] marker never came
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the trailing event is malformed or no message started
+    pub fn finish(&mut self) -> Result<Vec<CompletionChunk>> {
+        let mut chunks = Vec::new();
+        if let Some(event) = self.parser.flush().filter(|_| !self.complete) {
+            self.process_event(&event, &mut chunks)?;
+        }
+        if !self.complete {
+            if self.id.is_none() {
+                return Err(KodeError::Other("No message ID received".to_string()));
+            }
+            self.finish_stream(&mut chunks)?;
         }
+        Ok(chunks)
+    }
 
-        Ok(false)
+    fn process_events(&mut self, events: Vec<SseEvent>) -> Result<Vec<CompletionChunk>> {
+        let mut chunks = Vec::new();
+        for event in events {
+            if self.complete {
+                break;
+            }
+            self.process_event(&event, &mut chunks)?;
+        }
+        Ok(chunks)
     }
 
-    /// Process a single SSE event
-    fn process_event(&mut self, event: SseEvent) -> Result<()> {
+    /// Process a single SSE event, appending the chunks it produces
+    fn process_event(&mut self, event: &SseEvent, chunks: &mut Vec<CompletionChunk>) -> Result<()> {
+        if event.is_done_marker() {
+            return self.finish_stream(chunks);
+        }
+
         // Parse JSON data
         let chunk: OpenAIStreamChunk = serde_json::from_str(&event.data)
-            .map_err(|e| KodeError::Other(format!("Failed to parse SSE event: {}", e)))?;
+            .map_err(|e| KodeError::Other(format!("Failed to parse SSE event: {e}")))?;
 
         // Extract metadata
         if self.id.is_none() {
@@ -102,36 +163,37 @@ impl OpenAIStreamHandler {
         if self.created.is_none() {
             self.created = Some(chunk.created);
         }
-        if chunk.usage.is_some() {
-            self.usage = chunk.usage;
+        if let Some(usage) = chunk.usage {
+            self.usage = Some(usage.into());
         }
 
         // Process choices
-        if let Some(choice) = chunk.choices.first() {
-            let delta = &choice.delta;
-
-            // Text content
-            if let Some(content) = &delta.content {
-                self.text_content.push_str(content);
-            }
+        if let Some(choice) = chunk.choices.into_iter().next() {
+            let delta = choice.delta;
 
             // Thinking/reasoning (o1/o3 models)
-            if let Some(reasoning) = &delta.reasoning {
+            if let Some(reasoning) = delta.reasoning.filter(|r| !r.is_empty()) {
                 self.thinking_content
                     .get_or_insert_with(String::new)
-                    .push_str(reasoning);
+                    .push_str(&reasoning);
+                chunks.push(CompletionChunk::ThinkingDelta { thinking: reasoning });
+            }
+
+            // Text content
+            if let Some(content) = delta.content.filter(|c| !c.is_empty()) {
+                self.text_content.push_str(&content);
+                chunks.push(CompletionChunk::TextDelta { text: content });
             }
 
             // Tool calls
-            if let Some(tool_call_deltas) = &delta.tool_calls {
-                for tool_delta in tool_call_deltas {
-                    self.process_tool_call_delta(tool_delta)?;
-                }
+            for tool_delta in delta.tool_calls.unwrap_or_default() {
+                chunks.extend(self.process_tool_call_delta(&tool_delta));
             }
 
-            // Finish reason
-            if let Some(reason) = &choice.finish_reason {
-                self.finish_reason = Some(reason.clone());
+            // Finish reason: the tool calls are complete
+            if let Some(reason) = choice.finish_reason {
+                self.finish_reason = Some(reason);
+                self.complete_tool_calls(chunks)?;
             }
         }
 
@@ -139,10 +201,7 @@ impl OpenAIStreamHandler {
     }
 
     /// Process a tool call delta
-    fn process_tool_call_delta(
-        &mut self,
-        delta: &super::ToolCallDelta,
-    ) -> Result<()> {
+    fn process_tool_call_delta(&mut self, delta: &super::ToolCallDelta) -> Vec<CompletionChunk> {
         let builder = self
             .tool_calls
             .entry(delta.index)
@@ -150,6 +209,7 @@ impl OpenAIStreamHandler {
                 id: String::new(),
                 name: String::new(),
                 arguments: String::new(),
+                started: false,
             });
 
         // Update ID
@@ -157,16 +217,66 @@ impl OpenAIStreamHandler {
             builder.id = id.clone();
         }
 
+        let mut chunks = Vec::new();
+        let mut arguments = None;
         // Update function name and arguments
         if let Some(function) = &delta.function {
             if let Some(name) = &function.name {
                 builder.name = name.clone();
             }
-            if let Some(args) = &function.arguments {
+            if let Some(args) = function.arguments.as_ref().filter(|args| !args.is_empty()) {
                 builder.arguments.push_str(args);
+                arguments = Some(args.clone());
             }
         }
 
+        if !builder.started && !builder.id.is_empty() && !builder.name.is_empty() {
+            builder.started = true;
+            chunks.push(CompletionChunk::ToolUseStart {
+                id: builder.id.clone(),
+                name: builder.name.clone(),
+            });
+            // Arguments that arrived before the call was named
+            arguments = Some(builder.arguments.clone()).filter(|args| !args.is_empty());
+        }
+        if let Some(partial_json) = arguments.filter(|_| builder.started) {
+            chunks.push(CompletionChunk::ToolInputDelta {
+                id: builder.id.clone(),
+                partial_json,
+            });
+        }
+
+        chunks
+    }
+
+    /// Emit `ToolUseComplete` for every tool call, in index order
+    fn complete_tool_calls(&mut self, chunks: &mut Vec<CompletionChunk>) -> Result<()> {
+        if self.tools_completed {
+            return Ok(());
+        }
+        self.tools_completed = true;
+
+        let mut tool_indices: Vec<_> = self.tool_calls.keys().copied().collect();
+        tool_indices.sort_unstable();
+        for index in tool_indices {
+            let builder = &self.tool_calls[&index];
+            chunks.push(CompletionChunk::ToolUseComplete {
+                id: builder.id.clone(),
+                name: builder.name.clone(),
+                input: parse_arguments(&builder.arguments)?,
+            });
+        }
+        Ok(())
+    }
+
+    /// Complete the tool calls and emit `Done`
+    fn finish_stream(&mut self, chunks: &mut Vec<CompletionChunk>) -> Result<()> {
+        self.complete_tool_calls(chunks)?;
+        self.complete = true;
+        chunks.push(CompletionChunk::Done {
+            stop_reason: self.finish_reason.clone().unwrap_or_else(|| "stop".to_string()),
+            usage: Some(self.get_usage()),
+        });
         Ok(())
     }
 
@@ -187,6 +297,7 @@ impl OpenAIStreamHandler {
         if let Some(thinking) = &self.thinking_content {
             content_blocks.push(ContentBlock::Thinking {
                 thinking: thinking.clone(),
+                signature: None,
             });
         }
 
@@ -196,14 +307,7 @@ impl OpenAIStreamHandler {
 
         for index in tool_indices {
             if let Some(builder) = self.tool_calls.get(&index) {
-                // Parse arguments JSON
-                let input: serde_json::Value = if builder.arguments.is_empty() {
-                    serde_json::Value::Object(serde_json::Map::new())
-                } else {
-                    serde_json::from_str(&builder.arguments).map_err(|e| {
-                        KodeError::Other(format!("Failed to parse tool arguments: {}", e))
-                    })?
-                };
+                let input = parse_arguments(&builder.arguments)?;
 
                 content_blocks.push(ContentBlock::ToolUse {
                     id: builder.id.clone(),
@@ -218,7 +322,7 @@ impl OpenAIStreamHandler {
             .clone()
             .ok_or_else(|| KodeError::Other("No message ID received".to_string()))?;
 
-        let _model = self
+        let model = self
             .model
             .clone()
             .ok_or_else(|| KodeError::Other("No model received".to_string()))?;
@@ -234,6 +338,8 @@ impl OpenAIStreamHandler {
             duration_ms: 0,
             is_api_error_message: None,
             response_id: None,
+            usage: self.usage.clone(),
+            model: Some(model),
         })
     }
 
@@ -258,6 +364,15 @@ impl OpenAIStreamHandler {
     }
 }
 
+/// Parse the accumulated arguments of a tool call
+pub(super) fn parse_arguments(arguments: &str) -> Result<serde_json::Value> {
+    if arguments.is_empty() {
+        return Ok(serde_json::Value::Object(serde_json::Map::new()));
+    }
+    serde_json::from_str(arguments)
+        .map_err(|e| KodeError::Other(format!("Failed to parse tool arguments: {e}")))
+}
+
 impl Default for OpenAIStreamHandler {
     fn default() -> Self {
         Self::new()
@@ -276,23 +391,28 @@ mod tests {
         let chunk1 = r#"data: {"id":"chatcmpl-123","object":"chat.completion.chunk","created":1234567890,"model":"gpt-4","choices":[{"index":0,"delta":{"role":"assistant","content":"Hello"},"finish_reason":null}]}
 
 "#;
-        assert!(!handler.process_chunk(chunk1).unwrap());
+        let emitted = handler.process_chunk(chunk1).unwrap();
+        assert!(matches!(&emitted[..], [CompletionChunk::TextDelta { text }] if text == "Hello"));
 
         // Second chunk with more content
         let chunk2 = r#"data: {"id":"chatcmpl-123","object":"chat.completion.chunk","created":1234567890,"model":"gpt-4","choices":[{"index":0,"delta":{"content":" world"},"finish_reason":null}]}
 
 "#;
-        assert!(!handler.process_chunk(chunk2).unwrap());
+        let emitted = handler.process_chunk(chunk2).unwrap();
+        assert!(matches!(&emitted[..], [CompletionChunk::TextDelta { text }] if text == " world"));
 
         // Final chunk with finish reason
         let chunk3 = r#"data: {"id":"chatcmpl-123","object":"chat.completion.chunk","created":1234567890,"model":"gpt-4","choices":[{"index":0,"delta":{},"finish_reason":"stop"}]}
 
 "#;
-        assert!(!handler.process_chunk(chunk3).unwrap());
+        assert!(handler.process_chunk(chunk3).unwrap().is_empty());
+        assert!(!handler.is_complete());
 
         // Done marker
         let chunk4 = "data: [DONE]\n\n";
-        assert!(handler.process_chunk(chunk4).unwrap());
+        let emitted = handler.process_chunk(chunk4).unwrap();
+        assert!(matches!(&emitted[..], [CompletionChunk::Done { stop_reason, .. }] if stop_reason == "stop"));
+        assert!(handler.is_complete());
 
         let message = handler.get_message().unwrap();
         assert_eq!(message.message.content.len(), 1);
@@ -311,13 +431,22 @@ mod tests {
         let chunk1 = r#"data: {"id":"chatcmpl-123","object":"chat.completion.chunk","created":1234567890,"model":"gpt-4","choices":[{"index":0,"delta":{"role":"assistant","tool_calls":[{"index":0,"id":"call_abc","type":"function","function":{"name":"get_weather","arguments":""}}]},"finish_reason":null}]}
 
 "#;
-        handler.process_chunk(chunk1).unwrap();
+        let emitted = handler.process_chunk(chunk1).unwrap();
+        assert!(matches!(
+            &emitted[..],
+            [CompletionChunk::ToolUseStart { id, name }] if id == "call_abc" && name == "get_weather"
+        ));
 
         // Second chunk with arguments
         let chunk2 = r#"data: {"id":"chatcmpl-123","object":"chat.completion.chunk","created":1234567890,"model":"gpt-4","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"{\"location\":"}}]},"finish_reason":null}]}
 
 "#;
-        handler.process_chunk(chunk2).unwrap();
+        let emitted = handler.process_chunk(chunk2).unwrap();
+        assert!(matches!(
+            &emitted[..],
+            [CompletionChunk::ToolInputDelta { id, partial_json }]
+                if id == "call_abc" && partial_json == "{\"location\":"
+        ));
 
         // Third chunk with more arguments
         let chunk3 = r#"data: {"id":"chatcmpl-123","object":"chat.completion.chunk","created":1234567890,"model":"gpt-4","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"\"Boston\"}"}}]},"finish_reason":null}]}
@@ -327,7 +456,12 @@ mod tests {
 
         // Done marker
         let chunk4 = "data: [DONE]\n\n";
-        handler.process_chunk(chunk4).unwrap();
+        let emitted = handler.process_chunk(chunk4).unwrap();
+        assert!(matches!(
+            &emitted[..],
+            [CompletionChunk::ToolUseComplete { input, .. }, CompletionChunk::Done { .. }]
+                if input["location"] == "Boston"
+        ));
 
         let message = handler.get_message().unwrap();
         assert_eq!(message.message.content.len(), 1);
@@ -361,4 +495,45 @@ mod tests {
             panic!("Expected thinking block");
         }
     }
+
+    #[test]
+    fn test_usage_chunk() {
+        let mut handler = OpenAIStreamHandler::new();
+        let stream = concat!(
+            r#"data: {"id":"chatcmpl-123","object":"chat.completion.chunk","created":1234567890,"model":"gpt-4o","choices":[{"index":0,"delta":{"content":"Hi"},"finish_reason":"stop"}],"usage":null}"#,
+            "\n\n",
+            // Asked for with `stream_options.include_usage`, after the last choice
+            r#"data: {"id":"chatcmpl-123","object":"chat.completion.chunk","created":1234567890,"model":"gpt-4o","choices":[],"usage":{"prompt_tokens":120,"completion_tokens":5,"total_tokens":125,"prompt_tokens_details":{"cached_tokens":100}}}"#,
+            "\n\n",
+            "data: [DONE]\n\n",
+        );
+
+        let emitted = handler.process_chunk(stream).unwrap();
+        let Some(CompletionChunk::Done { usage: Some(usage), .. }) = emitted.last() else {
+            panic!("Expected Done with usage, got {emitted:?}");
+        };
+        assert_eq!(usage.input_tokens, 20);
+        assert_eq!(usage.output_tokens, 5);
+        assert_eq!(usage.cache_read_input_tokens, Some(100));
+        assert_eq!(handler.get_message().unwrap().message.text_content(), "Hi");
+    }
+
+    #[test]
+    fn test_bytes_split_inside_characters() {
+        let mut handler = OpenAIStreamHandler::new();
+        let stream = concat!(
+            r#"data: {"id":"chatcmpl-123","object":"chat.completion.chunk","created":1234567890,"model":"gpt-4","choices":[{"index":0,"delta":{"content":"日本語"},"finish_reason":null}]}"#,
+            "\n\n",
+        );
+        let bytes = stream.as_bytes();
+        let split = stream.find('本').unwrap() + 1;
+
+        assert!(handler.process_bytes(&bytes[..split]).unwrap().is_empty());
+        let emitted = handler.process_bytes(&bytes[split..]).unwrap();
+        assert!(matches!(&emitted[..], [CompletionChunk::TextDelta { text }] if text == "日本語"));
+
+        // The stream ends without the [DONE] marker
+        let emitted = handler.finish().unwrap();
+        assert!(matches!(&emitted[..], [CompletionChunk::Done { .. }]));
+    }
 }
@@ -0,0 +1,394 @@
+//! Responses API streaming handler
+//!
+//! Processes the typed Server-Sent Events of `/v1/responses`, emitting
+//! completion chunks as each event arrives. Reasoning summaries stream as
+//! thinking; the finished reasoning item, with its encrypted content, follows
+//! as a `Reasoning` chunk so it can be passed back on the next turn.
+
+use std::collections::HashMap;
+
+use crate::{
+    error::{KodeError, Result},
+    services::CompletionChunk,
+};
+
+use super::{
+    join_summary, openai_stream::parse_arguments, ResponsesOutputItem, ResponsesResponse,
+    ResponsesStreamEvent, SseEvent, SseParser,
+};
+
+/// Handler for Responses API streams
+pub struct ResponsesStreamHandler {
+    /// SSE parser
+    parser: SseParser,
+
+    /// Response ID, from `response.created`
+    id: Option<String>,
+
+    /// Call IDs of the function calls in progress, by output index
+    function_calls: HashMap<usize, String>,
+
+    /// Whether any function call completed
+    has_tool_calls: bool,
+
+    /// Output and summary index of the summary part being streamed
+    summary_part: Option<(usize, usize)>,
+
+    /// Whether the response completed
+    complete: bool,
+}
+
+impl ResponsesStreamHandler {
+    /// Create a new handler
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            parser: SseParser::new(),
+            id: None,
+            function_calls: HashMap::new(),
+            has_tool_calls: false,
+            summary_part: None,
+            complete: false,
+        }
+    }
+
+    /// Process a chunk of raw streaming bytes
+    ///
+    /// Returns the completion chunks for every event completed by these bytes
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for malformed events and failed responses
+    pub fn process_bytes(&mut self, bytes: &[u8]) -> Result<Vec<CompletionChunk>> {
+        let events = self.parser.parse_bytes(bytes);
+        self.process_events(events)
+    }
+
+    /// Process a chunk of streaming data
+    ///
+    /// Returns the completion chunks for every event completed by this chunk
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for malformed events and failed responses
+    pub fn process_chunk(&mut self, chunk: &str) -> Result<Vec<CompletionChunk>> {
+        let events = self.parser.parse_chunk(chunk);
+        self.process_events(events)
+    }
+
+    /// Whether the response completed
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Finish a stream whose bytes have run out
+    ///
+    /// Processes a trailing event and emits `Done` if the response never
+    /// reported completion
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the trailing event is malformed or no response started
+    pub fn finish(&mut self) -> Result<Vec<CompletionChunk>> {
+        let mut chunks = Vec::new();
+        if let Some(event) = self.parser.flush().filter(|_| !self.complete) {
+            self.process_event(&event, &mut chunks)?;
+        }
+        if !self.complete {
+            if self.id.is_none() {
+                return Err(KodeError::Other("No response ID received".to_string()));
+            }
+            self.complete = true;
+            let stop_reason = if self.has_tool_calls { "tool_use" } else { "end_turn" };
+            chunks.push(CompletionChunk::Done {
+                stop_reason: stop_reason.to_string(),
+                usage: None,
+            });
+        }
+        Ok(chunks)
+    }
+
+    fn process_events(&mut self, events: Vec<SseEvent>) -> Result<Vec<CompletionChunk>> {
+        let mut chunks = Vec::new();
+        for event in events {
+            if self.complete {
+                break;
+            }
+            self.process_event(&event, &mut chunks)?;
+        }
+        Ok(chunks)
+    }
+
+    /// Process a single SSE event, appending the chunks it produces
+    fn process_event(&mut self, event: &SseEvent, chunks: &mut Vec<CompletionChunk>) -> Result<()> {
+        let event: ResponsesStreamEvent = serde_json::from_str(&event.data)
+            .map_err(|e| KodeError::Other(format!("Failed to parse SSE event: {e}")))?;
+
+        match event {
+            ResponsesStreamEvent::Created { response } => self.id = Some(response.id),
+            ResponsesStreamEvent::OutputItemAdded { output_index, item } => {
+                if let ResponsesOutputItem::FunctionCall { call_id, name, .. } = item {
+                    chunks.push(CompletionChunk::ToolUseStart {
+                        id: call_id.clone(),
+                        name,
+                    });
+                    self.function_calls.insert(output_index, call_id);
+                }
+            }
+            ResponsesStreamEvent::FunctionCallArgumentsDelta {
+                output_index,
+                delta,
+            } => {
+                if let Some(call_id) = self.function_calls.get(&output_index) {
+                    if !delta.is_empty() {
+                        chunks.push(CompletionChunk::ToolInputDelta {
+                            id: call_id.clone(),
+                            partial_json: delta,
+                        });
+                    }
+                }
+            }
+            ResponsesStreamEvent::OutputItemDone { output_index, item } => {
+                self.complete_item(output_index, item, chunks)?;
+            }
+            ResponsesStreamEvent::OutputTextDelta { delta } => {
+                if !delta.is_empty() {
+                    chunks.push(CompletionChunk::TextDelta { text: delta });
+                }
+            }
+            ResponsesStreamEvent::ReasoningSummaryTextDelta {
+                output_index,
+                summary_index,
+                delta,
+            } => {
+                // Separate the parts of a summary like `join_summary` does
+                if matches!(self.summary_part, Some((item, part)) if item == output_index && part != summary_index)
+                {
+                    chunks.push(CompletionChunk::ThinkingDelta {
+                        thinking: "\n\n".to_string(),
+                    });
+                }
+                self.summary_part = Some((output_index, summary_index));
+                if !delta.is_empty() {
+                    chunks.push(CompletionChunk::ThinkingDelta { thinking: delta });
+                }
+            }
+            ResponsesStreamEvent::Completed { response }
+            | ResponsesStreamEvent::Incomplete { response } => {
+                self.complete = true;
+                chunks.push(CompletionChunk::Done {
+                    stop_reason: response.stop_reason(self.has_tool_calls),
+                    usage: response.usage.map(Into::into),
+                });
+            }
+            ResponsesStreamEvent::Failed { response } => return Err(failure(response)),
+            ResponsesStreamEvent::Error { code, message } => {
+                return Err(KodeError::ApiError {
+                    provider: "openai".to_string(),
+                    message: code.map_or(message.clone(), |code| format!("{code}: {message}")),
+                });
+            }
+            ResponsesStreamEvent::Other => {}
+        }
+
+        Ok(())
+    }
+
+    /// Emit the chunks for a finished output item
+    fn complete_item(
+        &mut self,
+        output_index: usize,
+        item: ResponsesOutputItem,
+        chunks: &mut Vec<CompletionChunk>,
+    ) -> Result<()> {
+        match item {
+            ResponsesOutputItem::FunctionCall {
+                call_id,
+                name,
+                arguments,
+            } => {
+                if self.function_calls.remove(&output_index).is_none() {
+                    chunks.push(CompletionChunk::ToolUseStart {
+                        id: call_id.clone(),
+                        name: name.clone(),
+                    });
+                }
+                self.has_tool_calls = true;
+                chunks.push(CompletionChunk::ToolUseComplete {
+                    id: call_id,
+                    name,
+                    input: parse_arguments(&arguments)?,
+                });
+            }
+            ResponsesOutputItem::Reasoning {
+                id,
+                summary,
+                encrypted_content,
+            } => chunks.push(CompletionChunk::Reasoning {
+                id,
+                summary: join_summary(&summary),
+                encrypted_content,
+            }),
+            ResponsesOutputItem::Message { .. } | ResponsesOutputItem::Other => {}
+        }
+        Ok(())
+    }
+}
+
+impl Default for ResponsesStreamHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Error for a response that failed
+pub(crate) fn failure(response: ResponsesResponse) -> KodeError {
+    let message = response.error.map_or_else(
+        || "Response failed".to_string(),
+        |error| match error.code {
+            Some(code) => format!("{code}: {}", error.message),
+            None => error.message,
+        },
+    );
+    KodeError::ApiError {
+        provider: "openai".to_string(),
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::services::Usage;
+
+    fn event(data: &serde_json::Value) -> String {
+        format!("event: {}\ndata: {data}\n\n", data["type"].as_str().unwrap())
+    }
+
+    fn created() -> String {
+        event(&json!({
+            "type": "response.created",
+            "response": {"id": "resp_1", "model": "gpt-5", "status": "in_progress", "output": []}
+        }))
+    }
+
+    #[test]
+    fn test_reasoning_then_text() {
+        let mut handler = ResponsesStreamHandler::new();
+        assert!(handler.process_chunk(&created()).unwrap().is_empty());
+
+        let summary = [
+            json!({"type": "response.reasoning_summary_text.delta", "output_index": 0, "summary_index": 0, "delta": "Plan"}),
+            json!({"type": "response.reasoning_summary_text.delta", "output_index": 0, "summary_index": 1, "delta": "Check"}),
+        ]
+        .iter()
+        .map(event)
+        .collect::<String>();
+        let emitted = handler.process_chunk(&summary).unwrap();
+        let thinking: Vec<_> = emitted
+            .iter()
+            .map(|chunk| match chunk {
+                CompletionChunk::ThinkingDelta { thinking } => thinking.as_str(),
+                other => panic!("Expected thinking, got {other:?}"),
+            })
+            .collect();
+        assert_eq!(thinking, ["Plan", "\n\n", "Check"]);
+
+        let item_done = event(&json!({
+            "type": "response.output_item.done",
+            "output_index": 0,
+            "item": {
+                "type": "reasoning",
+                "id": "rs_1",
+                "summary": [{"type": "summary_text", "text": "Plan"}, {"type": "summary_text", "text": "Check"}],
+                "encrypted_content": "gAAAA"
+            }
+        }));
+        let emitted = handler.process_chunk(&item_done).unwrap();
+        assert!(matches!(
+            &emitted[..],
+            [CompletionChunk::Reasoning { id, summary, encrypted_content: Some(encrypted) }]
+                if id == "rs_1" && summary == "Plan\n\nCheck" && encrypted == "gAAAA"
+        ));
+
+        let text = event(&json!({"type": "response.output_text.delta", "output_index": 1, "content_index": 0, "delta": "Hi"}));
+        let emitted = handler.process_chunk(&text).unwrap();
+        assert!(matches!(&emitted[..], [CompletionChunk::TextDelta { text }] if text == "Hi"));
+
+        let completed = event(&json!({
+            "type": "response.completed",
+            "response": {
+                "id": "resp_1",
+                "model": "gpt-5",
+                "status": "completed",
+                "output": [],
+                "usage": {"input_tokens": 100, "input_tokens_details": {"cached_tokens": 60}, "output_tokens": 20}
+            }
+        }));
+        let emitted = handler.process_chunk(&completed).unwrap();
+        match &emitted[..] {
+            [CompletionChunk::Done {
+                stop_reason,
+                usage: Some(Usage {
+                    input_tokens,
+                    output_tokens,
+                    cache_read_input_tokens,
+                    ..
+                }),
+            }] => {
+                assert_eq!(stop_reason, "end_turn");
+                assert_eq!((*input_tokens, *output_tokens), (40, 20));
+                assert_eq!(*cache_read_input_tokens, Some(60));
+            }
+            other => panic!("Expected done, got {other:?}"),
+        }
+        assert!(handler.is_complete());
+    }
+
+    #[test]
+    fn test_function_call() {
+        let mut handler = ResponsesStreamHandler::new();
+        let stream = [
+            json!({"type": "response.output_item.added", "output_index": 0, "item": {"type": "function_call", "id": "fc_1", "call_id": "call_1", "name": "Bash", "arguments": ""}}),
+            json!({"type": "response.function_call_arguments.delta", "output_index": 0, "item_id": "fc_1", "delta": "{\"command\":"}),
+            json!({"type": "response.function_call_arguments.delta", "output_index": 0, "item_id": "fc_1", "delta": "\"ls\"}"}),
+            json!({"type": "response.output_item.done", "output_index": 0, "item": {"type": "function_call", "id": "fc_1", "call_id": "call_1", "name": "Bash", "arguments": "{\"command\":\"ls\"}"}}),
+        ]
+        .iter()
+        .map(event)
+        .collect::<String>();
+
+        let emitted = handler.process_chunk(&(created() + &stream)).unwrap();
+        assert!(matches!(&emitted[0], CompletionChunk::ToolUseStart { id, name } if id == "call_1" && name == "Bash"));
+        assert!(matches!(&emitted[1], CompletionChunk::ToolInputDelta { id, partial_json } if id == "call_1" && partial_json == "{\"command\":"));
+        assert!(matches!(
+            &emitted[3],
+            CompletionChunk::ToolUseComplete { id, input, .. } if id == "call_1" && *input == json!({"command": "ls"})
+        ));
+
+        // The connection closed before `response.completed`
+        let emitted = handler.finish().unwrap();
+        assert!(matches!(&emitted[..], [CompletionChunk::Done { stop_reason, usage: None }] if stop_reason == "tool_use"));
+    }
+
+    #[test]
+    fn test_failed_response() {
+        let mut handler = ResponsesStreamHandler::new();
+        let failed = event(&json!({
+            "type": "response.failed",
+            "response": {
+                "id": "resp_1",
+                "model": "gpt-5",
+                "status": "failed",
+                "output": [],
+                "error": {"code": "server_error", "message": "The model crashed"}
+            }
+        }));
+
+        let error = handler.process_chunk(&failed).unwrap_err();
+        assert!(error.to_string().contains("server_error: The model crashed"));
+    }
+}
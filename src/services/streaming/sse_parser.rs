@@ -56,6 +56,9 @@ pub struct SseParser {
 
     /// Buffer for incomplete lines
     line_buffer: String,
+
+    /// Leading bytes of a UTF-8 character split across chunks
+    pending_bytes: Vec<u8>,
 }
 
 impl SseParser {
@@ -64,7 +67,44 @@ impl SseParser {
         Self {
             current_event: SseEvent::new(),
             line_buffer: String::new(),
+            pending_bytes: Vec::new(),
+        }
+    }
+
+    /// Parse a chunk of raw SSE bytes
+    ///
+    /// Network chunks may end in the middle of a multi-byte character; those
+    /// bytes are held back until the rest arrives. Invalid sequences are
+    /// replaced with U+FFFD.
+    pub fn parse_bytes(&mut self, bytes: &[u8]) -> Vec<SseEvent> {
+        self.pending_bytes.extend_from_slice(bytes);
+
+        let mut text = String::new();
+        let mut input = self.pending_bytes.as_slice();
+        loop {
+            match std::str::from_utf8(input) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    input = &[];
+                    break;
+                }
+                Err(e) => {
+                    let (valid, rest) = input.split_at(e.valid_up_to());
+                    text.push_str(&String::from_utf8_lossy(valid));
+                    // Incomplete character at the end: wait for the next chunk
+                    let Some(len) = e.error_len() else {
+                        input = rest;
+                        break;
+                    };
+                    text.push(char::REPLACEMENT_CHARACTER);
+                    input = &rest[len..];
+                }
+            }
         }
+        let incomplete = input.len();
+        self.pending_bytes.drain(..self.pending_bytes.len() - incomplete);
+
+        self.parse_chunk(&text)
     }
 
     /// Parse a chunk of SSE data
@@ -154,6 +194,12 @@ impl SseParser {
 
     /// Flush any remaining buffered event
     pub fn flush(&mut self) -> Option<SseEvent> {
+        // A stream cut off inside a character leaves its bytes undecodable
+        if !self.pending_bytes.is_empty() {
+            let bytes = std::mem::take(&mut self.pending_bytes);
+            self.line_buffer.push_str(&String::from_utf8_lossy(&bytes));
+        }
+
         // Process any remaining line in buffer
         if !self.line_buffer.is_empty() {
             let line = self.line_buffer.clone();
@@ -259,6 +305,32 @@ mod tests {
         assert_eq!(events[0].event_type, Some("message".to_string()));
     }
 
+    #[test]
+    fn test_parse_bytes_with_split_characters() {
+        let mut parser = SseParser::new();
+        let bytes = "data: héllo 👋\n\n".as_bytes();
+
+        // Split inside both the two-byte and the four-byte character
+        let e_acute = 8;
+        let wave = bytes.len() - 4;
+        assert!(parser.parse_bytes(&bytes[..e_acute]).is_empty());
+        assert!(parser.parse_bytes(&bytes[e_acute..wave]).is_empty());
+        assert!(parser.parse_bytes(&bytes[wave..wave + 2]).is_empty());
+        let events = parser.parse_bytes(&bytes[wave + 2..]);
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "héllo 👋");
+    }
+
+    #[test]
+    fn test_parse_bytes_replaces_invalid_sequences() {
+        let mut parser = SseParser::new();
+        let events = parser.parse_bytes(b"data: a\xffb\n\n");
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "a\u{FFFD}b");
+    }
+
     #[test]
     fn test_flush() {
         let mut parser = SseParser::new();
@@ -0,0 +1,102 @@
+//! Test doubles for model adapters
+
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+
+use super::{
+    CompletionChunk, CompletionOptions, CompletionResponse, CompletionStream, ModelAdapter,
+    ToolSchema,
+};
+use crate::{
+    cost::ModelPricing,
+    error::{KodeError, Result},
+    messages::Message,
+};
+
+/// Adapter replaying scripted chunk sequences, one per request
+pub(crate) struct ScriptedAdapter {
+    /// Remaining turns, consumed front to back
+    pub turns: Mutex<Vec<Vec<CompletionChunk>>>,
+
+    /// Messages and tools of every request received so far
+    pub requests: Mutex<Vec<(Vec<Message>, Vec<ToolSchema>)>>,
+
+    /// System prompt of every request received so far, shared so that it can
+    /// be inspected after the adapter is handed over
+    pub system_prompts: Arc<Mutex<Vec<Option<String>>>>,
+
+    /// Reported context window size
+    pub context_window: u32,
+
+    /// Reported prices
+    pub pricing: Option<ModelPricing>,
+}
+
+impl ScriptedAdapter {
+    pub fn new(turns: Vec<Vec<CompletionChunk>>) -> Self {
+        Self {
+            turns: Mutex::new(turns),
+            requests: Mutex::new(Vec::new()),
+            system_prompts: Arc::new(Mutex::new(Vec::new())),
+            context_window: 100_000,
+            pricing: None,
+        }
+    }
+
+    pub fn with_context_window(mut self, context_window: u32) -> Self {
+        self.context_window = context_window;
+        self
+    }
+
+    pub fn with_pricing(mut self, pricing: ModelPricing) -> Self {
+        self.pricing = Some(pricing);
+        self
+    }
+}
+
+#[async_trait]
+impl ModelAdapter for ScriptedAdapter {
+    fn provider(&self) -> &'static str {
+        "scripted"
+    }
+
+    fn model(&self) -> &'static str {
+        "scripted-model"
+    }
+
+    async fn complete(
+        &self,
+        _messages: Vec<Message>,
+        _tools: Vec<ToolSchema>,
+        _system_prompt: Option<String>,
+        _options: CompletionOptions,
+    ) -> Result<CompletionResponse> {
+        Err(KodeError::NotImplemented("complete".to_string()))
+    }
+
+    async fn stream_complete(
+        &self,
+        messages: Vec<Message>,
+        tools: Vec<ToolSchema>,
+        system_prompt: Option<String>,
+        _options: CompletionOptions,
+    ) -> Result<CompletionStream> {
+        self.requests.lock().unwrap().push((messages, tools));
+        self.system_prompts.lock().unwrap().push(system_prompt);
+        let chunks = self.turns.lock().unwrap().remove(0);
+        Ok(Box::pin(futures::stream::iter(chunks.into_iter().map(Ok))))
+    }
+
+    fn pricing(&self) -> Option<ModelPricing> {
+        self.pricing
+    }
+
+    fn max_context_tokens(&self) -> u32 {
+        self.context_window
+    }
+
+    fn max_output_tokens(&self) -> u32 {
+        4096
+    }
+}
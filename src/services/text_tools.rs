@@ -0,0 +1,551 @@
+//! Tool calling through text
+//!
+//! Many local models ignore the `tools` field of a request. For them,
+//! [`TextToolAdapter`] describes the tools in the system prompt and has the
+//! model call them by writing blocks like
+//!
+//! ```text
+//! <tool_call>
+//! {"name": "Bash", "input": {"command": "ls"}}
+//! </tool_call>
+//! ```
+//!
+//! The streamed text is scanned for these blocks, which become tool use
+//! chunks. Earlier calls and their results are sent back as text in the same
+//! format.
+
+use std::{collections::HashMap, fmt::Write};
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde_json::Value;
+
+use super::{
+    streaming::new_call_id, CompletionChunk, CompletionOptions, CompletionResponse,
+    CompletionStream, ModelAdapter, ToolSchema,
+};
+use crate::{
+    cost::ModelPricing,
+    error::Result,
+    messages::{ContentBlock, Message},
+};
+
+/// Tag opening a tool call
+const CALL_OPEN: &str = "<tool_call>";
+
+/// Tag closing a tool call
+const CALL_CLOSE: &str = "</tool_call>";
+
+/// Adapter giving tools to a model through its prompt and output text
+pub struct TextToolAdapter {
+    inner: Box<dyn ModelAdapter>,
+}
+
+impl TextToolAdapter {
+    /// Create an adapter calling tools through text with `inner`
+    #[must_use]
+    pub fn new(inner: Box<dyn ModelAdapter>) -> Self {
+        Self { inner }
+    }
+}
+
+/// Describe `tools` and how to call them, for the end of the system prompt
+#[must_use]
+pub fn render_tools(tools: &[ToolSchema]) -> String {
+    let mut prompt = format!(
+        "# Tools\n\n\
+         You can use the tools below. To call a tool, write its name and a JSON \
+         object matching its input schema in a block like this:\n\n\
+         {CALL_OPEN}\n{{\"name\": \"tool_name\", \"input\": {{\"parameter\": \"value\"}}}}\n{CALL_CLOSE}\n\n\
+         You may call several tools in one reply. Stop writing after your calls: \
+         the results arrive in the next message, inside <tool_result> blocks."
+    );
+    for tool in tools {
+        let _ = write!(
+            prompt,
+            "\n\n## {}\n\n{}\n\nInput schema: {}",
+            tool.name, tool.description, tool.input_schema
+        );
+    }
+    prompt
+}
+
+/// The system prompt with the tools appended
+fn system_prompt_with_tools(system_prompt: Option<String>, tools: &[ToolSchema]) -> Option<String> {
+    if tools.is_empty() {
+        return system_prompt;
+    }
+    let tools = render_tools(tools);
+    Some(match system_prompt {
+        Some(prompt) => format!("{prompt}\n\n{tools}"),
+        None => tools,
+    })
+}
+
+/// A tool call as the model is asked to write it
+fn format_call(name: &str, input: &Value) -> String {
+    let call = serde_json::json!({"name": name, "input": input});
+    format!("{CALL_OPEN}\n{call}\n{CALL_CLOSE}")
+}
+
+/// Replace tool calls and results with their text form
+///
+/// Images returned by tools stay image blocks right after the result.
+fn convert_messages(messages: Vec<Message>) -> Vec<Message> {
+    let mut tool_names = HashMap::new();
+    messages
+        .into_iter()
+        .map(|mut message| {
+            let mut content = Vec::with_capacity(message.content.len());
+            for block in message.content {
+                match block {
+                    ContentBlock::ToolUse { id, name, input } => {
+                        content.push(ContentBlock::Text {
+                            text: format_call(&name, &input),
+                        });
+                        tool_names.insert(id, name);
+                    }
+                    ContentBlock::ToolResult {
+                        tool_use_id,
+                        content: result,
+                        is_error,
+                        images,
+                    } => {
+                        let name = tool_names.get(&tool_use_id).map_or("unknown", String::as_str);
+                        let error = if is_error == Some(true) { " error=\"true\"" } else { "" };
+                        content.push(ContentBlock::Text {
+                            text: format!("<tool_result name=\"{name}\"{error}>\n{result}\n</tool_result>"),
+                        });
+                        content.extend(images.into_iter().map(|source| ContentBlock::Image { source }));
+                    }
+                    block => content.push(block),
+                }
+            }
+            message.content = content;
+            message
+        })
+        .collect()
+}
+
+/// Incremental parser turning tool call blocks in streamed text into tool
+/// use chunks
+///
+/// Text that may be the start of a tag is held back until it is known not to
+/// be. Blocks that are not valid calls are passed on as text.
+#[derive(Debug, Default)]
+pub struct TextToolParser {
+    /// Text not yet passed on
+    buffer: String,
+
+    /// Whether the buffer is inside a tool call block
+    in_call: bool,
+
+    /// Whether a tool call has been parsed
+    has_tool_calls: bool,
+}
+
+impl TextToolParser {
+    /// Create a new parser
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether a tool call has been parsed
+    #[must_use]
+    pub fn has_tool_calls(&self) -> bool {
+        self.has_tool_calls
+    }
+
+    /// Process a piece of streamed text
+    pub fn process_text(&mut self, text: &str) -> Vec<CompletionChunk> {
+        self.buffer.push_str(text);
+
+        let mut chunks = Vec::new();
+        loop {
+            if self.in_call {
+                let Some(end) = self.call_end() else {
+                    break;
+                };
+                let block: String = self.buffer.drain(..end + CALL_CLOSE.len()).collect();
+                self.in_call = false;
+                if !self.push_call(&block[..end], &mut chunks) {
+                    push_text(&format!("{CALL_OPEN}{block}"), &mut chunks);
+                }
+            } else if let Some(start) = self.buffer.find(CALL_OPEN) {
+                let text: String = self.buffer.drain(..start + CALL_OPEN.len()).collect();
+                push_text(&text[..start], &mut chunks);
+                self.in_call = true;
+            } else {
+                // Hold back what may be the start of an opening tag
+                let held = (1..CALL_OPEN.len())
+                    .rev()
+                    .find(|&len| self.buffer.ends_with(&CALL_OPEN[..len]))
+                    .unwrap_or(0);
+                let text: String = self.buffer.drain(..self.buffer.len() - held).collect();
+                push_text(&text, &mut chunks);
+                break;
+            }
+        }
+        chunks
+    }
+
+    /// Position of the closing tag ending the call being read
+    ///
+    /// Arguments may themselves contain the closing tag, so the call ends at
+    /// the first one before which the JSON body is complete.
+    fn call_end(&self) -> Option<usize> {
+        self.buffer.match_indices(CALL_CLOSE).map(|(end, _)| end).find(|&end| {
+            serde_json::from_str::<Value>(self.buffer[..end].trim())
+                .map_or_else(|e| !e.is_eof(), |_| true)
+        })
+    }
+
+    /// Pass on the text held back once the text has ended
+    ///
+    /// A call missing only its closing tag is still made.
+    pub fn finish(&mut self) -> Vec<CompletionChunk> {
+        let mut chunks = Vec::new();
+        let rest = std::mem::take(&mut self.buffer);
+        if !std::mem::take(&mut self.in_call) {
+            push_text(&rest, &mut chunks);
+        } else if !self.push_call(&rest, &mut chunks) {
+            push_text(&format!("{CALL_OPEN}{rest}"), &mut chunks);
+        }
+        chunks
+    }
+
+    /// Append the chunks of the call written as `body`
+    ///
+    /// Returns `false`, appending nothing, if `body` is not a valid call.
+    fn push_call(&mut self, body: &str, chunks: &mut Vec<CompletionChunk>) -> bool {
+        let Some((name, input)) = parse_call(body) else {
+            return false;
+        };
+        self.has_tool_calls = true;
+        let id = new_call_id();
+        chunks.push(CompletionChunk::ToolUseStart {
+            id: id.clone(),
+            name: name.clone(),
+        });
+        chunks.push(CompletionChunk::ToolInputDelta {
+            id: id.clone(),
+            partial_json: input.to_string(),
+        });
+        chunks.push(CompletionChunk::ToolUseComplete { id, name, input });
+        true
+    }
+}
+
+fn push_text(text: &str, chunks: &mut Vec<CompletionChunk>) {
+    if !text.is_empty() {
+        chunks.push(CompletionChunk::TextDelta {
+            text: text.to_string(),
+        });
+    }
+}
+
+/// Name and input of a call, accepting the argument keys models commonly use
+fn parse_call(body: &str) -> Option<(String, Value)> {
+    let mut call: Value = serde_json::from_str(body.trim()).ok()?;
+    let name = call.get("name")?.as_str()?.to_string();
+    let input = ["input", "arguments", "parameters"]
+        .into_iter()
+        .find_map(|key| call.get_mut(key).map(Value::take))
+        .unwrap_or_else(|| Value::Object(serde_json::Map::new()));
+    // Some models write the arguments as a JSON string
+    let input = match input {
+        Value::String(json) => serde_json::from_str(&json).ok()?,
+        input => input,
+    };
+    input.is_object().then_some((name, input))
+}
+
+/// Append parsed chunks to response content
+fn push_content(content: &mut Vec<ContentBlock>, chunks: Vec<CompletionChunk>) {
+    for chunk in chunks {
+        match chunk {
+            CompletionChunk::TextDelta { text } => match content.last_mut() {
+                Some(ContentBlock::Text { text: last }) => last.push_str(&text),
+                _ => content.push(ContentBlock::Text { text }),
+            },
+            CompletionChunk::ToolUseComplete { id, name, input } => {
+                content.push(ContentBlock::ToolUse { id, name, input });
+            }
+            _ => {}
+        }
+    }
+}
+
+#[async_trait]
+impl ModelAdapter for TextToolAdapter {
+    fn provider(&self) -> &str {
+        self.inner.provider()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    async fn complete(
+        &self,
+        messages: Vec<Message>,
+        tools: Vec<ToolSchema>,
+        system_prompt: Option<String>,
+        options: CompletionOptions,
+    ) -> Result<CompletionResponse> {
+        let system_prompt = system_prompt_with_tools(system_prompt, &tools);
+        let response = self
+            .inner
+            .complete(convert_messages(messages), Vec::new(), system_prompt, options)
+            .await?;
+
+        let mut parser = TextToolParser::new();
+        let mut content = Vec::new();
+        for block in response.content {
+            match block {
+                ContentBlock::Text { text } => push_content(&mut content, parser.process_text(&text)),
+                block => content.push(block),
+            }
+        }
+        push_content(&mut content, parser.finish());
+
+        Ok(CompletionResponse {
+            content,
+            stop_reason: if parser.has_tool_calls() {
+                Some("tool_use".to_string())
+            } else {
+                response.stop_reason
+            },
+            ..response
+        })
+    }
+
+    async fn stream_complete(
+        &self,
+        messages: Vec<Message>,
+        tools: Vec<ToolSchema>,
+        system_prompt: Option<String>,
+        options: CompletionOptions,
+    ) -> Result<CompletionStream> {
+        let system_prompt = system_prompt_with_tools(system_prompt, &tools);
+        let mut stream = self
+            .inner
+            .stream_complete(convert_messages(messages), Vec::new(), system_prompt, options)
+            .await?;
+
+        Ok(Box::pin(async_stream::try_stream! {
+            let mut parser = TextToolParser::new();
+            while let Some(chunk) = stream.next().await {
+                match chunk? {
+                    CompletionChunk::TextDelta { text } => {
+                        for chunk in parser.process_text(&text) {
+                            yield chunk;
+                        }
+                    }
+                    CompletionChunk::Done { stop_reason, usage } => {
+                        for chunk in parser.finish() {
+                            yield chunk;
+                        }
+                        let stop_reason = if parser.has_tool_calls() {
+                            "tool_use".to_string()
+                        } else {
+                            stop_reason
+                        };
+                        yield CompletionChunk::Done { stop_reason, usage };
+                    }
+                    chunk => yield chunk,
+                }
+            }
+            // Text held back when the stream ends without `Done`
+            for chunk in parser.finish() {
+                yield chunk;
+            }
+        }))
+    }
+
+    fn pricing(&self) -> Option<ModelPricing> {
+        self.inner.pricing()
+    }
+
+    fn max_context_tokens(&self) -> u32 {
+        self.inner.max_context_tokens()
+    }
+
+    fn max_output_tokens(&self) -> u32 {
+        self.inner.max_output_tokens()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{messages::Role, services::testing::ScriptedAdapter};
+
+    fn bash_tool() -> ToolSchema {
+        ToolSchema {
+            name: "Bash".to_string(),
+            description: "Run a shell command".to_string(),
+            input_schema: json!({"type": "object", "properties": {"command": {"type": "string"}}}),
+        }
+    }
+
+    /// Text and completed calls of a chunk sequence, with texts merged
+    fn summarize(chunks: &[CompletionChunk]) -> Vec<String> {
+        let mut summary: Vec<String> = Vec::new();
+        let mut last_was_text = false;
+        for chunk in chunks {
+            match chunk {
+                CompletionChunk::TextDelta { text } if last_was_text => {
+                    summary.last_mut().unwrap().push_str(text);
+                }
+                CompletionChunk::TextDelta { text } => summary.push(text.clone()),
+                CompletionChunk::ToolUseComplete { name, input, .. } => summary.push(format!("{name} {input}")),
+                _ => {}
+            }
+            last_was_text = matches!(chunk, CompletionChunk::TextDelta { .. });
+        }
+        summary
+    }
+
+    #[test]
+    fn test_parser_handles_split_tags() {
+        let output = "Let me look.\n<tool_call>\n{\"name\": \"Bash\", \"input\": {\"command\": \"ls\"}}\n</tool_call>\n\
+                      <tool_call>{\"name\": \"View\", \"arguments\": \"{\\\"file_path\\\": \\\"a < b.rs\\\"}\"}</tool_call>";
+
+        // Tags may be split anywhere between chunks
+        for size in [1, 3, 7, output.len()] {
+            let mut parser = TextToolParser::new();
+            let mut chunks = Vec::new();
+            let characters: Vec<char> = output.chars().collect();
+            for piece in characters.chunks(size) {
+                chunks.extend(parser.process_text(&piece.iter().collect::<String>()));
+            }
+            chunks.extend(parser.finish());
+
+            assert!(parser.has_tool_calls());
+            assert_eq!(
+                summarize(&chunks),
+                [
+                    "Let me look.\n",
+                    r#"Bash {"command":"ls"}"#,
+                    "\n",
+                    r#"View {"file_path":"a < b.rs"}"#,
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn test_parser_reads_closing_tags_inside_arguments() {
+        let output = "<tool_call>{\"name\": \"Write\", \"input\": {\"content\": \"a</tool_call>b\"}}</tool_call>Done";
+
+        for size in [1, 5, output.len()] {
+            let mut parser = TextToolParser::new();
+            let mut chunks = Vec::new();
+            let characters: Vec<char> = output.chars().collect();
+            for piece in characters.chunks(size) {
+                chunks.extend(parser.process_text(&piece.iter().collect::<String>()));
+            }
+            chunks.extend(parser.finish());
+
+            assert_eq!(
+                summarize(&chunks),
+                [r#"Write {"content":"a</tool_call>b"}"#, "Done"]
+            );
+        }
+    }
+
+    #[test]
+    fn test_parser_keeps_invalid_calls_as_text() {
+        let mut parser = TextToolParser::new();
+        let mut chunks = parser.process_text("Use <tool_call>not json</tool_call> like so <tool");
+        chunks.extend(parser.finish());
+        assert!(!parser.has_tool_calls());
+        assert_eq!(summarize(&chunks), ["Use <tool_call>not json</tool_call> like so <tool"]);
+
+        // A call missing only its closing tag is still made
+        let mut parser = TextToolParser::new();
+        let mut chunks = parser.process_text("<tool_call>{\"name\": \"Bash\", \"input\": {\"command\": \"pwd\"}}");
+        chunks.extend(parser.finish());
+        assert_eq!(summarize(&chunks), [r#"Bash {"command":"pwd"}"#]);
+    }
+
+    #[test]
+    fn test_tool_history_becomes_text() {
+        let messages = vec![
+            Message {
+                role: Role::Assistant,
+                content: vec![ContentBlock::ToolUse {
+                    id: "call_1".to_string(),
+                    name: "Bash".to_string(),
+                    input: json!({"command": "false"}),
+                }],
+                uuid: None,
+            },
+            Message {
+                role: Role::User,
+                content: vec![ContentBlock::ToolResult {
+                    tool_use_id: "call_1".to_string(),
+                    content: "exit code 1".to_string(),
+                    is_error: Some(true),
+                    images: Vec::new(),
+                }],
+                uuid: None,
+            },
+        ];
+
+        let converted = convert_messages(messages);
+        assert_eq!(
+            converted[0].text_content(),
+            "<tool_call>\n{\"input\":{\"command\":\"false\"},\"name\":\"Bash\"}\n</tool_call>"
+        );
+        assert_eq!(
+            converted[1].text_content(),
+            "<tool_result name=\"Bash\" error=\"true\">\nexit code 1\n</tool_result>"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_calls_tools_through_text() {
+        let inner = ScriptedAdapter::new(vec![vec![
+            CompletionChunk::TextDelta {
+                text: "Listing files <tool_".to_string(),
+            },
+            CompletionChunk::TextDelta {
+                text: "call>{\"name\": \"Bash\", \"input\": {\"command\": \"ls\"}}</tool_call>".to_string(),
+            },
+            CompletionChunk::Done {
+                stop_reason: "end_turn".to_string(),
+                usage: None,
+            },
+        ]]);
+        let system_prompts = inner.system_prompts.clone();
+        let adapter = TextToolAdapter::new(Box::new(inner));
+
+        let mut stream = adapter
+            .stream_complete(
+                vec![Message::user("What is here?")],
+                vec![bash_tool()],
+                Some("Be brief".to_string()),
+                CompletionOptions::default(),
+            )
+            .await
+            .unwrap();
+        let mut chunks = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            chunks.push(chunk.unwrap());
+        }
+
+        assert_eq!(summarize(&chunks), ["Listing files ", r#"Bash {"command":"ls"}"#]);
+        assert!(
+            matches!(chunks.last(), Some(CompletionChunk::Done { stop_reason, .. }) if stop_reason == "tool_use")
+        );
+
+        let system_prompt = system_prompts.lock().unwrap()[0].clone().unwrap();
+        assert!(system_prompt.starts_with("Be brief\n\n# Tools"));
+        assert!(system_prompt.contains("## Bash\n\nRun a shell command"));
+    }
+}
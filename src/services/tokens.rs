@@ -0,0 +1,266 @@
+//! Offline token counting
+//!
+//! OpenAI-compatible models are counted with the actual BPE vocabularies
+//! (`cl100k_base` and `o200k_base`, bundled with the crate). Claude's tokenizer
+//! is not published, so Anthropic models are estimated from the `cl100k_base`
+//! count scaled by a calibration factor. The factor errs high, as running
+//! over the context window is worse than compacting a little early.
+
+use tiktoken_rs::CoreBPE;
+
+use super::ToolSchema;
+use crate::messages::{ContentBlock, Message};
+
+/// Claude tokens per 100 `cl100k_base` tokens, measured on mixed prose and code
+const ANTHROPIC_TOKENS_PER_100: usize = 120;
+
+/// Tokens added by Anthropic's tool use system prompt whenever tools are sent
+const ANTHROPIC_TOOLS_OVERHEAD: u32 = 346;
+
+/// Tokens framing each message (role and separators)
+const TOKENS_PER_MESSAGE: u32 = 3;
+
+/// Tokens priming the assistant reply
+const REPLY_PRIMING_TOKENS: u32 = 3;
+
+/// Tokens framing each tool definition
+const TOKENS_PER_TOOL: u32 = 8;
+
+/// Tokens of an image downscaled to the largest size we send
+/// (about width * height / 750 for Claude)
+const IMAGE_TOKENS: u32 = 1_600;
+
+/// BPE vocabulary of a model family
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// GPT-3.5 and GPT-4; also used for OpenAI-compatible open models
+    Cl100kBase,
+
+    /// GPT-4o, GPT-4.1, GPT-5 and the o-series
+    O200kBase,
+}
+
+impl Encoding {
+    fn bpe(self) -> &'static CoreBPE {
+        match self {
+            Self::Cl100kBase => tiktoken_rs::cl100k_base_singleton(),
+            Self::O200kBase => tiktoken_rs::o200k_base_singleton(),
+        }
+    }
+}
+
+/// Counts tokens the way a provider family does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenCounter {
+    /// Exact BPE count
+    Bpe(Encoding),
+
+    /// Calibrated estimate for Claude models
+    Anthropic,
+}
+
+impl TokenCounter {
+    /// Pick the counter for a provider and model name
+    ///
+    /// Claude models are recognized by provider (`anthropic`, `bedrock`,
+    /// `vertex`) or by name; everything else is treated as OpenAI-compatible.
+    #[must_use]
+    pub fn for_model(provider: &str, model: &str) -> Self {
+        // Routers prefix the vendor, e.g. `openai/gpt-4o`
+        let model = model.rsplit('/').next().unwrap_or(model).to_lowercase();
+
+        if matches!(provider, "anthropic" | "bedrock" | "vertex") || model.contains("claude") {
+            return Self::Anthropic;
+        }
+
+        let o200k = ["gpt-4o", "gpt-4.1", "gpt-5", "gpt-oss", "chatgpt-4o", "o1", "o3", "o4"];
+        if o200k.iter().any(|prefix| model.starts_with(prefix)) {
+            Self::Bpe(Encoding::O200kBase)
+        } else {
+            Self::Bpe(Encoding::Cl100kBase)
+        }
+    }
+
+    /// Count the tokens of a piece of text
+    #[must_use]
+    pub fn count_text(self, text: &str) -> u32 {
+        if text.is_empty() {
+            return 0;
+        }
+        match self {
+            Self::Bpe(encoding) => saturate(encoding.bpe().encode_ordinary(text).len()),
+            Self::Anthropic => {
+                let base = Encoding::Cl100kBase.bpe().encode_ordinary(text).len();
+                saturate(base.saturating_mul(ANTHROPIC_TOKENS_PER_100).div_ceil(100))
+            }
+        }
+    }
+
+    /// Count the tokens of a message, including role framing and every block
+    #[must_use]
+    pub fn count_message(self, message: &Message) -> u32 {
+        message
+            .content
+            .iter()
+            .map(|block| self.count_block(block))
+            .fold(TOKENS_PER_MESSAGE, u32::saturating_add)
+    }
+
+    /// Count the tokens of tool definitions
+    #[must_use]
+    pub fn count_tools(self, tools: &[ToolSchema]) -> u32 {
+        if tools.is_empty() {
+            return 0;
+        }
+        let overhead = match self {
+            Self::Bpe(_) => 0,
+            Self::Anthropic => ANTHROPIC_TOOLS_OVERHEAD,
+        };
+        tools
+            .iter()
+            .map(|tool| {
+                TOKENS_PER_TOOL
+                    .saturating_add(self.count_text(&tool.name))
+                    .saturating_add(self.count_text(&tool.description))
+                    .saturating_add(self.count_text(&tool.input_schema.to_string()))
+            })
+            .fold(overhead, u32::saturating_add)
+    }
+
+    /// Count a whole request: system prompt, messages and tool definitions
+    #[must_use]
+    pub fn count_request(
+        self,
+        messages: &[Message],
+        system_prompt: Option<&str>,
+        tools: &[ToolSchema],
+    ) -> u32 {
+        let system = system_prompt.map_or(0, |prompt| {
+            TOKENS_PER_MESSAGE.saturating_add(self.count_text(prompt))
+        });
+        messages
+            .iter()
+            .map(|message| self.count_message(message))
+            .fold(REPLY_PRIMING_TOKENS, u32::saturating_add)
+            .saturating_add(system)
+            .saturating_add(self.count_tools(tools))
+    }
+
+    fn count_block(self, block: &ContentBlock) -> u32 {
+        match block {
+            ContentBlock::Text { text } => self.count_text(text),
+            ContentBlock::Thinking { thinking, .. } => self.count_text(thinking),
+            ContentBlock::RedactedThinking { data } => self.count_text(data),
+            ContentBlock::Reasoning { summary, .. } => self.count_text(summary),
+            ContentBlock::ToolUse { id, name, input } => self
+                .count_text(id)
+                .saturating_add(self.count_text(name))
+                .saturating_add(self.count_text(&input.to_string())),
+            ContentBlock::ToolResult {
+                tool_use_id,
+                content,
+                images,
+                ..
+            } => self
+                .count_text(tool_use_id)
+                .saturating_add(self.count_text(content))
+                .saturating_add(IMAGE_TOKENS.saturating_mul(saturate(images.len()))),
+            ContentBlock::Image { .. } => IMAGE_TOKENS,
+        }
+    }
+}
+
+fn saturate(count: usize) -> u32 {
+    u32::try_from(count).unwrap_or(u32::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::messages::Role;
+
+    const CL100K: TokenCounter = TokenCounter::Bpe(Encoding::Cl100kBase);
+    const O200K: TokenCounter = TokenCounter::Bpe(Encoding::O200kBase);
+
+    #[test]
+    fn test_for_model() {
+        assert_eq!(TokenCounter::for_model("openai", "gpt-4o-mini"), O200K);
+        assert_eq!(TokenCounter::for_model("openai", "gpt-5"), O200K);
+        assert_eq!(TokenCounter::for_model("openai", "o3-mini"), O200K);
+        assert_eq!(TokenCounter::for_model("openai", "gpt-4-turbo"), CL100K);
+        assert_eq!(TokenCounter::for_model("openai", "deepseek-chat"), CL100K);
+        assert_eq!(TokenCounter::for_model("openai", "openai/gpt-4o"), O200K);
+        assert_eq!(
+            TokenCounter::for_model("openai", "anthropic/claude-sonnet-4"),
+            TokenCounter::Anthropic
+        );
+        assert_eq!(
+            TokenCounter::for_model("anthropic", "claude-3-5-haiku-latest"),
+            TokenCounter::Anthropic
+        );
+    }
+
+    #[test]
+    fn test_bpe_counts_match_vocabularies() {
+        assert_eq!(CL100K.count_text(""), 0);
+        assert_eq!(CL100K.count_text("hello world"), 2);
+        assert_eq!(O200K.count_text("hello world"), 2);
+        // Code and non-English text take far more tokens than four bytes each
+        for text in ["你好，世界！", "fn main() { println!(\"{}\", x?); }"] {
+            assert!(CL100K.count_text(text) > saturate(text.len() / 4));
+        }
+    }
+
+    #[test]
+    fn test_anthropic_estimate_scales_cl100k() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let base = CL100K.count_text(text);
+        let estimate = TokenCounter::Anthropic.count_text(text);
+        assert!(estimate > base);
+        assert!(estimate <= base * 2);
+    }
+
+    #[test]
+    fn test_count_request_covers_tools_and_results() {
+        let messages = vec![
+            Message::user("List the files"),
+            Message {
+                role: Role::Assistant,
+                content: vec![ContentBlock::ToolUse {
+                    id: "toolu_1".to_string(),
+                    name: "Bash".to_string(),
+                    input: json!({"command": "ls -la"}),
+                }],
+                uuid: None,
+            },
+            Message {
+                role: Role::User,
+                content: vec![ContentBlock::ToolResult {
+                    tool_use_id: "toolu_1".to_string(),
+                    content: "Cargo.toml\nsrc\n".repeat(50),
+                    is_error: None,
+                    images: Vec::new(),
+                }],
+                uuid: None,
+            },
+        ];
+        let tools = vec![ToolSchema {
+            name: "Bash".to_string(),
+            description: "Run a shell command".to_string(),
+            input_schema: json!({"type": "object", "properties": {"command": {"type": "string"}}}),
+        }];
+
+        let bare = CL100K.count_request(&messages[..1], None, &[]);
+        let full = CL100K.count_request(&messages, Some("You are helpful"), &tools);
+        let results = CL100K.count_message(&messages[2]);
+
+        assert!(results > 100);
+        assert!(full > bare + results + CL100K.count_tools(&tools));
+        assert!(
+            TokenCounter::Anthropic.count_tools(&tools)
+                > ANTHROPIC_TOOLS_OVERHEAD + CL100K.count_tools(&tools)
+        );
+    }
+}
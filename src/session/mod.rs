@@ -0,0 +1,373 @@
+//! Session transcripts
+//!
+//! Every conversation is appended, one [`ConversationMessage`] per line, to a
+//! JSONL file named after the session ID. Files live in a per-project
+//! directory under the config dir (`<config>/projects/<sanitized cwd>/`), so
+//! `kode --continue` and `kode --resume` only offer sessions started in the
+//! current directory.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local};
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    error::{KodeError, Result},
+    messages::ConversationMessage,
+};
+
+/// Maximum length of the summary shown for a session
+const SUMMARY_MAX_CHARS: usize = 80;
+
+/// Transcript files of one project
+#[derive(Debug, Clone)]
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl SessionStore {
+    /// Store for transcripts in a specific directory
+    #[must_use]
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Store for the sessions of the project in `cwd`
+    #[must_use]
+    pub fn for_project(cwd: &Path) -> Self {
+        Self::new(
+            Config::config_dir()
+                .join("projects")
+                .join(sanitize_path(cwd)),
+        )
+    }
+
+    /// Directory holding the transcripts
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Start a new, empty session
+    ///
+    /// The transcript file is only created once the first message is appended.
+    #[must_use]
+    pub fn create(&self) -> Session {
+        let id = Uuid::new_v4().to_string();
+        Session {
+            path: self.dir.join(format!("{id}.jsonl")),
+            id,
+        }
+    }
+
+    /// Open an existing session
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the ID is malformed or no session with this ID
+    /// exists
+    pub fn open(&self, id: &str) -> Result<Session> {
+        // IDs are file stems; anything else could point outside the directory
+        let is_stem = !id.is_empty()
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !is_stem {
+            return Err(KodeError::InvalidInput(format!("Invalid session ID {id}")));
+        }
+        let path = self.dir.join(format!("{id}.jsonl"));
+        if !path.is_file() {
+            return Err(KodeError::InvalidInput(format!("No session found with ID {id}")));
+        }
+        Ok(Session {
+            id: id.to_string(),
+            path,
+        })
+    }
+
+    /// List all sessions, most recently updated first
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sessions directory cannot be read
+    pub fn list(&self) -> Result<Vec<SessionInfo>> {
+        if !self.dir.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut sessions = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("jsonl") {
+                continue;
+            }
+            let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+
+            let session = Session {
+                id: id.to_string(),
+                path: path.clone(),
+            };
+            match session.info() {
+                Ok(info) => sessions.push(info),
+                // One unreadable transcript should not hide the others
+                Err(e) => tracing::warn!("Skipping session {}: {e}", path.display()),
+            }
+        }
+
+        sessions.sort_by_key(|session| std::cmp::Reverse(session.modified));
+        Ok(sessions)
+    }
+
+    /// Most recently updated session, if any
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sessions directory cannot be read
+    pub fn latest(&self) -> Result<Option<Session>> {
+        self.list()?
+            .first()
+            .map(|info| self.open(&info.id))
+            .transpose()
+    }
+}
+
+/// A single conversation transcript
+#[derive(Debug, Clone)]
+pub struct Session {
+    id: String,
+    path: PathBuf,
+}
+
+impl Session {
+    /// Session ID, used with `kode --resume <id>`
+    #[must_use]
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Path of the transcript file
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Summary of the session for listings
+    fn info(&self) -> Result<SessionInfo> {
+        let messages = self.messages()?;
+        Ok(SessionInfo {
+            id: self.id.clone(),
+            modified: fs::metadata(&self.path)?.modified()?.into(),
+            message_count: messages.iter().filter(|m| !m.is_progress()).count(),
+            summary: summarize(&messages),
+        })
+    }
+
+    /// Append a message to the transcript
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the transcript cannot be written
+    pub fn append(&self, message: &ConversationMessage) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut line = serde_json::to_string(message)?;
+        line.push('\n');
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Read all messages of the transcript
+    ///
+    /// Lines that cannot be parsed, such as one cut short by a crash, are
+    /// skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the transcript exists but cannot be read
+    pub fn messages(&self) -> Result<Vec<ConversationMessage>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let reader = BufReader::new(fs::File::open(&self.path)?);
+        let mut messages = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if let Ok(message) = serde_json::from_str(&line) {
+                messages.push(message);
+            }
+        }
+        Ok(messages)
+    }
+}
+
+/// Overview of a stored session
+#[derive(Debug, Clone)]
+pub struct SessionInfo {
+    /// Session ID
+    pub id: String,
+
+    /// Time of the last message
+    pub modified: DateTime<Local>,
+
+    /// Number of user and assistant messages
+    pub message_count: usize,
+
+    /// First prompt of the session, shortened
+    pub summary: String,
+}
+
+/// Turn a project path into a single directory name
+fn sanitize_path(path: &Path) -> String {
+    path.to_string_lossy()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+/// Summarize a session by its first prompt
+fn summarize(messages: &[ConversationMessage]) -> String {
+    let prompt = messages
+        .iter()
+        .find_map(|message| match message {
+            ConversationMessage::User(user) if user.tool_use_result.is_none() => {
+                Some(user.message.text_content())
+            }
+            _ => None,
+        })
+        .unwrap_or_default();
+
+    let prompt = prompt.split_whitespace().collect::<Vec<_>>().join(" ");
+    if prompt.chars().count() > SUMMARY_MAX_CHARS {
+        let short: String = prompt.chars().take(SUMMARY_MAX_CHARS - 1).collect();
+        format!("{short}…")
+    } else {
+        prompt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::messages::{AssistantMessage, Message, UserMessage};
+
+    fn user(text: &str) -> ConversationMessage {
+        ConversationMessage::User(UserMessage::new(Message::user(text)))
+    }
+
+    fn assistant(text: &str) -> ConversationMessage {
+        ConversationMessage::Assistant(AssistantMessage {
+            message: Message::assistant(text),
+            uuid: Uuid::new_v4(),
+            cost_usd: 0.25,
+            duration_ms: 1200,
+            is_api_error_message: None,
+            response_id: None,
+            usage: None,
+            model: None,
+        })
+    }
+
+    #[test]
+    fn test_append_and_read_back() {
+        let dir = TempDir::new().unwrap();
+        let store = SessionStore::new(dir.path().join("sessions"));
+        let session = store.create();
+
+        session.append(&user("Refactor the parser")).unwrap();
+        session.append(&assistant("On it")).unwrap();
+
+        let messages = store.open(session.id()).unwrap().messages().unwrap();
+        assert_eq!(messages.len(), 2);
+        let ConversationMessage::Assistant(reply) = &messages[1] else {
+            panic!("Expected assistant message");
+        };
+        assert!((reply.cost_usd - 0.25).abs() < f64::EPSILON);
+        assert_eq!(reply.duration_ms, 1200);
+    }
+
+    #[test]
+    fn test_truncated_lines_are_skipped() {
+        let dir = TempDir::new().unwrap();
+        let store = SessionStore::new(dir.path());
+        let session = store.create();
+        session.append(&user("Hello")).unwrap();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(session.path())
+            .unwrap()
+            .write_all(b"{\"type\":\"assist")
+            .unwrap();
+
+        assert_eq!(session.messages().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_list_and_latest() {
+        let dir = TempDir::new().unwrap();
+        let store = SessionStore::new(dir.path());
+        assert!(store.latest().unwrap().is_none());
+
+        let older = store.create();
+        older.append(&user("First session")).unwrap();
+        let newer = store.create();
+        newer.append(&user("Second session")).unwrap();
+        newer.append(&assistant("Sure")).unwrap();
+
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(60 * 60);
+        fs::File::options()
+            .append(true)
+            .open(older.path())
+            .unwrap()
+            .set_modified(an_hour_ago)
+            .unwrap();
+
+        // Unreadable transcripts are left out of the listing
+        fs::create_dir(dir.path().join("broken.jsonl")).unwrap();
+
+        let sessions = store.list().unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].id, newer.id());
+        assert_eq!(sessions[0].message_count, 2);
+        assert_eq!(sessions[0].summary, "Second session");
+        assert_eq!(store.latest().unwrap().unwrap().id(), newer.id());
+    }
+
+    #[test]
+    fn test_open_unknown_session() {
+        let dir = TempDir::new().unwrap();
+        let store = SessionStore::new(dir.path());
+        assert!(matches!(store.open("missing"), Err(KodeError::InvalidInput(_))));
+
+        // IDs cannot reach outside the sessions directory
+        let sessions = dir.path().join("sessions");
+        fs::write(dir.path().join("outside.jsonl"), "").unwrap();
+        let store = SessionStore::new(&sessions);
+        assert!(store.open("../outside").is_err());
+        assert!(store.open("..").is_err());
+        assert!(store.open("").is_err());
+    }
+
+    #[test]
+    fn test_sanitize_path() {
+        assert_eq!(sanitize_path(Path::new("/home/me/my.project")), "-home-me-my-project");
+    }
+}
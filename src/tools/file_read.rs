@@ -13,6 +13,8 @@ use std::fs;
 
 use crate::{
     error::{KodeError, Result},
+    images,
+    messages::ImageSource,
     tools::{Tool, ToolContext, ToolStream, ToolStreamItem, ValidationResult},
 };
 
@@ -72,14 +74,7 @@ pub struct FileReadTool;
 impl FileReadTool {
     /// Check if a file is an image based on extension
     fn is_image(path: &Path) -> bool {
-        if let Some(ext) = path.extension() {
-            matches!(
-                ext.to_str().unwrap_or("").to_lowercase().as_str(),
-                "png" | "jpg" | "jpeg" | "gif" | "bmp" | "webp"
-            )
-        } else {
-            false
-        }
+        images::media_type_for_path(path).is_some()
     }
 
     /// Read text content from a file with optional line range
@@ -122,24 +117,18 @@ impl FileReadTool {
         })
     }
 
-    /// Read image content as base64
+    /// Read image content as base64, downscaled to provider limits
     fn read_image_content(path: &Path) -> Result<ImageFileContent> {
-        use base64::{Engine as _, engine::general_purpose};
-
-        let data = fs::read(path)?;
-        let base64 = general_purpose::STANDARD.encode(&data);
-
-        let media_type = match path.extension().and_then(|e| e.to_str()) {
-            Some("png") => "image/png",
-            Some("jpg") | Some("jpeg") => "image/jpeg",
-            Some("gif") => "image/gif",
-            Some("bmp") => "image/bmp",
-            Some("webp") => "image/webp",
-            _ => "image/png", // Default to PNG
+        match images::load_image(path)? {
+            ImageSource::Base64 { media_type, data } => Ok(ImageFileContent {
+                base64: data,
+                media_type,
+            }),
+            ImageSource::File { file_id } => Err(KodeError::ToolExecution(format!(
+                "Expected inline image data for {}, got uploaded file {file_id}",
+                path.display()
+            ))),
         }
-        .to_string();
-
-        Ok(ImageFileContent { base64, media_type })
     }
 
     /// Add line numbers to content
@@ -313,6 +302,16 @@ impl Tool for FileReadTool {
         }
     }
 
+    fn render_result_images(&self, output: &Self::Output) -> Vec<ImageSource> {
+        match output {
+            FileReadOutput::Text { .. } => Vec::new(),
+            FileReadOutput::Image { file } => vec![ImageSource::Base64 {
+                media_type: file.media_type.clone(),
+                data: file.base64.clone(),
+            }],
+        }
+    }
+
     async fn call(
         &self,
         input: Self::Input,
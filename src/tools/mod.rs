@@ -18,11 +18,16 @@ pub mod url_fetcher;
 use std::{collections::HashMap, path::PathBuf, pin::Pin};
 
 use async_trait::async_trait;
-use futures::Stream;
+use futures::{Stream, StreamExt};
+use indexmap::IndexMap;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::Value;
 
-use crate::{error::Result, messages::Message};
+pub use crate::services::ToolSchema;
+use crate::{
+    error::{KodeError, Result},
+    messages::{ImageSource, Message},
+};
 
 /// Tool execution context
 #[derive(Debug, Clone)]
@@ -167,6 +172,11 @@ pub trait Tool: Send + Sync {
         Ok(serde_json::to_string_pretty(output)?)
     }
 
+    /// Images to send to the assistant along with the rendered result
+    fn render_result_images(&self, _output: &Self::Output) -> Vec<ImageSource> {
+        Vec::new()
+    }
+
     /// Render a message showing tool use to the user
     fn render_tool_use(&self, input: &Self::Input, verbose: bool) -> String {
         if verbose {
@@ -190,9 +200,145 @@ pub trait Tool: Send + Sync {
     ) -> Result<ToolStream<Self::Output>>;
 }
 
+/// Type-erased tool operating on JSON input and output
+pub type DynTool = dyn Tool<Input = Value, Output = Value>;
+
+/// Wrapper exposing a typed [`Tool`] as a [`DynTool`]
+///
+/// Input is deserialized from the model's JSON before it reaches the inner tool
+/// and output is serialized back to JSON, so tools with concrete `Input`/`Output`
+/// types can live side by side in a [`ToolRegistry`].
+pub struct ErasedTool<T> {
+    inner: T,
+}
+
+impl<T> ErasedTool<T>
+where
+    T: Tool + 'static,
+    T::Output: DeserializeOwned,
+{
+    /// Wrap a typed tool
+    #[must_use]
+    pub const fn new(inner: T) -> Self {
+        Self { inner }
+    }
+
+    /// Wrap a typed tool and box it for registration
+    #[must_use]
+    pub fn boxed(inner: T) -> Box<DynTool> {
+        Box::new(Self::new(inner))
+    }
+
+    /// Deserialize raw JSON input into the inner tool's input type
+    fn parse_input(&self, input: &Value) -> Result<T::Input> {
+        T::Input::deserialize(input).map_err(|e| {
+            KodeError::ToolValidation(format!("Invalid input for {}: {e}", self.inner.name()))
+        })
+    }
+}
+
+#[async_trait]
+impl<T> Tool for ErasedTool<T>
+where
+    T: Tool + 'static,
+    T::Output: DeserializeOwned,
+{
+    type Input = Value;
+    type Output = Value;
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn description(&self) -> String {
+        self.inner.description().await
+    }
+
+    fn input_schema(&self) -> Value {
+        self.inner.input_schema()
+    }
+
+    async fn prompt(&self, safe_mode: bool) -> String {
+        self.inner.prompt(safe_mode).await
+    }
+
+    fn user_facing_name(&self) -> String {
+        self.inner.user_facing_name()
+    }
+
+    async fn is_enabled(&self) -> bool {
+        self.inner.is_enabled().await
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.inner.is_read_only()
+    }
+
+    fn is_concurrency_safe(&self) -> bool {
+        self.inner.is_concurrency_safe()
+    }
+
+    fn needs_permissions(&self, input: &Value) -> bool {
+        // Input that doesn't parse will fail validation anyway; ask to be safe
+        self.parse_input(input).map_or(true, |input| self.inner.needs_permissions(&input))
+    }
+
+    async fn validate_input(&self, input: &Value, context: &ToolContext) -> ValidationResult {
+        match self.parse_input(input) {
+            Ok(input) => self.inner.validate_input(&input, context).await,
+            Err(e) => ValidationResult::error(e.to_string()),
+        }
+    }
+
+    fn render_result(&self, output: &Value) -> Result<String> {
+        let output = T::Output::deserialize(output)?;
+        self.inner.render_result(&output)
+    }
+
+    fn render_result_images(&self, output: &Value) -> Vec<ImageSource> {
+        T::Output::deserialize(output)
+            .map(|output| self.inner.render_result_images(&output))
+            .unwrap_or_default()
+    }
+
+    fn render_tool_use(&self, input: &Value, verbose: bool) -> String {
+        match self.parse_input(input) {
+            Ok(input) => self.inner.render_tool_use(&input, verbose),
+            Err(_) => format!("Using {}", self.inner.name()),
+        }
+    }
+
+    async fn call(&self, input: Value, context: ToolContext) -> Result<ToolStream<Value>> {
+        let input = self.parse_input(&input)?;
+        let stream = self.inner.call(input, context).await?;
+
+        Ok(Box::pin(stream.map(|item| {
+            Ok(match item? {
+                ToolStreamItem::Progress {
+                    content,
+                    normalized_messages,
+                } => ToolStreamItem::Progress {
+                    content,
+                    normalized_messages,
+                },
+                ToolStreamItem::Result {
+                    data,
+                    result_for_assistant,
+                } => ToolStreamItem::Result {
+                    data: serde_json::to_value(data)?,
+                    result_for_assistant,
+                },
+            })
+        })))
+    }
+}
+
 /// Tool registry for managing available tools
+///
+/// Tools are kept in registration order so the schema list sent to the model
+/// is stable across requests.
 pub struct ToolRegistry {
-    tools: HashMap<String, Box<dyn Tool<Input = Value, Output = Value>>>,
+    tools: IndexMap<String, Box<DynTool>>,
 }
 
 impl ToolRegistry {
@@ -200,14 +346,46 @@ impl ToolRegistry {
     #[must_use]
     pub fn new() -> Self {
         Self {
-            tools: HashMap::new(),
+            tools: IndexMap::new(),
         }
     }
 
+    /// Create a registry containing every built-in tool
+    #[must_use]
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::new();
+        registry.register_typed(file_read::FileReadTool);
+        registry.register_typed(glob::GlobTool::new());
+        registry.register_typed(grep::GrepTool::new());
+        registry.register_typed(file_edit::FileEditTool);
+        registry.register_typed(file_write::FileWriteTool);
+        registry.register_typed(bash::BashTool);
+        registry.register_typed(url_fetcher::UrlFetcherTool);
+        registry.register_typed(memory_read::MemoryReadTool);
+        registry.register_typed(memory_write::MemoryWriteTool);
+        registry.register_typed(todo_write::TodoWriteTool);
+        registry.register_typed(think::ThinkTool);
+        registry
+    }
+
+    /// Register a tool, replacing any tool with the same name
+    pub fn register(&mut self, tool: Box<DynTool>) {
+        self.tools.insert(tool.name().to_string(), tool);
+    }
+
+    /// Register a typed tool through an [`ErasedTool`] wrapper
+    pub fn register_typed<T>(&mut self, tool: T)
+    where
+        T: Tool + 'static,
+        T::Output: DeserializeOwned,
+    {
+        self.register(ErasedTool::boxed(tool));
+    }
+
     /// Get a tool by name
     #[must_use]
-    pub fn get(&self, name: &str) -> Option<&dyn Tool<Input = Value, Output = Value>> {
-        self.tools.get(name).map(|t| t.as_ref())
+    pub fn get(&self, name: &str) -> Option<&DynTool> {
+        self.tools.get(name).map(AsRef::as_ref)
     }
 
     /// List all registered tool names
@@ -215,6 +393,30 @@ impl ToolRegistry {
     pub fn list(&self) -> Vec<String> {
         self.tools.keys().cloned().collect()
     }
+
+    /// Iterate over registered tools in registration order
+    pub fn iter(&self) -> impl Iterator<Item = &DynTool> {
+        self.tools.values().map(AsRef::as_ref)
+    }
+
+    /// Export the API schemas of all enabled tools
+    ///
+    /// The tool prompt is used as the schema description, since it carries the
+    /// usage instructions the model needs.
+    pub async fn tool_schemas(&self, safe_mode: bool) -> Vec<ToolSchema> {
+        let mut schemas = Vec::with_capacity(self.tools.len());
+        for tool in self.iter() {
+            if !tool.is_enabled().await {
+                continue;
+            }
+            schemas.push(ToolSchema {
+                name: tool.name().to_string(),
+                description: tool.prompt(safe_mode).await,
+                input_schema: tool.input_schema(),
+            });
+        }
+        schemas
+    }
 }
 
 impl Default for ToolRegistry {
@@ -244,4 +446,47 @@ mod tests {
         assert!(!ctx.safe_mode);
         assert!(ctx.cwd.is_absolute());
     }
+
+    #[tokio::test]
+    async fn test_registry_with_builtins() {
+        let registry = ToolRegistry::with_builtins();
+        for name in ["View", "Glob", "Grep", "Edit", "Write", "Bash", "WebFetch", "TodoWrite"] {
+            assert!(registry.get(name).is_some(), "missing built-in tool {name}");
+        }
+
+        let schemas = registry.tool_schemas(false).await;
+        assert!(schemas.iter().any(|schema| schema.name == "Bash"));
+        assert!(schemas.iter().all(|schema| schema.input_schema.is_object()));
+    }
+
+    #[tokio::test]
+    async fn test_erased_tool_forwards_to_inner_tool() {
+        let tool = ErasedTool::new(think::ThinkTool);
+        let input = serde_json::json!({"thought": "plan the refactor"});
+
+        assert!(tool.is_read_only());
+        assert!(!tool.needs_permissions(&input));
+        assert_eq!(tool.render_tool_use(&input, false), "plan the refactor");
+
+        let mut stream = tool.call(input, ToolContext::default()).await.unwrap();
+        match stream.next().await {
+            Some(Ok(ToolStreamItem::Result { data, .. })) => {
+                assert_eq!(data["thought"], "plan the refactor");
+                assert_eq!(tool.render_result(&data).unwrap(), "Your thought has been logged.");
+            }
+            other => panic!("Expected result, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_erased_tool_rejects_malformed_input() {
+        let tool = ErasedTool::new(bash::BashTool);
+        let input = serde_json::json!({"cmd": "ls"});
+
+        let validation = tool.validate_input(&input, &ToolContext::default()).await;
+        assert!(!validation.is_valid);
+        assert!(validation.message.unwrap().contains("Invalid input for Bash"));
+        assert!(tool.needs_permissions(&input));
+        assert!(tool.call(input, ToolContext::default()).await.is_err());
+    }
 }
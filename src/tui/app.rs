@@ -3,10 +3,23 @@
 ///! This is a simplified version that avoids ModelManager complexity for MVP.
 
 use crate::{
-    config::models::ModelProfile,
+    config::Config,
+    cost::CostTracker,
     error::{KodeError, Result},
-    messages::{ContentBlock, Message, Role},
-    services::{CompletionChunk, CompletionOptions, ModelAdapter},
+    images,
+    messages::{
+        answer_interrupted_tool_uses, normalize_messages_for_api, ContentBlock,
+        ConversationMessage, ImageSource, Message, Role, UserMessage,
+    },
+    permissions::{PermissionDecision, PermissionRules, PermissionService},
+    query::{self, Compaction, Compactor, QueryContext, QueryEvent},
+    services::{CompletionChunk, ModelAdapter, Usage},
+    session::Session,
+    tools::ToolRegistry,
+    tui::{
+        commands::SlashCommand,
+        permission::{PendingPermission, TuiPermissionPrompter, PERMISSION_OPTIONS},
+    },
 };
 use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
 use futures::StreamExt;
@@ -18,6 +31,8 @@ use tokio::sync::mpsc;
 pub enum InputMode {
     /// Normal prompt mode
     Prompt,
+    /// Answering a permission request
+    Permission,
 }
 
 /// Application events
@@ -25,10 +40,18 @@ pub enum InputMode {
 pub enum AppEvent {
     /// Streaming chunk received
     StreamChunk(CompletionChunk),
+    /// Conversation message produced by the query loop
+    Message(ConversationMessage),
     /// Streaming completed
     StreamComplete,
     /// Streaming error
     StreamError(KodeError),
+    /// A tool is waiting for the user's permission
+    PermissionRequest(PendingPermission),
+    /// Older turns were replaced by a summary
+    Compacted(Compaction),
+    /// Short notice for the user
+    Notice(String),
 }
 
 /// Main application state
@@ -39,24 +62,57 @@ pub struct App {
     /// Current input buffer
     input_buffer: String,
 
+    /// Images attached with `/image`, sent with the next message
+    pending_images: Vec<ImageSource>,
+
     /// Current input mode
     input_mode: InputMode,
 
     /// Scroll offset for message view
     scroll_offset: usize,
 
+    /// Whether thinking sections are expanded
+    show_thinking: bool,
+
     /// Whether the app is loading (streaming)
     is_loading: bool,
 
     /// Should quit flag
     should_quit: bool,
 
-    /// Model profile
-    model_profile: ModelProfile,
-
     /// Model adapter
     adapter: Arc<dyn ModelAdapter>,
 
+    /// Tools available to the model
+    tools: Arc<ToolRegistry>,
+
+    /// Loaded configuration
+    config: Config,
+
+    /// Permission checks for side-effecting tools
+    permissions: Arc<PermissionService>,
+
+    /// Permission request currently shown to the user
+    pending_permission: Option<PendingPermission>,
+
+    /// Latest progress update from a running tool
+    tool_progress: Option<String>,
+
+    /// Notice shown below the conversation, e.g. after a command
+    notice: Option<String>,
+
+    /// Fallback model answering while the configured one is unavailable
+    fallback_model: Option<String>,
+
+    /// Summarizes older turns for `/compact` and auto-compaction
+    compactor: Compactor,
+
+    /// Running cost of the session
+    costs: CostTracker,
+
+    /// Transcript every message is appended to
+    session: Session,
+
     /// Event channel for app events
     event_tx: mpsc::UnboundedSender<AppEvent>,
     event_rx: mpsc::UnboundedReceiver<AppEvent>,
@@ -67,22 +123,49 @@ pub struct App {
 
 impl App {
     /// Create a new app
+    ///
+    /// The conversation starts from the messages already in `session`.
     pub fn new(
         initial_prompt: Option<String>,
-        model_profile: ModelProfile,
         adapter: Arc<dyn ModelAdapter>,
+        tools: Arc<ToolRegistry>,
+        config: Config,
+        session: Session,
     ) -> Result<Self> {
         let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let permissions = Arc::new(PermissionService::new(
+            Arc::new(TuiPermissionPrompter::new(event_tx.clone())),
+            Config::project_config_path(),
+            PermissionRules::from_config(&config)?,
+        ));
+
+        let compactor = Compactor::from_config(&config, adapter.clone());
+        // An unreadable ledger must not keep the session from starting
+        let costs = CostTracker::new()
+            .with_ledger(CostTracker::default_ledger_path())
+            .for_session(session.id())
+            .unwrap_or_default();
 
         Ok(Self {
-            messages: Vec::new(),
+            messages: normalize_messages_for_api(&session.messages()?),
             input_buffer: initial_prompt.unwrap_or_default(),
+            pending_images: Vec::new(),
             input_mode: InputMode::Prompt,
             scroll_offset: 0,
+            show_thinking: false,
             is_loading: false,
             should_quit: false,
-            model_profile,
             adapter,
+            tools,
+            config,
+            permissions,
+            pending_permission: None,
+            tool_progress: None,
+            notice: None,
+            fallback_model: None,
+            compactor,
+            costs,
+            session,
             event_tx,
             event_rx,
             current_stream: None,
@@ -109,6 +192,12 @@ impl App {
         &self.input_buffer
     }
 
+    /// Number of images waiting to be sent with the next message
+    #[must_use]
+    pub fn pending_image_count(&self) -> usize {
+        self.pending_images.len()
+    }
+
     /// Get input mode
     pub fn input_mode(&self) -> InputMode {
         self.input_mode
@@ -119,11 +208,41 @@ impl App {
         self.scroll_offset
     }
 
+    /// Whether thinking sections are expanded
+    #[must_use]
+    pub const fn show_thinking(&self) -> bool {
+        self.show_thinking
+    }
+
     /// Check if loading
     pub fn is_loading(&self) -> bool {
         self.is_loading
     }
 
+    /// Get the permission request waiting for an answer, if any
+    #[must_use]
+    pub fn pending_permission(&self) -> Option<&PendingPermission> {
+        self.pending_permission.as_ref()
+    }
+
+    /// Get the latest tool progress update, if a tool is running
+    #[must_use]
+    pub fn tool_progress(&self) -> Option<&str> {
+        self.tool_progress.as_deref()
+    }
+
+    /// Running cost of the session
+    #[must_use]
+    pub const fn costs(&self) -> &CostTracker {
+        &self.costs
+    }
+
+    /// Notice shown below the conversation
+    #[must_use]
+    pub fn notice(&self) -> Option<&str> {
+        self.notice.as_deref()
+    }
+
     /// Handle terminal event
     pub async fn handle_terminal_event(&mut self, event: Event) -> Result<()> {
         match event {
@@ -145,6 +264,17 @@ impl App {
             return Ok(());
         }
 
+        if self.input_mode == InputMode::Permission {
+            self.handle_permission_key(key);
+            return Ok(());
+        }
+
+        // Ctrl+T expands or collapses thinking
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('t') {
+            self.show_thinking = !self.show_thinking;
+            return Ok(());
+        }
+
         match key.code {
             KeyCode::Char(c) => {
                 self.input_buffer.push(c);
@@ -153,7 +283,7 @@ impl App {
                 self.input_buffer.pop();
             }
             KeyCode::Enter => {
-                self.submit_prompt().await?;
+                self.submit_prompt()?;
             }
             KeyCode::Up => {
                 if self.scroll_offset > 0 {
@@ -176,70 +306,166 @@ impl App {
         Ok(())
     }
 
+    /// Handle a key while the permission dialog is shown
+    fn handle_permission_key(&mut self, key: KeyEvent) {
+        let Some(pending) = self.pending_permission.as_mut() else {
+            self.input_mode = InputMode::Prompt;
+            return;
+        };
+
+        let decision = match key.code {
+            KeyCode::Up => {
+                pending.selected = pending.selected.saturating_sub(1);
+                None
+            }
+            KeyCode::Down => {
+                pending.selected = (pending.selected + 1).min(PERMISSION_OPTIONS.len() - 1);
+                None
+            }
+            KeyCode::Enter => Some(PERMISSION_OPTIONS[pending.selected].0),
+            KeyCode::Char(c) => c
+                .to_digit(10)
+                .and_then(|digit| PERMISSION_OPTIONS.get((digit as usize).checked_sub(1)?))
+                .map(|(decision, _)| *decision),
+            KeyCode::Esc => Some(PermissionDecision::Deny),
+            _ => None,
+        };
+
+        if let Some(decision) = decision {
+            if let Some(pending) = self.pending_permission.take() {
+                pending.respond(decision);
+            }
+            self.input_mode = InputMode::Prompt;
+        }
+    }
+
     /// Submit the current prompt
-    async fn submit_prompt(&mut self) -> Result<()> {
-        if self.input_buffer.trim().is_empty() {
+    fn submit_prompt(&mut self) -> Result<()> {
+        if self.input_buffer.trim().is_empty() || self.is_loading {
             return Ok(());
         }
 
         // Add user message
         let user_content = self.input_buffer.clone();
         self.input_buffer.clear();
+        self.notice = None;
 
-        let user_message = Message::user(user_content.clone());
-        self.messages.push(user_message);
+        if let Some(command) = SlashCommand::parse(&user_content) {
+            self.run_command(command);
+            return Ok(());
+        }
 
-        // Create empty assistant message (will be filled by streaming)
-        let assistant_message = Message {
-            role: Role::Assistant,
-            content: Vec::new(),
-            uuid: Some(uuid::Uuid::new_v4()),
-        };
-        self.messages.push(assistant_message);
+        let mut user_message = Message::user(user_content);
+        user_message.content.extend(
+            self.pending_images
+                .drain(..)
+                .map(|source| ContentBlock::Image { source }),
+        );
+        self.record(&ConversationMessage::User(UserMessage::new(user_message.clone())));
+        self.messages.push(user_message);
 
         // Start streaming
-        self.start_streaming(user_content).await?;
+        self.start_streaming();
 
         Ok(())
     }
 
-    /// Start streaming response
-    async fn start_streaming(&mut self, _prompt: String) -> Result<()> {
+    /// Run a slash command
+    fn run_command(&mut self, command: SlashCommand) {
+        match command {
+            SlashCommand::Compact(instructions) => self.start_compaction(instructions),
+            SlashCommand::Cost => self.notice = Some(self.costs.report()),
+            SlashCommand::Image(path) => self.attach_image(path.as_deref()),
+            SlashCommand::Unknown(name) => {
+                self.notice = Some(format!("Unknown command: /{name}"));
+            }
+        }
+    }
+
+    /// Attach the image at `path` to the next message
+    fn attach_image(&mut self, path: Option<&str>) {
+        let Some(path) = path else {
+            self.notice = Some("Usage: /image <path>".to_string());
+            return;
+        };
+        // Paths dropped into the terminal arrive quoted
+        let path = path.trim_matches(|c| c == '\'' || c == '"');
+        self.notice = Some(match images::load_image(std::path::Path::new(path)) {
+            Ok(source) => {
+                self.pending_images.push(source);
+                format!(
+                    "Attached {path} ({} image(s) will be sent with your next message)",
+                    self.pending_images.len()
+                )
+            }
+            Err(e) => format!("Could not attach {path}: {e}"),
+        });
+    }
+
+    /// Summarize the conversation so far in the background
+    fn start_compaction(&mut self, instructions: Option<String>) {
         self.is_loading = true;
+        self.tool_progress = Some("Compacting conversation...".to_string());
 
-        // Use all messages except the empty assistant message we just added
-        let api_messages = self.messages[..self.messages.len().saturating_sub(1)].to_vec();
+        let messages = self.api_messages();
+        let compactor = self.compactor.clone();
+        let event_tx = self.event_tx.clone();
+        let handle = tokio::spawn(async move {
+            let event = match compactor.compact(&messages, instructions.as_deref()).await {
+                Ok(Some(compaction)) => AppEvent::Compacted(compaction),
+                Ok(None) => AppEvent::Notice("Not enough conversation to compact".to_string()),
+                Err(e) => AppEvent::StreamError(e),
+            };
+            let _ = event_tx.send(event);
+            let _ = event_tx.send(AppEvent::StreamComplete);
+        });
 
-        // Get tool schemas (empty for now)
-        let tools = Vec::new();
+        self.current_stream = Some(handle);
+    }
+
+    /// Conversation history to send to the model
+    fn api_messages(&self) -> Vec<Message> {
+        // Skip assistant placeholders left behind by cancelled turns
+        let mut messages: Vec<Message> =
+            self.messages.iter().filter(|m| !m.content.is_empty()).cloned().collect();
+        // A turn cancelled while its tools ran leaves tool uses without results
+        answer_interrupted_tool_uses(&mut messages);
+        messages
+    }
+
+    /// Start the query loop for the current conversation
+    fn start_streaming(&mut self) {
+        self.is_loading = true;
+        self.tool_progress = None;
+
+        let api_messages = self.api_messages();
 
-        // Start streaming
         let event_tx = self.event_tx.clone();
-        let adapter = self.adapter.clone();
-        let _model_profile = self.model_profile.clone();
 
-        // TODO: Get system prompt from config or agent
-        let system_prompt = None;
-        let options = CompletionOptions::default();
+        let mut context = QueryContext::new(self.adapter.clone(), self.tools.clone());
+        context.max_tool_concurrency = self.config.global.max_tool_use_concurrency;
+        context.permissions = Some(self.permissions.clone());
+        if self.config.global.auto_compact {
+            context.compactor = Some(self.compactor.clone());
+        }
 
-        let stream = adapter
-            .stream_complete(api_messages, tools, system_prompt, options)
-            .await?;
+        let stream = query::query(api_messages, context);
 
         let handle = tokio::spawn(async move {
             tokio::pin!(stream);
 
-            while let Some(chunk_result) = stream.next().await {
-                match chunk_result {
-                    Ok(chunk) => {
-                        if event_tx.send(AppEvent::StreamChunk(chunk)).is_err() {
-                            break;
-                        }
-                    }
+            while let Some(event_result) = stream.next().await {
+                let app_event = match event_result {
+                    Ok(QueryEvent::Chunk(chunk)) => AppEvent::StreamChunk(chunk),
+                    Ok(QueryEvent::Message(message)) => AppEvent::Message(message),
+                    Ok(QueryEvent::Compacted(compaction)) => AppEvent::Compacted(compaction),
                     Err(e) => {
                         let _ = event_tx.send(AppEvent::StreamError(e));
                         break;
                     }
+                };
+                if event_tx.send(app_event).is_err() {
+                    break;
                 }
             }
 
@@ -247,8 +473,6 @@ impl App {
         });
 
         self.current_stream = Some(handle);
-
-        Ok(())
     }
 
     /// Cancel current stream
@@ -256,6 +480,9 @@ impl App {
         if let Some(handle) = self.current_stream.take() {
             handle.abort();
         }
+        // Dropping the responder denies the request
+        self.pending_permission = None;
+        self.input_mode = InputMode::Prompt;
         self.is_loading = false;
     }
 
@@ -263,14 +490,41 @@ impl App {
     pub async fn handle_app_event(&mut self, event: AppEvent) -> Result<()> {
         match event {
             AppEvent::StreamChunk(chunk) => {
-                self.handle_stream_chunk(chunk)?;
+                self.handle_stream_chunk(&chunk);
+            }
+            AppEvent::Message(message) => {
+                self.handle_conversation_message(message);
+            }
+            AppEvent::PermissionRequest(pending) => {
+                self.pending_permission = Some(pending);
+                self.input_mode = InputMode::Permission;
+            }
+            AppEvent::Compacted(compaction) => {
+                if let Some(usage) = &compaction.usage {
+                    self.record_cost(&compaction.model, usage, compaction.cost_usd);
+                }
+                for entry in compaction.transcript() {
+                    self.record(&entry);
+                }
+                self.messages = compaction.messages();
+                self.scroll_offset = 0;
+                self.notice = Some("Conversation compacted".to_string());
+            }
+            AppEvent::Notice(notice) => {
+                self.notice = Some(notice);
             }
             AppEvent::StreamComplete => {
+                self.pending_permission = None;
+                self.input_mode = InputMode::Prompt;
                 self.is_loading = false;
+                self.tool_progress = None;
                 self.current_stream = None;
             }
             AppEvent::StreamError(err) => {
+                self.pending_permission = None;
+                self.input_mode = InputMode::Prompt;
                 self.is_loading = false;
+                self.tool_progress = None;
                 self.current_stream = None;
                 // Add error message
                 let error_msg = Message::user(format!("Error: {}", err));
@@ -282,55 +536,90 @@ impl App {
     }
 
     /// Handle streaming chunk
-    fn handle_stream_chunk(&mut self, chunk: CompletionChunk) -> Result<()> {
-        // Get the last message (should be assistant message)
+    fn handle_stream_chunk(&mut self, chunk: &CompletionChunk) {
+        if let CompletionChunk::Retrying {
+            attempt,
+            max_retries,
+            delay_ms,
+            reason,
+        } = chunk
+        {
+            self.tool_progress = Some(format!(
+                "Retrying in {}s (attempt {attempt}/{max_retries}): {reason}",
+                delay_ms.div_ceil(1000)
+            ));
+            return;
+        }
+        if let CompletionChunk::Fallback { model, reason } = chunk {
+            if self.fallback_model.as_deref() != Some(model.as_str()) {
+                self.notice = Some(format!("Switched to fallback model {model} ({reason})"));
+                self.fallback_model = Some(model.clone());
+            }
+            return;
+        }
+        self.tool_progress = None;
+
+        // Start a new assistant message for the first chunk of each turn
+        if self.messages.last().is_none_or(|msg| msg.role != Role::Assistant) {
+            self.messages.push(Message {
+                role: Role::Assistant,
+                content: Vec::new(),
+                uuid: Some(uuid::Uuid::new_v4()),
+            });
+        }
+
         if let Some(msg) = self.messages.last_mut() {
-            if msg.role == Role::Assistant {
-                match chunk {
-                    CompletionChunk::TextDelta { text } => {
-                        // Append to last text block or create new one
-                        if let Some(ContentBlock::Text { text: ref mut current }) =
-                            msg.content.last_mut()
-                        {
-                            current.push_str(&text);
-                        } else {
-                            msg.content.push(ContentBlock::Text { text });
-                        }
-                    }
-                    CompletionChunk::ThinkingDelta { thinking } => {
-                        // Append to last thinking block or create new one
-                        if let Some(ContentBlock::Thinking {
-                            thinking: ref mut current,
-                        }) = msg.content.last_mut()
-                        {
-                            current.push_str(&thinking);
-                        } else {
-                            msg.content.push(ContentBlock::Thinking { thinking });
-                        }
-                    }
-                    CompletionChunk::ToolUseStart { .. } => {
-                        // Tool use started - will be completed later
-                    }
-                    CompletionChunk::ToolInputDelta { .. } => {
-                        // Tool input accumulating - will be completed later
-                    }
-                    CompletionChunk::ToolUseComplete { id, name, input } => {
-                        msg.content.push(ContentBlock::ToolUse { id, name, input });
-                    }
-                    CompletionChunk::Done { .. } => {
-                        // Done - nothing to do for now
-                        // In a full implementation, we would store stop_reason and usage
-                    }
-                    CompletionChunk::Error { message } => {
-                        // Add error as text
-                        msg.content.push(ContentBlock::Text {
-                            text: format!("Error: {}", message),
-                        });
+            query::apply_chunk(msg, chunk);
+        }
+    }
+
+    /// Append a message to the session transcript
+    fn record(&self, message: &ConversationMessage) {
+        // A transcript that cannot be written must not interrupt the conversation
+        let _ = self.session.append(message);
+    }
+
+    /// Add the cost of a model response to the session totals
+    fn record_cost(&mut self, model: &str, usage: &Usage, cost_usd: f64) {
+        // A ledger that cannot be written must not interrupt the conversation
+        let _ = self.costs.record(model, usage, cost_usd);
+    }
+
+    /// Handle a completed message from the query loop
+    fn handle_conversation_message(&mut self, message: ConversationMessage) {
+        self.record(&message);
+        match message {
+            ConversationMessage::Assistant(assistant) => {
+                let model = assistant
+                    .model
+                    .clone()
+                    .unwrap_or_else(|| self.adapter.model().to_string());
+                if let Some(usage) = &assistant.usage {
+                    self.record_cost(&model, usage, assistant.cost_usd);
+                }
+                if self.fallback_model.is_some() && model == self.adapter.model() {
+                    self.fallback_model = None;
+                    self.notice = Some(format!("Back on {model}"));
+                }
+                // Replace the streamed copy with the final assembled turn
+                match self.messages.last_mut() {
+                    Some(last) if last.role == Role::Assistant => *last = assistant.message,
+                    _ => self.messages.push(assistant.message),
+                }
+            }
+            ConversationMessage::User(user) => {
+                self.tool_progress = None;
+                // Results of one turn go back to the model as a single user message
+                match self.messages.last_mut() {
+                    Some(last) if last.is_tool_result() => {
+                        last.content.extend(user.message.content);
                     }
+                    _ => self.messages.push(user.message),
                 }
             }
+            ConversationMessage::Progress(progress) => {
+                self.tool_progress = Some(progress.content.message.text_content());
+            }
         }
-
-        Ok(())
     }
 }
@@ -0,0 +1,75 @@
+//! Slash commands typed into the prompt
+
+/// A command entered as `/name [args]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SlashCommand {
+    /// `/compact [instructions]`: summarize the conversation so far
+    Compact(Option<String>),
+
+    /// `/cost`: show what the session has cost so far
+    Cost,
+
+    /// `/image <path>`: attach an image to the next message
+    Image(Option<String>),
+
+    /// Any other `/name`
+    Unknown(String),
+}
+
+impl SlashCommand {
+    /// Parse a prompt as a slash command
+    ///
+    /// Returns `None` for prompts that do not start with `/`.
+    #[must_use]
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim().strip_prefix('/')?;
+        let (name, args) = input
+            .split_once(char::is_whitespace)
+            .map_or((input, ""), |(name, args)| (name, args.trim()));
+        let args = (!args.is_empty()).then(|| args.to_string());
+
+        Some(match name {
+            "compact" => Self::Compact(args),
+            "cost" => Self::Cost,
+            "image" => Self::Image(args),
+            _ => Self::Unknown(name.to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_compact() {
+        assert_eq!(SlashCommand::parse("/compact"), Some(SlashCommand::Compact(None)));
+        assert_eq!(
+            SlashCommand::parse("/compact  keep the test names "),
+            Some(SlashCommand::Compact(Some("keep the test names".to_string())))
+        );
+    }
+
+    #[test]
+    fn test_parse_cost() {
+        assert_eq!(SlashCommand::parse("/cost"), Some(SlashCommand::Cost));
+    }
+
+    #[test]
+    fn test_parse_image() {
+        assert_eq!(
+            SlashCommand::parse("/image shots/bug.png"),
+            Some(SlashCommand::Image(Some("shots/bug.png".to_string())))
+        );
+        assert_eq!(SlashCommand::parse("/image"), Some(SlashCommand::Image(None)));
+    }
+
+    #[test]
+    fn test_parse_other_input() {
+        assert_eq!(SlashCommand::parse("fix the bug"), None);
+        assert_eq!(
+            SlashCommand::parse("/unknown arg"),
+            Some(SlashCommand::Unknown("unknown".to_string()))
+        );
+    }
+}
@@ -3,27 +3,39 @@
 //! Provides a terminal user interface using ratatui + crossterm.
 
 mod app;
+mod commands;
 mod event;
+mod permission;
+mod picker;
 mod terminal;
 mod ui;
 
 pub use app::{App, AppEvent, InputMode};
+pub use picker::pick_session;
 pub use terminal::{restore_terminal, setup_terminal};
 
-use crate::{config::models::ModelProfile, error::Result, services::ModelAdapter};
+use crate::{
+    config::Config,
+    error::Result,
+    services::ModelAdapter,
+    session::Session,
+    tools::ToolRegistry,
+};
 use std::sync::Arc;
 
 /// Run the TUI application
 pub async fn run(
     initial_prompt: Option<String>,
-    model_profile: ModelProfile,
     adapter: Arc<dyn ModelAdapter>,
+    tools: Arc<ToolRegistry>,
+    config: Config,
+    session: Session,
 ) -> Result<()> {
     // Set up terminal
     let mut terminal = setup_terminal()?;
 
     // Create app state
-    let mut app = App::new(initial_prompt, model_profile, adapter)?;
+    let mut app = App::new(initial_prompt, adapter, tools, config, session)?;
 
     // Run the main loop
     let result = run_app(&mut terminal, &mut app).await;
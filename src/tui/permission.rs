@@ -0,0 +1,73 @@
+//! Permission prompts shown in the TUI
+
+use crate::{
+    permissions::{PermissionDecision, PermissionPrompter, PermissionRequest},
+    tui::app::AppEvent,
+};
+use async_trait::async_trait;
+use tokio::sync::{mpsc, oneshot};
+
+/// Choices offered by the permission dialog, in display order
+pub const PERMISSION_OPTIONS: [(PermissionDecision, &str); 3] = [
+    (PermissionDecision::AllowOnce, "Yes, allow once"),
+    (PermissionDecision::AllowAlways, "Yes, and don't ask again in this project"),
+    (PermissionDecision::Deny, "No, and tell the model what to do differently (esc)"),
+];
+
+/// A permission request waiting for the user's answer
+#[derive(Debug)]
+pub struct PendingPermission {
+    /// The request being shown
+    pub request: PermissionRequest,
+
+    /// Index of the highlighted option in [`PERMISSION_OPTIONS`]
+    pub selected: usize,
+
+    /// Channel back to the waiting tool use
+    responder: oneshot::Sender<PermissionDecision>,
+}
+
+impl PendingPermission {
+    /// Create a pending request with the first option highlighted
+    pub fn new(request: PermissionRequest, responder: oneshot::Sender<PermissionDecision>) -> Self {
+        Self {
+            request,
+            selected: 0,
+            responder,
+        }
+    }
+
+    /// Send the decision back to the waiting tool use
+    pub fn respond(self, decision: PermissionDecision) {
+        let _ = self.responder.send(decision);
+    }
+}
+
+/// Prompter that forwards requests to the TUI event loop
+pub struct TuiPermissionPrompter {
+    event_tx: mpsc::UnboundedSender<AppEvent>,
+}
+
+impl TuiPermissionPrompter {
+    /// Create a prompter sending requests through the app event channel
+    pub fn new(event_tx: mpsc::UnboundedSender<AppEvent>) -> Self {
+        Self { event_tx }
+    }
+}
+
+#[async_trait]
+impl PermissionPrompter for TuiPermissionPrompter {
+    async fn request_permission(&self, request: PermissionRequest) -> PermissionDecision {
+        let (responder, decision) = oneshot::channel();
+        if self
+            .event_tx
+            .send(AppEvent::PermissionRequest(PendingPermission::new(request, responder)))
+            .is_err()
+        {
+            return PermissionDecision::Deny;
+        }
+
+        // A dropped dialog (e.g. cancelled query) counts as a denial
+        decision.await.unwrap_or(PermissionDecision::Deny)
+    }
+}
@@ -0,0 +1,93 @@
+//! Interactive session picker for `kode --resume`
+
+use crate::{
+    error::Result,
+    session::SessionInfo,
+    tui::terminal::{restore_terminal, setup_terminal},
+};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, ListState},
+};
+
+/// Let the user choose one of `sessions`
+///
+/// Returns the chosen session ID, or `None` if the picker was dismissed or
+/// there is nothing to pick from. Runs before the main TUI, reading terminal
+/// events synchronously.
+///
+/// # Errors
+///
+/// Returns an error if the terminal cannot be set up or drawn
+pub fn pick_session(sessions: &[SessionInfo]) -> Result<Option<String>> {
+    if sessions.is_empty() {
+        return Ok(None);
+    }
+
+    let mut terminal = setup_terminal()?;
+    let result = run_picker(&mut terminal, sessions);
+    restore_terminal(terminal)?;
+    result
+}
+
+fn run_picker(
+    terminal: &mut crate::tui::terminal::KodeTerminal,
+    sessions: &[SessionInfo],
+) -> Result<Option<String>> {
+    let items: Vec<ListItem> = sessions
+        .iter()
+        .map(|session| {
+            ListItem::new(Line::from(vec![
+                Span::styled(
+                    session.modified.format("%Y-%m-%d %H:%M  ").to_string(),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::styled(
+                    format!("{:>4} msgs  ", session.message_count),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::raw(session.summary.clone()),
+            ]))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Resume a session (Enter to select, Esc to cancel) "),
+        )
+        .highlight_style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
+        .highlight_symbol("❯ ");
+
+    let mut state = ListState::default();
+    state.select(Some(0));
+
+    loop {
+        terminal.draw(|f| f.render_stateful_widget(list.clone(), f.area(), &mut state))?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        match key.code {
+            KeyCode::Up => state.select_previous(),
+            KeyCode::Down => {
+                let next = state.selected().map_or(0, |i| (i + 1).min(sessions.len() - 1));
+                state.select(Some(next));
+            }
+            KeyCode::Enter => {
+                return Ok(state.selected().map(|i| sessions[i].id.clone()));
+            }
+            KeyCode::Esc => return Ok(None),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Ok(None);
+            }
+            _ => {}
+        }
+    }
+}
@@ -12,17 +12,24 @@ use ratatui::{
 pub fn render(f: &mut Frame, area: Rect, app: &App) {
     let mode_str = match app.input_mode() {
         InputMode::Prompt => "Prompt",
+        InputMode::Permission => "Permission",
     };
 
     let mode_color = match app.input_mode() {
         InputMode::Prompt => Color::Green,
+        InputMode::Permission => Color::Yellow,
+    };
+
+    let title = match app.pending_image_count() {
+        0 => format!(" {} ", mode_str),
+        n => format!(" {mode_str} [{n} image(s) attached] "),
     };
 
     let input = Paragraph::new(app.input_buffer())
         .block(
             Block::default()
                 .borders(Borders::ALL)
-                .title(format!(" {} ", mode_str))
+                .title(title)
                 .border_style(Style::default().fg(mode_color)),
         );
 
@@ -1,6 +1,6 @@
 ///! Main layout for the TUI
 
-use super::{input, message, status};
+use super::{input, message, permission, status};
 use crate::tui::app::App;
 use ratatui::{
     layout::{Constraint, Direction, Layout},
@@ -9,17 +9,28 @@ use ratatui::{
 
 /// Draw the main layout
 pub fn draw(f: &mut Frame, app: &App) {
+    // The permission dialog takes the place of the input field
+    let input_height = if app.pending_permission().is_some() {
+        permission::DIALOG_HEIGHT
+    } else {
+        3
+    };
+
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Min(1),    // Messages area (expandable)
-            Constraint::Length(3), // Input field (3 lines with border)
-            Constraint::Length(1), // Status bar (1 line)
+            Constraint::Min(1),               // Messages area (expandable)
+            Constraint::Length(input_height), // Input field or permission dialog
+            Constraint::Length(1),            // Status bar (1 line)
         ])
         .split(f.area());
 
     // Render components
     message::render(f, chunks[0], app);
-    input::render(f, chunks[1], app);
+    if app.pending_permission().is_some() {
+        permission::render(f, chunks[1], app);
+    } else {
+        input::render(f, chunks[1], app);
+    }
     status::render(f, chunks[2], app);
 }
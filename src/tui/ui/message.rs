@@ -1,7 +1,7 @@
 ///! Message rendering
 
 use crate::{
-    messages::{ContentBlock, Role},
+    messages::{ContentBlock, ImageSource, Role},
     tui::app::App,
 };
 use ratatui::{
@@ -20,16 +20,36 @@ pub fn render(f: &mut Frame, area: Rect, app: &App) {
     for msg in app.messages() {
         match msg.role {
             Role::User => {
-                // User message header
-                lines.push(Line::from(vec![
-                    Span::styled(
-                        "You: ",
-                        Style::default()
-                            .fg(Color::Blue)
-                            .add_modifier(Modifier::BOLD),
-                    ),
-                    Span::raw(msg.text_content()),
-                ]));
+                let text = msg.text_content();
+                if !text.is_empty() {
+                    // User message header
+                    lines.push(Line::from(vec![
+                        Span::styled(
+                            "You: ",
+                            Style::default()
+                                .fg(Color::Blue)
+                                .add_modifier(Modifier::BOLD),
+                        ),
+                        Span::raw(text),
+                    ]));
+                }
+
+                // Attached images and tool results fed back to the model
+                for block in &msg.content {
+                    match block {
+                        ContentBlock::Image { source } => lines.push(image_line(source)),
+                        ContentBlock::ToolResult {
+                            content,
+                            is_error,
+                            images,
+                            ..
+                        } => {
+                            lines.extend(tool_result_lines(content, is_error.unwrap_or(false)));
+                            lines.extend(images.iter().map(|source| image_line(source)));
+                        }
+                        _ => {}
+                    }
+                }
                 lines.push(Line::from("")); // Empty line for spacing
             }
             Role::Assistant => {
@@ -50,19 +70,20 @@ pub fn render(f: &mut Frame, area: Rect, app: &App) {
                                 lines.push(Line::from(line.to_string()));
                             }
                         }
-                        ContentBlock::Thinking { thinking } => {
-                            lines.push(Line::from(vec![
-                                Span::styled(
-                                    "[Thinking] ",
-                                    Style::default()
-                                        .fg(Color::Yellow)
-                                        .add_modifier(Modifier::ITALIC),
-                                ),
-                                Span::styled(
-                                    thinking,
-                                    Style::default().add_modifier(Modifier::ITALIC),
-                                ),
-                            ]));
+                        ContentBlock::Thinking { thinking, .. } => {
+                            lines.extend(thinking_lines(thinking, app.show_thinking()));
+                        }
+                        ContentBlock::Reasoning { summary, .. } if !summary.is_empty() => {
+                            lines.extend(thinking_lines(summary, app.show_thinking()));
+                        }
+                        ContentBlock::Reasoning { .. } => {}
+                        ContentBlock::RedactedThinking { .. } => {
+                            lines.push(Line::from(Span::styled(
+                                "▸ Thinking (redacted)",
+                                Style::default()
+                                    .fg(Color::Yellow)
+                                    .add_modifier(Modifier::ITALIC),
+                            )));
                         }
                         ContentBlock::ToolUse { id: _, name, input } => {
                             lines.push(Line::from(vec![
@@ -84,20 +105,11 @@ pub fn render(f: &mut Frame, area: Rect, app: &App) {
                             ]));
                         }
                         ContentBlock::ToolResult {
-                            tool_use_id: _,
-                            content,
-                            is_error,
+                            content, is_error, ..
                         } => {
-                            let color = if is_error.unwrap_or(false) {
-                                Color::Red
-                            } else {
-                                Color::Cyan
-                            };
-                            lines.push(Line::from(vec![
-                                Span::styled("[Result] ", Style::default().fg(color)),
-                                Span::raw(content),
-                            ]));
+                            lines.extend(tool_result_lines(content, is_error.unwrap_or(false)));
                         }
+                        ContentBlock::Image { source } => lines.push(image_line(source)),
                     }
                 }
 
@@ -109,10 +121,19 @@ pub fn render(f: &mut Frame, area: Rect, app: &App) {
         }
     }
 
-    // Show loading indicator
+    if let Some(notice) = app.notice() {
+        for line in notice.lines() {
+            lines.push(Line::from(Span::styled(
+                line.to_string(),
+                Style::default().fg(Color::DarkGray),
+            )));
+        }
+    }
+
+    // Show loading indicator (or the latest tool progress)
     if app.is_loading() {
         lines.push(Line::from(Span::styled(
-            "Loading...",
+            app.tool_progress().unwrap_or("Loading...").to_string(),
             Style::default()
                 .fg(Color::Gray)
                 .add_modifier(Modifier::ITALIC),
@@ -131,3 +152,76 @@ pub fn render(f: &mut Frame, area: Rect, app: &App) {
 
     f.render_widget(paragraph, area);
 }
+
+/// Render a thinking section, collapsed to its header unless `expanded`
+fn thinking_lines(thinking: &str, expanded: bool) -> Vec<Line<'static>> {
+    let header_style = Style::default()
+        .fg(Color::Yellow)
+        .add_modifier(Modifier::ITALIC);
+    let count = thinking.lines().count();
+
+    if !expanded {
+        let noun = if count == 1 { "line" } else { "lines" };
+        return vec![Line::from(Span::styled(
+            format!("▸ Thinking ({count} {noun}, Ctrl+T to expand)"),
+            header_style,
+        ))];
+    }
+
+    let mut lines = vec![Line::from(Span::styled(
+        "▾ Thinking (Ctrl+T to collapse)",
+        header_style,
+    ))];
+    lines.extend(thinking.lines().map(|line| {
+        Line::from(Span::styled(
+            format!("  {line}"),
+            Style::default()
+                .fg(Color::DarkGray)
+                .add_modifier(Modifier::ITALIC),
+        ))
+    }));
+    lines
+}
+
+/// Maximum number of tool result lines shown inline
+const MAX_TOOL_RESULT_LINES: usize = 5;
+
+/// Placeholder line for an image
+fn image_line(source: &ImageSource) -> Line<'static> {
+    let label = match source {
+        ImageSource::Base64 { media_type, .. } => format!("[Image: {media_type}]"),
+        ImageSource::File { file_id } => format!("[Image: {file_id}]"),
+    };
+    Line::from(Span::styled(label, Style::default().fg(Color::Magenta)))
+}
+
+/// Render a tool result, truncated to a few lines
+fn tool_result_lines(content: &str, is_error: bool) -> Vec<Line<'static>> {
+    let color = if is_error { Color::Red } else { Color::Cyan };
+    let total = content.lines().count();
+
+    let mut lines: Vec<Line<'static>> = content
+        .lines()
+        .take(MAX_TOOL_RESULT_LINES)
+        .enumerate()
+        .map(|(i, line)| {
+            let prefix = if i == 0 { "[Result] " } else { "         " };
+            Line::from(vec![
+                Span::styled(prefix, Style::default().fg(color)),
+                Span::raw(line.to_string()),
+            ])
+        })
+        .collect();
+
+    if lines.is_empty() {
+        lines.push(Line::from(Span::styled("[Result] (empty)", Style::default().fg(color))));
+    }
+    if total > MAX_TOOL_RESULT_LINES {
+        lines.push(Line::from(Span::styled(
+            format!("         ... (+{} lines)", total - MAX_TOOL_RESULT_LINES),
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+
+    lines
+}
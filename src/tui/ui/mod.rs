@@ -3,6 +3,7 @@
 mod input;
 mod layout;
 mod message;
+mod permission;
 mod status;
 
 use crate::tui::app::App;
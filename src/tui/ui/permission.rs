@@ -0,0 +1,61 @@
+//! Permission dialog rendering
+
+use crate::{
+    permissions::PermissionDecision,
+    tui::{app::App, permission::PERMISSION_OPTIONS},
+};
+use ratatui::{
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph, Wrap},
+    Frame,
+};
+
+/// Height of the dialog, including borders
+pub const DIALOG_HEIGHT: u16 = 9;
+
+/// Render the pending permission request, if any
+pub fn render(f: &mut Frame, area: Rect, app: &App) {
+    let Some(pending) = app.pending_permission() else {
+        return;
+    };
+
+    let mut lines = vec![
+        Line::from(Span::styled(
+            pending.request.tool_name.clone(),
+            Style::default().add_modifier(Modifier::BOLD),
+        )),
+        Line::from(Span::raw(format!("  {}", pending.request.description))),
+        Line::from(""),
+        Line::from("Do you want to proceed?"),
+    ];
+
+    for (index, (decision, label)) in PERMISSION_OPTIONS.iter().enumerate() {
+        let label = if *decision == PermissionDecision::AllowAlways {
+            format!("Yes, and don't ask again for {} in this project", pending.request.rule)
+        } else {
+            (*label).to_string()
+        };
+        let line = if index == pending.selected {
+            Line::from(Span::styled(
+                format!("❯ {}. {label}", index + 1),
+                Style::default().fg(Color::Cyan),
+            ))
+        } else {
+            Line::from(Span::raw(format!("  {}. {label}", index + 1)))
+        };
+        lines.push(line);
+    }
+
+    let dialog = Paragraph::new(lines)
+        .wrap(Wrap { trim: false })
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Permission required ")
+                .border_style(Style::default().fg(Color::Yellow)),
+        );
+
+    f.render_widget(dialog, area);
+}
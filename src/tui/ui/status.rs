@@ -1,6 +1,6 @@
 ///! Status bar rendering
 
-use crate::tui::app::App;
+use crate::{cost::format_cost, tui::app::App};
 use ratatui::{
     layout::Rect,
     style::{Color, Style},
@@ -44,6 +44,14 @@ pub fn render(f: &mut Frame, area: Rect, app: &App) {
         " to cancel/quit, ",
         Style::default().fg(Color::DarkGray),
     ));
+    spans.push(Span::styled(
+        "Ctrl+T",
+        Style::default().fg(Color::White),
+    ));
+    spans.push(Span::styled(
+        " to toggle thinking, ",
+        Style::default().fg(Color::DarkGray),
+    ));
     spans.push(Span::styled(
         "Ctrl+C",
         Style::default().fg(Color::White),
@@ -53,6 +61,13 @@ pub fn render(f: &mut Frame, area: Rect, app: &App) {
         Style::default().fg(Color::DarkGray),
     ));
 
+    // Running cost of the session
+    spans.push(Span::raw(" | "));
+    spans.push(Span::styled(
+        format_cost(app.costs().total().cost_usd),
+        Style::default().fg(Color::White),
+    ));
+
     // Safe mode indicator
     if app.is_loading() {
         spans.push(Span::raw(" | "));